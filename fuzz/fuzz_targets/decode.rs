@@ -0,0 +1,22 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use trip_night_core::decode::{decode_instruction, decode_slot};
+use trip_night_core::instruction::OpCode;
+use trip_night_core::machine::State;
+use trip_night_instruction::make_standard_set;
+
+fuzz_target!(|data: &[u8]| {
+    let set = make_standard_set();
+    let mut state = State::builder().build();
+
+    for bytes in data.chunks_exact(2) {
+        let opcode = OpCode::new(u16::from_be_bytes([bytes[0], bytes[1]]));
+
+        match (decode_slot(opcode), decode_instruction(&set, opcode)) {
+            (Ok(_), Ok(instruction)) => instruction.execute(opcode, &mut state),
+            (Err(_), Err(_)) => {}
+            _ => panic!("decode_slot and decode_instruction disagree on {opcode:?}"),
+        }
+    }
+});
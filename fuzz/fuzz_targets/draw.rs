@@ -0,0 +1,19 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use trip_night_core::screen::{Resolution, Screen};
+
+fuzz_target!(|data: &[u8]| {
+    let mut screen = Screen::default();
+
+    for op in data.chunks_exact(4) {
+        let [kind, vector, x, y] = [op[0], op[1], op[2], op[3]];
+
+        match kind % 4 {
+            0 => drop(screen.flip_vectored(vector, x, y)),
+            1 => drop(screen.flip_vectored_wrapping(vector, x, y)),
+            2 => screen.set_resolution(Resolution::Hires),
+            _ => screen.set_resolution(Resolution::Lores),
+        }
+    }
+});
@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use trip_night_core::movie::Movie;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(text) = std::str::from_utf8(data) else {
+        return;
+    };
+
+    if let Ok(movie) = Movie::parse(text) {
+        assert_eq!(Movie::parse(&movie.to_string()), Ok(movie));
+    }
+});
@@ -0,0 +1,41 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use trip_night_core::machine::{FaultPolicy, Machine, MachineError, State, MAX_ROM_SIZE};
+use trip_night_core::quirks::Quirks;
+use trip_night_instruction::make_set;
+
+fn trap(_: &mut State, error: &MachineError) -> bool {
+    !matches!(error, MachineError::StackOverflow { .. })
+}
+
+fuzz_target!(|data: &[u8]| {
+    // The first byte picks the configuration, the rest is the ROM
+    let Some((&config, rom)) = data.split_first() else {
+        return;
+    };
+
+    let quirks = [
+        Quirks::default(),
+        Quirks::cosmac_vip(),
+        Quirks::schip(),
+        Quirks::xo_chip(),
+    ][usize::from(config % 4)];
+
+    let fault_policy = match config / 4 % 3 {
+        0 => FaultPolicy::Halt,
+        1 => FaultPolicy::IgnoreAndContinue,
+        _ => FaultPolicy::TrapToHandler(trap),
+    };
+
+    let mut machine = Machine::builder()
+        .rom(&rom[..rom.len().min(MAX_ROM_SIZE)])
+        .quirks(quirks)
+        .instruction_set(make_set)
+        .deterministic(u32::from(config))
+        .build()
+        .expect("a ROM fitting in memory");
+    machine.fault_policy = fault_policy;
+
+    machine.run_cycles(10_000);
+});
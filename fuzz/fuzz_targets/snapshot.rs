@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use trip_night_core::snapshot::Snapshot;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(snapshot) = Snapshot::from_bytes(data) {
+        let decoded = Snapshot::from_bytes(&snapshot.to_bytes()).expect("a re-encoded snapshot");
+        assert_eq!(decoded.to_bytes(), snapshot.to_bytes());
+    }
+});
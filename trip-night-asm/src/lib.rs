@@ -0,0 +1,505 @@
+//! Assembler of the Trip Night emulator, building CHIP-8 ROMs from Cowgod-style assembly
+//!
+//! The syntax is the one of the disassembler of `trip-night-core`, so that disassembled ROMs and
+//! listings assemble back into the same bytes:
+//!
+//! ```text
+//! ; Draws a sprite forever
+//!         LD I, sprite
+//!         LD V0, 0x1C       ; x
+//!         LD V1, 12         ; y
+//! loop:   DRW V0, V1, 5
+//!         JP loop
+//! sprite: DB 0xF0, 0x90, 0x90, 0x90, 0xF0
+//! ```
+//!
+//! - mnemonics and registers are case-insensitive, labels are not
+//! - numbers are decimal, hexadecimal (`0x2A`) or binary (`0b0010_1010`)
+//! - a label can be used wherever an address or a number is expected
+//! - `DB` and `DW` emit bytes and big-endian words, `DW` also being how unknown opcodes are disassembled
+//! - comments start with `;`
+//! - `NAME EQU value` defines a constant, usable like a label
+//! - `MACRO name params` … `ENDM` define macros and `INCLUDE "file"` includes files (see `preprocess`)
+//!
+//! Programs written in Octo, the other popular CHIP-8 assembly language, are assembled by
+//! `assemble_octo`.
+
+mod octo;
+mod preprocess;
+mod syntax;
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::{fmt, io};
+
+use trip_night_core::symbols::Symbols;
+use trip_night_core::Address;
+
+use crate::preprocess::Preprocessor;
+use crate::syntax::{Line, Location, Operand, Value};
+
+/// Address the ROMs are loaded at by default
+pub const DEFAULT_BASE: u16 = 0x200;
+
+/// Highest address of the CHIP-8 address space
+const MAX_ADDRESS: u16 = 0xFFF;
+
+/// An error in the source, with its line
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    /// Included file of the error, `None` for the source given to the assembler
+    pub file: Option<PathBuf>,
+    /// Line of the error, starting at 1
+    pub line: usize,
+    pub kind: AsmErrorKind,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AsmErrorKind {
+    UnknownMnemonic(String),
+    /// The operands don't match any form of the instruction
+    InvalidOperands,
+    /// An operand is neither a register, a number nor a label
+    InvalidOperand(String),
+    UnknownLabel(String),
+    DuplicateLabel(String),
+    /// A number doesn't fit in its field, e.g. 256 as a byte
+    OutOfRange {
+        value: u32,
+        max: u32,
+    },
+    /// The program goes past the end of the address space
+    TooLong,
+    /// An included file can't be read
+    Include {
+        path: PathBuf,
+        error: String,
+    },
+    /// A file includes itself, directly or not
+    RecursiveInclude(PathBuf),
+    /// A `MACRO` without its `ENDM`
+    UnterminatedMacro(String),
+    MacroArguments {
+        expected: usize,
+        found: usize,
+    },
+    /// A macro expands to itself, directly or not
+    RecursiveMacro(String),
+    /// A block closed without being opened, or never closed, e.g. Octo's `loop` without `again`
+    Unbalanced(String),
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}:{}: ", file.display(), self.line)?,
+            None => write!(f, "line {}: ", self.line)?,
+        }
+
+        match &self.kind {
+            AsmErrorKind::UnknownMnemonic(mnemonic) => write!(f, "unknown mnemonic `{mnemonic}`"),
+            AsmErrorKind::InvalidOperands => write!(f, "invalid operands"),
+            AsmErrorKind::InvalidOperand(operand) => write!(f, "invalid operand `{operand}`"),
+            AsmErrorKind::UnknownLabel(label) => write!(f, "unknown label `{label}`"),
+            AsmErrorKind::DuplicateLabel(label) => write!(f, "label `{label}` already defined"),
+            AsmErrorKind::OutOfRange { value, max } => write!(f, "{value:#x} is out of range (max {max:#x})"),
+            AsmErrorKind::TooLong => write!(f, "program goes past the end of the address space"),
+            AsmErrorKind::Include { path, error } => write!(f, "can't include {}: {error}", path.display()),
+            AsmErrorKind::RecursiveInclude(path) => write!(f, "{} includes itself", path.display()),
+            AsmErrorKind::UnterminatedMacro(name) => write!(f, "macro `{name}` has no ENDM"),
+            AsmErrorKind::MacroArguments { expected, found } => {
+                write!(f, "expected {expected} macro arguments, found {found}")
+            }
+            AsmErrorKind::RecursiveMacro(name) => write!(f, "macro `{name}` expands to itself"),
+            AsmErrorKind::Unbalanced(token) => write!(f, "unbalanced `{token}`"),
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Assembled program
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Assembly {
+    pub rom: Vec<u8>,
+    /// Addresses of the labels, constants excluded, to debug the ROM by name (see
+    /// `trip_night_core::symbols`)
+    pub symbols: Symbols,
+}
+
+/// Assembles a ROM loaded at `DEFAULT_BASE`
+pub fn assemble(source: &str) -> Result<Assembly, AsmError> {
+    assemble_at(source, DEFAULT_BASE)
+}
+
+/// Assembles a ROM loaded at `base`, the address labels are relative to
+///
+/// Included files are relative to the current directory.
+pub fn assemble_at(source: &str, base: u16) -> Result<Assembly, AsmError> {
+    assemble_with(source, None, base, &mut |path| std::fs::read_to_string(path))
+}
+
+/// Assembles a source file into a ROM loaded at `base`, included files being relative to it
+pub fn assemble_file(path: &Path, base: u16) -> Result<Assembly, AsmError> {
+    let source = std::fs::read_to_string(path).map_err(|error| AsmError {
+        file: None,
+        line: 0,
+        kind: AsmErrorKind::Include {
+            path: path.to_path_buf(),
+            error: error.to_string(),
+        },
+    })?;
+
+    assemble_with(&source, Some(path.into()), base, &mut |path| {
+        std::fs::read_to_string(path)
+    })
+}
+
+/// Assembles an Octo program, loaded at `DEFAULT_BASE` like every Octo program (see `octo`)
+pub fn assemble_octo(source: &str) -> Result<Assembly, AsmError> {
+    assemble_lines(octo::parse(source, DEFAULT_BASE)?, DEFAULT_BASE)
+}
+
+fn assemble_with(
+    source: &str,
+    file: Option<Rc<Path>>,
+    base: u16,
+    load: &mut dyn FnMut(&Path) -> io::Result<String>,
+) -> Result<Assembly, AsmError> {
+    let lines = Preprocessor::new(load).expand(source, file)?;
+    let lines = lines
+        .iter()
+        .map(|line| {
+            let parsed = Line::parse(&line.text).map_err(|kind| line.location.error(kind))?;
+            Ok((line.location.clone(), parsed))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    assemble_lines(lines, base)
+}
+
+/// Assembles parsed lines, in two passes
+fn assemble_lines(lines: Vec<(Location, Line<'_>)>, base: u16) -> Result<Assembly, AsmError> {
+    let mut statements = Vec::new();
+    let mut labels = HashMap::new();
+    let mut symbols = Symbols::new();
+    let mut addr = u32::from(base);
+
+    // First pass: addresses of the labels and values of the constants
+    for (line, parsed) in lines {
+        let mut value = addr;
+        let mut constant = false;
+
+        if let Some((mnemonic, operands)) = parsed.statement {
+            let size = match mnemonic.to_ascii_uppercase().as_str() {
+                "EQU" => {
+                    let (Some(_), [operand]) = (parsed.label, operands.as_slice()) else {
+                        return Err(line.error(AsmErrorKind::InvalidOperands));
+                    };
+                    let encoder = Encoder { labels: &labels };
+                    value = encoder.resolve(operand).map_err(|kind| line.error(kind))?;
+                    constant = true;
+                    0
+                }
+                "DB" => operands.len(),
+                "DW" => operands.len() * 2,
+                _ => 2,
+            };
+
+            if size > 0 {
+                statements.push((line.clone(), mnemonic, operands));
+                addr += size as u32;
+            }
+        }
+
+        if let Some(label) = parsed.label {
+            if labels.insert(label, value).is_some() {
+                return Err(line.error(AsmErrorKind::DuplicateLabel(label.to_owned())));
+            }
+
+            // A label at the very end of the address space has no address to name
+            match u16::try_from(value).ok().and_then(Address::new) {
+                Some(addr) if !constant => {
+                    symbols.insert(label, addr);
+                }
+                _ => {}
+            }
+        }
+
+        if addr > u32::from(MAX_ADDRESS) + 1 {
+            return Err(line.error(AsmErrorKind::TooLong));
+        }
+    }
+
+    // Second pass: encoding, now that every label is known
+    let mut rom = Vec::new();
+
+    for (line, mnemonic, operands) in statements {
+        let encoder = Encoder { labels: &labels };
+        encoder
+            .encode(mnemonic, &operands, &mut rom)
+            .map_err(|kind| line.error(kind))?;
+    }
+
+    Ok(Assembly { rom, symbols })
+}
+
+struct Encoder<'a, 'src> {
+    labels: &'a HashMap<&'src str, u32>,
+}
+
+impl Encoder<'_, '_> {
+    fn encode(&self, mnemonic: &str, operands: &[Operand<'_>], rom: &mut Vec<u8>) -> Result<(), AsmErrorKind> {
+        use Operand::*;
+
+        let upper = mnemonic.to_ascii_uppercase();
+
+        match upper.as_str() {
+            "DB" => {
+                for operand in operands {
+                    rom.push(self.value(operand, 0xFF)? as u8);
+                }
+                return Ok(());
+            }
+            "DW" => {
+                for operand in operands {
+                    rom.extend_from_slice(&self.value(operand, 0xFFFF)?.to_be_bytes());
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let xy = |x: u8, y: u8| u16::from(x) << 8 | u16::from(y) << 4;
+        let x = |x: u8| u16::from(x) << 8;
+
+        let opcode = match (upper.as_str(), operands) {
+            ("CLS", []) => 0x00E0,
+            ("RET", []) => 0x00EE,
+            ("YIELD", []) => 0x00F1,
+            ("SYS", [addr]) => self.value(addr, 0xFFF)?,
+            ("JP", [addr @ Value(_)]) => 0x1000 | self.value(addr, 0xFFF)?,
+            ("JP", [Register(0), addr]) => 0xB000 | self.value(addr, 0xFFF)?,
+            ("CALL", [addr]) => 0x2000 | self.value(addr, 0xFFF)?,
+            ("SE", [Register(vx), Register(vy)]) => 0x5000 | xy(*vx, *vy),
+            ("SE", [Register(vx), byte]) => 0x3000 | x(*vx) | self.value(byte, 0xFF)?,
+            ("SNE", [Register(vx), Register(vy)]) => 0x9000 | xy(*vx, *vy),
+            ("SNE", [Register(vx), byte]) => 0x4000 | x(*vx) | self.value(byte, 0xFF)?,
+            ("LD", [Register(vx), Register(vy)]) => 0x8000 | xy(*vx, *vy),
+            ("LD", [Register(vx), DelayTimer]) => 0xF007 | x(*vx),
+            ("LD", [Register(vx), Key]) => 0xF00A | x(*vx),
+            ("LD", [Register(vx), IndexedMemory]) => 0xF065 | x(*vx),
+            ("LD", [Register(vx), byte @ Value(_)]) => 0x6000 | x(*vx) | self.value(byte, 0xFF)?,
+            ("LD", [Index, addr]) => 0xA000 | self.value(addr, 0xFFF)?,
+            ("LD", [DelayTimer, Register(vx)]) => 0xF015 | x(*vx),
+            ("LD", [SoundTimer, Register(vx)]) => 0xF018 | x(*vx),
+            ("LD", [Font, Register(vx)]) => 0xF029 | x(*vx),
+            ("LD", [Bcd, Register(vx)]) => 0xF033 | x(*vx),
+            ("LD", [IndexedMemory, Register(vx)]) => 0xF055 | x(*vx),
+            ("ADD", [Register(vx), Register(vy)]) => 0x8004 | xy(*vx, *vy),
+            ("ADD", [Register(vx), byte]) => 0x7000 | x(*vx) | self.value(byte, 0xFF)?,
+            ("ADD", [Index, Register(vx)]) => 0xF01E | x(*vx),
+            ("OR", [Register(vx), Register(vy)]) => 0x8001 | xy(*vx, *vy),
+            ("AND", [Register(vx), Register(vy)]) => 0x8002 | xy(*vx, *vy),
+            ("XOR", [Register(vx), Register(vy)]) => 0x8003 | xy(*vx, *vy),
+            ("SUB", [Register(vx), Register(vy)]) => 0x8005 | xy(*vx, *vy),
+            ("SHR", [Register(vx), Register(vy)]) => 0x8006 | xy(*vx, *vy),
+            ("SHR", [Register(vx)]) => 0x8006 | xy(*vx, *vx),
+            ("SUBN", [Register(vx), Register(vy)]) => 0x8007 | xy(*vx, *vy),
+            ("SHL", [Register(vx), Register(vy)]) => 0x800E | xy(*vx, *vy),
+            ("SHL", [Register(vx)]) => 0x800E | xy(*vx, *vx),
+            ("RND", [Register(vx), byte]) => 0xC000 | x(*vx) | self.value(byte, 0xFF)?,
+            ("DRW", [Register(vx), Register(vy), n]) => 0xD000 | xy(*vx, *vy) | self.value(n, 0xF)?,
+            ("SKP", [Register(vx)]) => 0xE09E | x(*vx),
+            ("SKNP", [Register(vx)]) => 0xE0A1 | x(*vx),
+            (
+                "CLS" | "RET" | "YIELD" | "SYS" | "JP" | "CALL" | "SE" | "SNE" | "LD" | "ADD" | "OR" | "AND" | "XOR"
+                | "SUB" | "SHR" | "SUBN" | "SHL" | "RND" | "DRW" | "SKP" | "SKNP",
+                _,
+            ) => return Err(AsmErrorKind::InvalidOperands),
+            _ => return Err(AsmErrorKind::UnknownMnemonic(mnemonic.to_owned())),
+        };
+
+        rom.extend_from_slice(&opcode.to_be_bytes());
+        Ok(())
+    }
+
+    /// Value of a number or label operand, which must not be greater than `max`
+    fn value(&self, operand: &Operand<'_>, max: u32) -> Result<u16, AsmErrorKind> {
+        let value = self.resolve(operand)?;
+
+        if value > max {
+            return Err(AsmErrorKind::OutOfRange { value, max });
+        }
+
+        Ok(value as u16)
+    }
+
+    /// Value of a number, label or constant operand
+    fn resolve(&self, operand: &Operand<'_>) -> Result<u32, AsmErrorKind> {
+        match operand {
+            Operand::Value(Value::Number(value)) => Ok(*value),
+            Operand::Value(Value::Label(label)) => self
+                .labels
+                .get(label)
+                .copied()
+                .ok_or_else(|| AsmErrorKind::UnknownLabel((*label).to_owned())),
+            _ => Err(AsmErrorKind::InvalidOperands),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use trip_night_core::disasm::disasm;
+    use trip_night_core::instruction::OpCode;
+    use trip_night_core::listing::Listing;
+    use trip_night_core::Address;
+
+    use super::*;
+
+    #[test]
+    fn labels() {
+        let source = "
+            ; Draws a sprite forever
+                    LD I, sprite
+                    LD V0, 0x1C       ; x
+                    ld v1, 12         ; y
+            loop:   DRW V0, V1, 5
+                    JP loop
+            sprite: DB 0xF0, 0x90, 0x90, 0x90, 0xF0
+        ";
+
+        assert_eq!(
+            assemble(source).map(|assembly| assembly.rom),
+            Ok(vec![
+                0xA2, 0x0A, 0x60, 0x1C, 0x61, 0x0C, 0xD0, 0x15, 0x12, 0x06, 0xF0, 0x90, 0x90, 0x90, 0xF0
+            ])
+        );
+        assert_eq!(
+            assemble_at("start: JP start", 0x600).map(|assembly| assembly.rom),
+            Ok(vec![0x16, 0x00])
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let error = |line, kind| Err(AsmError { file: None, line, kind });
+
+        assert_eq!(
+            assemble("CLS\nFOO V0"),
+            error(2, AsmErrorKind::UnknownMnemonic("FOO".to_owned()))
+        );
+        assert_eq!(assemble("LD DT, 5"), error(1, AsmErrorKind::InvalidOperands));
+        assert_eq!(
+            assemble("LD V0, 256"),
+            error(1, AsmErrorKind::OutOfRange { value: 256, max: 0xFF })
+        );
+        assert_eq!(
+            assemble("JP nowhere"),
+            error(1, AsmErrorKind::UnknownLabel("nowhere".to_owned()))
+        );
+        assert_eq!(
+            assemble("a: CLS\na: CLS"),
+            error(2, AsmErrorKind::DuplicateLabel("a".to_owned()))
+        );
+        assert_eq!(assemble_at("DW 0, 0", 0xFFE), error(1, AsmErrorKind::TooLong));
+        assert_eq!(
+            assemble("LD V0, 256").unwrap_err().to_string(),
+            "line 1: 0x100 is out of range (max 0xff)"
+        );
+    }
+
+    #[test]
+    fn constants_macros_and_includes() {
+        let files = HashMap::from([
+            (
+                "src/sprites.asm",
+                "digit_0: DB 0xF0, 0x90, 0x90, 0x90, 0xF0\nINCLUDE \"more.asm\"",
+            ),
+            ("src/more.asm", "SIZE equ 5"),
+            ("src/loop.asm", "INCLUDE \"loop.asm\""),
+        ]);
+        let mut load = |path: &Path| {
+            let path = path.to_str().unwrap();
+            files
+                .get(path)
+                .map(|source| source.to_string())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "not found"))
+        };
+        let mut assemble =
+            |source: &str| assemble_with(source, Some(Path::new("src/main.asm").into()), 0x200, &mut load);
+
+        let source = "
+            X EQU 0x1C
+            Y: EQU X + 0
+            MACRO draw x, y, sprite
+                LD I, sprite
+                DRW x, y, SIZE
+            ENDM
+            start:  LD V0, X
+                    draw V0, V1, digit_0
+                    JP start
+            INCLUDE \"sprites.asm\"
+        ";
+        assert_eq!(
+            assemble(source),
+            Err(AsmError {
+                file: Some(PathBuf::from("src/main.asm")),
+                line: 3,
+                kind: AsmErrorKind::InvalidOperand("X + 0".to_owned()),
+            })
+        );
+
+        let source = source.replace("X + 0", "X");
+        let assembly = assemble(&source).unwrap();
+        assert_eq!(assembly.symbols.to_string(), "200 start\n208 digit_0\n");
+        assert_eq!(
+            assembly.rom,
+            [0x60, 0x1C, 0xA2, 0x08, 0xD0, 0x15, 0x12, 0x00, 0xF0, 0x90, 0x90, 0x90, 0xF0]
+        );
+
+        assert_eq!(
+            assemble("INCLUDE \"loop.asm\"").unwrap_err().kind,
+            AsmErrorKind::RecursiveInclude(PathBuf::from("src/loop.asm"))
+        );
+        assert_eq!(
+            assemble("MACRO nop\nCLS").unwrap_err().kind,
+            AsmErrorKind::UnterminatedMacro("nop".to_owned())
+        );
+        assert_eq!(
+            assemble("MACRO nop\nnop\nENDM\nnop").unwrap_err().kind,
+            AsmErrorKind::RecursiveMacro("nop".to_owned())
+        );
+        assert_eq!(
+            assemble("MACRO skip x\nSE x, 0\nENDM\nskip V0, V1").unwrap_err().kind,
+            AsmErrorKind::MacroArguments { expected: 1, found: 2 }
+        );
+        assert_eq!(
+            assemble("INCLUDE \"sprites.asm\"\nDRW V0, V0, SIZE\nINCLUDE \"more.asm\"")
+                .unwrap_err()
+                .to_string(),
+            "src/more.asm:1: label `SIZE` already defined"
+        );
+    }
+
+    #[test]
+    fn disassembly_round_trip() {
+        for opcode in 0..=u16::MAX {
+            let text = disasm(OpCode::new(opcode)).to_string();
+            assert_eq!(assemble(&text).unwrap().rom, opcode.to_be_bytes(), "{text}");
+        }
+    }
+
+    #[test]
+    fn listing_round_trip() {
+        let rom = [
+            0xA2, 0x0C, 0x22, 0x08, 0x12, 0x06, 0x12, 0x06, 0x30, 0x00, 0x00, 0xEE, 0xF0, 0x90, 0x90, 0x90, 0xF0, 0xB2,
+            0x0E,
+        ];
+
+        let listing = Listing::analyze(&rom, Address::new(0x200).unwrap()).to_string();
+        assert_eq!(assemble(&listing).map(|assembly| assembly.rom), Ok(rom.to_vec()));
+    }
+}
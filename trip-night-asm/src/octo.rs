@@ -0,0 +1,527 @@
+//! Octo, the assembly language of the Octo IDE and the de facto standard of the CHIP-8 community
+//!
+//! Octo programs are made of whitespace-separated tokens and `#` comments:
+//!
+//! ```text
+//! :alias x v0
+//! : main
+//!     i := digit
+//!     loop
+//!         sprite x v1 5
+//!         x += 1
+//!         if x == 60 then x := 0
+//!     again
+//! : digit 0xF0 0x90 0x90 0x90 0xF0
+//! ```
+//!
+//! Execution starts at the `main` label: unless it comes first, the program starts with a jump to it.
+//!
+//! Supported are the CHIP-8 statements, `if … then`, `if … begin … else … end`, `loop … while …
+//! again`, subroutine calls by name, `:const`, `:alias` and `:byte`, and bare numbers as data. Not
+//! supported are `:macro`, `:calc`, `:org`, `:next`, `:unpack`, `:stringmode`, the `<`, `>`, `<=`
+//! and `>=` comparisons, and the SCHIP and XO-CHIP extensions.
+
+use std::collections::HashMap;
+
+use crate::syntax::{is_identifier, Line, Location, Operand, Value};
+use crate::{AsmError, AsmErrorKind};
+
+/// Parses an Octo program loaded at `base` into lines of the Cowgod syntax
+pub fn parse(source: &str, base: u16) -> Result<Vec<(Location, Line<'_>)>, AsmError> {
+    let tokens = source
+        .lines()
+        .enumerate()
+        .flat_map(|(idx, line)| {
+            let code = line.split('#').next().unwrap_or_default();
+            code.split_whitespace().map(move |token| (idx + 1, token))
+        })
+        .collect();
+
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        addr: u32::from(base),
+        aliases: HashMap::new(),
+        blocks: Vec::new(),
+        lines: Vec::new(),
+        started: false,
+    };
+
+    while parser.pos < parser.tokens.len() {
+        parser.statement()?;
+    }
+
+    if let Some(block) = parser.blocks.last() {
+        let (line, name) = match block {
+            Block::Loop { line, .. } => (*line, "loop"),
+            Block::If { line, .. } => (*line, "begin"),
+        };
+        return Err(location(line).error(AsmErrorKind::Unbalanced(name.to_owned())));
+    }
+
+    Ok(parser.lines)
+}
+
+/// Nested `loop` or `if … begin`, until its end
+enum Block {
+    Loop {
+        line: usize,
+        start: u32,
+        /// Jumps of the `while`s, out of the loop
+        exits: Vec<usize>,
+    },
+    If {
+        line: usize,
+        /// Jump to the `else` or the `end`
+        jump: usize,
+    },
+}
+
+/// Condition of `if` and `while`, as the skips taken when it holds or not
+struct Condition<'a> {
+    skip_if_true: &'static str,
+    skip_if_false: &'static str,
+    operands: Vec<Operand<'a>>,
+}
+
+struct Parser<'a> {
+    /// Tokens, with their line
+    tokens: Vec<(usize, &'a str)>,
+    pos: usize,
+    addr: u32,
+    aliases: HashMap<&'a str, u8>,
+    blocks: Vec<Block>,
+    lines: Vec<(Location, Line<'a>)>,
+    /// Whether code, data or a label came, after which `main` can't be the first thing anymore
+    started: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn next(&mut self) -> Result<(usize, &'a str), AsmError> {
+        let token = self.tokens.get(self.pos).copied().ok_or_else(|| {
+            let line = self.tokens.last().map_or(1, |(line, _)| *line);
+            location(line).error(AsmErrorKind::InvalidOperands)
+        })?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn push(&mut self, line: usize, label: Option<&'a str>, statement: Option<(&'a str, Vec<Operand<'a>>)>) {
+        self.lines.push((location(line), Line { label, statement }));
+    }
+
+    /// Emits an instruction, returning its index to patch jumps
+    fn emit(&mut self, line: usize, mnemonic: &'static str, operands: Vec<Operand<'a>>) -> usize {
+        self.push(line, None, Some((mnemonic, operands)));
+        self.addr += 2;
+        self.lines.len() - 1
+    }
+
+    /// Points a jump emitted before to the current address
+    fn patch(&mut self, jump: usize) {
+        let target = Operand::Value(Value::Number(self.addr));
+        self.lines[jump].1.statement = Some(("JP", vec![target]));
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let (line, token) = self.next()?;
+        let error = |kind| location(line).error(kind);
+
+        if !self.started && !matches!(token, ":alias" | ":const") {
+            self.started = true;
+            if (token, self.tokens.get(self.pos).map(|(_, name)| *name)) != (":", Some("main")) {
+                self.emit(line, "JP", vec![Operand::Value(Value::Label("main"))]);
+            }
+        }
+
+        match token {
+            ":" => {
+                let (_, name) = self.next()?;
+                self.push(line, Some(self.identifier(line, name)?), None);
+            }
+            ":const" => {
+                let (_, name) = self.next()?;
+                let name = self.identifier(line, name)?;
+                let (_, value) = self.next()?;
+                let value = self.operand(line, value)?;
+                self.push(line, Some(name), Some(("EQU", vec![value])));
+            }
+            ":alias" => {
+                let (_, name) = self.next()?;
+                let name = self.identifier(line, name)?;
+                let reg = self.register(line)?;
+                self.aliases.insert(name, reg);
+            }
+            ":byte" => {
+                let (_, value) = self.next()?;
+                let value = self.operand(line, value)?;
+                self.push(line, None, Some(("DB", vec![value])));
+                self.addr += 1;
+            }
+            "return" | ";" => {
+                self.emit(line, "RET", Vec::new());
+            }
+            "clear" => {
+                self.emit(line, "CLS", Vec::new());
+            }
+            "bcd" => {
+                let vx = self.register(line)?;
+                self.emit(line, "LD", vec![Operand::Bcd, Operand::Register(vx)]);
+            }
+            "save" => {
+                let vx = self.register(line)?;
+                self.emit(line, "LD", vec![Operand::IndexedMemory, Operand::Register(vx)]);
+            }
+            "load" => {
+                let vx = self.register(line)?;
+                self.emit(line, "LD", vec![Operand::Register(vx), Operand::IndexedMemory]);
+            }
+            "sprite" => {
+                let vx = self.register(line)?;
+                let vy = self.register(line)?;
+                let (_, n) = self.next()?;
+                let n = self.operand(line, n)?;
+                self.emit(line, "DRW", vec![Operand::Register(vx), Operand::Register(vy), n]);
+            }
+            "jump" | "jump0" | "native" => {
+                let (_, target) = self.next()?;
+                let target = self.operand(line, target)?;
+                match token {
+                    "jump" => self.emit(line, "JP", vec![target]),
+                    "jump0" => self.emit(line, "JP", vec![Operand::Register(0), target]),
+                    _ => self.emit(line, "SYS", vec![target]),
+                };
+            }
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let vx = self.register(line)?;
+                let timer = if token == "delay" {
+                    Operand::DelayTimer
+                } else {
+                    Operand::SoundTimer
+                };
+                self.emit(line, "LD", vec![timer, Operand::Register(vx)]);
+            }
+            "i" => self.index(line)?,
+            "if" => {
+                let condition = self.condition(line)?;
+                match self.next()?.1 {
+                    "then" => {
+                        self.emit(line, condition.skip_if_false, condition.operands);
+                    }
+                    "begin" => {
+                        self.emit(line, condition.skip_if_true, condition.operands);
+                        let jump = self.emit(line, "JP", Vec::new());
+                        self.blocks.push(Block::If { line, jump });
+                    }
+                    other => return Err(error(AsmErrorKind::InvalidOperand(other.to_owned()))),
+                }
+            }
+            "else" => {
+                let Some(Block::If { jump, .. }) = self.blocks.pop() else {
+                    return Err(error(AsmErrorKind::Unbalanced(token.to_owned())));
+                };
+                let end = self.emit(line, "JP", Vec::new());
+                self.patch(jump);
+                self.blocks.push(Block::If { line, jump: end });
+            }
+            "end" => {
+                let Some(Block::If { jump, .. }) = self.blocks.pop() else {
+                    return Err(error(AsmErrorKind::Unbalanced(token.to_owned())));
+                };
+                self.patch(jump);
+            }
+            "loop" => self.blocks.push(Block::Loop {
+                line,
+                start: self.addr,
+                exits: Vec::new(),
+            }),
+            "while" => {
+                let condition = self.condition(line)?;
+                self.emit(line, condition.skip_if_true, condition.operands);
+                let exit = self.emit(line, "JP", Vec::new());
+
+                let Some(Block::Loop { exits, .. }) = self
+                    .blocks
+                    .iter_mut()
+                    .rev()
+                    .find(|block| matches!(block, Block::Loop { .. }))
+                else {
+                    return Err(error(AsmErrorKind::Unbalanced(token.to_owned())));
+                };
+                exits.push(exit);
+            }
+            "again" => {
+                let Some(Block::Loop { start, exits, .. }) = self.blocks.pop() else {
+                    return Err(error(AsmErrorKind::Unbalanced(token.to_owned())));
+                };
+                self.emit(line, "JP", vec![Operand::Value(Value::Number(start))]);
+                for exit in exits {
+                    self.patch(exit);
+                }
+            }
+            _ if self.is_register(token) => self.assignment(line, token)?,
+            _ if token.starts_with(|c: char| c.is_ascii_digit() || c == '-') => {
+                let value = self.operand(line, token)?;
+                self.push(line, None, Some(("DB", vec![value])));
+                self.addr += 1;
+            }
+            _ if is_identifier(token) => {
+                self.emit(line, "CALL", vec![Operand::Value(Value::Label(token))]);
+            }
+            _ => return Err(error(AsmErrorKind::UnknownMnemonic(token.to_owned()))),
+        }
+
+        Ok(())
+    }
+
+    /// `vx := …`, `vx += …` and the other operations on registers
+    fn assignment(&mut self, line: usize, token: &'a str) -> Result<(), AsmError> {
+        let vx = Operand::Register(self.register_of(line, token)?);
+        let (_, op) = self.next()?;
+        let (_, rhs) = self.next()?;
+
+        if op == ":=" {
+            match rhs {
+                "delay" => self.emit(line, "LD", vec![vx, Operand::DelayTimer]),
+                "key" => self.emit(line, "LD", vec![vx, Operand::Key]),
+                "random" => {
+                    let (_, mask) = self.next()?;
+                    let mask = self.operand(line, mask)?;
+                    self.emit(line, "RND", vec![vx, mask])
+                }
+                _ => {
+                    let rhs = self.operand(line, rhs)?;
+                    self.emit(line, "LD", vec![vx, rhs])
+                }
+            };
+            return Ok(());
+        }
+
+        let rhs = self.operand(line, rhs)?;
+        let mnemonic = match (op, rhs) {
+            ("+=", _) => "ADD",
+            ("-=", Operand::Register(_)) => "SUB",
+            ("=-", Operand::Register(_)) => "SUBN",
+            ("|=", Operand::Register(_)) => "OR",
+            ("&=", Operand::Register(_)) => "AND",
+            ("^=", Operand::Register(_)) => "XOR",
+            (">>=", Operand::Register(_)) => "SHR",
+            ("<<=", Operand::Register(_)) => "SHL",
+            ("-=" | "=-" | "|=" | "&=" | "^=" | ">>=" | "<<=", _) => {
+                return Err(location(line).error(AsmErrorKind::InvalidOperands))
+            }
+            _ => return Err(location(line).error(AsmErrorKind::UnknownMnemonic(op.to_owned()))),
+        };
+        self.emit(line, mnemonic, vec![vx, rhs]);
+
+        Ok(())
+    }
+
+    /// `i := …` and `i += vx`
+    fn index(&mut self, line: usize) -> Result<(), AsmError> {
+        let (_, op) = self.next()?;
+        let (_, rhs) = self.next()?;
+
+        match op {
+            ":=" if rhs == "hex" => {
+                let vx = self.register(line)?;
+                self.emit(line, "LD", vec![Operand::Font, Operand::Register(vx)]);
+            }
+            ":=" => {
+                let addr = self.operand(line, rhs)?;
+                self.emit(line, "LD", vec![Operand::Index, addr]);
+            }
+            "+=" => {
+                let vx = self.register_of(line, rhs)?;
+                self.emit(line, "ADD", vec![Operand::Index, Operand::Register(vx)]);
+            }
+            _ => return Err(location(line).error(AsmErrorKind::UnknownMnemonic(op.to_owned()))),
+        }
+
+        Ok(())
+    }
+
+    /// `vx == …`, `vx != …`, `vx key` or `vx -key`
+    fn condition(&mut self, line: usize) -> Result<Condition<'a>, AsmError> {
+        let vx = Operand::Register(self.register(line)?);
+        let (_, op) = self.next()?;
+
+        let (skip_if_true, skip_if_false, operands) = match op {
+            "key" => ("SKP", "SKNP", vec![vx]),
+            "-key" => ("SKNP", "SKP", vec![vx]),
+            "==" | "!=" => {
+                let (_, rhs) = self.next()?;
+                let rhs = self.operand(line, rhs)?;
+                if op == "==" {
+                    ("SE", "SNE", vec![vx, rhs])
+                } else {
+                    ("SNE", "SE", vec![vx, rhs])
+                }
+            }
+            _ => return Err(location(line).error(AsmErrorKind::UnknownMnemonic(op.to_owned()))),
+        };
+
+        Ok(Condition {
+            skip_if_true,
+            skip_if_false,
+            operands,
+        })
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), AsmError> {
+        match self.next()? {
+            (_, token) if token == expected => Ok(()),
+            (line, token) => Err(location(line).error(AsmErrorKind::InvalidOperand(token.to_owned()))),
+        }
+    }
+
+    fn identifier(&self, line: usize, token: &'a str) -> Result<&'a str, AsmError> {
+        if is_identifier(token) {
+            Ok(token)
+        } else {
+            Err(location(line).error(AsmErrorKind::InvalidOperand(token.to_owned())))
+        }
+    }
+
+    fn is_register(&self, token: &str) -> bool {
+        self.register_of(0, token).is_ok()
+    }
+
+    fn register(&mut self, line: usize) -> Result<u8, AsmError> {
+        let (_, token) = self.next()?;
+        self.register_of(line, token)
+    }
+
+    /// Register named by a token, `v3` or an alias
+    fn register_of(&self, line: usize, token: &str) -> Result<u8, AsmError> {
+        if let Some(reg) = self.aliases.get(token) {
+            return Ok(*reg);
+        }
+
+        match token.strip_prefix(['v', 'V']).map(|reg| u8::from_str_radix(reg, 16)) {
+            Some(Ok(reg)) if token.len() == 2 => Ok(reg),
+            _ => Err(location(line).error(AsmErrorKind::InvalidOperand(token.to_owned()))),
+        }
+    }
+
+    /// Register, number, label or constant; negative numbers being bytes in two's complement
+    fn operand(&self, line: usize, token: &'a str) -> Result<Operand<'a>, AsmError> {
+        if let Ok(reg) = self.register_of(line, token) {
+            return Ok(Operand::Register(reg));
+        }
+
+        let error = |kind| location(line).error(kind);
+        let value = match token.strip_prefix('-') {
+            Some(magnitude) => match Value::parse(magnitude).map_err(error)? {
+                Value::Number(n @ 1..=0x80) => Value::Number(0x100 - n),
+                _ => return Err(error(AsmErrorKind::InvalidOperand(token.to_owned()))),
+            },
+            None => Value::parse(token).map_err(error)?,
+        };
+
+        Ok(Operand::Value(value))
+    }
+}
+
+fn location(line: usize) -> Location {
+    Location { file: None, line }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+        crate::assemble_octo(source).map(|assembly| assembly.rom)
+    }
+
+    #[test]
+    fn statements() {
+        let source = "
+            :alias x v0
+            :const SPEED 2
+            : main
+                i := digit      # sprite
+                loop
+                    sprite x v1 5
+                    x += SPEED
+                    if x == 60 then x := 0
+                    v2 := key
+                    v2 -= x
+                    while v2 != -1
+                    delay := v2
+                again
+                i := hex v3
+                v3 := random 0x0F
+                ;
+            : digit 0xF0 0x90 0x90 0x90 0xF0
+        ";
+
+        assert_eq!(
+            assemble(source),
+            Ok(vec![
+                0xA2, 0x1C, // i := digit
+                0xD0, 0x15, // loop: sprite x v1 5
+                0x70, 0x02, // x += SPEED
+                0x40, 0x3C, 0x60, 0x00, // if x == 60 then x := 0
+                0xF2, 0x0A, // v2 := key
+                0x82, 0x05, // v2 -= x
+                0x42, 0xFF, 0x12, 0x16, // while v2 != -1
+                0xF2, 0x15, // delay := v2
+                0x12, 0x02, // again
+                0xF3, 0x29, // i := hex v3
+                0xC3, 0x0F, // v3 := random 0x0F
+                0x00, 0xEE, // ;
+                0xF0, 0x90, 0x90, 0x90, 0xF0,
+            ])
+        );
+    }
+
+    #[test]
+    fn entry_point_and_blocks() {
+        let source = "
+            : draw
+                if v0 key begin
+                    clear
+                else
+                    v0 <<= v0
+                end
+                return
+            : main
+                draw
+                jump main
+        ";
+
+        assert_eq!(
+            assemble(source),
+            Ok(vec![
+                0x12, 0x0E, // jump main
+                0xE0, 0x9E, 0x12, 0x0A, // if v0 key begin
+                0x00, 0xE0, // clear
+                0x12, 0x0C, // else
+                0x80, 0x0E, // v0 <<= v0
+                0x00, 0xEE, // end return
+                0x22, 0x02, // main: draw
+                0x12, 0x0E, // jump main
+            ])
+        );
+
+        assert_eq!(
+            assemble(": main\nloop\nend").unwrap_err(),
+            location(3).error(AsmErrorKind::Unbalanced("end".to_owned()))
+        );
+        assert_eq!(
+            assemble(": main\nloop").unwrap_err(),
+            location(2).error(AsmErrorKind::Unbalanced("loop".to_owned()))
+        );
+        assert_eq!(
+            assemble(": main\n:calc x { 1 + 2 }").unwrap_err(),
+            location(2).error(AsmErrorKind::UnknownMnemonic(":calc".to_owned()))
+        );
+        assert_eq!(
+            assemble(": main\nv0 |= 3").unwrap_err(),
+            location(2).error(AsmErrorKind::InvalidOperands)
+        );
+    }
+}
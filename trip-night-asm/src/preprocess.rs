@@ -0,0 +1,252 @@
+//! Expansion of includes and macros, before the source is assembled
+//!
+//! ```text
+//! INCLUDE "sprites.asm"   ; path relative to the including file
+//!
+//! MACRO draw x, y, sprite ; parameters are substituted wherever they appear as a word
+//!     LD I, sprite
+//!     DRW x, y, 5
+//! ENDM
+//!
+//!     draw V0, V1, digit_0
+//! ```
+//!
+//! Labels defined within a macro are defined again by each expansion, so a macro with labels can
+//! only be used once.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::syntax::{is_identifier, split_label, Location};
+use crate::{AsmError, AsmErrorKind};
+
+/// How deep macros can expand within macros, which catches recursive macros
+const MAX_MACRO_DEPTH: usize = 16;
+
+/// A line of the expanded source, and where it comes from
+#[derive(Clone, Debug)]
+pub struct SourceLine {
+    pub location: Location,
+    pub text: String,
+}
+
+impl SourceLine {
+    fn error(&self, kind: AsmErrorKind) -> AsmError {
+        self.location.error(kind)
+    }
+}
+
+#[derive(Debug)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<SourceLine>,
+}
+
+/// Macro being defined, until its `ENDM`
+struct Definition {
+    name: String,
+    start: SourceLine,
+    macro_: Macro,
+}
+
+pub struct Preprocessor<'a> {
+    load: &'a mut dyn FnMut(&Path) -> io::Result<String>,
+    macros: HashMap<String, Macro>,
+    definition: Option<Definition>,
+    /// Files being expanded, to catch recursive includes
+    files: Vec<Rc<Path>>,
+    lines: Vec<SourceLine>,
+}
+
+impl<'a> Preprocessor<'a> {
+    /// `load` reads included files
+    pub fn new(load: &'a mut dyn FnMut(&Path) -> io::Result<String>) -> Self {
+        Self {
+            load,
+            macros: HashMap::new(),
+            definition: None,
+            files: Vec::new(),
+            lines: Vec::new(),
+        }
+    }
+
+    /// Expands a whole source, `file` being its path if it was read from one
+    pub fn expand(mut self, source: &str, file: Option<Rc<Path>>) -> Result<Vec<SourceLine>, AsmError> {
+        self.expand_file(source, file)?;
+        Ok(self.lines)
+    }
+
+    fn expand_file(&mut self, source: &str, file: Option<Rc<Path>>) -> Result<(), AsmError> {
+        self.files.extend(file.clone());
+
+        for (idx, text) in source.lines().enumerate() {
+            let line = SourceLine {
+                location: Location {
+                    file: file.clone(),
+                    line: idx + 1,
+                },
+                text: text.to_owned(),
+            };
+            self.expand_line(line, 0)?;
+        }
+
+        if let Some(definition) = self.definition.take() {
+            return Err(definition.start.error(AsmErrorKind::UnterminatedMacro(definition.name)));
+        }
+
+        if file.is_some() {
+            self.files.pop();
+        }
+
+        Ok(())
+    }
+
+    fn expand_line(&mut self, line: SourceLine, depth: usize) -> Result<(), AsmError> {
+        let code = line.text.split(';').next().unwrap_or_default().trim();
+        let (label, rest) = split_label(code);
+        let (word, args) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let args = args.trim();
+
+        if let Some(definition) = &mut self.definition {
+            if word.eq_ignore_ascii_case("ENDM") {
+                let definition = self.definition.take().expect("a macro being defined");
+                self.macros.insert(definition.name, definition.macro_);
+            } else {
+                definition.macro_.body.push(line);
+            }
+            return Ok(());
+        }
+
+        // Labels in front of directives and macros still name the current address
+        let label_line = |line: &SourceLine| SourceLine {
+            text: label.map(|label| format!("{label}:")).unwrap_or_default(),
+            ..line.clone()
+        };
+
+        if word.eq_ignore_ascii_case("MACRO") {
+            let (name, params) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+            let params: Vec<String> = match params.trim() {
+                "" => Vec::new(),
+                params => params.split(',').map(|param| param.trim().to_owned()).collect(),
+            };
+
+            if let Some(invalid) = [name]
+                .into_iter()
+                .chain(params.iter().map(String::as_str))
+                .find(|n| !is_identifier(n))
+            {
+                return Err(line.error(AsmErrorKind::InvalidOperand(invalid.to_owned())));
+            }
+
+            self.lines.push(label_line(&line));
+            self.definition = Some(Definition {
+                name: name.to_owned(),
+                start: line,
+                macro_: Macro {
+                    params,
+                    body: Vec::new(),
+                },
+            });
+        } else if word.eq_ignore_ascii_case("INCLUDE") {
+            let path = Path::new(args.trim_matches('"'));
+            let path: Rc<Path> = match line.location.file.as_deref().and_then(Path::parent) {
+                Some(dir) => dir.join(path).into(),
+                None => path.into(),
+            };
+
+            if self.files.contains(&path) {
+                return Err(line.error(AsmErrorKind::RecursiveInclude(path.to_path_buf())));
+            }
+
+            let source = (self.load)(&path).map_err(|error| {
+                line.error(AsmErrorKind::Include {
+                    path: path.to_path_buf(),
+                    error: error.to_string(),
+                })
+            })?;
+
+            self.lines.push(label_line(&line));
+            self.expand_file(&source, Some(path))?;
+        } else if let Some(macro_) = self.macros.get(word) {
+            let args: Vec<&str> = match args {
+                "" => Vec::new(),
+                args => args.split(',').map(str::trim).collect(),
+            };
+
+            if args.len() != macro_.params.len() {
+                return Err(line.error(AsmErrorKind::MacroArguments {
+                    expected: macro_.params.len(),
+                    found: args.len(),
+                }));
+            }
+
+            if depth == MAX_MACRO_DEPTH {
+                return Err(line.error(AsmErrorKind::RecursiveMacro(word.to_owned())));
+            }
+
+            let body: Vec<SourceLine> = macro_
+                .body
+                .iter()
+                .map(|body_line| SourceLine {
+                    text: substitute(&body_line.text, &macro_.params, &args),
+                    ..body_line.clone()
+                })
+                .collect();
+
+            self.lines.push(label_line(&line));
+            for body_line in body {
+                self.expand_line(body_line, depth + 1)?;
+            }
+        } else {
+            self.lines.push(line);
+        }
+
+        Ok(())
+    }
+}
+
+/// Replaces the words of `text` which are parameters with their argument
+fn substitute(text: &str, params: &[String], args: &[&str]) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while !rest.is_empty() {
+        let word_len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+
+        if word_len == 0 {
+            let c = rest.chars().next().expect("a character");
+            result.push(c);
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+
+        let (word, tail) = rest.split_at(word_len);
+        match params.iter().position(|param| param == word) {
+            Some(idx) => result.push_str(args[idx]),
+            None => result.push_str(word),
+        }
+        rest = tail;
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parameters_are_words() {
+        let params = ["x".to_owned(), "sprite".to_owned()];
+
+        assert_eq!(
+            substitute("LD I, sprite ; x", &params, &["V3", "digits"]),
+            "LD I, digits ; V3"
+        );
+        assert_eq!(substitute("DRW x, vx, 0x5", &params, &["V3", "s"]), "DRW V3, vx, 0x5");
+    }
+}
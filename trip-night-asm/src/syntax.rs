@@ -0,0 +1,216 @@
+//! Lines of source, split into labels, mnemonics and operands
+
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::{AsmError, AsmErrorKind};
+
+/// A line of source, without its comment
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line<'a> {
+    pub label: Option<&'a str>,
+    /// Instruction or directive, with its operands
+    pub statement: Option<(&'a str, Vec<Operand<'a>>)>,
+}
+
+/// Where a line comes from
+#[derive(Clone, Debug)]
+pub struct Location {
+    /// Included file, `None` for the source given to the assembler
+    pub file: Option<Rc<Path>>,
+    /// Line within the file, starting at 1
+    pub line: usize,
+}
+
+impl Location {
+    pub fn error(&self, kind: AsmErrorKind) -> AsmError {
+        AsmError {
+            file: self.file.as_deref().map(Path::to_path_buf),
+            line: self.line,
+            kind,
+        }
+    }
+}
+
+/// Operand of an instruction or directive
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand<'a> {
+    /// V0 to VF
+    Register(u8),
+    /// I
+    Index,
+    /// [I]
+    IndexedMemory,
+    /// DT
+    DelayTimer,
+    /// ST
+    SoundTimer,
+    /// K
+    Key,
+    /// F
+    Font,
+    /// B
+    Bcd,
+    Value(Value<'a>),
+}
+
+/// Number or label
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Value<'a> {
+    Number(u32),
+    Label(&'a str),
+}
+
+impl<'a> Line<'a> {
+    pub fn parse(line: &'a str) -> Result<Self, AsmErrorKind> {
+        let line = line.split(';').next().unwrap_or_default().trim();
+
+        let (label, rest) = match split_label(line) {
+            // `NAME EQU value` defines a constant, like `NAME: EQU value`
+            (None, rest) => match rest.split_once(char::is_whitespace) {
+                Some((name, constant)) if is_identifier(name) && starts_with_equ(constant.trim_start()) => {
+                    (Some(name), constant.trim_start())
+                }
+                _ => (None, rest),
+            },
+            labelled => labelled,
+        };
+
+        if rest.is_empty() {
+            return Ok(Self { label, statement: None });
+        }
+
+        let (mnemonic, operands) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let operands = match operands.trim() {
+            "" => Vec::new(),
+            operands => operands
+                .split(',')
+                .map(|operand| Operand::parse(operand.trim()))
+                .collect::<Result<_, _>>()?,
+        };
+
+        Ok(Self {
+            label,
+            statement: Some((mnemonic, operands)),
+        })
+    }
+}
+
+impl<'a> Operand<'a> {
+    fn parse(text: &'a str) -> Result<Self, AsmErrorKind> {
+        let operand = match text.to_ascii_uppercase().as_str() {
+            "I" => Self::Index,
+            "[I]" => Self::IndexedMemory,
+            "DT" => Self::DelayTimer,
+            "ST" => Self::SoundTimer,
+            "K" => Self::Key,
+            "F" => Self::Font,
+            "B" => Self::Bcd,
+            upper => match upper.strip_prefix('V').map(|reg| u8::from_str_radix(reg, 16)) {
+                Some(Ok(reg)) if upper.len() == 2 => Self::Register(reg),
+                _ => Self::Value(Value::parse(text)?),
+            },
+        };
+
+        Ok(operand)
+    }
+}
+
+impl<'a> Value<'a> {
+    pub fn parse(text: &'a str) -> Result<Self, AsmErrorKind> {
+        let invalid = || AsmErrorKind::InvalidOperand(text.to_owned());
+
+        if text.starts_with(|c: char| c.is_ascii_digit()) {
+            let (digits, radix) = match text.get(..2) {
+                Some("0x" | "0X") => (&text[2..], 16),
+                Some("0b" | "0B") => (&text[2..], 2),
+                _ => (text, 10),
+            };
+
+            u32::from_str_radix(&digits.replace('_', ""), radix)
+                .map(Self::Number)
+                .map_err(|_| invalid())
+        } else if is_identifier(text) {
+            Ok(Self::Label(text))
+        } else {
+            Err(invalid())
+        }
+    }
+}
+
+/// Splits the label in front of a line of code, if any
+pub fn split_label(code: &str) -> (Option<&str>, &str) {
+    match code.split_once(':') {
+        Some((label, rest)) if is_identifier(label.trim()) => (Some(label.trim()), rest.trim()),
+        _ => (None, code),
+    }
+}
+
+fn starts_with_equ(text: &str) -> bool {
+    let word = text.split(char::is_whitespace).next().unwrap_or_default();
+    word.eq_ignore_ascii_case("EQU")
+}
+
+/// Whether the text can name a label: letters, digits and underscores, not starting with a digit
+pub fn is_identifier(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines() {
+        assert_eq!(
+            Line::parse("loop: ld v3, 0x2A ; comment"),
+            Ok(Line {
+                label: Some("loop"),
+                statement: Some(("ld", vec![Operand::Register(3), Operand::Value(Value::Number(0x2A))])),
+            })
+        );
+        assert_eq!(
+            Line::parse("  LD [I], VF"),
+            Ok(Line {
+                label: None,
+                statement: Some(("LD", vec![Operand::IndexedMemory, Operand::Register(0xF)])),
+            })
+        );
+        assert_eq!(
+            Line::parse("JP L_0x2A4"),
+            Ok(Line {
+                label: None,
+                statement: Some(("JP", vec![Operand::Value(Value::Label("L_0x2A4"))])),
+            })
+        );
+        assert_eq!(
+            Line::parse("end:"),
+            Ok(Line {
+                label: Some("end"),
+                statement: None,
+            })
+        );
+        assert_eq!(
+            Line::parse("DB 0b1111_0000, 12"),
+            Ok(Line {
+                label: None,
+                statement: Some((
+                    "DB",
+                    vec![Operand::Value(Value::Number(0xF0)), Operand::Value(Value::Number(12))]
+                )),
+            })
+        );
+        assert_eq!(
+            Line::parse("SPEED equ 3"),
+            Ok(Line {
+                label: Some("SPEED"),
+                statement: Some(("equ", vec![Operand::Value(Value::Number(3))])),
+            })
+        );
+        assert_eq!(
+            Line::parse("LD V0, 0xZZ"),
+            Err(AsmErrorKind::InvalidOperand("0xZZ".to_owned()))
+        );
+    }
+}
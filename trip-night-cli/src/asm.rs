@@ -0,0 +1,33 @@
+//! Assembly of Cowgod-style or Octo sources into ROMs (see `trip_night_asm`)
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use crate::disasm::parse_address;
+use crate::Args;
+
+pub fn run(args: &Args<'_>) -> Result<(), Box<dyn Error>> {
+    let source = args.positional(0, "source")?;
+    let out = args.positional(1, "out")?;
+    let base: String = args.option("base", "200".to_owned())?;
+    let base = parse_address(&base).ok_or_else(|| format!("invalid base address: {base}"))?;
+
+    let symbols: String = args.option("symbols", String::new())?;
+
+    let path = Path::new(source);
+    let assembly = if path.extension().map_or(false, |ext| ext == "8o") {
+        trip_night_asm::assemble_octo(&fs::read_to_string(path)?)?
+    } else {
+        trip_night_asm::assemble_file(path, base.get())?
+    };
+    fs::write(out, &assembly.rom)?;
+    println!("{} bytes written to {out}", assembly.rom.len());
+
+    if !symbols.is_empty() {
+        fs::write(&symbols, assembly.symbols.to_string())?;
+        println!("{} symbols written to {symbols}", assembly.symbols.len());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,128 @@
+//! Execution throughput of the instruction set, with and without the decode cache
+//!
+//! The ROM is run headless for the same number of cycles through the set built by `make_set`, then
+//! again with the decode cache (see `Machine::enable_decode_cache`). A ROM which halts is reset so that every cycle counts, and the fastest of several runs is reported
+//! for each execution.
+
+use std::error::Error;
+use std::time::{Duration, Instant};
+use std::{fmt, fs};
+
+use trip_night_core::machine::Machine;
+use trip_night_core::quirks::Quirks;
+use trip_night_instruction::make_set;
+
+use crate::Args;
+
+pub fn run(args: &Args<'_>) -> Result<(), Box<dyn Error>> {
+    let rom = fs::read(args.positional(0, "rom")?)?;
+    let cycles = args.option("cycles", 10_000_000)?;
+    let runs: usize = args.option("runs", 5)?;
+    let quirks_name: String = args.option("quirks", "standard".to_owned())?;
+    let quirks = Quirks::by_name(&quirks_name).ok_or_else(|| format!("unknown quirks: {quirks_name}"))?;
+
+    let machine = Machine::builder()
+        .rom(&rom)
+        .quirks(quirks)
+        .instruction_set(make_set)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let mut cached = machine.clone();
+    cached.enable_decode_cache();
+
+    let mut executions = [("instruction set", machine), ("decode cache", cached)];
+
+    // Runs alternate so that every execution sees the same noise, the fastest run of each is kept
+    let mut best: [Option<Throughput>; 2] = [None; 2];
+    for _ in 0..runs.max(1) {
+        for ((_, machine), best) in executions.iter_mut().zip(&mut best) {
+            let run = Throughput::measure(machine, cycles);
+            *best = Some(best.map_or(run, |best| best.fastest(run)));
+        }
+    }
+
+    let baseline = best[0].expect("at least one run").per_second();
+    for ((name, _), best) in executions.iter().zip(best) {
+        let best = best.expect("at least one run");
+        println!(
+            "{name:<16} {best} ({:.2}x)",
+            best.per_second() / baseline.max(f64::MIN_POSITIVE)
+        );
+    }
+
+    Ok(())
+}
+
+/// Instructions executed over a run
+#[derive(Clone, Copy, Debug)]
+pub struct Throughput {
+    pub instructions: u64,
+    pub elapsed: Duration,
+}
+
+impl Throughput {
+    /// Runs the machine for the given number of cycles, resetting it whenever it halts
+    pub fn measure(machine: &mut Machine, cycles: usize) -> Self {
+        let before = machine.perf_counters();
+        let start = Instant::now();
+
+        for _ in 0..cycles {
+            if machine.is_halted() {
+                machine.reset();
+            }
+
+            // Faults are skipped, or halt the machine which is then reset by the next cycle
+            let _ = machine.cycle();
+        }
+
+        Self {
+            instructions: machine.perf_counters().since(&before).instructions,
+            elapsed: start.elapsed(),
+        }
+    }
+
+    pub fn fastest(self, other: Self) -> Self {
+        if other.per_second() > self.per_second() {
+            other
+        } else {
+            self
+        }
+    }
+
+    pub fn per_second(&self) -> f64 {
+        self.instructions as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+impl fmt::Display for Throughput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} instructions in {:.3} s, {:.1} M instructions/s",
+            self.instructions,
+            self.elapsed.as_secs_f64(),
+            self.per_second() / 1e6
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn both_executions_agree() {
+        // Counts V0 up to 0x20, then halts on a jump to itself
+        let rom = [0x70, 0x01, 0x30, 0x20, 0x12, 0x00, 0x12, 0x06];
+
+        let mut machine = Machine::builder().rom(&rom).instruction_set(make_set).build().unwrap();
+        let mut cached = machine.clone();
+        cached.enable_decode_cache();
+
+        let uncached = Throughput::measure(&mut machine, 200);
+        let cached = Throughput::measure(&mut cached, 200);
+
+        assert_eq!(uncached.instructions, cached.instructions);
+        assert!(uncached.instructions > 0);
+    }
+}
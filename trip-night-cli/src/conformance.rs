@@ -0,0 +1,326 @@
+//! Self-hosted conformance pack: tiny ROMs each exercising one instruction edge case
+//!
+//! Every ROM reports its outcome by storing `PASS` or `FAIL` at `RESULT_ADDRESS`, then loops
+//! forever. Reporting only relies on 1NNN, 3XNN, 6XNN, ANNN and FX55, so that a broken instruction
+//! under test can't make the whole pack pass.
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use trip_night_core::machine::Machine;
+use trip_night_core::Address;
+use trip_night_instruction::make_standard_set;
+
+use crate::Args;
+
+/// Where ROMs store their outcome
+pub const RESULT_ADDRESS: Address = Address::new_masked(0xE00);
+pub const PASS: u8 = 0x01;
+pub const FAIL: u8 = 0x02;
+
+/// Cycles after which a ROM not reporting anything is considered stuck
+const MAX_CYCLES: usize = 1_000;
+
+pub fn run(args: &Args<'_>) -> Result<(), Box<dyn Error>> {
+    let out_dir = args.positional(0, "out-dir").ok();
+
+    let cases = cases();
+    let mut failed = 0;
+
+    for case in &cases {
+        if let Some(out_dir) = out_dir {
+            fs::create_dir_all(out_dir)?;
+            fs::write(Path::new(out_dir).join(format!("{}.ch8", case.name)), &case.rom)?;
+        }
+
+        let outcome = case.run(&mut Machine::new(&case.rom, make_standard_set(), 600));
+
+        if outcome != Outcome::Pass {
+            failed += 1;
+        }
+
+        println!("{:<32} {outcome:?}", case.name);
+    }
+
+    println!("{} passed, {failed} failed", cases.len() - failed);
+
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err("conformance failures".into())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Fail,
+    /// Nothing was reported in time
+    Stuck,
+}
+
+/// A conformance ROM
+pub struct Case {
+    pub name: &'static str,
+    pub rom: Vec<u8>,
+}
+
+impl Case {
+    /// Runs the ROM, which must be loaded in the given machine, until it reports its outcome
+    pub fn run(&self, machine: &mut Machine) -> Outcome {
+        for _ in 0..MAX_CYCLES {
+            let _ = machine.cycle();
+
+            match machine.state.ram[RESULT_ADDRESS] {
+                PASS => return Outcome::Pass,
+                FAIL => return Outcome::Fail,
+                _ => {}
+            }
+
+            if machine.is_halted() {
+                break;
+            }
+        }
+
+        Outcome::Stuck
+    }
+}
+
+/// Every case of the pack
+///
+/// Shift flags are not covered yet.
+pub fn cases() -> Vec<Case> {
+    let case = |name, rom: RomBuilder| Case { name, rom: rom.build() };
+
+    vec![
+        case(
+            "add_carry",
+            RomBuilder::new()
+                .ops(&[0x60FF, 0x6102, 0x8014])
+                .expect(0x0, 0x01)
+                .expect(0xF, 0x01),
+        ),
+        case(
+            "add_no_carry",
+            RomBuilder::new()
+                .ops(&[0x6010, 0x6102, 0x8014])
+                .expect(0x0, 0x12)
+                .expect(0xF, 0x00),
+        ),
+        case(
+            "add_const_wraps_without_flag",
+            RomBuilder::new()
+                .ops(&[0x6F55, 0x60FF, 0x7002])
+                .expect(0x0, 0x01)
+                .expect(0xF, 0x55),
+        ),
+        case(
+            "sub_no_borrow",
+            RomBuilder::new()
+                .ops(&[0x6005, 0x6102, 0x8015])
+                .expect(0x0, 0x03)
+                .expect(0xF, 0x01),
+        ),
+        case(
+            "sub_borrow",
+            RomBuilder::new()
+                .ops(&[0x6001, 0x6102, 0x8015])
+                .expect(0x0, 0xFF)
+                .expect(0xF, 0x00),
+        ),
+        case(
+            "subn_no_borrow",
+            RomBuilder::new()
+                .ops(&[0x6002, 0x6105, 0x8017])
+                .expect(0x0, 0x03)
+                .expect(0xF, 0x01),
+        ),
+        case(
+            "subn_borrow",
+            RomBuilder::new()
+                .ops(&[0x6005, 0x6102, 0x8017])
+                .expect(0x0, 0xFD)
+                .expect(0xF, 0x00),
+        ),
+        case(
+            "logic_ops",
+            RomBuilder::new()
+                .ops(&[0x600C, 0x610A, 0x6200, 0x8011])
+                .expect(0x0, 0x0E)
+                .ops(&[0x8012])
+                .expect(0x0, 0x0A)
+                .ops(&[0x8013])
+                .expect(0x0, 0x00)
+                .ops(&[0x8213])
+                .expect(0x2, 0x0A),
+        ),
+        case(
+            "skip_registers",
+            RomBuilder::new()
+                .ops(&[0x6005, 0x6105, 0x5010, 0x6201, 0x6106, 0x9010, 0x6301])
+                .expect(0x2, 0x00)
+                .expect(0x3, 0x00),
+        ),
+        case(
+            "jump_offset",
+            // B202 jumps over 61EE, to 0x206
+            RomBuilder::new()
+                .ops(&[0x6004, 0xB202, 0x61EE, 0x6201])
+                .expect(0x1, 0x00)
+                .expect(0x2, 0x01),
+        ),
+        case(
+            "call_ret",
+            // The subroutine at 0x206 sets V1
+            RomBuilder::new()
+                .ops(&[0x2206, 0x6201, 0x120A, 0x6101, 0x00EE])
+                .expect(0x1, 0x01)
+                .expect(0x2, 0x01),
+        ),
+        case(
+            "random_mask",
+            RomBuilder::new().ops(&[0x60FF, 0xC000]).expect(0x0, 0x00),
+        ),
+        case(
+            "bcd",
+            RomBuilder::new()
+                .ops(&[0x60FE, 0xA300, 0xF033, 0xF265])
+                .expect(0x0, 0x02)
+                .expect(0x1, 0x05)
+                .expect(0x2, 0x04),
+        ),
+        case(
+            "store_load_round_trip",
+            RomBuilder::new()
+                .ops(&[0x6011, 0x6122, 0xA300, 0xF155, 0x6000, 0x6100, 0xA300, 0xF165])
+                .expect(0x0, 0x11)
+                .expect(0x1, 0x22),
+        ),
+        case(
+            "add_to_index",
+            // I = 0x0FF + 1, then 0x42 is stored at 0x100 and read back
+            RomBuilder::new()
+                .ops(&[0xA0FF, 0x6001, 0xF01E, 0x6042, 0xF055, 0x6000, 0xA100, 0xF065])
+                .expect(0x0, 0x42),
+        ),
+        case(
+            "font_address",
+            // Rows 0 and 2 of the "A" glyph are F0, row 2 of the "0" glyph is 90
+            RomBuilder::new()
+                .ops(&[0x600A, 0xF029, 0xF265])
+                .expect(0x0, 0xF0)
+                .expect(0x1, 0x90)
+                .expect(0x2, 0xF0),
+        ),
+        case(
+            "draw_collision",
+            RomBuilder::new()
+                .ops(&[0xA050, 0x6000, 0x6100, 0xD015])
+                .expect(0xF, 0x00)
+                .ops(&[0xD015])
+                .expect(0xF, 0x01),
+        ),
+        case(
+            "draw_wraps_horizontally",
+            // The first row of the "0" glyph (F0) is drawn at columns 62, 63, 0 and 1
+            RomBuilder::new()
+                .ops(&[0xA050, 0x603E, 0x6100, 0xD011, 0x6000, 0xD011])
+                .expect(0xF, 0x01),
+        ),
+        case(
+            "draw_wraps_vertically",
+            // The second row of the "0" glyph wraps to the top of the screen
+            RomBuilder::new()
+                .ops(&[0xA050, 0x6000, 0x611F, 0xD012, 0x6100, 0xD011])
+                .expect(0xF, 0x01),
+        ),
+    ]
+}
+
+/// Assembles a case: instructions under test, expected register values, then the reporting code
+struct RomBuilder {
+    ops: Vec<u16>,
+    /// Indices of the jumps to the failure report
+    fail_jumps: Vec<usize>,
+}
+
+impl RomBuilder {
+    fn new() -> Self {
+        Self {
+            ops: Vec::new(),
+            fail_jumps: Vec::new(),
+        }
+    }
+
+    fn ops(mut self, ops: &[u16]) -> Self {
+        self.ops.extend_from_slice(ops);
+        self
+    }
+
+    /// Checks that register `reg` holds `value` at this point of the program
+    fn expect(mut self, reg: u8, value: u8) -> Self {
+        // 3XNN skips the jump to the failure report when the value is the expected one
+        self.ops.push(0x3000 | u16::from(reg) << 8 | u16::from(value));
+        self.fail_jumps.push(self.ops.len());
+        self.ops.push(0x1000);
+        self
+    }
+
+    fn build(mut self) -> Vec<u8> {
+        let pass = Self::address_of(self.ops.len());
+        self.ops.extend_from_slice(&Self::report(pass, PASS));
+
+        let fail = Self::address_of(self.ops.len());
+        self.ops.extend_from_slice(&Self::report(fail, FAIL));
+
+        for idx in self.fail_jumps {
+            self.ops[idx] = 0x1000 | fail;
+        }
+
+        self.ops.iter().flat_map(|op| op.to_be_bytes()).collect()
+    }
+
+    fn report(start: u16, code: u8) -> [u16; 4] {
+        [
+            0xA000 | RESULT_ADDRESS.get(),
+            0x6000 | u16::from(code),
+            0xF055,
+            // Loop forever
+            0x1000 | (start + 6),
+        ]
+    }
+
+    fn address_of(idx: usize) -> u16 {
+        0x200 + 2 * u16::try_from(idx).expect("a tiny ROM")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_pass() {
+        for case in cases() {
+            let outcome = case.run(&mut Machine::new(&case.rom, make_standard_set(), 600));
+            assert_eq!(outcome, Outcome::Pass, "{}", case.name);
+        }
+    }
+
+    #[test]
+    fn failure_is_reported() {
+        let rom = RomBuilder::new().ops(&[0x6001]).expect(0x0, 0x02).build();
+        let mut machine = Machine::new(&rom, trip_night_core::instruction::make_nop_set(), 600);
+        let outcome = Case { name: "nop", rom }.run(&mut machine);
+        assert_eq!(outcome, Outcome::Stuck);
+
+        let rom = RomBuilder::new().ops(&[0x6001]).expect(0x0, 0x02).build();
+        let outcome = Case {
+            name: "wrong",
+            rom: rom.clone(),
+        }
+        .run(&mut Machine::new(&rom, make_standard_set(), 600));
+        assert_eq!(outcome, Outcome::Fail);
+    }
+}
@@ -0,0 +1,49 @@
+//! Listing of a ROM in Cowgod-style assembly, with labels and cross-references (see
+//! `trip_night_core::listing`), named after a symbol map if given
+
+use std::error::Error;
+use std::fs;
+
+use trip_night_core::listing::Listing;
+use trip_night_core::symbols::Symbols;
+use trip_night_core::Address;
+
+use crate::Args;
+
+pub fn run(args: &Args<'_>) -> Result<(), Box<dyn Error>> {
+    let rom = fs::read(args.positional(0, "rom")?)?;
+    let base: String = args.option("base", "200".to_owned())?;
+    let base = parse_address(&base).ok_or_else(|| format!("invalid base address: {base}"))?;
+    let symbols: String = args.option("symbols", String::new())?;
+
+    let mut listing = Listing::analyze(&rom, base);
+    if !symbols.is_empty() {
+        let symbols: Symbols = fs::read_to_string(&symbols)?
+            .parse()
+            .map_err(|e| format!("{symbols}: {e}"))?;
+        listing = listing.with_symbols(&symbols);
+    }
+
+    print!("{listing}");
+
+    Ok(())
+}
+
+/// Parses a hexadecimal address, with or without `0x` prefix
+pub fn parse_address(text: &str) -> Option<Address> {
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    u16::from_str_radix(digits, 16).ok().and_then(Address::new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_address() {
+        assert_eq!(parse_address("200"), Address::new(0x200));
+        assert_eq!(parse_address("0x600"), Address::new(0x600));
+        assert_eq!(parse_address("1000"), None);
+        assert_eq!(parse_address("zz"), None);
+    }
+}
@@ -0,0 +1,119 @@
+//! Flag traces, locking in the VF semantics of the instruction sets with golden files
+//!
+//! The built-in ROM runs every 8XYN instruction setting VF on operands exercising carries, borrows,
+//! equal values and the shifted out bits, then writes VF itself and draws colliding sprites. Its
+//! traces for the quirks profiles are kept in `golden/`, regenerated with:
+//!
+//! ```text
+//! trip-night-cli flag-trace --quirks standard > golden/flags-standard.trace
+//! ```
+
+use std::error::Error;
+use std::fs;
+
+use trip_night_core::machine::Machine;
+use trip_night_core::quirks::Quirks;
+use trip_night_core::trace::{compare_flag_traces, FlagTraceWriter};
+use trip_night_instruction::make_set;
+
+use crate::Args;
+
+/// Operands of the built-in ROM, as (Vx, Vy) pairs
+const OPERANDS: [(u8, u8); 4] = [(0xFF, 0x01), (0x01, 0xFF), (0x80, 0x80), (0x3C, 0x5A)];
+
+pub fn run(args: &Args<'_>) -> Result<(), Box<dyn Error>> {
+    let rom = match args.positional(0, "rom") {
+        Ok(path) => fs::read(path)?,
+        Err(_) => flags_rom(),
+    };
+    let quirks_name: String = args.option("quirks", "standard".to_owned())?;
+    let quirks = Quirks::by_name(&quirks_name).ok_or_else(|| format!("unknown quirks: {quirks_name}"))?;
+    let cycles = args.option("cycles", 10_000)?;
+    let golden: String = args.option("golden", String::new())?;
+
+    let trace = trace(&rom, &quirks, cycles);
+
+    if golden.is_empty() {
+        println!("# flag trace with {quirks_name} quirks");
+        print!("{trace}");
+        return Ok(());
+    }
+
+    let expected = fs::read_to_string(&golden)?;
+
+    match compare_flag_traces(&expected, &trace) {
+        Ok(()) => {
+            println!("{golden}: identical");
+            Ok(())
+        }
+        Err(mismatch) => Err(format!("{golden}: {mismatch}").into()),
+    }
+}
+
+/// Runs the ROM for at most the given number of cycles, stopping early if it halts
+pub fn trace(rom: &[u8], quirks: &Quirks, cycles: usize) -> String {
+    let mut machine = Machine::new(rom, make_set(quirks), 600);
+    let mut writer = FlagTraceWriter::new(String::new());
+
+    for cycle in 0..cycles {
+        if machine.is_halted() {
+            break;
+        }
+
+        // Lets DXYN complete with the display wait quirk
+        if cycle % (machine.frequency_hz / 60).max(1) == 0 {
+            machine.vblank();
+        }
+
+        writer.step(&mut machine).expect("writing to a string");
+    }
+
+    writer.into_inner()
+}
+
+/// Built-in ROM exercising the instructions setting VF
+pub fn flags_rom() -> Vec<u8> {
+    let mut rom = Vec::new();
+
+    for n in [0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0xE] {
+        for (x, y) in OPERANDS {
+            // VF = 1 so that resetting it shows, V1 = x, V2 = y, then 812N
+            rom.extend_from_slice(&[0x6F, 0x01, 0x61, x, 0x62, y, 0x81, 0x20 | n]);
+        }
+    }
+
+    rom.extend_from_slice(&[
+        0x6F, 0xFF, 0x61, 0x01, 0x8F, 0x14, // VF += V1, the carry overwrites the sum
+        0x6F, 0x03, 0x61, 0x01, 0x81, 0xF5, // V1 -= VF
+        0xA2, 0x00, 0x61, 0x00, 0x62, 0x00, // I = 0x200, V1 = 0, V2 = 0
+        0xD1, 0x21, 0xD1, 0x21, // draws a sprite, then erases it
+    ]);
+
+    // Jumps to itself, halting the machine
+    let end = 0x200 + u16::try_from(rom.len()).expect("a small ROM");
+    rom.extend_from_slice(&(0x1000 | end).to_be_bytes());
+
+    rom
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_traces() {
+        let goldens = [
+            ("standard", include_str!("../golden/flags-standard.trace")),
+            ("cosmac-vip", include_str!("../golden/flags-cosmac-vip.trace")),
+        ];
+
+        for (name, golden) in goldens {
+            let quirks = Quirks::by_name(name).unwrap();
+            let trace = trace(&flags_rom(), &quirks, 10_000);
+
+            if let Err(mismatch) = compare_flag_traces(golden, &trace) {
+                panic!("{name}: {mismatch}, regenerate the golden file if the change is intended");
+            }
+        }
+    }
+}
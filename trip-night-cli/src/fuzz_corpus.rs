@@ -0,0 +1,213 @@
+//! Generation of fuzzing inputs resembling real programs
+//!
+//! Uniformly random opcodes are mostly unknown or meaningless instructions. Instead, the ROMs of a
+//! directory are scanned to count how often each instruction is used, and random programs are
+//! generated by picking instructions with the same frequencies and random operands.
+
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use trip_night_core::decode::decode_slot;
+use trip_night_core::instruction::{OpCode, SLOT_COUNT};
+
+use crate::Args;
+
+pub fn run(args: &Args<'_>) -> Result<(), Box<dyn Error>> {
+    let rom_dir = args.positional(0, "rom-dir")?;
+    let out_dir = args.positional(1, "out-dir")?;
+    let count: usize = args.option("count", 100)?;
+    let length: usize = args.option("length", 256)?;
+    let seed: u64 = args.option("seed", 0x7219_4E17)?;
+
+    let mut model = OpcodeModel::default();
+    let mut rom_count = 0;
+
+    for entry in fs::read_dir(rom_dir)? {
+        let path = entry?.path();
+
+        if path.is_file() {
+            model.add_rom(&fs::read(&path)?);
+            rom_count += 1;
+        }
+    }
+
+    if model.total() == 0 {
+        return Err(format!("no instruction found in {rom_dir}").into());
+    }
+
+    fs::create_dir_all(out_dir)?;
+
+    let mut rng = XorShift64::new(seed);
+
+    for idx in 0..count {
+        let program = model.generate(&mut rng, length);
+        fs::write(Path::new(out_dir).join(format!("corpus_{idx:04}.ch8")), program)?;
+    }
+
+    println!(
+        "Generated {count} programs from {} instructions found in {rom_count} ROMs",
+        model.total()
+    );
+
+    Ok(())
+}
+
+/// How often each instruction of the standard set is used
+#[derive(Clone, Debug)]
+pub struct OpcodeModel {
+    counts: [u64; SLOT_COUNT],
+}
+
+impl Default for OpcodeModel {
+    fn default() -> Self {
+        Self {
+            counts: [0; SLOT_COUNT],
+        }
+    }
+}
+
+impl OpcodeModel {
+    /// Counts the instructions of a ROM (data interleaved with code is counted too, unknown
+    /// opcodes are ignored)
+    pub fn add_rom(&mut self, rom: &[u8]) {
+        let opcodes = rom
+            .chunks_exact(2)
+            .map(|word| OpCode::new(u16::from_be_bytes([word[0], word[1]])));
+
+        for slot in opcodes.filter_map(|op| decode_slot(op).ok()) {
+            self.counts[slot] += 1;
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Generates a program of `length` instructions
+    pub fn generate(&self, rng: &mut XorShift64, length: usize) -> Vec<u8> {
+        let total = self.total();
+        assert!(total > 0, "empty model");
+
+        let mut program = Vec::with_capacity(length * 2);
+
+        for _ in 0..length {
+            let mut pick = rng.next() % total;
+            let slot = self
+                .counts
+                .iter()
+                .position(|count| {
+                    if pick < *count {
+                        true
+                    } else {
+                        pick -= count;
+                        false
+                    }
+                })
+                .expect("pick is lower than the total");
+
+            let (fixed, operands) = template(slot);
+            let opcode = fixed | (rng.next() as u16 & operands);
+            program.extend_from_slice(&opcode.to_be_bytes());
+        }
+
+        program
+    }
+}
+
+/// Fixed bits and operand bits of the opcodes handled by a slot
+fn template(slot: usize) -> (u16, u16) {
+    use trip_night_core::instruction::*;
+
+    match slot {
+        OP_00E0 => (0x00E0, 0x0000),
+        OP_00EE => (0x00EE, 0x0000),
+        OP_1NNN => (0x1000, 0x0FFF),
+        OP_2NNN => (0x2000, 0x0FFF),
+        OP_3XNN => (0x3000, 0x0FFF),
+        OP_4XNN => (0x4000, 0x0FFF),
+        OP_5XY0 => (0x5000, 0x0FF0),
+        OP_6XNN => (0x6000, 0x0FFF),
+        OP_7XNN => (0x7000, 0x0FFF),
+        OP_8XY0 => (0x8000, 0x0FF0),
+        OP_8XY1 => (0x8001, 0x0FF0),
+        OP_8XY2 => (0x8002, 0x0FF0),
+        OP_8XY3 => (0x8003, 0x0FF0),
+        OP_8XY4 => (0x8004, 0x0FF0),
+        OP_8XY5 => (0x8005, 0x0FF0),
+        OP_8XY6 => (0x8006, 0x0FF0),
+        OP_8XY7 => (0x8007, 0x0FF0),
+        OP_8XYE => (0x800E, 0x0FF0),
+        OP_9XY0 => (0x9000, 0x0FF0),
+        OP_ANNN => (0xA000, 0x0FFF),
+        OP_BNNN => (0xB000, 0x0FFF),
+        OP_CXNN => (0xC000, 0x0FFF),
+        OP_DXYN => (0xD000, 0x0FFF),
+        OP_EX9E => (0xE09E, 0x0F00),
+        OP_EXA1 => (0xE0A1, 0x0F00),
+        OP_FX07 => (0xF007, 0x0F00),
+        OP_FX0A => (0xF00A, 0x0F00),
+        OP_FX15 => (0xF015, 0x0F00),
+        OP_FX18 => (0xF018, 0x0F00),
+        OP_FX1E => (0xF01E, 0x0F00),
+        OP_FX29 => (0xF029, 0x0F00),
+        OP_FX33 => (0xF033, 0x0F00),
+        OP_FX55 => (0xF055, 0x0F00),
+        OP_FX65 => (0xF065, 0x0F00),
+        OP_00F1 => (0x00F1, 0x0000),
+        _ => unreachable!("unknown slot {slot}"),
+    }
+}
+
+/// Small and fast pseudo-random number generator, good enough for fuzzing inputs
+pub struct XorShift64(u64);
+
+impl XorShift64 {
+    pub fn new(seed: u64) -> Self {
+        // The state must never be zero
+        Self(seed | 1)
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn templates_match_decoder() {
+        let mut rng = XorShift64::new(1);
+
+        for slot in 0..SLOT_COUNT {
+            for _ in 0..64 {
+                let (fixed, operands) = template(slot);
+                let op = OpCode::new(fixed | (rng.next() as u16 & operands));
+                assert_eq!(decode_slot(op).unwrap(), slot);
+            }
+        }
+    }
+
+    #[test]
+    fn generation_follows_frequencies() {
+        use trip_night_core::instruction::{OP_00E0, OP_6XNN};
+
+        let mut model = OpcodeModel::default();
+        model.add_rom(&[0x00, 0xE0, 0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0x00, 0x00]);
+        assert_eq!(model.total(), 4);
+
+        let program = model.generate(&mut XorShift64::new(7), 1000);
+        assert_eq!(program.len(), 2000);
+
+        let mut generated = OpcodeModel::default();
+        generated.add_rom(&program);
+        assert_eq!(generated.total(), 1000);
+        assert!((150..350).contains(&generated.counts[OP_00E0]));
+        assert!((650..850).contains(&generated.counts[OP_6XNN]));
+    }
+}
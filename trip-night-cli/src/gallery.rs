@@ -0,0 +1,272 @@
+//! Thumbnail gallery of a ROM collection
+//!
+//! Every ROM of a directory is run at full speed until its title screen shows up (see
+//! `Machine::run_until_first_draw`), then a few more frames so that the screen is complete. The
+//! screen is saved as a PNG thumbnail along with a JSON file of metadata, and an index of the
+//! whole collection is written as HTML or Markdown.
+
+use std::error::Error;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use trip_night_core::machine::{FastStartStop, Machine};
+use trip_night_core::rom_db::RomHash;
+use trip_night_frontend_kit::capture::Framebuffer;
+use trip_night_frontend_kit::config::Config;
+use trip_night_frontend_kit::palette::Palette;
+use trip_night_instruction::make_set;
+
+use crate::Args;
+
+pub fn run(args: &Args<'_>) -> Result<(), Box<dyn Error>> {
+    let rom_dir = args.positional(0, "rom-dir")?;
+    let out_dir = Path::new(args.positional(1, "out-dir")?);
+    let cycles: usize = args.option("cycles", 1_000_000)?;
+    let settle: usize = args.option("settle", 30)?;
+    let scale: usize = args.option("scale", 4)?;
+    let format: String = args.option("format", "html".to_owned())?;
+
+    let write_index = match format.as_str() {
+        "html" => write_html,
+        "markdown" => write_markdown,
+        _ => return Err(format!("unknown index format: {format}").into()),
+    };
+
+    let mut paths: Vec<PathBuf> = fs::read_dir(rom_dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    paths.retain(|path| path.is_file());
+    paths.sort();
+
+    fs::create_dir_all(out_dir)?;
+
+    // Global settings, with the quirks and speed of well-known ROMs
+    let config = Config::default();
+    let mut entries = Vec::new();
+
+    for path in paths {
+        let file = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        let rom = fs::read(&path)?;
+
+        let entry = match Entry::capture(file, &rom, &config, cycles, settle) {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!("{}: skipped, {e}", path.display());
+                continue;
+            }
+        };
+
+        entry.framebuffer.save_png(out_dir.join(entry.thumbnail()), scale)?;
+        fs::write(out_dir.join(format!("{}.json", entry.stem())), entry.metadata())?;
+
+        entries.push(entry);
+    }
+
+    let (index_name, index) = write_index(&entries);
+    fs::write(out_dir.join(index_name), index)?;
+
+    println!("{} thumbnails written to {}", entries.len(), out_dir.display());
+
+    Ok(())
+}
+
+/// A ROM of the gallery
+pub struct Entry {
+    /// File name of the ROM
+    pub file: String,
+    pub hash: RomHash,
+    pub size: usize,
+    /// Name of the quirks profile the ROM was run with, if it matches one
+    pub quirks: Option<&'static str>,
+    pub frequency_hz: usize,
+    /// Why the fast start stopped
+    pub stop: FastStartStop,
+    /// Cycles run until the fast start stopped
+    pub cycles: usize,
+    framebuffer: Framebuffer,
+}
+
+impl Entry {
+    /// Runs the ROM until its title screen shows up, then `settle` more frames
+    pub fn capture(
+        file: String,
+        rom: &[u8],
+        config: &Config,
+        cycles: usize,
+        settle: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        let settings = config.rom_settings(rom);
+
+        let mut machine = Machine::builder()
+            .rom(rom)
+            .quirks(settings.quirks)
+            .instruction_set(make_set)
+            .frequency(settings.frequency_hz)
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        let start = machine.run_until_first_draw(cycles);
+
+        for _ in 0..settle {
+            if machine.is_halted() {
+                break;
+            }
+            machine.run_frame();
+        }
+
+        Ok(Self {
+            file,
+            hash: RomHash::of(rom),
+            size: rom.len(),
+            quirks: settings.quirks.name(),
+            frequency_hz: settings.frequency_hz,
+            stop: start.stop,
+            cycles: start.cycles,
+            framebuffer: Framebuffer::from_screen(machine.screen(), &Palette::default()),
+        })
+    }
+
+    /// File name without extension, shared by the thumbnail and the metadata
+    fn stem(&self) -> &str {
+        Path::new(&self.file)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or(&self.file)
+    }
+
+    fn thumbnail(&self) -> String {
+        format!("{}.png", self.stem())
+    }
+
+    fn stop_name(&self) -> &'static str {
+        match self.stop {
+            FastStartStop::Draw => "draw",
+            FastStartStop::InputPoll => "input-poll",
+            FastStartStop::CycleLimit => "cycle-limit",
+            FastStartStop::Halted => "halted",
+        }
+    }
+
+    /// Metadata of the ROM, as JSON
+    pub fn metadata(&self) -> String {
+        let quirks = self.quirks.map_or("null".to_owned(), json_string);
+
+        format!(
+            "{{\n  \"file\": {},\n  \"sha1\": \"{}\",\n  \"size\": {},\n  \"quirks\": {quirks},\n  \
+             \"frequency\": {},\n  \"stop\": \"{}\",\n  \"cycles\": {},\n  \"thumbnail\": {}\n}}\n",
+            json_string(&self.file),
+            self.hash,
+            self.size,
+            self.frequency_hz,
+            self.stop_name(),
+            self.cycles,
+            json_string(&self.thumbnail()),
+        )
+    }
+}
+
+fn write_html(entries: &[Entry]) -> (&'static str, String) {
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>ROM gallery</title>\n\
+         <style>figure { display: inline-block; margin: 8px; } img { image-rendering: pixelated; width: 256px; }</style>\n\
+         </head>\n<body>\n",
+    );
+
+    for entry in entries {
+        let _ = writeln!(
+            html,
+            "<figure><img src=\"{}\" alt=\"{file}\"><figcaption>{file}<br><small>{}</small></figcaption></figure>",
+            html_escape(&entry.thumbnail()),
+            entry.hash,
+            file = html_escape(&entry.file),
+        );
+    }
+
+    html.push_str("</body>\n</html>\n");
+    ("index.html", html)
+}
+
+fn write_markdown(entries: &[Entry]) -> (&'static str, String) {
+    let mut markdown = String::from("# ROM gallery\n\n| Title screen | ROM | SHA-1 | Quirks |\n|---|---|---|---|\n");
+
+    for entry in entries {
+        let file = entry.file.replace('|', "\\|");
+        let _ = writeln!(
+            markdown,
+            "| ![{file}](<{}>) | {file} | `{}` | {} |",
+            entry.thumbnail(),
+            entry.hash,
+            entry.quirks.unwrap_or("custom"),
+        );
+    }
+
+    ("index.md", markdown)
+}
+
+fn json_string(value: &str) -> String {
+    let mut json = String::from("\"");
+
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            c if c.is_control() => {
+                let _ = write!(json, "\\u{:04x}", u32::from(c));
+            }
+            c => json.push(c),
+        }
+    }
+
+    json.push('"');
+    json
+}
+
+fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture() {
+        let rom = [
+            0xA2, 0x06, // I = 0x206
+            0xD0, 0x11, // draws the byte below
+            0x12, 0x04, // jump 0x204 (halts)
+            0xF0, // sprite
+        ];
+
+        let entry = Entry::capture("a \"b\".ch8".to_owned(), &rom, &Config::default(), 1_000, 5).unwrap();
+        assert_eq!(entry.stop, FastStartStop::Draw);
+        assert_eq!(entry.cycles, 2);
+        assert_eq!(
+            entry.framebuffer.pixels[..5],
+            [
+                Palette::default().foreground,
+                Palette::default().foreground,
+                Palette::default().foreground,
+                Palette::default().foreground,
+                Palette::default().background,
+            ]
+        );
+
+        let metadata = entry.metadata();
+        assert!(metadata.starts_with("{\n  \"file\": \"a \\\"b\\\".ch8\",\n"));
+        assert!(metadata.contains("\"stop\": \"draw\",\n  \"cycles\": 2,\n  \"thumbnail\": \"a \\\"b\\\".png\"\n}"));
+
+        let (name, html) = write_html(std::slice::from_ref(&entry));
+        assert_eq!(name, "index.html");
+        assert!(html.contains("<img src=\"a &quot;b&quot;.png\" alt=\"a &quot;b&quot;.ch8\">"));
+
+        let (name, markdown) = write_markdown(&[entry]);
+        assert_eq!(name, "index.md");
+        assert!(markdown.contains("| ![a \"b\".ch8](<a \"b\".png>) | a \"b\".ch8 |"));
+    }
+}
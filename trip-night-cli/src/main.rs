@@ -0,0 +1,120 @@
+mod asm;
+mod bench;
+mod conformance;
+mod disasm;
+mod flag_trace;
+mod fuzz_corpus;
+mod gallery;
+mod profile;
+mod usage;
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::process::ExitCode;
+
+const USAGE: &str = "\
+Usage: trip-night-cli <command> [arguments]
+
+Commands:
+  asm <source> <out> [--base ADDRESS] [--symbols FILE]
+      Assembles the Cowgod-style source into a ROM loaded at the hexadecimal ADDRESS (200 by
+      default), the syntax being the one of disasm listings, with constants, macros and includes;
+      sources with the .8o extension are Octo programs, always loaded at 200. The addresses of the
+      labels are written to the symbol map FILE if given
+  bench <rom> [--cycles N] [--quirks NAME] [--runs N]
+      Runs the ROM headless for N cycles (10000000 by default) with the instruction set, with and
+      without the decode cache, and compares their best throughput over the runs (5 by default)
+  conformance [out-dir]
+      Runs the built-in conformance ROMs against the standard instruction set, and writes them to
+      <out-dir> if given
+  disasm <rom> [--base ADDRESS] [--symbols FILE]
+      Lists the ROM in Cowgod-style assembly, with labels on jump, call and data targets and their
+      cross-references, the ROM being loaded at the hexadecimal ADDRESS (200 by default), labels
+      being named after the symbol map FILE if given
+  flag-trace [rom] [--quirks NAME] [--cycles N] [--golden FILE]
+      Prints the instructions setting VF with their operands and results, for the ROM or the
+      built-in flag test ROM, or compares them with a golden file
+  fuzz-corpus <rom-dir> <out-dir> [--count N] [--length INSTRUCTIONS] [--seed SEED]
+      Generates random programs whose instruction frequencies resemble the ROMs found in <rom-dir>
+  gallery <rom-dir> <out-dir> [--cycles N] [--settle FRAMES] [--scale N] [--format html|markdown]
+      Runs every ROM of <rom-dir> until its title screen shows up, and writes a PNG thumbnail and
+      JSON metadata per ROM in <out-dir>, along with an index of the collection
+  profile <rom> [--cycles N] [--sample N] [--top N]
+      Runs the ROM headless and lists the addresses executed the most, counting every instruction
+      or one every N, then the number of executions of every kind of instruction
+  usage <rom> [--cycles N]
+      Runs the ROM headless and reports its stack depth, highest RAM address written and unused
+      registers
+";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    let result = match args.first().map(String::as_str) {
+        Some("asm") => asm::run(&Args::parse(&args[1..])),
+        Some("bench") => bench::run(&Args::parse(&args[1..])),
+        Some("conformance") => conformance::run(&Args::parse(&args[1..])),
+        Some("disasm") => disasm::run(&Args::parse(&args[1..])),
+        Some("flag-trace") => flag_trace::run(&Args::parse(&args[1..])),
+        Some("fuzz-corpus") => fuzz_corpus::run(&Args::parse(&args[1..])),
+        Some("gallery") => gallery::run(&Args::parse(&args[1..])),
+        Some("profile") => profile::run(&Args::parse(&args[1..])),
+        Some("usage") => usage::run(&Args::parse(&args[1..])),
+        _ => {
+            eprint!("{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Positional arguments and `--name value` options of a command
+pub struct Args<'a> {
+    positional: Vec<&'a str>,
+    options: HashMap<&'a str, &'a str>,
+}
+
+impl<'a> Args<'a> {
+    pub fn parse(args: &'a [String]) -> Self {
+        let mut positional = Vec::new();
+        let mut options = HashMap::new();
+        let mut iter = args.iter();
+
+        while let Some(arg) = iter.next() {
+            match arg.strip_prefix("--") {
+                Some(name) => {
+                    options.insert(name, iter.next().map_or("", String::as_str));
+                }
+                None => positional.push(arg.as_str()),
+            }
+        }
+
+        Self { positional, options }
+    }
+
+    pub fn positional(&self, idx: usize, name: &str) -> Result<&'a str, Box<dyn Error>> {
+        self.positional
+            .get(idx)
+            .copied()
+            .ok_or_else(|| format!("missing <{name}> argument").into())
+    }
+
+    pub fn option<T>(&self, name: &str, default: T) -> Result<T, Box<dyn Error>>
+    where
+        T: std::str::FromStr,
+    {
+        match self.options.get(name) {
+            Some(value) => value
+                .parse()
+                .map_err(|_| format!("invalid value for --{name}: {value}").into()),
+            None => Ok(default),
+        }
+    }
+}
@@ -0,0 +1,139 @@
+//! Hottest code locations of a ROM
+//!
+//! The ROM is run headless with profiling enabled (see `Machine::enable_profiling`), then the
+//! addresses executed the most are listed with their disassembled instruction. Busy-wait loops, e.g. polling the
+//! delay timer, show up at the top. The number of executions of every kind of instruction follows.
+
+use std::error::Error;
+use std::{fmt, fs};
+
+use trip_night_core::disasm::disasm;
+use trip_night_core::instruction::OpCode;
+use trip_night_core::machine::Machine;
+use trip_night_core::profile::HotSpot;
+use trip_night_core::stats::OpcodeStats;
+use trip_night_instruction::make_standard_set;
+
+use crate::Args;
+
+pub fn run(args: &Args<'_>) -> Result<(), Box<dyn Error>> {
+    let rom = fs::read(args.positional(0, "rom")?)?;
+    let cycles = args.option("cycles", 100_000)?;
+    let sample: usize = args.option("sample", 1)?;
+    let top = args.option("top", 20)?;
+
+    let mut machine = Machine::new(&rom, make_standard_set(), 700);
+    print!("{}", Report::measure(&mut machine, cycles, sample, top));
+
+    Ok(())
+}
+
+/// Hottest addresses of a run
+pub struct Report {
+    pub cycles: usize,
+    pub interval: usize,
+    pub samples: u64,
+    /// Hottest addresses first, with the instruction found there at the end of the run
+    pub hot_spots: Vec<(HotSpot, OpCode)>,
+    /// Every executed instruction, not only the sampled ones
+    pub opcode_stats: OpcodeStats,
+}
+
+impl Report {
+    /// Runs the machine frame by frame for at least the given number of cycles, stopping early if it halts
+    pub fn measure(machine: &mut Machine, cycles: usize, interval: usize, top: usize) -> Self {
+        machine.enable_profiling(interval);
+        machine.enable_opcode_stats();
+
+        let mut run = 0;
+        while run < cycles && !machine.is_halted() {
+            run += machine.run_frame().cycles;
+        }
+
+        let profile = machine.disable_profiling().expect("profiling enabled above");
+        let opcode_stats = machine.disable_opcode_stats().expect("opcode statistics enabled above");
+        let ram = &machine.state.ram;
+
+        Self {
+            cycles: run,
+            interval: profile.interval(),
+            samples: profile.samples(),
+            hot_spots: profile
+                .hottest(top)
+                .into_iter()
+                .map(|hot_spot| {
+                    let opcode = u16::from_be_bytes([ram[hot_spot.addr], ram[hot_spot.addr + 1]]);
+                    (hot_spot, OpCode::new(opcode))
+                })
+                .collect(),
+            opcode_stats,
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "cycles run: {}, {} instructions sampled (1 every {})",
+            self.cycles, self.samples, self.interval
+        )?;
+        writeln!(f, "address  opcode    samples   share  instruction")?;
+
+        for (hot_spot, opcode) in &self.hot_spots {
+            let share = f64::from(hot_spot.count) * 100.0 / self.samples as f64;
+            writeln!(
+                f,
+                "    {}    {:04x}  {:>9}  {share:5.1}%  {}",
+                hot_spot.addr,
+                opcode.get_inner(),
+                hot_spot.count,
+                disasm(*opcode),
+            )?;
+        }
+
+        writeln!(f, "executions per instruction:")?;
+        write!(f, "{}", self.opcode_stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use trip_night_core::instruction::OP_6XNN;
+    use trip_night_core::Address;
+
+    use super::*;
+
+    #[test]
+    fn measure() {
+        let rom = [
+            0x60, 0x05, // V0 = 5
+            0xF0, 0x15, // delay timer = V0
+            0xF1, 0x07, // V1 = delay timer
+            0x31, 0x00, // skip if V1 == 0
+            0x12, 0x04, // jump 0x204
+            0x12, 0x0A, // jump 0x20A (halts)
+        ];
+
+        let mut machine = Machine::new(&rom, make_standard_set(), 600);
+        let report = Report::measure(&mut machine, 1_000, 1, 3);
+
+        assert!(machine.is_halted());
+        assert!(machine.profile().is_none());
+        assert_eq!(report.samples, report.cycles as u64);
+
+        // The delay timer is polled for 5 frames
+        let (hot_spot, opcode) = report.hot_spots[0];
+        assert_eq!(hot_spot.addr, Address::new(0x204).unwrap());
+        assert_eq!(opcode.get_inner(), 0xF107);
+        assert_eq!(report.hot_spots.len(), 3);
+
+        assert_eq!(report.opcode_stats.total(), report.cycles as u64);
+        assert_eq!(report.opcode_stats.count(OP_6XNN), 1);
+
+        let text = report.to_string();
+        assert!(text.contains("\n    204    f107"));
+        assert!(text.contains("%  LD V1, DT\n"));
+        assert!(text.contains("\n6XNN 1\n"));
+    }
+}
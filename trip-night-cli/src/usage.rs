@@ -0,0 +1,178 @@
+//! Resource usage of a ROM, to check it fits the constraints of historic machines
+//!
+//! The ROM is run headless while the executed instructions are observed: deepest stack, highest RAM
+//! address written and registers referenced. Registers are also looked up statically in the whole
+//! ROM, since code not reached during the run may still use them.
+
+use std::error::Error;
+use std::{fmt, fs};
+
+use trip_night_core::decode::decode_slot;
+use trip_night_core::instruction::OpCode;
+use trip_night_core::machine::{Machine, State, VIP_STACK_AREA_START};
+use trip_night_core::{Address, RegIdent};
+use trip_night_instruction::make_standard_set;
+
+use crate::Args;
+
+/// Depth of the stack of the COSMAC VIP interpreter
+const VIP_STACK_DEPTH: usize = 12;
+
+pub fn run(args: &Args<'_>) -> Result<(), Box<dyn Error>> {
+    let rom = fs::read(args.positional(0, "rom")?)?;
+    let cycles = args.option("cycles", 100_000)?;
+
+    let mut machine = Machine::new(&rom, make_standard_set(), 700);
+    let usage = Usage::measure(&mut machine, cycles);
+    let static_registers = registers_in_rom(&rom);
+
+    print!("{usage}");
+
+    let unused: Vec<String> = (0..16)
+        .filter(|idx| (usage.registers | static_registers) & (1 << idx) == 0)
+        .map(|idx| RegIdent::try_from(idx).expect("a register").to_string())
+        .collect();
+
+    if unused.is_empty() {
+        println!("unused registers: none");
+    } else {
+        println!("unused registers: {}", unused.join(" "));
+    }
+
+    Ok(())
+}
+
+/// Resources used by the executed instructions
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub cycles: usize,
+    pub max_stack_depth: usize,
+    pub highest_written: Option<Address>,
+    /// Bit n is set when Vn was referenced
+    pub registers: u16,
+}
+
+impl Usage {
+    /// Runs the machine for at most the given number of cycles, stopping early if it halts
+    pub fn measure(machine: &mut Machine, cycles: usize) -> Self {
+        let mut usage = Self::default();
+        let mut max_stack_depth = 0;
+
+        for _ in 0..cycles {
+            if machine.is_halted() {
+                break;
+            }
+
+            if usage.cycles % (machine.frequency_hz / 60).max(1) == 0 {
+                machine.vblank();
+            }
+
+            // Faults are accounted for by the fault counter, halting the machine if needed
+            let _ = machine.cycle_with_hooks(
+                |state, opcode| usage.record_before(state, opcode),
+                |state, _| max_stack_depth = max_stack_depth.max(state.stack_len()),
+            );
+            usage.cycles += 1;
+        }
+
+        usage.max_stack_depth = max_stack_depth;
+        usage
+    }
+
+    fn record_before(&mut self, state: &State, opcode: OpCode) {
+        self.registers |= registers_of(opcode);
+
+        let written = match opcode.get_first_nibble() {
+            0xF => match opcode.get_nn() {
+                0x33 => Some(state.index + 2),
+                0x55 => Some(state.index + u16::from(opcode.get_x().get())),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        if let Some(addr) = written {
+            self.highest_written = self.highest_written.max(Some(addr));
+        }
+    }
+}
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "cycles run: {}", self.cycles)?;
+
+        write!(f, "max stack depth: {}", self.max_stack_depth)?;
+        if self.max_stack_depth > VIP_STACK_DEPTH {
+            write!(f, " (exceeds the {VIP_STACK_DEPTH} levels of the COSMAC VIP)")?;
+        }
+        writeln!(f)?;
+
+        match self.highest_written {
+            Some(addr) => {
+                write!(f, "highest RAM address written: {addr}")?;
+                if addr >= VIP_STACK_AREA_START {
+                    write!(f, " (in the area reserved by the COSMAC VIP interpreter)")?;
+                }
+                writeln!(f)
+            }
+            None => writeln!(f, "highest RAM address written: none"),
+        }
+    }
+}
+
+/// Registers referenced by the instructions of the ROM, as a bit set
+pub fn registers_in_rom(rom: &[u8]) -> u16 {
+    rom.chunks_exact(2)
+        .map(|bytes| registers_of(OpCode::new(u16::from_be_bytes([bytes[0], bytes[1]]))))
+        .fold(0, |registers, used| registers | used)
+}
+
+/// Registers referenced by an instruction, as a bit set
+fn registers_of(opcode: OpCode) -> u16 {
+    use trip_night_core::instruction::*;
+
+    let x = 1 << opcode.get_x().get();
+    let y = 1 << opcode.get_y().get();
+    let vf = 1 << RegIdent::VF.get();
+
+    match decode_slot(opcode) {
+        Ok(OP_3XNN | OP_4XNN | OP_6XNN | OP_7XNN | OP_CXNN | OP_EX9E | OP_EXA1) => x,
+        Ok(OP_FX07 | OP_FX0A | OP_FX15 | OP_FX18 | OP_FX1E | OP_FX29 | OP_FX33) => x,
+        Ok(OP_5XY0 | OP_8XY0 | OP_8XY1 | OP_8XY2 | OP_8XY3 | OP_9XY0) => x | y,
+        Ok(OP_8XY4 | OP_8XY5 | OP_8XY6 | OP_8XY7 | OP_8XYE | OP_DXYN) => x | y | vf,
+        Ok(OP_BNNN) => 1,
+        // V0 to Vx
+        Ok(OP_FX55 | OP_FX65) => u16::MAX >> (15 - opcode.get_x().get()),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measure() {
+        let rom = [
+            0x22, 0x08, // call 0x208
+            0x12, 0x02, // jump 0x202 (halts)
+            0x00, 0x00, // padding
+            0x00, 0x00, // padding
+            0xA3, 0x00, // I = 0x300
+            0x63, 0x2A, // V3 = 42
+            0xF3, 0x33, // BCD of V3 at I
+            0xF1, 0x55, // V0 and V1 at I
+            0x00, 0xEE, // return
+        ];
+
+        let mut machine = Machine::new(&rom, make_standard_set(), 700);
+        let usage = Usage::measure(&mut machine, 100);
+
+        assert_eq!(usage.cycles, 7);
+        assert_eq!(usage.max_stack_depth, 1);
+        assert_eq!(usage.highest_written, Some(Address::new(0x302).unwrap()));
+        assert_eq!(usage.registers, 0b1011);
+        assert_eq!(registers_in_rom(&rom), 0b1011);
+        assert_eq!(registers_in_rom(&[0xFF, 0x65]), 0xFFFF);
+    }
+}
@@ -0,0 +1,214 @@
+//! Static analysis of ROMs
+
+use crate::instruction::OpCode;
+use crate::keypad::Key;
+use crate::RegIdent;
+
+/// How many instructions are inspected backward to find the value loaded into a key register
+const KEY_LOOKBEHIND: usize = 8;
+
+/// Keys read by a ROM, as found by `used_keys`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeyUsage {
+    /// One bit per key known to be checked with EX9E or EXA1
+    keys: u16,
+    /// Set when a key check could not be resolved statically (any key may be used)
+    pub unresolved: bool,
+    /// Set when the ROM waits for any key with FX0A
+    pub waits_for_key: bool,
+}
+
+impl KeyUsage {
+    pub fn contains(&self, key: Key) -> bool {
+        self.keys & (1 << key.get()) != 0
+    }
+
+    pub fn count(&self) -> u32 {
+        self.keys.count_ones()
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = Key> + '_ {
+        (0..=0xF)
+            .map(|value| Key::try_from(value).expect("an u8 with only the first nibble set"))
+            .filter(|key| self.contains(*key))
+    }
+
+    /// Suggests an ergonomic arrows + action layout when the ROM uses at most 4 keys
+    ///
+    /// Keys are assigned to a direction according to their position on the original keypad
+    /// relative to the other used keys (e.g. 1 and 4 become up and down).
+    pub fn arrow_layout(&self) -> Option<ArrowLayout> {
+        if self.unresolved || self.count() == 0 || self.count() > 4 {
+            return None;
+        }
+
+        let count = i32::try_from(self.count()).expect("at most 4 keys");
+        let (sum_col, sum_row) = self
+            .keys()
+            .map(keypad_position)
+            .fold((0, 0), |(c, r), (col, row)| (c + col, r + row));
+
+        let mut layout = ArrowLayout::default();
+
+        for key in self.keys() {
+            let (col, row) = keypad_position(key);
+            let dx = col * count - sum_col;
+            let dy = row * count - sum_row;
+
+            let slot = if dx == 0 && dy == 0 {
+                &mut layout.action
+            } else if dx.abs() > dy.abs() {
+                if dx < 0 {
+                    &mut layout.left
+                } else {
+                    &mut layout.right
+                }
+            } else if dy < 0 {
+                &mut layout.up
+            } else {
+                &mut layout.down
+            };
+
+            if slot.is_none() {
+                *slot = Some(key);
+            } else if layout.action.is_none() {
+                layout.action = Some(key);
+            } else {
+                return None;
+            }
+        }
+
+        Some(layout)
+    }
+}
+
+/// Keys suggested for the arrows and the action (e.g. space) host keys
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ArrowLayout {
+    pub up: Option<Key>,
+    pub down: Option<Key>,
+    pub left: Option<Key>,
+    pub right: Option<Key>,
+    pub action: Option<Key>,
+}
+
+/// Finds which keys a ROM reads
+///
+/// The operand of EX9E and EXA1 is a register, its value is resolved by looking for the closest
+/// preceding `6XNN` loading that register.
+pub fn used_keys(rom: &[u8]) -> KeyUsage {
+    let opcodes = || {
+        rom.chunks_exact(2)
+            .map(|word| OpCode::new(u16::from_be_bytes([word[0], word[1]])))
+    };
+
+    let mut usage = KeyUsage::default();
+
+    for (idx, op) in opcodes().enumerate() {
+        match (op.get_first_nibble(), op.get_nn()) {
+            (0xE, 0x9E) | (0xE, 0xA1) => {
+                let reg = op.get_x();
+                let mut previous = opcodes().take(idx).rev().take(KEY_LOOKBEHIND);
+
+                match previous.find(|prev| writes_register(*prev, reg)) {
+                    Some(load) if load.get_first_nibble() == 0x6 => usage.keys |= 1 << (load.get_nn() & 0xF),
+                    _ => usage.unresolved = true,
+                }
+            }
+            (0xF, 0x0A) => usage.waits_for_key = true,
+            _ => {}
+        }
+    }
+
+    usage
+}
+
+/// Conservatively tells whether an instruction may write into the given register
+fn writes_register(op: OpCode, reg: RegIdent) -> bool {
+    match op.get_first_nibble() {
+        0x6 | 0x7 | 0x8 | 0xC => op.get_x() == reg,
+        0xF => match op.get_nn() {
+            0x07 | 0x0A => op.get_x() == reg,
+            0x65 => reg.get() <= op.get_x().get(),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Position (column, row) of a key on the original COSMAC VIP keypad
+fn keypad_position(key: Key) -> (i32, i32) {
+    const LAYOUT: [[u8; 4]; 4] = [
+        [0x1, 0x2, 0x3, 0xC],
+        [0x4, 0x5, 0x6, 0xD],
+        [0x7, 0x8, 0x9, 0xE],
+        [0xA, 0x0, 0xB, 0xF],
+    ];
+
+    LAYOUT
+        .iter()
+        .zip(0..)
+        .find_map(|(row, y)| row.iter().zip(0..).find(|(k, _)| **k == key.get()).map(|(_, x)| (x, y)))
+        .expect("every key is on the keypad")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_key_registers() {
+        let rom = [
+            0x60, 0x01, // LD V0, 1
+            0x61, 0x04, // LD V1, 4
+            0xE0, 0x9E, // SKP V0
+            0x12, 0x00, // JP 200
+            0xE1, 0xA1, // SKNP V1
+            0x12, 0x00, // JP 200
+        ];
+
+        let usage = used_keys(&rom);
+        assert!(!usage.unresolved);
+        assert!(!usage.waits_for_key);
+        assert_eq!(usage.count(), 2);
+        assert!(usage.contains(Key::K1));
+        assert!(usage.contains(Key::K4));
+
+        let layout = usage.arrow_layout().unwrap();
+        assert_eq!(layout.up, Some(Key::K1));
+        assert_eq!(layout.down, Some(Key::K4));
+        assert_eq!(layout.left, None);
+    }
+
+    #[test]
+    fn unresolved_key_registers() {
+        let rom = [
+            0x60, 0x01, // LD V0, 1
+            0x70, 0x01, // ADD V0, 1
+            0xE0, 0x9E, // SKP V0
+            0xF2, 0x0A, // LD V2, K
+        ];
+
+        let usage = used_keys(&rom);
+        assert!(usage.unresolved);
+        assert!(usage.waits_for_key);
+        assert_eq!(usage.arrow_layout(), None);
+    }
+
+    #[test]
+    fn arrow_layout_around_center() {
+        let rom = [
+            0x60, 0x02, 0xE0, 0x9E, // 2
+            0x60, 0x04, 0xE0, 0x9E, // 4
+            0x60, 0x06, 0xE0, 0x9E, // 6
+            0x60, 0x08, 0xE0, 0x9E, // 8
+        ];
+
+        let layout = used_keys(&rom).arrow_layout().unwrap();
+        assert_eq!(layout.up, Some(Key::K2));
+        assert_eq!(layout.left, Some(Key::K4));
+        assert_eq!(layout.right, Some(Key::K6));
+        assert_eq!(layout.down, Some(Key::K8));
+        assert_eq!(layout.action, None);
+    }
+}
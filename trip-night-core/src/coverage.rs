@@ -0,0 +1,106 @@
+//! Instruction coverage, which addresses of the RAM were executed as instructions
+//!
+//! Coverage is opt-in (see `Machine::enable_coverage`): a machine then marks the address of every
+//! instruction it fetches, so that test harnesses can report the code their inputs never reached
+//! and ROM authors can spot dead paths.
+
+use core::fmt;
+
+use crate::{Address, RAM_SIZE};
+
+/// Set of executed addresses, one bit per address of the address space
+#[derive(Clone, PartialEq, Eq)]
+pub struct Coverage {
+    bits: [u64; RAM_SIZE / 64],
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Self {
+            bits: [0; RAM_SIZE / 64],
+        }
+    }
+}
+
+impl fmt::Debug for Coverage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl Coverage {
+    pub(crate) fn mark(&mut self, addr: Address) {
+        let (word, bit) = Self::position(addr);
+        self.bits[word] |= bit;
+    }
+
+    /// Whether an instruction was executed at this address
+    pub fn contains(&self, addr: Address) -> bool {
+        let (word, bit) = Self::position(addr);
+        self.bits[word] & bit != 0
+    }
+
+    /// Number of addresses executed as instructions
+    pub fn len(&self) -> usize {
+        self.bits.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|&word| word == 0)
+    }
+
+    pub fn clear(&mut self) {
+        self.bits = [0; RAM_SIZE / 64];
+    }
+
+    /// Addresses executed as instructions, in increasing order
+    pub fn iter(&self) -> impl Iterator<Item = Address> + '_ {
+        (0..=Address::MAX)
+            .map(Address::new_masked)
+            .filter(|&addr| self.contains(addr))
+    }
+
+    /// Instruction addresses never executed among the `len` bytes starting at `start`, e.g. those of
+    /// the loaded ROM to report its unexecuted code
+    ///
+    /// Instructions being 2 bytes long, every other address is checked: data interleaved with code is
+    /// reported too, and code at odd addresses is only checked when `start` is odd.
+    pub fn missed(&self, start: Address, len: usize) -> impl Iterator<Item = Address> + '_ {
+        let end = core::cmp::min(start.as_usize() + len, RAM_SIZE);
+
+        (start.as_usize()..end)
+            .step_by(2)
+            .map(|addr| Address::new_masked(addr as u16))
+            .filter(|&addr| !self.contains(addr))
+    }
+
+    fn position(addr: Address) -> (usize, u64) {
+        (addr.as_usize() / 64, 1 << (addr.as_usize() % 64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marks_addresses() {
+        let mut coverage = Coverage::default();
+        assert!(coverage.is_empty());
+
+        coverage.mark(Address(0x200));
+        coverage.mark(Address(0x202));
+        coverage.mark(Address(0x202));
+        coverage.mark(Address(0xFFF));
+
+        assert_eq!(coverage.len(), 3);
+        assert!(coverage.contains(Address(0x202)));
+        assert!(!coverage.contains(Address(0x201)));
+        assert!(coverage.iter().eq([Address(0x200), Address(0x202), Address(0xFFF)]));
+        assert!(coverage.missed(Address(0x200), 8).eq([Address(0x204), Address(0x206)]));
+        assert!(coverage.missed(Address(0xFFE), 8).eq([Address(0xFFE)]));
+
+        coverage.clear();
+        assert!(coverage.is_empty());
+    }
+}
@@ -0,0 +1,698 @@
+//! Debugging support: breakpoints stopping the machine before an instruction is executed,
+//! possibly on a condition, watchpoints stopping it after an instruction accessed watched memory,
+//! and observers of every memory access
+
+use core::fmt;
+use core::str::FromStr;
+
+use crate::instruction::OpCode;
+use crate::machine::State;
+use crate::{Address, RegIdent, RAM_SIZE};
+
+/// Maximum number of watchpoints set at once
+pub const MAX_WATCHPOINTS: usize = 8;
+
+/// Set of breakpoint addresses, one bit per address of the address space
+#[derive(Clone, PartialEq, Eq)]
+pub struct Breakpoints {
+    bits: [u64; RAM_SIZE / 64],
+}
+
+impl Default for Breakpoints {
+    fn default() -> Self {
+        Self {
+            bits: [0; RAM_SIZE / 64],
+        }
+    }
+}
+
+impl fmt::Debug for Breakpoints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl Breakpoints {
+    /// Returns whether the breakpoint is new
+    pub fn insert(&mut self, addr: Address) -> bool {
+        let (word, bit) = Self::position(addr);
+        let new = self.bits[word] & bit == 0;
+        self.bits[word] |= bit;
+        new
+    }
+
+    /// Returns whether there was a breakpoint at this address
+    pub fn remove(&mut self, addr: Address) -> bool {
+        let (word, bit) = Self::position(addr);
+        let present = self.bits[word] & bit != 0;
+        self.bits[word] &= !bit;
+        present
+    }
+
+    pub fn contains(&self, addr: Address) -> bool {
+        let (word, bit) = Self::position(addr);
+        self.bits[word] & bit != 0
+    }
+
+    pub fn clear(&mut self) {
+        self.bits = [0; RAM_SIZE / 64];
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bits.iter().all(|&word| word == 0)
+    }
+
+    /// Addresses of the breakpoints, in increasing order
+    pub fn iter(&self) -> impl Iterator<Item = Address> + '_ {
+        (0..=Address::MAX)
+            .map(Address::new_masked)
+            .filter(|&addr| self.contains(addr))
+    }
+
+    fn position(addr: Address) -> (usize, u64) {
+        (addr.as_usize() / 64, 1 << (addr.as_usize() % 64))
+    }
+}
+
+/// Maximum number of conditional breakpoints set at once
+pub const MAX_CONDITIONAL_BREAKPOINTS: usize = 8;
+
+/// Value of the machine a condition looks at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operand {
+    Register(RegIdent),
+    /// I
+    Index,
+    Pc,
+    DelayTimer,
+    SoundTimer,
+    Constant(u16),
+}
+
+impl Operand {
+    fn value(self, state: &State) -> u16 {
+        match self {
+            Operand::Register(reg) => u16::from(state.reg_read(reg)),
+            Operand::Index => state.index.get(),
+            Operand::Pc => state.pc.get(),
+            Operand::DelayTimer => u16::from(state.delay_timer),
+            Operand::SoundTimer => u16::from(state.sound_timer),
+            Operand::Constant(value) => value,
+        }
+    }
+
+    fn parse(token: &str) -> Option<Self> {
+        let lowercase = |expected: &str| token.eq_ignore_ascii_case(expected);
+
+        if lowercase("i") {
+            Some(Operand::Index)
+        } else if lowercase("pc") {
+            Some(Operand::Pc)
+        } else if lowercase("dt") {
+            Some(Operand::DelayTimer)
+        } else if lowercase("st") {
+            Some(Operand::SoundTimer)
+        } else if let Some(reg) = token.strip_prefix(['v', 'V']).filter(|reg| reg.len() == 1) {
+            let reg = u8::from_str_radix(reg, 16).ok()?;
+            RegIdent::try_from(reg).ok().map(Operand::Register)
+        } else {
+            parse_number(token).map(Operand::Constant)
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Register(reg) => write!(f, "{reg}"),
+            Operand::Index => write!(f, "I"),
+            Operand::Pc => write!(f, "PC"),
+            Operand::DelayTimer => write!(f, "DT"),
+            Operand::SoundTimer => write!(f, "ST"),
+            Operand::Constant(value) => write!(f, "{value:#x}"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    const ALL: [(Comparison, &'static str); 6] = [
+        (Comparison::Eq, "=="),
+        (Comparison::Ne, "!="),
+        (Comparison::Lt, "<"),
+        (Comparison::Le, "<="),
+        (Comparison::Gt, ">"),
+        (Comparison::Ge, ">="),
+    ];
+
+    fn holds(self, left: u16, right: u16) -> bool {
+        match self {
+            Comparison::Eq => left == right,
+            Comparison::Ne => left != right,
+            Comparison::Lt => left < right,
+            Comparison::Le => left <= right,
+            Comparison::Gt => left > right,
+            Comparison::Ge => left >= right,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        Self::ALL
+            .iter()
+            .find(|(comparison, _)| *comparison == self)
+            .map_or("", |(_, symbol)| symbol)
+    }
+}
+
+/// Condition evaluated between instructions
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Condition {
+    Compare {
+        left: Operand,
+        comparison: Comparison,
+        right: Operand,
+    },
+    /// The operand changed since the previous instruction
+    Changes(Operand),
+}
+
+/// Breakpoint stopping at an address, when a condition holds, or both
+///
+/// Parsed from text such as `at 0x3AE when V3 == 0x10`, `when VF changes` or `at 0x200`.
+/// Operands are registers (`V0`…`VF`), `I`, `PC`, `DT`, `ST`, and numbers (decimal, or hexadecimal
+/// with the `0x` prefix). Comparisons are `==`, `!=`, `<`, `<=`, `>` and `>=`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConditionalBreakpoint {
+    pub at: Option<Address>,
+    pub condition: Option<Condition>,
+}
+
+/// Text which isn't a valid conditional breakpoint
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseBreakpointError {
+    /// The text ended while a token was expected
+    UnexpectedEnd,
+    /// The token starting at this byte offset isn't expected
+    UnexpectedToken { offset: usize },
+}
+
+impl fmt::Display for ParseBreakpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseBreakpointError::UnexpectedEnd => write!(f, "unexpected end of breakpoint"),
+            ParseBreakpointError::UnexpectedToken { offset } => write!(f, "unexpected token at offset {offset}"),
+        }
+    }
+}
+
+impl FromStr for ConditionalBreakpoint {
+    type Err = ParseBreakpointError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Self::parse_with(text, |_| None)
+    }
+}
+
+impl ConditionalBreakpoint {
+    /// Parses a breakpoint whose address can also be a name, such as `at draw_score`, `resolve`
+    /// giving the address of a name (see `Symbols::address`)
+    pub fn parse_with(text: &str, resolve: impl Fn(&str) -> Option<Address>) -> Result<Self, ParseBreakpointError> {
+        let mut tokens = Tokens { text, offset: 0 };
+        let mut breakpoint = ConditionalBreakpoint {
+            at: None,
+            condition: None,
+        };
+
+        let mut keyword = tokens.next();
+
+        if keyword.map_or(false, |(_, word)| word.eq_ignore_ascii_case("at")) {
+            let (offset, addr) = tokens.expect()?;
+            let addr = parse_number(addr).and_then(Address::new).or_else(|| resolve(addr));
+            breakpoint.at = Some(addr.ok_or(ParseBreakpointError::UnexpectedToken { offset })?);
+            keyword = tokens.next();
+        }
+
+        match keyword {
+            Some((_, word)) if word.eq_ignore_ascii_case("when") => {
+                let (offset, left) = tokens.expect()?;
+                let left = Operand::parse(left).ok_or(ParseBreakpointError::UnexpectedToken { offset })?;
+                let (offset, comparison) = tokens.expect()?;
+
+                breakpoint.condition = Some(if comparison.eq_ignore_ascii_case("changes") {
+                    Condition::Changes(left)
+                } else {
+                    let comparison = Comparison::ALL
+                        .iter()
+                        .find(|(_, symbol)| *symbol == comparison)
+                        .map(|(comparison, _)| *comparison)
+                        .ok_or(ParseBreakpointError::UnexpectedToken { offset })?;
+                    let (offset, right) = tokens.expect()?;
+                    let right = Operand::parse(right).ok_or(ParseBreakpointError::UnexpectedToken { offset })?;
+
+                    Condition::Compare {
+                        left,
+                        comparison,
+                        right,
+                    }
+                });
+            }
+            Some((offset, _)) => return Err(ParseBreakpointError::UnexpectedToken { offset }),
+            None if breakpoint.at.is_none() => return Err(ParseBreakpointError::UnexpectedEnd),
+            None => {}
+        }
+
+        match tokens.next() {
+            Some((offset, _)) => Err(ParseBreakpointError::UnexpectedToken { offset }),
+            None => Ok(breakpoint),
+        }
+    }
+}
+
+impl fmt::Display for ConditionalBreakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(at) = self.at {
+            write!(f, "at 0x{at}")?;
+
+            if self.condition.is_some() {
+                write!(f, " ")?;
+            }
+        }
+
+        match self.condition {
+            Some(Condition::Compare {
+                left,
+                comparison,
+                right,
+            }) => write!(f, "when {left} {} {right}", comparison.symbol()),
+            Some(Condition::Changes(operand)) => write!(f, "when {operand} changes"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Words and comparison operators of a breakpoint, with their byte offset
+struct Tokens<'a> {
+    text: &'a str,
+    offset: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn expect(&mut self) -> Result<(usize, &'a str), ParseBreakpointError> {
+        self.next().ok_or(ParseBreakpointError::UnexpectedEnd)
+    }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = (usize, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let is_operator = |c: char| "=!<>".contains(c);
+
+        let rest = &self.text[self.offset..];
+        let start = self.offset + (rest.len() - rest.trim_start().len());
+        let rest = &self.text[start..];
+        let first = rest.chars().next()?;
+
+        let len = rest
+            .find(|c: char| c.is_whitespace() || is_operator(c) != is_operator(first))
+            .unwrap_or(rest.len());
+
+        self.offset = start + len;
+        Some((start, &rest[..len]))
+    }
+}
+
+fn parse_number(token: &str) -> Option<u16> {
+    match token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => token.parse().ok(),
+    }
+}
+
+/// Set of up to `MAX_CONDITIONAL_BREAKPOINTS` conditional breakpoints
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConditionalBreakpoints {
+    /// Breakpoints with the value of their `Changes` operand at the previous instruction
+    slots: [Option<(ConditionalBreakpoint, u16)>; MAX_CONDITIONAL_BREAKPOINTS],
+}
+
+impl ConditionalBreakpoints {
+    /// Returns `false` if `MAX_CONDITIONAL_BREAKPOINTS` breakpoints are already set
+    pub fn insert(&mut self, breakpoint: ConditionalBreakpoint, state: &State) -> bool {
+        match self.slots.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some((breakpoint, Self::tracked(&breakpoint, state)));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns whether the breakpoint was set
+    pub fn remove(&mut self, breakpoint: &ConditionalBreakpoint) -> bool {
+        let mut removed = false;
+
+        for slot in &mut self.slots {
+            if slot.map_or(false, |(set, _)| set == *breakpoint) {
+                *slot = None;
+                removed = true;
+            }
+        }
+
+        removed
+    }
+
+    pub fn clear(&mut self) {
+        self.slots = [None; MAX_CONDITIONAL_BREAKPOINTS];
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.iter().all(Option::is_none)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ConditionalBreakpoint> + '_ {
+        self.slots.iter().flatten().map(|(breakpoint, _)| breakpoint)
+    }
+
+    /// Evaluates the breakpoints before the next instruction, returns whether any matches
+    ///
+    /// Every `Changes` operand is compared with its value at the previous call, then recorded.
+    pub(crate) fn check(&mut self, state: &State) -> bool {
+        let mut hit = false;
+
+        for (breakpoint, previous) in self.slots.iter_mut().flatten() {
+            let at = breakpoint.at.map_or(true, |at| at == state.pc);
+
+            let holds = match breakpoint.condition {
+                Some(Condition::Compare {
+                    left,
+                    comparison,
+                    right,
+                }) => comparison.holds(left.value(state), right.value(state)),
+                Some(Condition::Changes(operand)) => {
+                    let value = operand.value(state);
+                    let changed = value != *previous;
+                    *previous = value;
+                    changed
+                }
+                None => true,
+            };
+
+            hit |= at && holds;
+        }
+
+        hit
+    }
+
+    fn tracked(breakpoint: &ConditionalBreakpoint, state: &State) -> u16 {
+        match breakpoint.condition {
+            Some(Condition::Changes(operand)) => operand.value(state),
+            _ => 0,
+        }
+    }
+}
+
+/// Memory access made by an instruction
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Accesses stopping the execution at a watchpoint
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl WatchKind {
+    fn matches(self, access: Access) -> bool {
+        matches!(
+            (self, access),
+            (WatchKind::ReadWrite, _) | (WatchKind::Read, Access::Read) | (WatchKind::Write, Access::Write)
+        )
+    }
+}
+
+/// Range of RAM whose accesses stop the execution
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub start: Address,
+    /// Number of bytes watched from `start`, not wrapping around the address space
+    pub len: u16,
+    pub kind: WatchKind,
+}
+
+impl Watchpoint {
+    fn overlaps(&self, start: usize, len: usize, access: Access) -> bool {
+        let watched = self.start.as_usize()..self.start.as_usize() + usize::from(self.len);
+        self.kind.matches(access) && watched.start < start + len && start < watched.end
+    }
+}
+
+/// Set of up to `MAX_WATCHPOINTS` watchpoints, checked by the `State::ram_*` accessors
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Watchpoints {
+    slots: [Option<Watchpoint>; MAX_WATCHPOINTS],
+}
+
+impl Watchpoints {
+    /// Returns `false` if `MAX_WATCHPOINTS` watchpoints are already set
+    pub fn insert(&mut self, watchpoint: Watchpoint) -> bool {
+        match self.slots.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => {
+                *slot = Some(watchpoint);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes the watchpoints starting at the given address, returns whether there was any
+    pub fn remove(&mut self, start: Address) -> bool {
+        let mut removed = false;
+
+        for slot in &mut self.slots {
+            if slot.map_or(false, |watchpoint| watchpoint.start == start) {
+                *slot = None;
+                removed = true;
+            }
+        }
+
+        removed
+    }
+
+    pub fn clear(&mut self) {
+        self.slots = [None; MAX_WATCHPOINTS];
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.iter().all(Option::is_none)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Watchpoint> + '_ {
+        self.slots.iter().flatten()
+    }
+
+    /// Whether accessing the `len` bytes from `start` hits a watchpoint
+    pub(crate) fn hit(&self, start: usize, len: usize, access: Access) -> bool {
+        self.iter().any(|watchpoint| watchpoint.overlaps(start, len, access))
+    }
+}
+
+/// Instruction which accessed watched memory, see `Machine::add_watchpoint`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WatchpointHit {
+    /// Address of the instruction
+    pub pc: Address,
+    pub opcode: OpCode,
+    /// First watched address accessed
+    pub addr: Address,
+    pub access: Access,
+}
+
+/// Observer of the accesses made through the `State::ram_*` accessors, fetching included (see
+/// `State::memory_observer`)
+///
+/// Unlike watchpoints, observers don't stop the execution and see every byte accessed, so that
+/// watchpoints of any complexity, heat maps or detectors of self-modifying code can live outside
+/// the core.
+pub trait MemoryObserver {
+    /// Called after the byte at the address was read, or written with the given value
+    fn observe(&mut self, addr: Address, value: u8, access: Access);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn breakpoints() {
+        let addr = |value| Address::new(value).unwrap();
+        let mut breakpoints = Breakpoints::default();
+        assert!(breakpoints.is_empty());
+
+        assert!(breakpoints.insert(addr(0x200)));
+        assert!(breakpoints.insert(addr(0xFFF)));
+        assert!(!breakpoints.insert(addr(0x200)));
+        assert!(breakpoints.contains(addr(0x200)));
+        assert!(!breakpoints.contains(addr(0x202)));
+
+        assert!(breakpoints.iter().eq([addr(0x200), addr(0xFFF)]));
+
+        assert!(breakpoints.remove(addr(0x200)));
+        assert!(!breakpoints.remove(addr(0x200)));
+        breakpoints.clear();
+        assert!(breakpoints.is_empty());
+    }
+
+    #[test]
+    fn watchpoints() {
+        let addr = |value| Address::new(value).unwrap();
+        let mut watchpoints = Watchpoints::default();
+        assert!(watchpoints.insert(Watchpoint {
+            start: addr(0x300),
+            len: 4,
+            kind: WatchKind::Write,
+        }));
+
+        assert!(watchpoints.hit(0x303, 1, Access::Write));
+        assert!(watchpoints.hit(0x2F0, 0x11, Access::Write));
+        assert!(!watchpoints.hit(0x304, 1, Access::Write));
+        assert!(!watchpoints.hit(0x2F0, 0x10, Access::Write));
+        assert!(!watchpoints.hit(0x300, 1, Access::Read));
+
+        for _ in 1..MAX_WATCHPOINTS {
+            assert!(watchpoints.insert(Watchpoint {
+                start: addr(0x400),
+                len: 1,
+                kind: WatchKind::ReadWrite,
+            }));
+        }
+        assert!(!watchpoints.insert(Watchpoint {
+            start: addr(0x500),
+            len: 1,
+            kind: WatchKind::Read,
+        }));
+
+        assert!(watchpoints.remove(addr(0x400)));
+        assert_eq!(watchpoints.iter().count(), 1);
+        watchpoints.clear();
+        assert!(watchpoints.is_empty());
+    }
+
+    #[test]
+    fn parse_conditional_breakpoints() {
+        let parse = |text: &str| text.parse::<ConditionalBreakpoint>();
+
+        let breakpoint = parse("at 0x3AE when V3 == 0x10").unwrap();
+        assert_eq!(
+            breakpoint,
+            ConditionalBreakpoint {
+                at: Some(Address::new(0x3AE).unwrap()),
+                condition: Some(Condition::Compare {
+                    left: Operand::Register(RegIdent::V3),
+                    comparison: Comparison::Eq,
+                    right: Operand::Constant(0x10),
+                }),
+            }
+        );
+        assert_eq!(parse(&display(&breakpoint)), Ok(breakpoint));
+
+        assert_eq!(
+            parse("when vf changes"),
+            Ok(ConditionalBreakpoint {
+                at: None,
+                condition: Some(Condition::Changes(Operand::Register(RegIdent::VF))),
+            })
+        );
+        assert_eq!(
+            parse("when I>=dt"),
+            Ok(ConditionalBreakpoint {
+                at: None,
+                condition: Some(Condition::Compare {
+                    left: Operand::Index,
+                    comparison: Comparison::Ge,
+                    right: Operand::DelayTimer,
+                }),
+            })
+        );
+        assert_eq!(parse("at 512").unwrap().at, Address::new(0x200));
+
+        let resolve = |name: &str| (name == "draw").then_some(Address(0x2A4));
+        assert_eq!(
+            ConditionalBreakpoint::parse_with("at draw when V0 == 1", resolve).map(|breakpoint| breakpoint.at),
+            Ok(Address::new(0x2A4))
+        );
+        assert_eq!(
+            ConditionalBreakpoint::parse_with("at drew", resolve),
+            Err(ParseBreakpointError::UnexpectedToken { offset: 3 })
+        );
+
+        assert_eq!(parse(""), Err(ParseBreakpointError::UnexpectedEnd));
+        assert_eq!(
+            parse("at 0x1000"),
+            Err(ParseBreakpointError::UnexpectedToken { offset: 3 })
+        );
+        assert_eq!(
+            parse("when V3 =< 1"),
+            Err(ParseBreakpointError::UnexpectedToken { offset: 8 })
+        );
+        assert_eq!(
+            parse("when V3 == 1 or"),
+            Err(ParseBreakpointError::UnexpectedToken { offset: 13 })
+        );
+        assert_eq!(
+            parse("when VG changes"),
+            Err(ParseBreakpointError::UnexpectedToken { offset: 5 })
+        );
+        assert_eq!(parse("when V3 =="), Err(ParseBreakpointError::UnexpectedEnd));
+    }
+
+    /// Formats into a fixed buffer, the tests not depending on `alloc`
+    fn display(breakpoint: &ConditionalBreakpoint) -> Buffer {
+        use core::fmt::Write;
+
+        let mut buffer = Buffer { bytes: [0; 64], len: 0 };
+        write!(buffer, "{breakpoint}").unwrap();
+        buffer
+    }
+
+    struct Buffer {
+        bytes: [u8; 64],
+        len: usize,
+    }
+
+    impl fmt::Write for Buffer {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let end = self.len + s.len();
+            self.bytes
+                .get_mut(self.len..end)
+                .ok_or(fmt::Error)?
+                .copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+
+    impl core::ops::Deref for Buffer {
+        type Target = str;
+
+        fn deref(&self) -> &str {
+            core::str::from_utf8(&self.bytes[..self.len]).unwrap()
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use core::fmt;
 
 use crate::instruction::{Instruction, InstructionSet, OpCode};
+use crate::{Address, RegIdent};
 
 #[derive(Clone, Copy, Debug)]
 pub struct UnknownInstructionError;
@@ -12,77 +13,301 @@ impl fmt::Display for UnknownInstructionError {
 }
 
 pub fn decode_instruction(set: &InstructionSet, op: OpCode) -> Result<&dyn Instruction, UnknownInstructionError> {
+    decode_slot(op).map(|slot| set.get(slot).expect("a slot of the instruction set"))
+}
+
+/// Finds the slot of the instruction set (one of the `OP_*` constants) handling the given opcode
+pub fn decode_slot(op: OpCode) -> Result<usize, UnknownInstructionError> {
     use crate::instruction::*;
 
-    let instruction = match op.get_first_nibble() {
+    let slot = match op.get_first_nibble() {
         0x0 => match op.get_inner() {
-            0x00E0 => set[OP_00E0],
-            0x00EE => set[OP_00EE],
+            0x00E0 => OP_00E0,
+            0x00EE => OP_00EE,
+            0x00F1 => OP_00F1,
             _ => return Err(UnknownInstructionError),
         },
 
-        0x1 => set[OP_1NNN],
+        0x1 => OP_1NNN,
 
-        0x2 => set[OP_2NNN],
+        0x2 => OP_2NNN,
 
-        0x3 => set[OP_3XNN],
+        0x3 => OP_3XNN,
 
-        0x4 => set[OP_4XNN],
+        0x4 => OP_4XNN,
 
-        0x5 => set[OP_5XY0],
+        0x5 if op.get_n() == 0x0 => OP_5XY0,
 
-        0x6 => set[OP_6XNN],
+        0x6 => OP_6XNN,
 
-        0x7 => set[OP_7XNN],
+        0x7 => OP_7XNN,
 
         0x8 => match op.get_n() {
-            0x0 => set[OP_8XY0],
-            0x1 => set[OP_8XY1],
-            0x2 => set[OP_8XY2],
-            0x3 => set[OP_8XY3],
-            0x4 => set[OP_8XY4],
-            0x5 => set[OP_8XY5],
-            0x6 => set[OP_8XY6],
-            0x7 => set[OP_8XY7],
-            0xE => set[OP_8XYE],
+            0x0 => OP_8XY0,
+            0x1 => OP_8XY1,
+            0x2 => OP_8XY2,
+            0x3 => OP_8XY3,
+            0x4 => OP_8XY4,
+            0x5 => OP_8XY5,
+            0x6 => OP_8XY6,
+            0x7 => OP_8XY7,
+            0xE => OP_8XYE,
             _ => return Err(UnknownInstructionError),
         },
 
-        0x9 => set[OP_9XY0],
+        0x9 if op.get_n() == 0x0 => OP_9XY0,
 
-        0xA => set[OP_ANNN],
+        0x5 | 0x9 => return Err(UnknownInstructionError),
 
-        0xB => set[OP_BNNN],
+        0xA => OP_ANNN,
 
-        0xC => set[OP_CXNN],
+        0xB => OP_BNNN,
 
-        0xD => set[OP_DXYN],
+        0xC => OP_CXNN,
+
+        0xD => OP_DXYN,
 
         0xE => match op.get_nn() {
-            0x9E => set[OP_EX9E],
-            0xA1 => set[OP_EXA1],
+            0x9E => OP_EX9E,
+            0xA1 => OP_EXA1,
             _ => return Err(UnknownInstructionError),
         },
 
         0xF => match op.get_nn() {
-            0x07 => set[OP_FX07],
-            0x0A => set[OP_FX0A],
-            0x15 => set[OP_FX15],
-            0x18 => set[OP_FX18],
-            0x1E => set[OP_FX1E],
-            0x29 => set[OP_FX29],
-            0x33 => set[OP_FX33],
-            0x55 => set[OP_FX55],
-            0x65 => set[OP_FX65],
+            0x07 => OP_FX07,
+            0x0A => OP_FX0A,
+            0x15 => OP_FX15,
+            0x18 => OP_FX18,
+            0x1E => OP_FX1E,
+            0x29 => OP_FX29,
+            0x33 => OP_FX33,
+            0x55 => OP_FX55,
+            0x65 => OP_FX65,
             _ => return Err(UnknownInstructionError),
         },
 
         _ => unreachable!("a possible value for the most significant nibble is not handled; this is a bug"),
     };
 
+    Ok(slot)
+}
+
+/// An instruction with its decoded operands, to match exhaustively on every instruction instead of
+/// going through an instruction set
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodedInstruction {
+    /// 00E0
+    ClearScreen,
+    /// 00EE
+    Return,
+    /// 1NNN
+    Jump { addr: Address },
+    /// 2NNN
+    Call { addr: Address },
+    /// 3XNN
+    SkipEqByte { x: RegIdent, byte: u8 },
+    /// 4XNN
+    SkipNeByte { x: RegIdent, byte: u8 },
+    /// 5XY0
+    SkipEq { x: RegIdent, y: RegIdent },
+    /// 6XNN
+    LoadByte { x: RegIdent, byte: u8 },
+    /// 7XNN
+    AddByte { x: RegIdent, byte: u8 },
+    /// 8XY0
+    Load { x: RegIdent, y: RegIdent },
+    /// 8XY1
+    Or { x: RegIdent, y: RegIdent },
+    /// 8XY2
+    And { x: RegIdent, y: RegIdent },
+    /// 8XY3
+    Xor { x: RegIdent, y: RegIdent },
+    /// 8XY4
+    Add { x: RegIdent, y: RegIdent },
+    /// 8XY5
+    Sub { x: RegIdent, y: RegIdent },
+    /// 8XY6
+    ShiftRight { x: RegIdent, y: RegIdent },
+    /// 8XY7
+    SubN { x: RegIdent, y: RegIdent },
+    /// 8XYE
+    ShiftLeft { x: RegIdent, y: RegIdent },
+    /// 9XY0
+    SkipNe { x: RegIdent, y: RegIdent },
+    /// ANNN
+    LoadIndex { addr: Address },
+    /// BNNN
+    JumpV0 { addr: Address },
+    /// CXNN
+    Random { x: RegIdent, mask: u8 },
+    /// DXYN
+    Draw { x: RegIdent, y: RegIdent, height: u8 },
+    /// EX9E
+    SkipKeyPressed { x: RegIdent },
+    /// EXA1
+    SkipKeyNotPressed { x: RegIdent },
+    /// FX07
+    LoadDelay { x: RegIdent },
+    /// FX0A
+    WaitKey { x: RegIdent },
+    /// FX15
+    SetDelay { x: RegIdent },
+    /// FX18
+    SetSound { x: RegIdent },
+    /// FX1E
+    AddIndex { x: RegIdent },
+    /// FX29
+    LoadFont { x: RegIdent },
+    /// FX33
+    StoreBcd { x: RegIdent },
+    /// FX55
+    StoreRegisters { x: RegIdent },
+    /// FX65
+    LoadRegisters { x: RegIdent },
+    /// 00F1
+    Yield,
+}
+
+/// Decodes an opcode with its operands, see `DecodedInstruction`
+pub fn decode_full(op: OpCode) -> Result<DecodedInstruction, UnknownInstructionError> {
+    use DecodedInstruction::*;
+
+    use crate::instruction::*;
+
+    let (x, y, addr) = (op.get_x(), op.get_y(), op.get_nnn());
+    let byte = op.get_nn();
+
+    let instruction = match decode_slot(op)? {
+        OP_00E0 => ClearScreen,
+        OP_00EE => Return,
+        OP_1NNN => Jump { addr },
+        OP_2NNN => Call { addr },
+        OP_3XNN => SkipEqByte { x, byte },
+        OP_4XNN => SkipNeByte { x, byte },
+        OP_5XY0 => SkipEq { x, y },
+        OP_6XNN => LoadByte { x, byte },
+        OP_7XNN => AddByte { x, byte },
+        OP_8XY0 => Load { x, y },
+        OP_8XY1 => Or { x, y },
+        OP_8XY2 => And { x, y },
+        OP_8XY3 => Xor { x, y },
+        OP_8XY4 => Add { x, y },
+        OP_8XY5 => Sub { x, y },
+        OP_8XY6 => ShiftRight { x, y },
+        OP_8XY7 => SubN { x, y },
+        OP_8XYE => ShiftLeft { x, y },
+        OP_9XY0 => SkipNe { x, y },
+        OP_ANNN => LoadIndex { addr },
+        OP_BNNN => JumpV0 { addr },
+        OP_CXNN => Random { x, mask: byte },
+        OP_DXYN => Draw {
+            x,
+            y,
+            height: op.get_n(),
+        },
+        OP_EX9E => SkipKeyPressed { x },
+        OP_EXA1 => SkipKeyNotPressed { x },
+        OP_FX07 => LoadDelay { x },
+        OP_FX0A => WaitKey { x },
+        OP_FX15 => SetDelay { x },
+        OP_FX18 => SetSound { x },
+        OP_FX1E => AddIndex { x },
+        OP_FX29 => LoadFont { x },
+        OP_FX33 => StoreBcd { x },
+        OP_FX55 => StoreRegisters { x },
+        OP_FX65 => LoadRegisters { x },
+        OP_00F1 => Yield,
+        _ => unreachable!("a slot returned by decode_slot is not handled; this is a bug"),
+    };
+
     Ok(instruction)
 }
 
+impl DecodedInstruction {
+    /// Slot of the instruction set executing it, one of the `OP_*` constants
+    pub fn slot(self) -> usize {
+        decode_slot(self.encode()).expect("the opcode of a decoded instruction")
+    }
+
+    /// Opcode of the instruction, which `decode_full` decodes back to it
+    pub fn encode(self) -> OpCode {
+        use DecodedInstruction::*;
+
+        let xy = |prefix: u16, x: RegIdent, y: RegIdent, n: u16| {
+            prefix << 12 | u16::from(x.get()) << 8 | u16::from(y.get()) << 4 | n
+        };
+        let xnn = |prefix: u16, x: RegIdent, byte: u8| prefix << 12 | u16::from(x.get()) << 8 | u16::from(byte);
+        let fx = |x: RegIdent, nn: u16| 0xF000 | u16::from(x.get()) << 8 | nn;
+
+        let opcode = match self {
+            ClearScreen => 0x00E0,
+            Return => 0x00EE,
+            Jump { addr } => 0x1000 | addr.get(),
+            Call { addr } => 0x2000 | addr.get(),
+            SkipEqByte { x, byte } => xnn(0x3, x, byte),
+            SkipNeByte { x, byte } => xnn(0x4, x, byte),
+            SkipEq { x, y } => xy(0x5, x, y, 0x0),
+            LoadByte { x, byte } => xnn(0x6, x, byte),
+            AddByte { x, byte } => xnn(0x7, x, byte),
+            Load { x, y } => xy(0x8, x, y, 0x0),
+            Or { x, y } => xy(0x8, x, y, 0x1),
+            And { x, y } => xy(0x8, x, y, 0x2),
+            Xor { x, y } => xy(0x8, x, y, 0x3),
+            Add { x, y } => xy(0x8, x, y, 0x4),
+            Sub { x, y } => xy(0x8, x, y, 0x5),
+            ShiftRight { x, y } => xy(0x8, x, y, 0x6),
+            SubN { x, y } => xy(0x8, x, y, 0x7),
+            ShiftLeft { x, y } => xy(0x8, x, y, 0xE),
+            SkipNe { x, y } => xy(0x9, x, y, 0x0),
+            LoadIndex { addr } => 0xA000 | addr.get(),
+            JumpV0 { addr } => 0xB000 | addr.get(),
+            Random { x, mask } => xnn(0xC, x, mask),
+            Draw { x, y, height } => xy(0xD, x, y, u16::from(height & 0xF)),
+            SkipKeyPressed { x } => xnn(0xE, x, 0x9E),
+            SkipKeyNotPressed { x } => xnn(0xE, x, 0xA1),
+            LoadDelay { x } => fx(x, 0x07),
+            WaitKey { x } => fx(x, 0x0A),
+            SetDelay { x } => fx(x, 0x15),
+            SetSound { x } => fx(x, 0x18),
+            AddIndex { x } => fx(x, 0x1E),
+            LoadFont { x } => fx(x, 0x29),
+            StoreBcd { x } => fx(x, 0x33),
+            StoreRegisters { x } => fx(x, 0x55),
+            LoadRegisters { x } => fx(x, 0x65),
+            Yield => 0x00F1,
+        };
+
+        OpCode::new(opcode)
+    }
+}
+
 pub trait DecodeOpCode {
     fn decode(op: OpCode) -> Self;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_decoding() {
+        for opcode in (0..=u16::MAX).map(OpCode::new) {
+            match (decode_slot(opcode), decode_full(opcode)) {
+                (Ok(slot), Ok(instruction)) => {
+                    assert_eq!(instruction.slot(), slot, "{opcode:?}");
+                    assert_eq!(instruction.encode(), opcode);
+                }
+                (Err(_), Err(_)) => {}
+                _ => panic!("decode_slot and decode_full disagree on {opcode:?}"),
+            }
+        }
+
+        assert_eq!(
+            decode_full(OpCode::new(0x8124)).ok(),
+            Some(DecodedInstruction::Add {
+                x: RegIdent::V1,
+                y: RegIdent::V2
+            })
+        );
+    }
+}
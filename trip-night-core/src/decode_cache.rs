@@ -0,0 +1,136 @@
+//! Cache of decoded instructions, keyed by address
+//!
+//! Caching is opt-in (see `Machine::enable_decode_cache`): a machine then decodes the instruction at
+//! an address the first time it is executed, looks up its slot in the instruction set, and reuses
+//! both every time the address is executed again. `trip-night-cli bench` compares the throughput
+//! with and without it.
+//!
+//! Each entry keeps the opcode it was decoded from, and is checked against the opcode fetched from
+//! RAM before being reused. Self-modifying code, or a host poking the RAM, thus invalidates the
+//! entries it overwrites, which are decoded again. Instructions replaced in the set are not noticed
+//! though, the cache has to be cleared after replacing them.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::decode::{decode_slot, UnknownInstructionError};
+use crate::instruction::{Instruction, InstructionSet, OpCode};
+use crate::{Address, RAM_SIZE};
+
+/// Effectiveness of a decode cache
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DecodeCacheStats {
+    /// Instructions found decoded
+    pub hits: u64,
+    /// Instructions decoded for the first time at their address
+    pub misses: u64,
+    /// Instructions decoded again because their opcode was overwritten
+    pub invalidations: u64,
+}
+
+/// Instruction decoded at an address
+#[derive(Clone, Copy)]
+pub struct CachedInstruction {
+    /// Opcode it was decoded from
+    pub opcode: OpCode,
+    /// Slot of the instruction set executing it, one of the `OP_*` constants
+    pub slot: usize,
+    /// Instruction of the set in that slot when it was decoded
+    pub instruction: &'static dyn Instruction,
+}
+
+/// Decoded instructions per address
+#[derive(Clone)]
+pub struct DecodeCache {
+    /// One entry per address of the address space
+    entries: Vec<Option<CachedInstruction>>,
+    stats: DecodeCacheStats,
+}
+
+impl Default for DecodeCache {
+    fn default() -> Self {
+        Self {
+            entries: vec![None; RAM_SIZE],
+            stats: DecodeCacheStats::default(),
+        }
+    }
+}
+
+impl DecodeCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decodes the opcode fetched at the address, from the cache unless it was overwritten since
+    ///
+    /// Unknown opcodes are not cached.
+    pub fn decode(
+        &mut self,
+        addr: Address,
+        opcode: OpCode,
+        set: &InstructionSet,
+    ) -> Result<CachedInstruction, UnknownInstructionError> {
+        let entry = &mut self.entries[addr.as_usize()];
+
+        match *entry {
+            Some(cached) if cached.opcode == opcode => {
+                self.stats.hits += 1;
+                return Ok(cached);
+            }
+            Some(_) => self.stats.invalidations += 1,
+            None => self.stats.misses += 1,
+        }
+
+        let decoded = decode_slot(opcode).map(|slot| CachedInstruction {
+            opcode,
+            slot,
+            instruction: set.get(slot).expect("a slot of the instruction set"),
+        });
+        *entry = decoded.ok();
+        decoded
+    }
+
+    /// Forgets every decoded instruction, keeping the statistics
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+    }
+
+    pub fn stats(&self) -> DecodeCacheStats {
+        self.stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overwritten_opcodes() {
+        use crate::instruction::{make_nop_set, OP_00E0, OP_7XNN};
+
+        let set = make_nop_set();
+        let mut cache = DecodeCache::new();
+        let addr = Address(0x200);
+        let mut slot = |opcode| {
+            cache
+                .decode(addr, OpCode::new(opcode), &set)
+                .ok()
+                .map(|cached| cached.slot)
+        };
+
+        assert_eq!(slot(0x7102), Some(OP_7XNN));
+        assert_eq!(slot(0x7102), Some(OP_7XNN));
+        assert_eq!(slot(0x00E0), Some(OP_00E0));
+        assert_eq!(slot(0xFFFF), None);
+        assert_eq!(slot(0xFFFF), None);
+
+        assert_eq!(
+            cache.stats(),
+            DecodeCacheStats {
+                hits: 1,
+                misses: 2,
+                invalidations: 2,
+            }
+        );
+    }
+}
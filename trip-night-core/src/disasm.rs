@@ -0,0 +1,199 @@
+//! Disassembler, turning opcodes into Cowgod-style assembly
+//!
+//! Every opcode has a textual form, e.g. `6A2A` is `LD VA, 0x2A`. Opcodes not part of the
+//! instruction set are shown as `SYS` calls when they start with 0, as data words (`DW`) otherwise,
+//! so that data interleaved with code can be disassembled as well.
+
+use core::fmt;
+
+use crate::decode::decode_slot;
+use crate::instruction::*;
+use crate::Address;
+
+/// Textual form of an opcode, see `disasm`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Mnemonic {
+    opcode: OpCode,
+    slot: Option<usize>,
+}
+
+/// Disassembles an opcode
+pub fn disasm(opcode: OpCode) -> Mnemonic {
+    Mnemonic {
+        opcode,
+        slot: decode_slot(opcode).ok(),
+    }
+}
+
+/// Disassembles the opcodes of `bytes`, the first one being at `base_addr`
+///
+/// Opcodes are read 2 bytes at a time, a trailing odd byte is ignored.
+pub fn disassemble(bytes: &[u8], base_addr: Address) -> impl Iterator<Item = (Address, OpCode, Mnemonic)> + '_ {
+    (0..).step_by(2).zip(bytes.chunks_exact(2)).map(move |(offset, bytes)| {
+        let opcode = OpCode::new(u16::from_be_bytes([bytes[0], bytes[1]]));
+        (base_addr + offset, opcode, disasm(opcode))
+    })
+}
+
+impl Mnemonic {
+    pub fn opcode(&self) -> OpCode {
+        self.opcode
+    }
+
+    /// Whether the opcode is part of the instruction set
+    pub fn is_known(&self) -> bool {
+        self.slot.is_some()
+    }
+
+    /// Name of the operation, e.g. `LD`
+    pub fn name(&self) -> &'static str {
+        match self.slot {
+            Some(OP_00E0) => "CLS",
+            Some(OP_00EE) => "RET",
+            Some(OP_1NNN | OP_BNNN) => "JP",
+            Some(OP_2NNN) => "CALL",
+            Some(OP_3XNN | OP_5XY0) => "SE",
+            Some(OP_4XNN | OP_9XY0) => "SNE",
+            Some(OP_6XNN | OP_8XY0 | OP_ANNN) => "LD",
+            Some(OP_FX07 | OP_FX0A | OP_FX15 | OP_FX18 | OP_FX29 | OP_FX33 | OP_FX55 | OP_FX65) => "LD",
+            Some(OP_7XNN | OP_8XY4 | OP_FX1E) => "ADD",
+            Some(OP_8XY1) => "OR",
+            Some(OP_8XY2) => "AND",
+            Some(OP_8XY3) => "XOR",
+            Some(OP_8XY5) => "SUB",
+            Some(OP_8XY6) => "SHR",
+            Some(OP_8XY7) => "SUBN",
+            Some(OP_8XYE) => "SHL",
+            Some(OP_CXNN) => "RND",
+            Some(OP_DXYN) => "DRW",
+            Some(OP_EX9E) => "SKP",
+            Some(OP_EXA1) => "SKNP",
+            Some(OP_00F1) => "YIELD",
+            _ if self.opcode.get_first_nibble() == 0 => "SYS",
+            _ => "DW",
+        }
+    }
+}
+
+impl fmt::Display for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = self.opcode;
+        let (x, y) = (op.get_x(), op.get_y());
+        let name = self.name();
+
+        match self.slot {
+            Some(OP_00E0 | OP_00EE | OP_00F1) => f.write_str(name),
+            Some(OP_1NNN | OP_2NNN) => write!(f, "{name} 0x{:03X}", op.get_nnn().get()),
+            Some(OP_3XNN | OP_4XNN | OP_6XNN | OP_7XNN | OP_CXNN) => write!(f, "{name} {x}, 0x{:02X}", op.get_nn()),
+            Some(OP_5XY0 | OP_9XY0) => write!(f, "{name} {x}, {y}"),
+            Some(OP_8XY0 | OP_8XY1 | OP_8XY2 | OP_8XY3 | OP_8XY4 | OP_8XY5 | OP_8XY6 | OP_8XY7 | OP_8XYE) => {
+                write!(f, "{name} {x}, {y}")
+            }
+            Some(OP_ANNN) => write!(f, "{name} I, 0x{:03X}", op.get_nnn().get()),
+            Some(OP_BNNN) => write!(f, "{name} V0, 0x{:03X}", op.get_nnn().get()),
+            Some(OP_DXYN) => write!(f, "{name} {x}, {y}, {}", op.get_n()),
+            Some(OP_EX9E | OP_EXA1) => write!(f, "{name} {x}"),
+            Some(OP_FX07) => write!(f, "{name} {x}, DT"),
+            Some(OP_FX0A) => write!(f, "{name} {x}, K"),
+            Some(OP_FX15) => write!(f, "{name} DT, {x}"),
+            Some(OP_FX18) => write!(f, "{name} ST, {x}"),
+            Some(OP_FX1E) => write!(f, "{name} I, {x}"),
+            Some(OP_FX29) => write!(f, "{name} F, {x}"),
+            Some(OP_FX33) => write!(f, "{name} B, {x}"),
+            Some(OP_FX55) => write!(f, "{name} [I], {x}"),
+            Some(OP_FX65) => write!(f, "{name} {x}, [I]"),
+            _ if op.get_first_nibble() == 0 => write!(f, "{name} 0x{:03X}", op.get_nnn().get()),
+            _ => write!(f, "{name} 0x{:04X}", op.get_inner()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Buffer {
+        bytes: [u8; 32],
+        len: usize,
+    }
+
+    impl fmt::Write for Buffer {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let end = self.len + s.len();
+            self.bytes
+                .get_mut(self.len..end)
+                .ok_or(fmt::Error)?
+                .copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+
+    fn text(opcode: u16) -> Buffer {
+        use core::fmt::Write;
+
+        let mut buffer = Buffer { bytes: [0; 32], len: 0 };
+        write!(buffer, "{}", disasm(OpCode::new(opcode))).unwrap();
+        buffer
+    }
+
+    #[test]
+    fn cowgod_syntax() {
+        let cases: [(u16, &str); 14] = [
+            (0x00E0, "CLS"),
+            (0x1ABC, "JP 0xABC"),
+            (0x632A, "LD V3, 0x2A"),
+            (0x8AB4, "ADD VA, VB"),
+            (0x8AB6, "SHR VA, VB"),
+            (0xA22A, "LD I, 0x22A"),
+            (0xB300, "JP V0, 0x300"),
+            (0xD01F, "DRW V0, V1, 15"),
+            (0xE1A1, "SKNP V1"),
+            (0xF50A, "LD V5, K"),
+            (0xF233, "LD B, V2"),
+            (0xFF65, "LD VF, [I]"),
+            (0x0123, "SYS 0x123"),
+            (0xFFFF, "DW 0xFFFF"),
+        ];
+
+        for (opcode, expected) in cases {
+            let buffer = text(opcode);
+            assert_eq!(core::str::from_utf8(&buffer.bytes[..buffer.len]), Ok(expected));
+        }
+
+        assert!(disasm(OpCode::new(0x00EE)).is_known());
+        assert!(!disasm(OpCode::new(0xFFFF)).is_known());
+    }
+
+    #[test]
+    fn opcode_formatting() {
+        use core::fmt::Write;
+
+        let mut buffer = Buffer { bytes: [0; 32], len: 0 };
+        write!(buffer, "{:?} {}", OpCode::new(0x8124), OpCode::new(0xFFFF)).unwrap();
+        assert_eq!(
+            core::str::from_utf8(&buffer.bytes[..buffer.len]),
+            Ok("8124 (ADD V1, V2) DW 0xFFFF")
+        );
+    }
+
+    #[test]
+    fn disassembles_bytes() {
+        let bytes = [0x00, 0xE0, 0x12, 0x00, 0xFF];
+        let mut lines = disassemble(&bytes, Address(0x200));
+
+        let (addr, opcode, mnemonic) = lines.next().unwrap();
+        assert_eq!(
+            (addr, opcode.get_inner(), mnemonic.name()),
+            (Address(0x200), 0x00E0, "CLS")
+        );
+
+        let (addr, opcode, mnemonic) = lines.next().unwrap();
+        assert_eq!(
+            (addr, opcode.get_inner(), mnemonic.name()),
+            (Address(0x202), 0x1200, "JP")
+        );
+
+        assert!(lines.next().is_none());
+    }
+}
@@ -0,0 +1,122 @@
+//! Running many machines in one thread
+
+use crate::machine::Machine;
+use crate::screen::Screen;
+
+/// Runs many machines in a single thread, sharing a cycle budget fairly between them
+///
+/// Machines are stepped round-robin, `quantum` cycles at a time. The machine to be served next is
+/// remembered across calls, so that budgets that are not a multiple of the number of machines do
+/// not always favor the first ones.
+pub struct MachineFarm<T> {
+    machines: T,
+    quantum: usize,
+    next: usize,
+}
+
+impl<T> MachineFarm<T>
+where
+    T: AsRef<[Machine]> + AsMut<[Machine]>,
+{
+    pub fn new(machines: T) -> Self {
+        Self {
+            machines,
+            quantum: 1,
+            next: 0,
+        }
+    }
+
+    /// Sets how many cycles a machine runs before giving its turn to the next one
+    pub fn with_quantum(mut self, quantum: usize) -> Self {
+        self.quantum = core::cmp::max(quantum, 1);
+        self
+    }
+
+    pub fn machines(&self) -> &[Machine] {
+        self.machines.as_ref()
+    }
+
+    pub fn machines_mut(&mut self) -> &mut [Machine] {
+        self.machines.as_mut()
+    }
+
+    /// Screens of all the machines, in order, for tiled rendering
+    pub fn screens(&self) -> impl Iterator<Item = &Screen> {
+        self.machines().iter().map(Machine::screen)
+    }
+
+    pub fn into_inner(self) -> T {
+        self.machines
+    }
+
+    /// Runs `budget` cycles in total, distributed among the machines
+    pub fn run(&mut self, budget: usize) {
+        let machines = self.machines.as_mut();
+
+        if machines.is_empty() {
+            return;
+        }
+
+        let mut remaining = budget;
+
+        while remaining > 0 {
+            let cycles = core::cmp::min(self.quantum, remaining);
+            machines[self.next % machines.len()].run_cycles(cycles);
+            remaining -= cycles;
+            self.next = (self.next + 1) % machines.len();
+        }
+    }
+
+    /// Runs one 60 Hz frame worth of cycles for each machine, according to their own frequency
+    pub fn run_frame(&mut self) {
+        self.machines.as_mut().iter_mut().for_each(Machine::vblank);
+
+        let budget = self
+            .machines()
+            .iter()
+            .map(|machine| core::cmp::max(machine.frequency_hz / 60, 1))
+            .sum();
+
+        self.run(budget);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::make_nop_set;
+
+    fn make_machines() -> [Machine; 3] {
+        let mut game_code = [0; 128];
+        game_code.chunks_mut(2).for_each(|op| op.copy_from_slice(&[0x00, 0xE0]));
+
+        core::array::from_fn(|_| Machine::new(&game_code, make_nop_set(), 600))
+    }
+
+    #[test]
+    fn fair_distribution() {
+        let mut farm = MachineFarm::new(make_machines());
+
+        farm.run(4);
+        let counters: [usize; 3] = core::array::from_fn(|idx| farm.machines()[idx].counter);
+        assert_eq!(counters, [2, 1, 1]);
+
+        farm.run(5);
+        let counters: [usize; 3] = core::array::from_fn(|idx| farm.machines()[idx].counter);
+        assert_eq!(counters, [3, 3, 3]);
+    }
+
+    #[test]
+    fn quantum_and_frame() {
+        let mut farm = MachineFarm::new(make_machines()).with_quantum(4);
+
+        farm.run(6);
+        let counters: [usize; 3] = core::array::from_fn(|idx| farm.machines()[idx].counter);
+        assert_eq!(counters, [4, 2, 0]);
+
+        farm.run_frame();
+        let total: usize = farm.machines().iter().map(|machine| machine.counter).sum();
+        assert_eq!(total, 36);
+        assert_eq!(farm.screens().count(), 3);
+    }
+}
@@ -1,5 +1,23 @@
 // TODO: move this away (separate crate?)
 
+use crate::Address;
+
+/// Where the standard font is loaded in memory
+pub const STANDARD_ADDRESS: Address = Address(0x50);
+
+/// Size in bytes of a standard font character sprite
+pub const STANDARD_CHAR_SIZE: u16 = 5;
+
+/// Address of the standard font sprite for the given hexadecimal digit (only the lowest nibble is considered)
+pub fn standard_char_address(digit: u8) -> Address {
+    char_address(STANDARD_ADDRESS, digit)
+}
+
+/// Same as `standard_char_address`, for a font loaded at the given address
+pub fn char_address(font_address: Address, digit: u8) -> Address {
+    font_address + u16::from(digit & 0xF) * STANDARD_CHAR_SIZE
+}
+
 pub const STANDARD: &[u8] = &[
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
     0x20, 0x60, 0x20, 0x20, 0x70, // 1
@@ -0,0 +1,165 @@
+//! Lightweight execution history, much cheaper than full tracing
+//!
+//! Only the last `HISTORY_LEN` instructions are kept, each with the registers it modified, so that
+//! the path to a fault can be inspected after the fact (see `Machine::fault_history`).
+
+use core::fmt;
+
+use crate::instruction::OpCode;
+use crate::Address;
+
+/// Number of instructions kept in the history
+pub const HISTORY_LEN: usize = 64;
+
+/// An executed instruction
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Executed {
+    pub pc: Address,
+    pub opcode: OpCode,
+    /// New value of the registers modified by the instruction
+    pub registers: [Option<u8>; 16],
+    /// New value of the index register, if modified by the instruction
+    pub index: Option<Address>,
+}
+
+/// Ring buffer of the last `HISTORY_LEN` executed instructions
+#[derive(Clone)]
+pub struct History {
+    entries: [Executed; HISTORY_LEN],
+    next: usize,
+    len: usize,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            entries: [Executed {
+                pc: Address(0),
+                opcode: OpCode::new(0),
+                registers: [None; 16],
+                index: None,
+            }; HISTORY_LEN],
+            next: 0,
+            len: 0,
+        }
+    }
+}
+
+impl History {
+    pub fn record(&mut self, pc: Address, opcode: OpCode) {
+        self.entries[self.next] = Executed {
+            pc,
+            opcode,
+            registers: [None; 16],
+            index: None,
+        };
+        self.next = (self.next + 1) % HISTORY_LEN;
+        self.len = core::cmp::min(self.len + 1, HISTORY_LEN);
+    }
+
+    /// Completes the last recorded instruction with the registers and index it modified
+    pub(crate) fn record_changes(&mut self, before: ([u8; 16], Address), after: ([u8; 16], Address)) {
+        if self.len == 0 {
+            return;
+        }
+
+        let last = &mut self.entries[(self.next + HISTORY_LEN - 1) % HISTORY_LEN];
+
+        for (change, (old, new)) in last.registers.iter_mut().zip(before.0.iter().zip(after.0.iter())) {
+            *change = (old != new).then_some(*new);
+        }
+        last.index = (before.1 != after.1).then_some(after.1);
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.next = 0;
+        self.len = 0;
+    }
+
+    /// Iterates over the recorded instructions, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = Executed> + '_ {
+        let start = (self.next + HISTORY_LEN - self.len) % HISTORY_LEN;
+        (0..self.len).map(move |offset| self.entries[(start + offset) % HISTORY_LEN])
+    }
+}
+
+impl fmt::Display for Executed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {:04x}", self.pc, self.opcode.get_inner())?;
+
+        for (reg, value) in self.registers.iter().enumerate() {
+            if let Some(value) = value {
+                write!(f, " v{reg:x}={value:02x}")?;
+            }
+        }
+
+        if let Some(index) = self.index {
+            write!(f, " i={index}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for History {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.iter().try_for_each(|executed| writeln!(f, "{executed}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_last_entries() {
+        let mut history = History::default();
+        assert!(history.is_empty());
+
+        for op in 0..100 {
+            history.record(Address(0x200 + op * 2), OpCode::new(op));
+        }
+
+        assert_eq!(history.len(), HISTORY_LEN);
+        let first = history.iter().next().unwrap();
+        assert_eq!(first.opcode.get_inner(), 36);
+        assert_eq!(first.pc, Address(0x248));
+        assert_eq!(history.iter().last().unwrap().opcode.get_inner(), 99);
+    }
+
+    #[test]
+    fn register_changes() {
+        let mut history = History::default();
+        history.record_changes(([0; 16], Address(0)), ([1; 16], Address(1)));
+        assert!(history.is_empty());
+
+        let mut after = [0; 16];
+        after[0x3] = 0x2A;
+        after[0xF] = 1;
+
+        history.record(Address(0x200), OpCode::new(0x8334));
+        history.record_changes(([0; 16], Address(0x300)), (after, Address(0x300)));
+        history.record(Address(0x202), OpCode::new(0xA22A));
+        history.record_changes((after, Address(0x300)), (after, Address(0x22A)));
+
+        let mut entries = history.iter();
+
+        let add = entries.next().unwrap();
+        assert_eq!(add.registers.iter().flatten().count(), 2);
+        assert_eq!(add.registers[0x3], Some(0x2A));
+        assert_eq!(add.registers[0xF], Some(1));
+        assert_eq!(add.index, None);
+
+        let load = entries.next().unwrap();
+        assert_eq!(load.registers, [None; 16]);
+        assert_eq!(load.index, Some(Address(0x22A)));
+    }
+}
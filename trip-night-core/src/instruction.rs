@@ -1,7 +1,10 @@
+use core::fmt;
+
 use bit_field::BitField as _;
 
 use crate::decode::DecodeOpCode;
-use crate::machine::State;
+use crate::disasm::disasm;
+use crate::machine::{Fault, State};
 use crate::{Address, RegIdent};
 
 /// CLS
@@ -88,6 +91,19 @@ pub const OP_FX55: usize = 32;
 /// LD Vx, [I]
 pub const OP_FX65: usize = 33;
 
+/// YIELD, only supported by the fantasy instruction set
+pub const OP_00F1: usize = 34;
+
+/// Number of slots in an instruction set
+pub const SLOT_COUNT: usize = 35;
+
+/// Opcode pattern of every slot, e.g. `SLOT_PATTERNS[OP_8XY4]` is `"8XY4"`
+pub const SLOT_PATTERNS: [&str; SLOT_COUNT] = [
+    "00E0", "00EE", "1NNN", "2NNN", "3XNN", "4XNN", "5XY0", "6XNN", "7XNN", "8XY0", "8XY1", "8XY2", "8XY3", "8XY4",
+    "8XY5", "8XY6", "8XY7", "8XYE", "9XY0", "ANNN", "BNNN", "CXNN", "DXYN", "EX9E", "EXA1", "FX07", "FX0A", "FX15",
+    "FX18", "FX1E", "FX29", "FX33", "FX55", "FX65", "00F1",
+];
+
 #[macro_export]
 macro_rules! make_instruction {
     ($impl:path) => {{
@@ -100,8 +116,30 @@ macro_rules! make_instruction {
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OpCode(u16);
 
+/// The opcode and its disassembly, e.g. `8124 (ADD V1, V2)`
+impl fmt::Debug for OpCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04X} ({})", self.0, disasm(*self))
+    }
+}
+
+/// The disassembly of the opcode, e.g. `ADD V1, V2` (see `disasm`)
+impl fmt::Display for OpCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", disasm(*self))
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for OpCode {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "{=u16:04X}", self.0)
+    }
+}
+
 impl OpCode {
     pub fn new(inner: u16) -> Self {
         Self(inner)
@@ -149,11 +187,207 @@ where
     }
 }
 
-pub type InstructionSet = [&'static dyn Instruction; 34];
+/// Defines the fields of `InstructionSet`, the matching methods of `InstructionSetBuilder`, and the
+/// mapping between the fields and the slots
+macro_rules! instruction_set {
+    ($($(#[$doc:meta])* $field:ident: $slot:ident,)+) => {
+        /// Instructions executing each kind of opcode, one field per slot (see the `OP_*` constants)
+        ///
+        /// ```ignore
+        /// let mut set = make_standard_set();
+        /// set.op_8xy6 = make_instruction!(ShiftRightLegacy::execute);
+        /// ```
+        #[derive(Clone, Copy)]
+        pub struct InstructionSet {
+            $($(#[$doc])* pub $field: &'static dyn Instruction,)+
+        }
+
+        impl InstructionSet {
+            fn filled(instruction: &'static dyn Instruction) -> Self {
+                Self {
+                    $($field: instruction,)+
+                }
+            }
+
+            /// Instruction of a slot, one of the `OP_*` constants
+            pub fn get(&self, slot: usize) -> Option<&'static dyn Instruction> {
+                match slot {
+                    $($slot => Some(self.$field),)+
+                    _ => None,
+                }
+            }
+
+            /// Instruction of a slot, one of the `OP_*` constants
+            pub fn get_mut(&mut self, slot: usize) -> Option<&mut &'static dyn Instruction> {
+                match slot {
+                    $($slot => Some(&mut self.$field),)+
+                    _ => None,
+                }
+            }
+        }
+
+        impl InstructionSetBuilder {
+            $(
+                $(#[$doc])*
+                pub fn $field(mut self, instruction: &'static dyn Instruction) -> Self {
+                    self.set.$field = instruction;
+                    self
+                }
+            )+
+        }
+    };
+}
+
+instruction_set! {
+    /// CLS
+    op_00e0: OP_00E0,
+    /// RET
+    op_00ee: OP_00EE,
+    /// JP addr
+    op_1nnn: OP_1NNN,
+    /// CALL addr
+    op_2nnn: OP_2NNN,
+    /// SE Vx, byte
+    op_3xnn: OP_3XNN,
+    /// SNE Vx, byte
+    op_4xnn: OP_4XNN,
+    /// SE Vx, Vy
+    op_5xy0: OP_5XY0,
+    /// LD Vx, byte
+    op_6xnn: OP_6XNN,
+    /// ADD Vx, byte
+    op_7xnn: OP_7XNN,
+    /// LD Vx, Vy
+    op_8xy0: OP_8XY0,
+    /// OR Vx, Vy
+    op_8xy1: OP_8XY1,
+    /// AND Vx, Vy
+    op_8xy2: OP_8XY2,
+    /// XOR Vx, Vy
+    op_8xy3: OP_8XY3,
+    /// ADD Vx, Vy
+    op_8xy4: OP_8XY4,
+    /// SUB Vx, Vy
+    op_8xy5: OP_8XY5,
+    /// SHR Vx {, Vy}
+    op_8xy6: OP_8XY6,
+    /// SUBN Vx, Vy
+    op_8xy7: OP_8XY7,
+    /// SHL Vx {, Vy}
+    op_8xye: OP_8XYE,
+    /// SNE Vx, Vy
+    op_9xy0: OP_9XY0,
+    /// LD I, addr
+    op_annn: OP_ANNN,
+    /// JP V0, addr
+    op_bnnn: OP_BNNN,
+    /// RND Vx, byte
+    op_cxnn: OP_CXNN,
+    /// DRW Vx, Vy, nibble
+    op_dxyn: OP_DXYN,
+    /// SKP Vx
+    op_ex9e: OP_EX9E,
+    /// SKNP Vx
+    op_exa1: OP_EXA1,
+    /// LD Vx, DT
+    op_fx07: OP_FX07,
+    /// LD Vx, K
+    op_fx0a: OP_FX0A,
+    /// LD DT, Vx
+    op_fx15: OP_FX15,
+    /// LD ST, Vx
+    op_fx18: OP_FX18,
+    /// ADD I, Vx
+    op_fx1e: OP_FX1E,
+    /// LD F, Vx
+    op_fx29: OP_FX29,
+    /// LD B, Vx
+    op_fx33: OP_FX33,
+    /// LD [I], Vx
+    op_fx55: OP_FX55,
+    /// LD Vx, [I]
+    op_fx65: OP_FX65,
+    /// YIELD, only supported by the fantasy instruction set
+    op_00f1: OP_00F1,
+}
+
+/// Step by step construction of an instruction set, see `InstructionSet::builder`
+#[derive(Clone, Copy)]
+pub struct InstructionSetBuilder {
+    set: InstructionSet,
+}
+
+impl InstructionSet {
+    /// Builds a set instruction by instruction, every instruction left out being a NOP
+    ///
+    /// ```ignore
+    /// let set = InstructionSet::builder()
+    ///     .op_00e0(make_instruction!(ClearScreen::execute))
+    ///     .op_1nnn(make_instruction!(Jump::execute))
+    ///     .build();
+    /// ```
+    pub fn builder() -> InstructionSetBuilder {
+        make_nop_set().to_builder()
+    }
+
+    /// Builds a set from this one, replacing some of its instructions
+    pub fn to_builder(self) -> InstructionSetBuilder {
+        InstructionSetBuilder { set: self }
+    }
+}
+
+impl InstructionSetBuilder {
+    pub fn build(self) -> InstructionSet {
+        self.set
+    }
+}
+
+impl InstructionSet {
+    /// Builds a hybrid set taking the given slots from `other` and every other slot from `self`, e.g.
+    /// to study the quirks of a single instruction
+    ///
+    /// Every slot of the hybrid set comes from exactly one of the two sets, so the composition is
+    /// rejected if a slot doesn't exist or is listed twice.
+    pub fn overlay(&self, other: &InstructionSet, slots: &[usize]) -> Result<InstructionSet, OverlayError> {
+        let mut set = *self;
+        let mut overlaid = [false; SLOT_COUNT];
+
+        for &slot in slots {
+            match (overlaid.get_mut(slot), set.get_mut(slot), other.get(slot)) {
+                (Some(true), _, _) => return Err(OverlayError::DuplicateSlot { slot }),
+                (Some(overlaid), Some(instruction), Some(other)) => {
+                    *overlaid = true;
+                    *instruction = other;
+                }
+                _ => return Err(OverlayError::UnknownSlot { slot }),
+            }
+        }
+
+        Ok(set)
+    }
+}
+
+/// Slots of an overlay which can't be composed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverlayError {
+    /// The slot is not lower than `SLOT_COUNT`
+    UnknownSlot { slot: usize },
+    /// The slot is listed more than once
+    DuplicateSlot { slot: usize },
+}
+
+impl fmt::Display for OverlayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OverlayError::UnknownSlot { slot } => write!(f, "unknown instruction slot {slot}"),
+            OverlayError::DuplicateSlot { slot } => write!(f, "instruction slot {slot} overlaid twice"),
+        }
+    }
+}
 
 /// Builds an NOP-only instruction set for placeholding purposes
 pub fn make_nop_set() -> InstructionSet {
-    [make_instruction!(Nop::execute); 34]
+    InstructionSet::filled(make_instruction!(Nop::execute))
 }
 
 /// NOP (No Operation)
@@ -170,3 +404,21 @@ impl DecodeOpCode for Nop {
 impl Nop {
     pub fn execute(self, _: &mut State) {}
 }
+
+/// Placeholder for instructions outside of the profile of a set
+///
+/// Raises the same fault as opcodes unknown to the decoder, e.g. to keep the standard sets free of
+/// custom instructions.
+pub struct Unsupported;
+
+impl DecodeOpCode for Unsupported {
+    fn decode(_: OpCode) -> Self {
+        Self
+    }
+}
+
+impl Unsupported {
+    pub fn execute(self, state: &mut State) {
+        state.raise(Fault::UnknownOpCode);
+    }
+}
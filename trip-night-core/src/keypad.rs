@@ -0,0 +1,151 @@
+use core::fmt;
+
+use crate::RegIdent;
+
+/// A key of the 16-key hexadecimal keypad
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct Key(u8);
+
+impl Key {
+    pub const K0: Self = Self(0x0);
+    pub const K1: Self = Self(0x1);
+    pub const K2: Self = Self(0x2);
+    pub const K3: Self = Self(0x3);
+    pub const K4: Self = Self(0x4);
+    pub const K5: Self = Self(0x5);
+    pub const K6: Self = Self(0x6);
+    pub const K7: Self = Self(0x7);
+    pub const K8: Self = Self(0x8);
+    pub const K9: Self = Self(0x9);
+    pub const KA: Self = Self(0xA);
+    pub const KB: Self = Self(0xB);
+    pub const KC: Self = Self(0xC);
+    pub const KD: Self = Self(0xD);
+    pub const KE: Self = Self(0xE);
+    pub const KF: Self = Self(0xF);
+
+    pub fn get(self) -> u8 {
+        self.0
+    }
+}
+
+impl TryFrom<u8> for Key {
+    type Error = ();
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        if value <= 0xF {
+            Ok(Self(value))
+        } else {
+            Err(())
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:X}", self.0)
+    }
+}
+
+/// State of the 16-key hexadecimal keypad
+///
+/// Layout of the original COSMAC VIP keypad:
+///
+/// ```text
+/// 1 | 2 | 3 | C
+/// 4 | 5 | 6 | D
+/// 7 | 8 | 9 | E
+/// A | 0 | B | F
+/// ```
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Keypad {
+    /// One bit per key, set when the key is held down
+    pressed: u16,
+}
+
+impl Keypad {
+    pub fn press(&mut self, key: Key) {
+        self.pressed |= 1 << key.get();
+    }
+
+    pub fn release(&mut self, key: Key) {
+        self.pressed &= !(1 << key.get());
+    }
+
+    pub fn set(&mut self, key: Key, is_pressed: bool) {
+        if is_pressed {
+            self.press(key);
+        } else {
+            self.release(key);
+        }
+    }
+
+    pub fn release_all(&mut self) {
+        self.pressed = 0;
+    }
+
+    pub fn is_pressed(&self, key: Key) -> bool {
+        self.pressed & (1 << key.get()) != 0
+    }
+
+    /// Returns the pressed key with the lowest value, if any
+    pub fn first_pressed(&self) -> Option<Key> {
+        if self.pressed == 0 {
+            None
+        } else {
+            // There is at most 15 trailing zeros when at least one bit is set
+            Some(Key(self.pressed.trailing_zeros() as u8))
+        }
+    }
+}
+
+impl fmt::Debug for Keypad {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016b}", self.pressed)
+    }
+}
+
+/// Progress of a "wait for key" (FX0A) instruction
+///
+/// While waiting, the machine does not fetch any instruction but timers keep running.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum WaitingForKey {
+    /// Waiting for any key to be pressed
+    Press {
+        target: RegIdent,
+        /// When set, the key must also be released before being stored (original COSMAC VIP behavior)
+        await_release: bool,
+    },
+    /// A key was pressed, waiting for it to be released
+    Release { target: RegIdent, key: Key },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn press_and_release() {
+        let mut keypad = Keypad::default();
+        assert_eq!(keypad.first_pressed(), None);
+
+        keypad.press(Key::K5);
+        keypad.press(Key::KF);
+        assert!(keypad.is_pressed(Key::K5));
+        assert!(keypad.is_pressed(Key::KF));
+        assert!(!keypad.is_pressed(Key::K0));
+        assert_eq!(keypad.first_pressed(), Some(Key::K5));
+
+        keypad.release(Key::K5);
+        assert!(!keypad.is_pressed(Key::K5));
+        assert_eq!(keypad.first_pressed(), Some(Key::KF));
+
+        keypad.set(Key::K0, true);
+        assert_eq!(keypad.first_pressed(), Some(Key::K0));
+
+        keypad.release_all();
+        assert_eq!(keypad.first_pressed(), None);
+    }
+}
@@ -1,13 +1,182 @@
+//! Core of the Trip Night emulator
+//!
+//! # Stability
+//!
+//! Executing machines (`machine`, `instruction`, `decode`, `screen`, `keypad`, `snapshot`…) is covered by semver.
+//! Experimental subsystems are only available with the `unstable` feature, and may change in any release:
+//!
+//! - debugging: `debug` (breakpoints, watchpoints and memory observers), `symbols` and `listing`
+//! - diagnostics: `coverage`, `profile` and `stats`
+//! - extensions of the machine: `patch`, `peripheral` and `decode_cache`
+//! - recording and replaying: `movie`, `rewind`, `timeline` and `trace`
+//! - tooling: `analysis`, `farm` and `pacing`
+//!
+//! The matching methods of `Machine` and `State`, and the `CycleOutcome` variants reporting breakpoints
+//! and watchpoints, are gated alike.
+//!
+//! # Logging
+//!
+//! With the `log` feature, machines emit structured events (`fetch`, `decode`, `execute`, `draw`
+//! and `timer tick`) at the trace level through the `tracing` facade, so that any subscriber can
+//! record what a misbehaving ROM does.
+//!
+//! On microcontrollers, the `defmt` feature logs every executed instruction at the trace level,
+//! faults and halts with `defmt`, whose compact encoding suits RTT debugging.
+
 #![no_std]
 
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+/// Emits a trace event with the `log` feature, compiled out otherwise
+macro_rules! event {
+    ($($args:tt)*) => {
+        #[cfg(feature = "log")]
+        tracing::trace!($($args)*);
+    };
+}
+
+#[cfg(feature = "unstable")]
+pub mod analysis;
+#[cfg(feature = "unstable")]
+pub mod coverage;
+#[cfg(feature = "unstable")]
+pub mod debug;
 pub mod decode;
+#[cfg(all(feature = "unstable", feature = "alloc"))]
+pub mod decode_cache;
+pub mod disasm;
+#[cfg(feature = "unstable")]
+pub mod farm;
 pub mod font;
+#[cfg(feature = "history")]
+pub mod history;
 pub mod instruction;
+pub mod keypad;
+#[cfg(all(feature = "unstable", feature = "alloc"))]
+pub mod listing;
 pub mod machine;
+#[cfg(all(feature = "unstable", feature = "alloc"))]
+pub mod movie;
+#[cfg(feature = "unstable")]
+pub mod pacing;
+#[cfg(all(feature = "unstable", feature = "alloc"))]
+pub mod patch;
+#[cfg(all(feature = "unstable", feature = "alloc"))]
+pub mod peripheral;
+#[cfg(all(feature = "unstable", feature = "alloc"))]
+pub mod profile;
+pub mod quirks;
+#[cfg(all(feature = "unstable", feature = "alloc"))]
+pub mod rewind;
+pub mod rng;
+pub mod rom_db;
 pub mod screen;
+#[cfg(feature = "serde")]
+mod serde_support;
+pub mod snapshot;
+#[cfg(feature = "unstable")]
+pub mod stats;
+#[cfg(all(feature = "unstable", feature = "alloc"))]
+pub mod symbols;
+#[cfg(all(feature = "unstable", feature = "alloc"))]
+pub mod timeline;
+pub mod timing;
+#[cfg(feature = "unstable")]
+pub mod trace;
+
+/// Big-endian bytes of the given opcodes, to write ROMs inline
+///
+/// ```
+/// use trip_night_core::opcodes;
+///
+/// let rom = opcodes![0x6001, 0xD015];
+/// assert_eq!(rom, [0x60, 0x01, 0xD0, 0x15]);
+/// ```
+#[macro_export]
+macro_rules! opcodes {
+    ($($opcode:expr),* $(,)?) => {
+        [$($crate::opcode_byte($opcode, 0), $crate::opcode_byte($opcode, 1)),*]
+    };
+}
 
+#[doc(hidden)]
+pub const fn opcode_byte(opcode: u16, idx: usize) -> u8 {
+    opcode.to_be_bytes()[idx]
+}
+
+/// Copies groups of bytes into a RAM (or ROM) image, each at its address
+///
+/// Addresses are `u16` offsets into the image, groups are anything dereferencing to `[u8]`, such
+/// as arrays or `opcodes!`. Panics if a group doesn't fit in the image.
+///
+/// ```
+/// use trip_night_core::{opcodes, poke};
+///
+/// let mut ram = [0; 0x400];
+/// poke!(ram,
+///     0x200 => opcodes![0xA300, 0xD015],
+///     0x300 => [0xF0, 0x90, 0x90, 0x90, 0xF0],
+/// );
+/// assert_eq!(ram[0x202..0x204], [0xD0, 0x15]);
+/// assert_eq!(ram[0x304], 0xF0);
+/// ```
+#[macro_export]
+macro_rules! poke {
+    ($ram:expr, $($addr:expr => $bytes:expr),+ $(,)?) => {{
+        let ram: &mut [u8] = &mut $ram;
+        $(
+            let addr: u16 = $addr;
+            let bytes: &[u8] = &$bytes;
+            let start = usize::from(addr);
+            ram[start..start + bytes.len()].copy_from_slice(bytes);
+        )+
+    }};
+}
+
+/// Size of the RAM, covering the whole address space
+pub const RAM_SIZE: usize = Address::MAX as usize + 1;
+
+/// An address of the 4 kB address space, always fitting in 12 bits
+///
+/// Arithmetic wraps around the address space, so that indexing the RAM with an address never
+/// goes out of bounds.
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Address(pub u16);
+pub struct Address(u16);
+
+impl Address {
+    /// Highest valid address
+    pub const MAX: u16 = 0xFFF;
+
+    /// Returns `None` if the value doesn't fit in 12 bits
+    pub const fn new(value: u16) -> Option<Self> {
+        if value <= Self::MAX {
+            Some(Self(value))
+        } else {
+            None
+        }
+    }
+
+    /// Keeps only the lowest 12 bits of the value
+    pub const fn new_masked(value: u16) -> Self {
+        Self(value & Self::MAX)
+    }
+
+    pub fn get(self) -> u16 {
+        self.0
+    }
+
+    /// The address as an index into the RAM, always lower than `RAM_SIZE`
+    pub const fn as_usize(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl From<Address> for usize {
+    fn from(addr: Address) -> Self {
+        addr.as_usize()
+    }
+}
 
 impl core::fmt::Display for Address {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
@@ -21,11 +190,18 @@ impl core::fmt::Debug for Address {
     }
 }
 
+#[cfg(feature = "defmt")]
+impl defmt::Format for Address {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "{=u16:03x}", self.0)
+    }
+}
+
 impl core::ops::Add<u16> for Address {
     type Output = Address;
 
     fn add(self, rhs: u16) -> Self::Output {
-        Self(self.0 + rhs)
+        Self::new_masked(self.0.wrapping_add(rhs))
     }
 }
 
@@ -39,7 +215,7 @@ impl core::ops::Sub<u16> for Address {
     type Output = Address;
 
     fn sub(self, rhs: u16) -> Self::Output {
-        Self(self.0 - rhs)
+        Self::new_masked(self.0.wrapping_sub(rhs))
     }
 }
 
@@ -49,17 +225,43 @@ impl core::ops::SubAssign<u16> for Address {
     }
 }
 
-impl core::ops::Index<Address> for [u8] {
+/// Indexes the RAM, and any array of `RAM_SIZE` bytes, without ever going out of bounds
+impl core::ops::Index<Address> for [u8; RAM_SIZE] {
     type Output = u8;
 
     fn index(&self, index: Address) -> &Self::Output {
-        self.index(usize::from(index.0))
+        &self[index.as_usize()]
+    }
+}
+
+impl core::ops::IndexMut<Address> for [u8; RAM_SIZE] {
+    fn index_mut(&mut self, index: Address) -> &mut Self::Output {
+        &mut self[index.as_usize()]
     }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct RegIdent(u8);
 
+impl core::fmt::Display for RegIdent {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "V{:X}", self.0)
+    }
+}
+
+impl core::fmt::Debug for RegIdent {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        core::fmt::Display::fmt(self, f)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for RegIdent {
+    fn format(&self, f: defmt::Formatter<'_>) {
+        defmt::write!(f, "V{=u8:X}", self.0)
+    }
+}
+
 impl RegIdent {
     pub const V0: Self = Self(0x0);
     pub const V1: Self = Self(0x1);
@@ -0,0 +1,363 @@
+//! Label-aware disassembly of whole ROMs, essentially objdump for .ch8 files
+//!
+//! Code is told apart from data by following the control flow from the entry point: fallthroughs,
+//! jumps, calls and both outcomes of skips. Jump and call targets get a label (`L_0x2A4`), so do
+//! the data regions referenced by ANNN, and every label lists where it is referenced from.
+//!
+//! The listing is valid assembly: instructions use the syntax of `disasm`, bytes never reached as
+//! code are emitted as `DB` directives, and addresses, opcodes and cross-references are comments:
+//!
+//! ```text
+//!     LD I, L_0x206           ; 200  a206
+//!     DRW V0, V0, 5           ; 202  d005
+//! L_0x204:                    ; xrefs: 204 (JP)
+//!     JP L_0x204              ; 204  1204
+//! L_0x206:                    ; xrefs: 200 (LD I)
+//!     DB 0xF0, 0x90, 0x90, 0x90, 0xF0 ; 206
+//! ```
+//!
+//! Code only reached through computed jumps (BNNN) can't be found statically, and is listed as data.
+//!
+//! With the symbol map of the assembler (see `Listing::with_symbols`), labels are named after the
+//! source instead.
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use alloc::{format, vec};
+use core::fmt;
+
+use crate::decode::decode_slot;
+use crate::disasm::disasm;
+use crate::instruction::*;
+use crate::symbols::Symbols;
+use crate::Address;
+
+/// Number of data bytes per `DB` directive
+const BYTES_PER_LINE: usize = 8;
+
+/// How a label is referenced
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum XRefKind {
+    /// 1NNN, or BNNN for the base of a jump table
+    Jump,
+    /// 2NNN
+    Call,
+    /// ANNN, typically sprites or other data
+    Index,
+}
+
+/// Reference to a label
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct XRef {
+    /// Address of the referencing instruction
+    pub from: Address,
+    pub kind: XRefKind,
+}
+
+/// Disassembly of a ROM, see the module documentation
+#[derive(Clone, Debug)]
+pub struct Listing {
+    base: Address,
+    bytes: Vec<u8>,
+    /// Whether an instruction reached from the entry point starts at each byte
+    code: Vec<bool>,
+    /// References to the addresses within the ROM
+    labels: BTreeMap<Address, Vec<XRef>>,
+    /// Names of the labels from a symbol map, instead of their address
+    names: BTreeMap<Address, String>,
+}
+
+impl Listing {
+    /// Analyzes a ROM loaded at `base`, which is also its entry point
+    pub fn analyze(rom: &[u8], base: Address) -> Self {
+        let mut listing = Self {
+            base,
+            bytes: rom.to_vec(),
+            code: vec![false; rom.len()],
+            labels: BTreeMap::new(),
+            names: BTreeMap::new(),
+        };
+
+        let mut pending = vec![base];
+
+        while let Some(addr) = pending.pop() {
+            let Some(opcode) = listing.opcode_at(addr) else {
+                continue;
+            };
+            let offset = listing.offset(addr).expect("an address within the ROM");
+
+            if listing.code[offset] {
+                continue;
+            }
+
+            let Ok(slot) = decode_slot(opcode) else {
+                // Unknown opcodes are data, or machine code called with SYS
+                continue;
+            };
+
+            listing.code[offset] = true;
+            let next = addr + 2;
+            let target = opcode.get_nnn();
+
+            match slot {
+                OP_00EE => {}
+                OP_1NNN => {
+                    listing.reference(target, addr, XRefKind::Jump);
+                    pending.push(target);
+                }
+                OP_2NNN => {
+                    listing.reference(target, addr, XRefKind::Call);
+                    pending.extend([next, target]);
+                }
+                OP_BNNN => listing.reference(target, addr, XRefKind::Jump),
+                OP_3XNN | OP_4XNN | OP_5XY0 | OP_9XY0 | OP_EX9E | OP_EXA1 => pending.extend([next + 2, next]),
+                OP_ANNN => {
+                    listing.reference(target, addr, XRefKind::Index);
+                    pending.push(next);
+                }
+                _ => pending.push(next),
+            }
+        }
+
+        listing
+    }
+
+    /// Names the labels after a symbol map, also giving a label to every symbol within the ROM
+    pub fn with_symbols(mut self, symbols: &Symbols) -> Self {
+        for (addr, _) in symbols.iter() {
+            if let (Some(_), Some(name)) = (self.offset(addr), symbols.name(addr)) {
+                self.labels.entry(addr).or_default();
+                self.names.insert(addr, name.to_string());
+            }
+        }
+
+        self
+    }
+
+    /// Whether an instruction reached from the entry point starts at this address
+    pub fn is_code(&self, addr: Address) -> bool {
+        self.offset(addr).map_or(false, |offset| self.code[offset])
+    }
+
+    /// Addresses which got a label, in increasing order
+    pub fn labels(&self) -> impl Iterator<Item = Address> + '_ {
+        self.labels.keys().copied()
+    }
+
+    /// Instructions referencing the address, empty when it has no label
+    pub fn xrefs(&self, addr: Address) -> &[XRef] {
+        self.labels.get(&addr).map_or(&[], Vec::as_slice)
+    }
+
+    fn reference(&mut self, target: Address, from: Address, kind: XRefKind) {
+        if self.offset(target).is_some() {
+            self.labels.entry(target).or_default().push(XRef { from, kind });
+        }
+    }
+
+    fn offset(&self, addr: Address) -> Option<usize> {
+        let offset = addr.as_usize().checked_sub(self.base.as_usize())?;
+        (offset < self.bytes.len()).then_some(offset)
+    }
+
+    fn opcode_at(&self, addr: Address) -> Option<OpCode> {
+        let offset = self.offset(addr)?;
+        let bytes = self.bytes.get(offset..offset + 2)?;
+        Some(OpCode::new(u16::from_be_bytes([bytes[0], bytes[1]])))
+    }
+
+    /// Whether a line must start at this offset, to keep the label in front of it
+    fn starts_line(&self, offset: usize) -> bool {
+        self.code[offset] || self.labels.contains_key(&(self.base + offset as u16))
+    }
+
+    fn label(&self, addr: Address) -> Label<'_> {
+        Label {
+            addr,
+            name: self.names.get(&addr).map(String::as_str),
+        }
+    }
+
+    fn write_instruction(&self, f: &mut fmt::Formatter<'_>, addr: Address, opcode: OpCode) -> fmt::Result {
+        let target = opcode.get_nnn();
+        let label = self.label(target);
+        let mnemonic = disasm(opcode);
+        let name = mnemonic.name();
+
+        let text = match decode_slot(opcode) {
+            Ok(OP_1NNN | OP_2NNN) if self.labels.contains_key(&target) => format!("{name} {label}"),
+            Ok(OP_ANNN) if self.labels.contains_key(&target) => format!("{name} I, {label}"),
+            Ok(OP_BNNN) if self.labels.contains_key(&target) => format!("{name} V0, {label}"),
+            _ => mnemonic.to_string(),
+        };
+
+        writeln!(f, "    {text:<23} ; {addr}  {:04x}", opcode.get_inner())
+    }
+}
+
+impl fmt::Display for Listing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut offset = 0;
+
+        while offset < self.bytes.len() {
+            let addr = self.base + offset as u16;
+
+            if let Some(xrefs) = self.labels.get(&addr) {
+                let label = format!("{}:", self.label(addr));
+
+                if xrefs.is_empty() {
+                    f.write_str(&label)?;
+                } else {
+                    write!(f, "{label:<27} ; xrefs:")?;
+                }
+                for xref in xrefs {
+                    let kind = match xref.kind {
+                        XRefKind::Jump => "JP",
+                        XRefKind::Call => "CALL",
+                        XRefKind::Index => "LD I",
+                    };
+                    write!(f, " {} ({kind})", xref.from)?;
+                }
+                writeln!(f)?;
+            }
+
+            // Instructions overlapping a label or another instruction are listed as data
+            let instruction = self.code[offset] && offset + 1 < self.bytes.len() && !self.starts_line(offset + 1);
+
+            if instruction {
+                let opcode = self.opcode_at(addr).expect("an opcode within the ROM");
+                self.write_instruction(f, addr, opcode)?;
+                offset += 2;
+                continue;
+            }
+
+            let end = (offset + 1..self.bytes.len())
+                .take(BYTES_PER_LINE - 1)
+                .find(|&end| self.starts_line(end))
+                .unwrap_or_else(|| core::cmp::min(offset + BYTES_PER_LINE, self.bytes.len()));
+
+            f.write_str("    DB")?;
+            for (n, byte) in self.bytes[offset..end].iter().enumerate() {
+                write!(f, "{} 0x{byte:02X}", if n == 0 { "" } else { "," })?;
+            }
+            writeln!(f, " ; {addr}")?;
+
+            offset = end;
+        }
+
+        Ok(())
+    }
+}
+
+/// Label of an address, its symbol or e.g. `L_0x2A4`
+struct Label<'a> {
+    addr: Address,
+    name: Option<&'a str>,
+}
+
+impl fmt::Display for Label<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            Some(name) => f.write_str(name),
+            None => write!(f, "L_0x{:03X}", self.addr.get()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labels_and_xrefs() {
+        let rom = [
+            0xA2, 0x0C, // 200: LD I, sprite
+            0x22, 0x08, // 202: CALL 208
+            0x12, 0x06, // 204: JP 206
+            0x12, 0x06, // 206: JP 206
+            0x30, 0x00, // 208: SE V0, 0
+            0x00, 0xEE, // 20a: RET
+            0xF0, 0x90, 0x90, 0x90, 0xF0, // 20c: sprite
+        ];
+
+        let listing = Listing::analyze(&rom, Address(0x200));
+
+        assert!(listing.is_code(Address(0x20A)));
+        assert!(!listing.is_code(Address(0x20C)));
+        assert!(listing.labels().eq([Address(0x206), Address(0x208), Address(0x20C)]));
+        assert_eq!(
+            listing.xrefs(Address(0x206)),
+            [
+                XRef {
+                    from: Address(0x204),
+                    kind: XRefKind::Jump
+                },
+                XRef {
+                    from: Address(0x206),
+                    kind: XRefKind::Jump
+                },
+            ]
+        );
+
+        assert_eq!(
+            listing.to_string(),
+            "    LD I, L_0x20C           ; 200  a20c
+    CALL L_0x208            ; 202  2208
+    JP L_0x206              ; 204  1206
+L_0x206:                    ; xrefs: 204 (JP) 206 (JP)
+    JP L_0x206              ; 206  1206
+L_0x208:                    ; xrefs: 202 (CALL)
+    SE V0, 0x00             ; 208  3000
+    RET                     ; 20a  00ee
+L_0x20C:                    ; xrefs: 200 (LD I)
+    DB 0xF0, 0x90, 0x90, 0x90, 0xF0 ; 20c
+"
+        );
+    }
+
+    #[test]
+    fn symbol_names() {
+        let rom = [
+            0x22, 0x04, // 200: CALL 204
+            0x12, 0x02, // 202: JP 202
+            0x00, 0xEE, // 204: RET
+            0x12, 0x34, // 206: data
+        ];
+        let symbols: Symbols = "200 main\n204 draw\n206 table\n300 outside".parse().unwrap();
+
+        let listing = Listing::analyze(&rom, Address(0x200)).with_symbols(&symbols);
+        assert!(listing
+            .labels()
+            .eq([Address(0x200), Address(0x202), Address(0x204), Address(0x206)]));
+
+        assert_eq!(
+            listing.to_string(),
+            "main:
+    CALL draw               ; 200  2204
+L_0x202:                    ; xrefs: 202 (JP)
+    JP L_0x202              ; 202  1202
+draw:                       ; xrefs: 200 (CALL)
+    RET                     ; 204  00ee
+table:
+    DB 0x12, 0x34 ; 206
+"
+        );
+    }
+
+    #[test]
+    fn unreachable_bytes_are_data() {
+        // Jumps over 10 bytes, then a computed jump which can't be followed
+        let mut rom = [0x12, 0x0C].to_vec();
+        rom.extend([0xFF; 10]);
+        rom.extend([0xB2, 0x0E, 0x00, 0xE0, 0x01]);
+
+        let text = Listing::analyze(&rom, Address(0x200)).to_string();
+        let lines: Vec<&str> = text.lines().collect();
+
+        assert_eq!(lines[1], "    DB 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF ; 202");
+        assert_eq!(lines[2], "    DB 0xFF, 0xFF ; 20a");
+        assert_eq!(lines[4], "    JP V0, L_0x20E          ; 20c  b20e");
+        assert_eq!(lines[6], "    DB 0x00, 0xE0, 0x01 ; 20e");
+    }
+}
@@ -1,158 +1,3437 @@
+#[cfg(all(feature = "unstable", feature = "alloc"))]
+use alloc::rc::Rc;
+#[cfg(feature = "unstable")]
+use core::cell::Cell;
+#[cfg(all(feature = "unstable", feature = "alloc"))]
+use core::cell::RefCell;
 use core::fmt;
 
-use crate::decode::decode_instruction;
-use crate::instruction::{InstructionSet, OpCode};
+#[cfg(feature = "unstable")]
+use crate::coverage::Coverage;
+#[cfg(all(feature = "unstable", feature = "alloc"))]
+use crate::debug::MemoryObserver;
+#[cfg(feature = "unstable")]
+use crate::debug::{
+    Access, Breakpoints, ConditionalBreakpoint, ConditionalBreakpoints, Watchpoint, WatchpointHit, Watchpoints,
+};
+use crate::decode::{decode_slot, UnknownInstructionError};
+#[cfg(all(feature = "unstable", feature = "alloc"))]
+use crate::decode_cache::DecodeCache;
+#[cfg(feature = "history")]
+use crate::history::History;
+use crate::instruction::{Instruction, InstructionSet, OpCode};
+use crate::keypad::{Key, Keypad, WaitingForKey};
+#[cfg(all(feature = "unstable", feature = "alloc"))]
+use crate::patch::InstructionPatches;
+#[cfg(all(feature = "unstable", feature = "alloc"))]
+use crate::peripheral::Peripherals;
+#[cfg(all(feature = "unstable", feature = "alloc"))]
+use crate::profile::Profile;
+use crate::quirks::{Quirks, Variant};
+use crate::rng::{MachineRng, XorShiftRng};
 use crate::screen::Screen;
-use crate::{Address, RegIdent};
+use crate::snapshot::Snapshot;
+#[cfg(feature = "unstable")]
+use crate::stats::{OpcodeStats, PerfCounters};
+#[cfg(all(feature = "unstable", feature = "alloc"))]
+use crate::symbols::Symbols;
+use crate::timing::{vip_cycles, Timing, VIP_FETCH_CYCLES, VIP_INTERPRETER_CYCLES_PER_FRAME};
+use crate::{Address, RegIdent, RAM_SIZE};
 
 /// A Chip8 virtual machine
+///
+/// # Determinism
+///
+/// Two machines built from the same ROM and configuration go through exactly the same states when
+/// their keypads are set the same way before each `run_frame`, which replays and netplay rely on.
+/// This holds as long as:
+///
+/// - the random number generator is seeded (`MachineBuilder::deterministic`), not a custom one
+///   drawing from the host
+/// - timers are ticked by the cycles (`TimerClock::Cycles`), not by the wall clock of the frontend
+/// - inputs only change between frames, never from within hooks
+///
+/// `check_deterministic` verifies the first two conditions, and `state_hash` proves two runs
+/// identical frame after frame.
+#[derive(Clone)]
 pub struct Machine {
     pub state: State,
     pub instruction_set: InstructionSet,
+    /// Instructions executed instead of `instruction_set` in their slots
+    #[cfg(all(feature = "unstable", feature = "alloc"))]
+    pub patches: InstructionPatches,
     pub frequency_hz: usize,
     pub counter: usize,
+    /// Platform emulated, recorded in snapshots (CHIP-8 unless built from SUPER-CHIP or XO-CHIP quirks)
+    pub variant: Variant,
+    /// Source of the 60 Hz timer ticks
+    pub timer_clock: TimerClock,
+    /// How long instructions take in `run_frame`
+    pub timing: Timing,
+    /// Ticks accumulated by the cycles, in 1/`frequency_hz` of a 60 Hz period
+    timer_accumulator: usize,
+    /// Remaining cycles to idle before the first instruction is executed
+    pub start_delay: usize,
+    /// First write to the VIP stack area detected when the stack mode is `StackMode::Watched`
+    pub stack_area_violation: Option<StackAreaViolation>,
+    /// Hard limits enforced by the `run_*` methods
+    pub limits: Limits,
+    /// What happens after a fault
+    pub fault_policy: FaultPolicy,
+    /// Machine routines called by the 0NNN opcodes the instructions don't decode, see `HostCall`
+    pub host_call: Option<HostCall>,
+    /// Number of faults (unknown instructions) in a row, reset by any successfully decoded instruction
+    pub consecutive_faults: usize,
+    /// Set on faults (see `FaultPolicy`) and when the ROM jumps to itself forever, a halted machine doesn't
+    /// cycle anymore
+    pub halted: bool,
+    /// Addresses stopping the execution right before their instruction is executed
+    #[cfg(feature = "unstable")]
+    pub breakpoints: Breakpoints,
+    /// Breakpoints stopping the execution when their condition holds, see `add_conditional_breakpoint`
+    #[cfg(feature = "unstable")]
+    pub conditional_breakpoints: ConditionalBreakpoints,
+    /// Names of the addresses of the ROM, typically loaded from the symbol map of the assembler, to
+    /// set breakpoints by name (see `add_breakpoint_at_symbol` and `ConditionalBreakpoint::parse_with`)
+    #[cfg(all(feature = "unstable", feature = "alloc"))]
+    pub symbols: Symbols,
+    /// Breakpoint the machine stopped at, whose instruction is executed by the next cycle
+    #[cfg(feature = "unstable")]
+    stopped_at: Option<Address>,
+    /// Addresses executed as instructions, when enabled
+    #[cfg(feature = "unstable")]
+    coverage: Option<Coverage>,
+    /// Execution counts per address, when enabled
+    #[cfg(all(feature = "unstable", feature = "alloc"))]
+    profile: Option<Profile>,
+    /// Decoded instructions per address, when enabled
+    #[cfg(all(feature = "unstable", feature = "alloc"))]
+    decode_cache: Option<DecodeCache>,
+    /// Execution counts per kind of instruction, when enabled
+    #[cfg(feature = "unstable")]
+    opcode_stats: Option<OpcodeStats>,
+    #[cfg(feature = "unstable")]
+    perf_counters: PerfCounters,
+    /// Last executed instructions
+    #[cfg(feature = "history")]
+    pub history: History,
+    /// Copy of the history taken when the last fault occurred, the faulty instruction being the last entry
+    #[cfg(feature = "history")]
+    pub fault_history: Option<History>,
+    /// Configuration the machine was powered on with, reused by `reset`
+    power_on: PowerOn,
+    /// Where the game code and the font are loaded, reused by `reset`
+    layout: MemoryLayout,
+    /// Loaded game code, reused by `reset`
+    rom: [u8; MAX_ROM_SIZE],
+    rom_len: usize,
+}
+
+/// Maximum size of a ROM, loaded at 0x200
+pub const MAX_ROM_SIZE: usize = RAM_SIZE - 0x200;
+
+/// A ROM which can't be loaded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RomError {
+    Empty,
+    /// Doesn't fit in RAM after the entry point, or is longer than `MAX_ROM_SIZE`
+    TooLarge {
+        len: usize,
+        max: usize,
+    },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::Empty => write!(f, "the ROM is empty"),
+            RomError::TooLarge { len, max } => write!(f, "the ROM is {len} bytes long, {max} bytes at most"),
+        }
+    }
+}
+
+/// Where the game code and the font are loaded in RAM
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MemoryLayout {
+    /// Address the game code is loaded at, and where the execution starts
+    pub entry: Address,
+    /// Address of the font sprites, used by FX29
+    pub font: Address,
+}
+
+impl MemoryLayout {
+    /// Maximum size of a ROM loaded at the entry point
+    pub fn rom_capacity(&self) -> usize {
+        MAX_ROM_SIZE.min(RAM_SIZE - self.entry.as_usize())
+    }
+
+    fn check_rom(&self, game_code: &[u8]) -> Result<(), RomError> {
+        if game_code.is_empty() {
+            return Err(RomError::Empty);
+        }
+
+        let max = self.rom_capacity();
+
+        if game_code.len() > max {
+            return Err(RomError::TooLarge {
+                len: game_code.len(),
+                max,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for MemoryLayout {
+    fn default() -> Self {
+        Self {
+            entry: Address(0x200),
+            font: crate::font::STANDARD_ADDRESS,
+        }
+    }
+}
+
+/// Step by step configuration of a machine, see `Machine::builder`
+#[derive(Clone)]
+pub struct MachineBuilder<'rom> {
+    game_code: &'rom [u8],
+    entry: u16,
+    font: u16,
+    quirks: Quirks,
+    make_instruction_set: Option<fn(&Quirks) -> InstructionSet>,
+    host_call: Option<HostCall>,
+    rng: MachineRng,
+    frequency_hz: usize,
+    power_on: PowerOn,
+}
+
+impl Default for MachineBuilder<'_> {
+    fn default() -> Self {
+        let layout = MemoryLayout::default();
+
+        Self {
+            game_code: &[],
+            entry: layout.entry.0,
+            font: layout.font.0,
+            quirks: Quirks::default(),
+            make_instruction_set: None,
+            host_call: None,
+            rng: MachineRng::default(),
+            frequency_hz: 700,
+            power_on: PowerOn::default(),
+        }
+    }
+}
+
+impl<'rom> MachineBuilder<'rom> {
+    /// Game code to load at the entry point (required)
+    pub fn rom(mut self, game_code: &'rom [u8]) -> Self {
+        self.game_code = game_code;
+        self
+    }
+
+    /// Address the game code is loaded at and where the execution starts, 0x200 by default
+    pub fn entry(mut self, address: u16) -> Self {
+        self.entry = address;
+        self
+    }
+
+    /// Address the font is loaded at, 0x50 by default
+    pub fn font_at(mut self, address: u16) -> Self {
+        self.font = address;
+        self
+    }
+
+    /// Quirks handed to the instruction set constructor, none by default
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Constructor of the instruction set, such as `make_set` of the instruction crate (required)
+    pub fn instruction_set(mut self, make_instruction_set: fn(&Quirks) -> InstructionSet) -> Self {
+        self.make_instruction_set = Some(make_instruction_set);
+        self
+    }
+
+    /// Handler of the machine routines called by 0NNN, none by default (see `HostCall`)
+    pub fn host_call(mut self, host_call: HostCall) -> Self {
+        self.host_call = Some(host_call);
+        self
+    }
+
+    pub fn rng(mut self, rng: impl Into<MachineRng>) -> Self {
+        self.rng = rng.into();
+        self
+    }
+
+    /// Seeds the default random number generator, so that the machine is deterministic
+    pub fn deterministic(self, seed: u32) -> Self {
+        self.rng(XorShiftRng::new(seed))
+    }
+
+    /// Number of cycles per second, 700 by default
+    pub fn frequency(mut self, frequency_hz: usize) -> Self {
+        self.frequency_hz = frequency_hz;
+        self
+    }
+
+    pub fn power_on(mut self, power_on: PowerOn) -> Self {
+        self.power_on = power_on;
+        self
+    }
+
+    pub fn build(self) -> Result<Machine, BuildError> {
+        let make_instruction_set = self.make_instruction_set.ok_or(BuildError::MissingInstructionSet)?;
+
+        if self.frequency_hz == 0 {
+            return Err(BuildError::ZeroFrequency);
+        }
+
+        let entry = Address::new(self.entry).ok_or(BuildError::EntryOutOfBounds { entry: self.entry })?;
+
+        let font_len = crate::font::STANDARD.len();
+        let font = match Address::new(self.font) {
+            Some(font) if font.as_usize() + font_len <= RAM_SIZE => font,
+            _ => return Err(BuildError::FontOutOfBounds { font: self.font }),
+        };
+
+        let layout = MemoryLayout { entry, font };
+        layout.check_rom(self.game_code)?;
+
+        let rom = entry.as_usize()..entry.as_usize() + self.game_code.len();
+        let font_range = font.as_usize()..font.as_usize() + font_len;
+
+        if rom.start < font_range.end && font_range.start < rom.end {
+            return Err(BuildError::FontOverlapsRom);
+        }
+
+        let mut machine = Machine::with_layout(
+            self.game_code,
+            make_instruction_set(&self.quirks),
+            self.frequency_hz,
+            self.power_on,
+            layout,
+        );
+        machine.state.rng = self.rng;
+        machine.host_call = self.host_call;
+        machine.variant = Variant::of(&self.quirks);
+
+        Ok(machine)
+    }
+}
+
+/// An invalid machine configuration, returned by `MachineBuilder::build`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BuildError {
+    Rom(RomError),
+    MissingInstructionSet,
+    ZeroFrequency,
+    /// The entry point is past the end of the RAM
+    EntryOutOfBounds {
+        entry: u16,
+    },
+    /// The font doesn't fit in RAM at the given address
+    FontOutOfBounds {
+        font: u16,
+    },
+    /// The font and the game code share some addresses
+    FontOverlapsRom,
+}
+
+impl From<RomError> for BuildError {
+    fn from(error: RomError) -> Self {
+        BuildError::Rom(error)
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Rom(error) => error.fmt(f),
+            BuildError::MissingInstructionSet => write!(f, "no instruction set was provided"),
+            BuildError::ZeroFrequency => write!(f, "the frequency must not be zero"),
+            BuildError::EntryOutOfBounds { entry } => write!(f, "entry point {entry:#X} is past the end of the RAM"),
+            BuildError::FontOutOfBounds { font } => write!(f, "the font doesn't fit in RAM at {font:#X}"),
+            BuildError::FontOverlapsRom => write!(f, "the font overlaps the game code"),
+        }
+    }
 }
 
 impl Machine {
     pub fn new(game_code: &[u8], instruction_set: InstructionSet, frequency_hz: usize) -> Self {
+        Self::with_power_on(game_code, instruction_set, frequency_hz, PowerOn::default())
+    }
+
+    pub fn with_power_on(
+        game_code: &[u8],
+        instruction_set: InstructionSet,
+        frequency_hz: usize,
+        power_on: PowerOn,
+    ) -> Self {
+        Self::with_layout(
+            game_code,
+            instruction_set,
+            frequency_hz,
+            power_on,
+            MemoryLayout::default(),
+        )
+    }
+
+    /// Configures a machine step by step, the configuration being checked by `MachineBuilder::build`
+    ///
+    /// ```ignore
+    /// let machine = Machine::builder()
+    ///     .rom(&game_code)
+    ///     .quirks(Quirks::schip())
+    ///     .instruction_set(make_set)
+    ///     .frequency(700)
+    ///     .build()?;
+    /// ```
+    pub fn builder<'rom>() -> MachineBuilder<'rom> {
+        MachineBuilder::default()
+    }
+
+    fn with_layout(
+        game_code: &[u8],
+        instruction_set: InstructionSet,
+        frequency_hz: usize,
+        power_on: PowerOn,
+        layout: MemoryLayout,
+    ) -> Self {
+        let mut rom = [0; MAX_ROM_SIZE];
+        rom[..game_code.len()].copy_from_slice(game_code);
+
         Self {
-            state: State::new(game_code),
+            state: State::new(game_code, power_on.ram_pattern, layout),
             instruction_set,
             frequency_hz,
             counter: 0,
+            variant: Variant::default(),
+            timer_clock: TimerClock::default(),
+            timing: Timing::default(),
+            timer_accumulator: 0,
+            start_delay: power_on.start_delay,
+            stack_area_violation: None,
+            limits: Limits::default(),
+            fault_policy: FaultPolicy::default(),
+            host_call: None,
+            consecutive_faults: 0,
+            halted: false,
+            #[cfg(feature = "unstable")]
+            breakpoints: Breakpoints::default(),
+            #[cfg(feature = "unstable")]
+            conditional_breakpoints: ConditionalBreakpoints::default(),
+            #[cfg(all(feature = "unstable", feature = "alloc"))]
+            symbols: Symbols::default(),
+            #[cfg(all(feature = "unstable", feature = "alloc"))]
+            patches: InstructionPatches::default(),
+            #[cfg(feature = "unstable")]
+            coverage: None,
+            #[cfg(all(feature = "unstable", feature = "alloc"))]
+            profile: None,
+            #[cfg(all(feature = "unstable", feature = "alloc"))]
+            decode_cache: None,
+            #[cfg(feature = "unstable")]
+            opcode_stats: None,
+            #[cfg(feature = "unstable")]
+            perf_counters: PerfCounters::default(),
+            #[cfg(feature = "unstable")]
+            stopped_at: None,
+            #[cfg(feature = "history")]
+            history: History::default(),
+            #[cfg(feature = "history")]
+            fault_history: None,
+            power_on,
+            layout,
+            rom,
+            rom_len: game_code.len(),
+        }
+    }
+
+    /// Where the game code and the font are loaded
+    pub fn layout(&self) -> MemoryLayout {
+        self.layout
+    }
+
+    /// Loaded game code
+    pub fn rom(&self) -> &[u8] {
+        &self.rom[..self.rom_len]
+    }
+
+    /// Replaces the loaded ROM, then resets the machine to start it
+    pub fn load_rom(&mut self, game_code: &[u8]) -> Result<(), RomError> {
+        self.layout.check_rom(game_code)?;
+
+        self.rom[..game_code.len()].copy_from_slice(game_code);
+        self.rom_len = game_code.len();
+        self.reset();
+
+        Ok(())
+    }
+
+    /// Restarts the loaded ROM as if the machine was powered on again
+    ///
+    /// The configuration is kept: instruction set, frequency, memory layout, limits, fault policy,
+    /// stack mode and depth, memory bounds, peripherals, memory observer and random number generator.
+    pub fn reset(&mut self) {
+        let mut state = State::new(&self.rom[..self.rom_len], self.power_on.ram_pattern, self.layout);
+        state.stack_mode = self.state.stack_mode;
+        state.stack_depth = self.state.stack_depth;
+        state.memory_bounds = self.state.memory_bounds;
+        #[cfg(feature = "unstable")]
+        {
+            state.watchpoints = self.state.watchpoints.clone();
+        }
+        #[cfg(all(feature = "unstable", feature = "alloc"))]
+        {
+            state.peripherals = core::mem::take(&mut self.state.peripherals);
+            state.memory_observer = self.state.memory_observer.take();
+        }
+        state.rng = core::mem::take(&mut self.state.rng);
+        // Copies taken with `Screen::snapshot_into` must see the new screen as a change
+        state.screen.generation = self.state.screen.generation.wrapping_add(1);
+        self.state = state;
+
+        self.counter = 0;
+        self.timer_accumulator = 0;
+        self.start_delay = self.power_on.start_delay;
+        self.stack_area_violation = None;
+        self.consecutive_faults = 0;
+        self.halted = false;
+        #[cfg(feature = "unstable")]
+        {
+            self.stopped_at = None;
+        }
+
+        #[cfg(feature = "history")]
+        {
+            self.history.clear();
+            self.fault_history = None;
+        }
+    }
+
+    /// Captures the emulated hardware, to be restored later with `restore`
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            variant: self.variant,
+            ram: self.state.ram,
+            registers: self.state.registers,
+            stack: self.state.stack,
+            stack_pointer: self.state.stack_pointer,
+            delay_timer: self.state.delay_timer,
+            sound_timer: self.state.sound_timer,
+            index: self.state.index,
+            pc: self.state.pc,
+            screen: self.state.screen.clone(),
+            waiting_for_key: self.state.waiting_for_key,
+            waiting_for_vblank: self.state.waiting_for_vblank,
         }
     }
 
+    /// Brings the emulated hardware back to a snapshot
+    ///
+    /// The configuration of the machine is kept, and a halted machine resumes.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.state.ram = snapshot.ram;
+        self.state.registers = snapshot.registers;
+        self.state.stack = snapshot.stack;
+        self.state.stack_pointer = snapshot.stack_pointer;
+        self.state.delay_timer = snapshot.delay_timer;
+        self.state.sound_timer = snapshot.sound_timer;
+        self.state.index = snapshot.index;
+        self.state.pc = snapshot.pc;
+        let generation = self.state.screen.generation;
+        self.state.screen = snapshot.screen.clone();
+        self.state.screen.generation = generation.wrapping_add(1);
+        self.state.waiting_for_key = snapshot.waiting_for_key;
+        self.state.waiting_for_vblank = snapshot.waiting_for_vblank;
+        self.state.fault = None;
+
+        self.consecutive_faults = 0;
+        self.halted = false;
+    }
+
+    /// Whether the sound timer is running, a halted machine being silent
     pub fn is_beeping(&self) -> bool {
-        self.state.sound_timer > 0
+        !self.halted && self.state.sound_timer > 0
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
     }
 
     pub fn screen(&self) -> &Screen {
         &self.state.screen
     }
 
-    pub fn cycle(&mut self) {
-        self.update_counter();
-        self.state.screen.reset_changed_flag();
+    /// Screen of a paused machine, for tooling such as sprite editors
+    ///
+    /// The ROM keeps drawing over the changes once the machine resumes.
+    pub fn screen_mut(&mut self) -> &mut Screen {
+        &mut self.state.screen
+    }
 
-        let opcode = self.fetch_opcode();
-        let instruction = decode_instruction(&self.instruction_set, opcode).unwrap();
-        instruction.execute(opcode, &mut self.state);
+    pub fn keypad(&self) -> &Keypad {
+        &self.state.keypad
     }
 
-    pub fn update_counter(&mut self) {
-        self.counter += 1;
+    pub fn keypad_mut(&mut self) -> &mut Keypad {
+        &mut self.state.keypad
+    }
+
+    /// Runs a cycle
+    ///
+    /// Faults are returned as errors and the faulty instruction is skipped, the machine halting once
+    /// `Limits::max_consecutive_faults` faults occurred in a row.
+    pub fn cycle(&mut self) -> Result<CycleOutcome, MachineError> {
+        self.cycle_with_hooks(|_, _| {}, |_, _| {})
+    }
 
-        // NOTE: this is a rough approximation, timers will decrease a bit faster than they should
-        let modulus = core::cmp::max(self.frequency_hz / 60, 1);
+    /// Runs a cycle, calling `pre` right before the instruction is executed and `post` right after
+    ///
+    /// Hooks are not called for cycles not executing any instruction (start delay, waiting for a key, fault…).
+    pub fn cycle_with_hooks(
+        &mut self,
+        mut pre: impl FnMut(&State, OpCode),
+        mut post: impl FnMut(&State, OpCode),
+    ) -> Result<CycleOutcome, MachineError> {
+        if self.halted {
+            return Ok(CycleOutcome::Halted);
+        }
 
-        if self.counter % modulus == 0 {
-            self.state.delay_timer = self.state.delay_timer.saturating_sub(1);
-            self.state.sound_timer = self.state.sound_timer.saturating_sub(1);
+        #[cfg(feature = "unstable")]
+        if let Some(pc) = self.check_breakpoint() {
+            return Ok(CycleOutcome::BreakpointHit { pc });
         }
+
+        self.update_counter();
+        self.step(&mut pre, &mut post)
     }
 
-    fn fetch_opcode(&mut self) -> OpCode {
-        let first = self.state.ram[self.state.pc];
-        let second = self.state.ram[self.state.pc + 1];
-        let op = u16::from_be_bytes([first, second]);
-        self.state.pc += 2;
-        OpCode::new(op)
+    /// Runs one 60 Hz frame: `frequency_hz / 60` cycles, with the timers ticking exactly once
+    ///
+    /// With `TimerClock::External`, the timers are left to the host and don't tick.
+    ///
+    /// With `Timing::CosmacVip`, the frame rather runs instructions until they took
+    /// `timing::VIP_INTERPRETER_CYCLES_PER_FRAME` machine cycles. The instruction crossing the end of
+    /// the frame completes in it, and its extra machine cycles are not carried over to the next one.
+    ///
+    /// The frame ends early when the machine halts or stops at a breakpoint or a watchpoint.
+    pub fn run_frame(&mut self) -> FrameSummary {
+        #[cfg(feature = "unstable")]
+        {
+            self.perf_counters.frames += 1;
+        }
+        self.vblank();
+
+        if self.timer_clock == TimerClock::Cycles {
+            self.tick_timers();
+        }
+
+        let cycles_per_frame = core::cmp::max(self.frequency_hz / 60, 1);
+        let count = match self.timing {
+            Timing::Uniform => core::cmp::min(cycles_per_frame, self.limits.max_cycles_per_call),
+            // Bounded by the machine cycles of the frame below
+            Timing::CosmacVip => self.limits.max_cycles_per_call,
+        };
+        let mut vip_budget = VIP_INTERPRETER_CYCLES_PER_FRAME;
+
+        let mut summary = FrameSummary::default();
+
+        for _ in 0..count {
+            if self.halted {
+                break;
+            }
+
+            #[cfg(feature = "unstable")]
+            if let Some(pc) = self.check_breakpoint() {
+                summary.breakpoint = Some(pc);
+                break;
+            }
+
+            // Faults are accounted for by the fault counter, halting the machine if needed
+            let pc = self.state.pc;
+            let outcome = self.step(&mut |_, _| {}, &mut |_, _| {});
+            self.counter += 1;
+            summary.screen_changed |= self.state.screen.is_changed();
+            summary.cycles += 1;
+
+            #[cfg(feature = "unstable")]
+            if let Ok(CycleOutcome::WatchpointHit(hit)) = outcome {
+                summary.watchpoint = Some(hit);
+                break;
+            }
+
+            if self.timing == Timing::CosmacVip {
+                let cost = match outcome {
+                    Ok(CycleOutcome::Executed(opcode)) => vip_cycles(opcode, pc, &self.state),
+                    // Faults, and the interpreter idling while waiting
+                    _ => VIP_FETCH_CYCLES,
+                };
+
+                vip_budget = vip_budget.saturating_sub(cost);
+                if vip_budget == 0 {
+                    break;
+                }
+            }
+        }
+
+        summary.beeping = self.is_beeping();
+        summary.halted = self.halted;
+
+        summary
     }
-}
 
-impl fmt::Display for Machine {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        writeln!(f, "------------ Machine ------------")?;
-        write!(f, "{}", self.state)
+    /// Adds a breakpoint, returns whether it is new
+    #[cfg(feature = "unstable")]
+    pub fn add_breakpoint(&mut self, addr: Address) -> bool {
+        self.breakpoints.insert(addr)
     }
-}
 
-pub struct State {
-    /// Memory: 4 kB (or 4096 bytes) of RAM
-    pub ram: [u8; 4096],
-    /// Program counter, points at the current instruction in memory
-    pub pc: Address,
-    // Index register pointing at location of a sprite when drawing
-    pub index: Address,
-    /// Stores return addresses when calling subroutines
-    stack: [Address; 16],
-    /// Stack pointer, points to the next available slot in the stack
-    stack_pointer: u8,
-    /// Delay timer register, will be decremented at a rate of 60 Hz until 0 is reached
-    pub delay_timer: u8,
-    /// Sound timer register, a "beep" will be produced until it reaches 0
-    pub sound_timer: u8,
-    /// General-purpose registers
-    registers: [u8; 16],
-    /// Chip8 Screen
-    pub screen: Screen,
-}
+    /// Adds a breakpoint at the address of a symbol, returns whether it is new, `None` when there is no
+    /// such symbol
+    #[cfg(all(feature = "unstable", feature = "alloc"))]
+    pub fn add_breakpoint_at_symbol(&mut self, name: &str) -> Option<bool> {
+        let addr = self.symbols.address(name)?;
+        Some(self.add_breakpoint(addr))
+    }
 
-impl State {
-    fn new(game_code: &[u8]) -> Self {
-        use crate::font;
+    /// Removes a breakpoint, returns whether there was one at this address
+    #[cfg(feature = "unstable")]
+    pub fn remove_breakpoint(&mut self, addr: Address) -> bool {
+        self.breakpoints.remove(addr)
+    }
 
-        let mut ram = [0; 4096];
+    /// Adds a breakpoint with a condition, evaluated between instructions, returns `false` if
+    /// `debug::MAX_CONDITIONAL_BREAKPOINTS` conditional breakpoints are already set
+    ///
+    /// `Changes` conditions compare with the values at the time the breakpoint is added, then at the
+    /// previous instruction.
+    #[cfg(feature = "unstable")]
+    pub fn add_conditional_breakpoint(&mut self, breakpoint: ConditionalBreakpoint) -> bool {
+        self.conditional_breakpoints.insert(breakpoint, &self.state)
+    }
 
-        ram[0x50..0x50 + font::STANDARD.len()].copy_from_slice(font::STANDARD);
-        ram[0x200..0x200 + game_code.len()].copy_from_slice(game_code);
+    /// Adds a watchpoint, returns `false` if `debug::MAX_WATCHPOINTS` watchpoints are already set
+    ///
+    /// The cycle executing an instruction which accesses the watched memory through the `State::ram_*`
+    /// accessors returns `CycleOutcome::WatchpointHit`, and `run_frame` stops after it.
+    #[cfg(feature = "unstable")]
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        self.state.watchpoints.insert(watchpoint)
+    }
 
-        Self {
-            ram,
-            pc: Address(0x200),
-            index: Address(0),
-            stack: [Address(0); 16],
-            stack_pointer: 0,
-            delay_timer: 0,
-            sound_timer: 0,
-            registers: [0; 16],
-            screen: Screen::default(),
-        }
+    /// Removes the watchpoints starting at the given address, returns whether there was any
+    #[cfg(feature = "unstable")]
+    pub fn remove_watchpoint(&mut self, start: Address) -> bool {
+        self.state.watchpoints.remove(start)
     }
-}
 
-impl State {
-    pub fn stack_push(&mut self, value: Address) {
-        self.stack[usize::from(self.stack_pointer)] = value;
-        self.stack_pointer += 1;
+    /// Starts marking the addresses of the executed instructions, from an empty coverage map
+    ///
+    /// The coverage map is kept across resets, so that it accumulates the runs of a test harness.
+    #[cfg(feature = "unstable")]
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::default());
     }
 
-    pub fn stack_pop(&mut self) -> Address {
-        self.stack_pointer -= 1;
-        self.stack[usize::from(self.stack_pointer)]
+    /// Stops marking the executed instructions, returns the coverage map if it was enabled
+    #[cfg(feature = "unstable")]
+    pub fn disable_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
     }
 
-    pub fn reg_write(&mut self, reg: RegIdent, value: u8) {
-        self.registers[usize::from(reg.get())] = value;
+    /// Addresses executed as instructions since `enable_coverage`, `None` when disabled
+    #[cfg(feature = "unstable")]
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
     }
 
-    pub fn reg_read(&mut self, reg: RegIdent) -> u8 {
-        self.registers[usize::from(reg.get())]
+    /// Starts counting the executed instructions per address, sampling one every `sample_interval`
+    ///
+    /// Like the coverage map, the profile is kept across resets.
+    #[cfg(all(feature = "unstable", feature = "alloc"))]
+    pub fn enable_profiling(&mut self, sample_interval: usize) {
+        self.profile = Some(Profile::new(sample_interval));
     }
-}
 
-impl fmt::Display for State {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let pc_ram_start = usize::from(self.pc.0);
-        let pc_ram_end = pc_ram_start + 4;
-        let memory_at_pc = &self.ram[pc_ram_start..pc_ram_end];
+    /// Stops profiling, returns the profile if it was enabled
+    #[cfg(all(feature = "unstable", feature = "alloc"))]
+    pub fn disable_profiling(&mut self) -> Option<Profile> {
+        self.profile.take()
+    }
 
-        let i_ram_start = usize::from(self.index.0);
-        let i_ram_end = i_ram_start + 4;
-        let memory_at_index = &self.ram[i_ram_start..i_ram_end];
+    /// Execution counts since `enable_profiling`, `None` when disabled
+    #[cfg(all(feature = "unstable", feature = "alloc"))]
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
 
-        writeln!(f, "pc: {}", self.pc)?;
-        writeln!(f, "ram[{pc_ram_start:03x}..{pc_ram_end:03x}]: {memory_at_pc:02x?}")?;
-        writeln!(f, "i: {}", self.index)?;
-        writeln!(f, "ram[{i_ram_start:03x}..{i_ram_end:03x}]: {memory_at_index:02x?}")?;
-        writeln!(f, "sp: {}", self.stack_pointer)?;
-        writeln!(f, "stack: {:?}", self.stack)?;
-        writeln!(f, "dt: {:02x}", self.delay_timer)?;
-        writeln!(f, "st: {:02x}", self.sound_timer)?;
-        writeln!(f, "registers: {:02x?}", self.registers)?;
-        write!(f, "screen:\n{}", self.screen)?;
+    /// Starts caching the decoded instructions per address, along with their instruction in
+    /// `instruction_set`, from an empty cache
+    ///
+    /// Entries are checked against the RAM before being reused, so the cache is kept across resets and
+    /// survives self-modifying code. Patches still take over the cached instructions, but replacing an
+    /// instruction of `instruction_set` requires enabling the cache again.
+    #[cfg(all(feature = "unstable", feature = "alloc"))]
+    pub fn enable_decode_cache(&mut self) {
+        self.decode_cache = Some(DecodeCache::new());
+    }
 
-        Ok(())
+    /// Stops caching the decoded instructions, returns the cache if it was enabled
+    #[cfg(all(feature = "unstable", feature = "alloc"))]
+    pub fn disable_decode_cache(&mut self) -> Option<DecodeCache> {
+        self.decode_cache.take()
+    }
+
+    /// Instructions decoded since `enable_decode_cache`, `None` when disabled
+    #[cfg(all(feature = "unstable", feature = "alloc"))]
+    pub fn decode_cache(&self) -> Option<&DecodeCache> {
+        self.decode_cache.as_ref()
+    }
+
+    /// Instructions, draws and frames run so far, kept across resets
+    ///
+    /// Rates are derived from two readings, see `PerfCounters::since`.
+    #[cfg(feature = "unstable")]
+    pub fn perf_counters(&self) -> PerfCounters {
+        self.perf_counters
+    }
+
+    /// Starts counting the executed instructions per slot of the instruction set, from zero
+    ///
+    /// Unknown opcodes are not counted. Like the coverage map, the statistics are kept across resets.
+    #[cfg(feature = "unstable")]
+    pub fn enable_opcode_stats(&mut self) {
+        self.opcode_stats = Some(OpcodeStats::default());
+    }
+
+    /// Stops counting the executed instructions, returns the statistics if they were enabled
+    #[cfg(feature = "unstable")]
+    pub fn disable_opcode_stats(&mut self) -> Option<OpcodeStats> {
+        self.opcode_stats.take()
+    }
+
+    /// Executed instructions per slot since `enable_opcode_stats`, `None` when disabled
+    #[cfg(feature = "unstable")]
+    pub fn opcode_stats(&self) -> Option<&OpcodeStats> {
+        self.opcode_stats.as_ref()
+    }
+
+    /// Returns the PC if the next instruction is at a breakpoint the machine didn't stop at yet
+    ///
+    /// Stopping at a breakpoint doesn't execute anything, the next cycle executes the instruction,
+    /// so that the execution resumes by running the machine again.
+    #[cfg(feature = "unstable")]
+    fn check_breakpoint(&mut self) -> Option<Address> {
+        let pc = self.state.pc;
+        let about_to_fetch =
+            self.start_delay == 0 && self.state.waiting_for_key.is_none() && !self.state.waiting_for_vblank;
+
+        if !about_to_fetch {
+            return None;
+        }
+
+        // Evaluated even when stopped at this PC, so that changes are tracked instruction after instruction
+        let condition_hit = self.conditional_breakpoints.check(&self.state);
+
+        if !(self.breakpoints.contains(pc) || condition_hit) || self.stopped_at == Some(pc) {
+            return None;
+        }
+
+        self.stopped_at = Some(pc);
+        Some(pc)
+    }
+
+    /// Runs a cycle without updating the timers
+    fn step(
+        &mut self,
+        pre: &mut impl FnMut(&State, OpCode),
+        post: &mut impl FnMut(&State, OpCode),
+    ) -> Result<CycleOutcome, MachineError> {
+        self.state.screen.reset_changed_flag();
+
+        if self.start_delay > 0 {
+            self.start_delay -= 1;
+            return Ok(CycleOutcome::Waiting);
+        }
+
+        if self.state.waiting_for_key.is_some() {
+            self.state.poll_key();
+            return Ok(CycleOutcome::Waiting);
+        }
+
+        if self.state.waiting_for_vblank {
+            return Ok(CycleOutcome::Waiting);
+        }
+
+        let pc = self.state.pc;
+        #[cfg(feature = "unstable")]
+        {
+            self.stopped_at = None;
+        }
+        let opcode = match self.fetch_opcode() {
+            Ok(opcode) => opcode,
+            Err(_) => return Err(self.fault(MachineError::MemoryOutOfBounds { pc })),
+        };
+        event!(%pc, ?opcode, "fetch");
+
+        #[cfg(feature = "unstable")]
+        if let Some(coverage) = &mut self.coverage {
+            coverage.mark(pc);
+        }
+
+        #[cfg(all(feature = "unstable", feature = "alloc"))]
+        if let Some(profile) = &mut self.profile {
+            profile.record(pc);
+        }
+
+        #[cfg(feature = "history")]
+        self.history.record(pc, opcode);
+
+        // The slot is only needed by the diagnostics and the patches
+        #[cfg_attr(not(any(feature = "unstable", feature = "log")), allow(unused_variables))]
+        let (slot, instruction) = match self.decode(pc, opcode) {
+            Ok((slot, instruction)) => (Some(slot), Some(instruction)),
+            // Machine routine, with no slot of its own
+            Err(_) if self.host_call.is_some() && opcode.get_first_nibble() == 0 => (None, None),
+            // The faulty instruction is skipped
+            Err(_) => return Err(self.fault(MachineError::UnknownOpCode { pc, opcode })),
+        };
+        event!(%pc, ?opcode, ?slot, "decode");
+
+        #[cfg(feature = "unstable")]
+        if let (Some(stats), Some(slot)) = (&mut self.opcode_stats, slot) {
+            stats.record(slot);
+        }
+
+        let host_call = self.host_call;
+        let routine = |opcode: OpCode, state: &mut State| match host_call.map(|call| call(opcode.get_nnn(), state)) {
+            Some(HostCallOutcome::Continue) => {}
+            // Jumping to itself halts the machine
+            Some(HostCallOutcome::Halt) => state.pc = pc,
+            Some(HostCallOutcome::Unknown) | None => state.raise(Fault::UnknownOpCode),
+        };
+
+        // Patches run in place of the instruction set
+        #[cfg(all(feature = "unstable", feature = "alloc"))]
+        let patch: Option<&dyn Instruction> = slot.and_then(|slot| self.patches.get(slot));
+        #[cfg(not(all(feature = "unstable", feature = "alloc")))]
+        let patch: Option<&dyn Instruction> = None;
+
+        let execute = |state: &mut State| match (patch, instruction) {
+            (Some(patch), _) => patch.execute(opcode, state),
+            (None, Some(instruction)) => instruction.execute(opcode, state),
+            (None, None) => routine(opcode, state),
+        };
+        // Only the accesses of the instruction itself are watched, not fetching it
+        #[cfg(feature = "unstable")]
+        self.state.watch_hit.set(None);
+
+        pre(&self.state, opcode);
+
+        #[cfg(feature = "history")]
+        let before = (self.state.registers, self.state.index);
+
+        if self.state.stack_mode == StackMode::Watched && self.stack_area_violation.is_none() {
+            let before = self.state.stack_area();
+            execute(&mut self.state);
+            let after = self.state.stack_area();
+
+            if let Some(offset) = before.iter().zip(after.iter()).position(|(a, b)| a != b) {
+                // The stack area is 32 bytes long, the offset fits in an u16
+                let addr = VIP_STACK_AREA_START + offset as u16;
+                self.stack_area_violation = Some(StackAreaViolation { pc, addr });
+            }
+        } else {
+            execute(&mut self.state);
+        }
+
+        #[cfg(feature = "history")]
+        self.history
+            .record_changes(before, (self.state.registers, self.state.index));
+
+        post(&self.state, opcode);
+
+        #[cfg(feature = "defmt")]
+        defmt::trace!("{} {}", pc, opcode);
+
+        event!(%pc, ?opcode, next_pc = %self.state.pc, vf = self.state.reg_read(RegIdent::VF), "execute");
+
+        #[cfg(feature = "unstable")]
+        {
+            self.perf_counters.instructions += 1;
+        }
+
+        if self.state.screen.is_changed() {
+            #[cfg(feature = "unstable")]
+            {
+                self.perf_counters.draws += 1;
+            }
+            event!(%pc, ?opcode, "draw");
+        }
+
+        let fault = self.state.fault.take();
+
+        // Only an instruction completing without a fault ends a streak of faults
+        if fault.is_none() {
+            self.consecutive_faults = 0;
+        }
+
+        match fault {
+            Some(Fault::UnknownOpCode) => Err(self.fault(MachineError::UnknownOpCode { pc, opcode })),
+            Some(Fault::StackOverflow) => Err(self.fault(MachineError::StackOverflow { pc })),
+            Some(Fault::StackUnderflow) => Err(self.fault(MachineError::StackUnderflow { pc })),
+            Some(Fault::MemoryOutOfBounds) => Err(self.fault(MachineError::MemoryOutOfBounds { pc })),
+            // Nothing can break an instruction jumping to itself, the ROM is over
+            None if self.state.pc == pc => {
+                #[cfg(feature = "defmt")]
+                defmt::info!("halted at {}", pc);
+
+                self.halted = true;
+                Ok(CycleOutcome::Halted)
+            }
+            #[cfg(feature = "unstable")]
+            None => match self.state.watch_hit.take() {
+                Some((addr, access)) => Ok(CycleOutcome::WatchpointHit(WatchpointHit {
+                    pc,
+                    opcode,
+                    addr,
+                    access,
+                })),
+                None => Ok(CycleOutcome::Executed(opcode)),
+            },
+            #[cfg(not(feature = "unstable"))]
+            None => Ok(CycleOutcome::Executed(opcode)),
+        }
+    }
+
+    /// Finds the slot of the instruction fetched at `pc` and its instruction in the set, from the
+    /// decode cache when enabled
+    #[cfg_attr(not(all(feature = "unstable", feature = "alloc")), allow(unused_variables))]
+    fn decode(
+        &mut self,
+        pc: Address,
+        opcode: OpCode,
+    ) -> Result<(usize, &'static dyn Instruction), UnknownInstructionError> {
+        #[cfg(all(feature = "unstable", feature = "alloc"))]
+        if let Some(cache) = &mut self.decode_cache {
+            return cache
+                .decode(pc, opcode, &self.instruction_set)
+                .map(|cached| (cached.slot, cached.instruction));
+        }
+
+        let slot = decode_slot(opcode)?;
+        Ok((
+            slot,
+            self.instruction_set.get(slot).expect("a slot of the instruction set"),
+        ))
+    }
+
+    fn fault(&mut self, error: MachineError) -> MachineError {
+        #[cfg(feature = "history")]
+        {
+            self.fault_history = Some(self.history.clone());
+        }
+
+        self.consecutive_faults += 1;
+
+        let resume = match self.fault_policy {
+            FaultPolicy::Halt => false,
+            FaultPolicy::IgnoreAndContinue => true,
+            FaultPolicy::TrapToHandler(handler) => handler(&mut self.state, &error),
+        };
+
+        self.halted = !resume || self.consecutive_faults >= self.limits.max_consecutive_faults;
+
+        #[cfg(feature = "defmt")]
+        defmt::warn!("{} (halted: {})", error, self.halted);
+
+        error
+    }
+
+    /// Signals the vertical blank, to be called by the frontend at the start of each 60 Hz frame
+    ///
+    /// Resumes the execution suspended by draw instructions with display wait.
+    pub fn vblank(&mut self) {
+        self.state.waiting_for_vblank = false;
+    }
+
+    /// Runs the given number of cycles, within the limits
+    ///
+    /// Returns the number of cycles actually run, which is lower than requested when
+    /// `Limits::max_cycles_per_call` is exceeded, when the machine halts or stops at a breakpoint or a
+    /// watchpoint.
+    pub fn run_cycles(&mut self, count: usize) -> usize {
+        let count = core::cmp::min(count, self.limits.max_cycles_per_call);
+
+        for run in 0..count {
+            if self.halted {
+                return run;
+            }
+
+            // Faults are accounted for by the fault counter, halting the machine if needed
+            match self.cycle() {
+                #[cfg(feature = "unstable")]
+                Ok(CycleOutcome::BreakpointHit { .. }) => return run,
+                #[cfg(feature = "unstable")]
+                Ok(CycleOutcome::WatchpointHit(_)) => return run + 1,
+                _ => {}
+            }
+        }
+
+        count
+    }
+
+    /// Runs as fast as possible until the first draw (DXYN) or input poll (EX9E, EXA1 or FX0A)
+    ///
+    /// Meant for tools grabbing a representative "title screen" without emulating at realtime pace,
+    /// such as thumbnailers. At most `max_cycles` cycles are run, within the limits. The vertical
+    /// blank is signaled every `frequency_hz / 60` cycles, like `run_frame` does, so that draws with
+    /// display wait and timers behave as in realtime.
+    pub fn run_until_first_draw(&mut self, max_cycles: usize) -> FastStart {
+        use crate::instruction::{OP_DXYN, OP_EX9E, OP_EXA1, OP_FX0A};
+
+        let cycles_per_frame = core::cmp::max(self.frequency_hz / 60, 1);
+        let max_cycles = core::cmp::min(max_cycles, self.limits.max_cycles_per_call);
+
+        for cycles in 0..max_cycles {
+            let stop = match self.fast_cycle(cycles, cycles_per_frame) {
+                Ok(CycleOutcome::Executed(opcode)) => match decode_slot(opcode) {
+                    Ok(OP_DXYN) => Some(FastStartStop::Draw),
+                    Ok(OP_EX9E | OP_EXA1 | OP_FX0A) => Some(FastStartStop::InputPoll),
+                    _ => None,
+                },
+                Ok(CycleOutcome::Halted) => Some(FastStartStop::Halted),
+                _ => None,
+            };
+
+            if let Some(stop) = stop {
+                return FastStart {
+                    stop,
+                    cycles: cycles + 1,
+                };
+            }
+        }
+
+        FastStart {
+            stop: FastStartStop::CycleLimit,
+            cycles: max_cycles,
+        }
+    }
+
+    /// Runs as fast as possible until the screen changes (draw or clear), returns the number of cycles run
+    ///
+    /// Meant for tools capturing the next visual update, such as thumbnailers or tests asserting when
+    /// drawing happens. At most `max_cycles` cycles are run, within the limits, and the vertical blank
+    /// is signaled as with `run_until_first_draw`. `Screen::is_changed` tells whether the last cycle
+    /// run changed the screen, rather than the limit being reached or the machine halting.
+    pub fn run_until_screen_change(&mut self, max_cycles: usize) -> usize {
+        let cycles_per_frame = core::cmp::max(self.frequency_hz / 60, 1);
+        let max_cycles = core::cmp::min(max_cycles, self.limits.max_cycles_per_call);
+
+        for cycles in 0..max_cycles {
+            if self.halted {
+                return cycles;
+            }
+
+            let _ = self.fast_cycle(cycles, cycles_per_frame);
+
+            if self.state.screen.is_changed() {
+                return cycles + 1;
+            }
+        }
+
+        max_cycles
+    }
+
+    /// Number of subroutines being run, increased by 2NNN and decreased by 00EE
+    pub fn call_depth(&self) -> usize {
+        self.state.stack_len()
+    }
+
+    /// Executes the next instruction, or the whole subroutine if it is a call (2NNN)
+    ///
+    /// Breakpoints, watchpoints and faults within the subroutine stop the step early. At most
+    /// `Limits::max_cycles_per_call` cycles are run.
+    #[cfg(feature = "unstable")]
+    pub fn step_over(&mut self) -> Step {
+        use crate::instruction::OP_2NNN;
+
+        let depth = self.call_depth();
+
+        if self.peek_opcode().and_then(|next| decode_slot(next).ok()) == Some(OP_2NNN) {
+            self.step_until(|machine| machine.call_depth() <= depth)
+        } else {
+            self.step_until(|_| true)
+        }
+    }
+
+    /// Runs until the current subroutine returns, that is until the call depth decreases
+    ///
+    /// Stops early like `step_over`, and only at the cycle limit when no subroutine is running.
+    #[cfg(feature = "unstable")]
+    pub fn step_out(&mut self) -> Step {
+        let depth = self.call_depth();
+        self.step_until(|machine| machine.call_depth() < depth)
+    }
+
+    /// Runs until an executed instruction leaves the machine in the expected state
+    ///
+    /// The vertical blank is signaled every `frequency_hz / 60` cycles, so that display wait
+    /// doesn't suspend the step forever.
+    #[cfg(feature = "unstable")]
+    fn step_until(&mut self, done: impl Fn(&Self) -> bool) -> Step {
+        let cycles_per_frame = core::cmp::max(self.frequency_hz / 60, 1);
+        let max_cycles = self.limits.max_cycles_per_call;
+
+        for cycles in 0..max_cycles {
+            if cycles > 0 && cycles % cycles_per_frame == 0 {
+                self.vblank();
+            }
+
+            let stop = match self.cycle() {
+                Ok(CycleOutcome::Executed(_)) if done(self) => Some(StepStop::Completed),
+                Ok(CycleOutcome::Executed(_) | CycleOutcome::Waiting) => None,
+                Ok(CycleOutcome::BreakpointHit { pc }) => Some(StepStop::Breakpoint(pc)),
+                Ok(CycleOutcome::WatchpointHit(hit)) => Some(StepStop::Watchpoint(hit)),
+                Ok(CycleOutcome::Halted) => Some(StepStop::Halted),
+                Err(error) => Some(StepStop::Fault(error)),
+            };
+
+            if let Some(stop) = stop {
+                return Step {
+                    stop,
+                    cycles: cycles + 1,
+                };
+            }
+        }
+
+        Step {
+            stop: StepStop::CycleLimit,
+            cycles: max_cycles,
+        }
+    }
+
+    /// Runs a cycle, signaling the vertical blank every `cycles_per_frame` cycles
+    fn fast_cycle(&mut self, cycles: usize, cycles_per_frame: usize) -> Result<CycleOutcome, MachineError> {
+        if cycles % cycles_per_frame == 0 {
+            self.vblank();
+
+            if self.timer_clock == TimerClock::External {
+                self.tick_timers();
+            }
+        }
+
+        // Faults are accounted for by the fault counter, halting the machine if needed
+        self.cycle()
+    }
+
+    /// Checks nothing but the ROM, the configuration and the inputs can influence the execution
+    ///
+    /// See the determinism section of `Machine`, inputs changing from within frames can't be detected.
+    pub fn check_deterministic(&self) -> Result<(), NonDeterminism> {
+        #[cfg(feature = "alloc")]
+        if matches!(self.state.rng, MachineRng::Custom(_)) {
+            return Err(NonDeterminism::CustomRng);
+        }
+
+        if self.timer_clock == TimerClock::External {
+            return Err(NonDeterminism::ExternalTimerClock);
+        }
+
+        Ok(())
+    }
+
+    /// Hash of the emulated hardware and of the timer scheduling, typically compared after each frame
+    ///
+    /// Equal hashes mean, barring collisions, that two deterministic runs are still in sync. The
+    /// state of the random number generator isn't hashed: a divergence shows up on the next draw.
+    pub fn state_hash(&self) -> u64 {
+        const FNV_OFFSET_BASIS: u64 = 0xCBF2_9CE4_8422_2325;
+        const FNV_PRIME: u64 = 0x0100_0000_01B3;
+
+        let timer_accumulator = (self.timer_accumulator as u64).to_be_bytes();
+
+        // FNV-1a, stable across platforms and releases unlike `core::hash::Hasher` implementations
+        self.snapshot()
+            .to_bytes()
+            .iter()
+            .chain(&timer_accumulator)
+            .chain(&[u8::from(self.halted)])
+            .fold(FNV_OFFSET_BASIS, |hash, byte| {
+                (hash ^ u64::from(*byte)).wrapping_mul(FNV_PRIME)
+            })
+    }
+
+    pub fn update_counter(&mut self) {
+        self.counter += 1;
+
+        if self.timer_clock == TimerClock::External {
+            return;
+        }
+
+        // Each cycle lasts 60 / `frequency_hz` timer periods, accumulated without any rounding
+        let period = core::cmp::max(self.frequency_hz, 1);
+        self.timer_accumulator += 60;
+
+        while self.timer_accumulator >= period {
+            self.timer_accumulator -= period;
+            self.tick_timers();
+        }
+    }
+
+    /// Decrements the delay and sound timers, meant to be called at 60 Hz with `TimerClock::External`
+    pub fn tick_timers(&mut self) {
+        self.state.delay_timer = self.state.delay_timer.saturating_sub(1);
+        self.state.sound_timer = self.state.sound_timer.saturating_sub(1);
+        event!(
+            delay_timer = self.state.delay_timer,
+            sound_timer = self.state.sound_timer,
+            "timer tick"
+        );
+    }
+
+    /// Fetches the opcode at the program counter, which is advanced even when the fetch faults
+    fn fetch_opcode(&mut self) -> Result<OpCode, Fault> {
+        let first = self.state.ram_read(self.state.pc, 0);
+        let second = self.state.ram_read(self.state.pc, 1);
+        self.state.pc += 2;
+        Ok(OpCode::new(u16::from_be_bytes([first?, second?])))
+    }
+
+    /// Opcode at the program counter, read from the RAM under any peripheral so that neither the
+    /// peripherals, the watchpoints nor the memory observer notice an instruction which didn't run
+    #[cfg(feature = "unstable")]
+    fn peek_opcode(&self) -> Option<OpCode> {
+        let first = self.state.ram_address(self.state.pc, 0).ok()?;
+        let second = self.state.ram_address(self.state.pc, 1).ok()?;
+        Some(OpCode::new(u16::from_be_bytes([
+            self.state.ram[first],
+            self.state.ram[second],
+        ])))
+    }
+}
+
+impl fmt::Display for Machine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "------------ Machine ------------")?;
+        write!(f, "{}", self.state)
+    }
+}
+
+/// What happened during a frame run by `Machine::run_frame`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameSummary {
+    /// Number of cycles run
+    pub cycles: usize,
+    /// The screen changed at least once during the frame
+    pub screen_changed: bool,
+    /// The sound timer is still running at the end of the frame
+    pub beeping: bool,
+    pub halted: bool,
+    /// Breakpoint which stopped the frame early, see `Machine::add_breakpoint`
+    #[cfg(feature = "unstable")]
+    pub breakpoint: Option<Address>,
+    /// Watchpoint which stopped the frame early, see `Machine::add_watchpoint`
+    #[cfg(feature = "unstable")]
+    pub watchpoint: Option<WatchpointHit>,
+}
+
+/// What can make a machine non deterministic, see `Machine::check_deterministic`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum NonDeterminism {
+    /// A custom random number generator may draw from the host
+    CustomRng,
+    /// Timers are ticked by the frontend, usually on its own clock
+    ExternalTimerClock,
+}
+
+impl fmt::Display for NonDeterminism {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NonDeterminism::CustomRng => write!(f, "a custom random number generator is used"),
+            NonDeterminism::ExternalTimerClock => write!(f, "timers are ticked by the frontend"),
+        }
+    }
+}
+
+/// Where `Machine::run_until_first_draw` stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FastStart {
+    pub stop: FastStartStop,
+    /// Number of cycles run, including the one of the draw or input poll
+    pub cycles: usize,
+}
+
+/// Reason `Machine::run_until_first_draw` stopped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FastStartStop {
+    /// A DXYN instruction was executed
+    Draw,
+    /// An EX9E, EXA1 or FX0A instruction was executed
+    InputPoll,
+    /// The cycle limit was reached without drawing nor polling the input
+    CycleLimit,
+    /// The machine halted before drawing or polling the input
+    Halted,
+}
+
+/// Where `Machine::step_over` or `Machine::step_out` stopped
+#[cfg(feature = "unstable")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Step {
+    pub stop: StepStop,
+    /// Number of cycles run, including the one which stopped the step
+    pub cycles: usize,
+}
+
+/// Reason a step stopped
+#[cfg(feature = "unstable")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StepStop {
+    /// The step is over
+    Completed,
+    /// The PC reached a breakpoint, whose instruction isn't executed yet
+    Breakpoint(Address),
+    Watchpoint(WatchpointHit),
+    Fault(MachineError),
+    Halted,
+    /// `Limits::max_cycles_per_call` cycles were run
+    CycleLimit,
+}
+
+/// What a successful cycle did
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CycleOutcome {
+    /// An instruction was executed
+    Executed(OpCode),
+    /// No instruction was executed: start delay, waiting for a key or for the vertical blank
+    Waiting,
+    /// The machine is halted and doesn't execute instructions anymore, either because of a fault or
+    /// because the ROM jumped to itself
+    Halted,
+    /// No instruction was executed: the PC reached a breakpoint, the next cycle executes the instruction
+    #[cfg(feature = "unstable")]
+    BreakpointHit { pc: Address },
+    /// An instruction was executed and accessed watched memory
+    #[cfg(feature = "unstable")]
+    WatchpointHit(WatchpointHit),
+}
+
+/// A fault interrupting the execution of an instruction
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MachineError {
+    /// The opcode doesn't match any instruction
+    UnknownOpCode { pc: Address, opcode: OpCode },
+    /// A subroutine was called while the stack is full
+    StackOverflow { pc: Address },
+    /// Returned from a subroutine while the stack is empty
+    StackUnderflow { pc: Address },
+    /// Accessed memory past the end of the RAM with `MemoryBounds::Fault`
+    MemoryOutOfBounds { pc: Address },
+}
+
+impl fmt::Display for MachineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MachineError::UnknownOpCode { pc, opcode } => {
+                write!(f, "unknown opcode {:04x} at {pc}", opcode.get_inner())
+            }
+            MachineError::StackOverflow { pc } => write!(f, "stack overflow at {pc}"),
+            MachineError::StackUnderflow { pc } => write!(f, "stack underflow at {pc}"),
+            MachineError::MemoryOutOfBounds { pc } => write!(f, "out-of-bounds memory access at {pc}"),
+        }
+    }
+}
+
+/// Handles a fault, returns whether the execution should resume
+///
+/// The state can be modified, for instance to jump to a recovery routine of the ROM.
+pub type TrapHandler = fn(&mut State, &MachineError) -> bool;
+
+/// Runs the machine routine at the address given by a 0NNN opcode, on behalf of the ROM
+///
+/// 0NNN called routines of the host machine on the original interpreters, and is unused by CHIP-8
+/// programs. Embedders can give it a meaning of their own, such as printing to the host console,
+/// signaling the end of a test ROM or driving custom peripherals, and assemble the calls with `SYS`.
+/// Only the opcodes which the decoder doesn't know reach the handler, so 00E0, 00EE and 00F1 keep
+/// their meaning.
+pub type HostCall = fn(Address, &mut State) -> HostCallOutcome;
+
+/// What the machine does after a machine routine, see `HostCall`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostCallOutcome {
+    /// Resumes the execution with the next instruction
+    Continue,
+    /// Halts the machine, like a ROM jumping to itself
+    Halt,
+    /// Faults like an unknown instruction, the machine then applies its fault policy
+    Unknown,
+}
+
+/// What the machine does after a fault, the faulty instruction being skipped in any case
+///
+/// The machine always halts once `Limits::max_consecutive_faults` is reached, whatever the policy.
+#[derive(Clone, Copy, Default)]
+pub enum FaultPolicy {
+    /// Halts on the first fault, to fail fast
+    Halt,
+    /// Resumes the execution with the next instruction
+    #[default]
+    IgnoreAndContinue,
+    /// Lets the handler decide, after giving it a chance to alter the state
+    TrapToHandler(TrapHandler),
+}
+
+/// Source of the 60 Hz ticks decrementing the delay and sound timers
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum TimerClock {
+    /// Timers tick every `frequency_hz / 60` cycles
+    #[default]
+    Cycles,
+    /// Timers only tick when `Machine::tick_timers` is called, typically by the frontend on vsync
+    External,
+}
+
+/// Hard limits guaranteeing a machine can't monopolize its host, even when running adversarial ROMs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Limits {
+    /// Maximum number of cycles run by a single call to a `run_*` method
+    pub max_cycles_per_call: usize,
+    /// Number of faults in a row after which the machine is halted
+    pub max_consecutive_faults: usize,
+}
+
+impl Default for Limits {
+    /// No limit, faults being handled by the `FaultPolicy` alone
+    fn default() -> Self {
+        Self {
+            max_cycles_per_call: usize::MAX,
+            max_consecutive_faults: usize::MAX,
+        }
+    }
+}
+
+/// Power-on configuration of the machine
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PowerOn {
+    /// Pattern RAM is filled with before the font and the game code are loaded
+    pub ram_pattern: RamPattern,
+    /// Number of cycles to idle (timers are still running) before executing the first instruction
+    pub start_delay: usize,
+}
+
+/// Initial content of the RAM
+///
+/// Real hardware didn't boot with zeroed RAM, non-zero patterns help flushing out ROMs relying on
+/// uninitialized memory.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum RamPattern {
+    /// Every byte is 0x00
+    #[default]
+    Zeroed,
+    /// Every byte is set to the given value
+    Filled(u8),
+    /// Pseudo-random bytes, always the same for a given seed
+    Random { seed: u64 },
+}
+
+impl RamPattern {
+    fn fill(self, ram: &mut [u8]) {
+        match self {
+            RamPattern::Zeroed => ram.fill(0),
+            RamPattern::Filled(value) => ram.fill(value),
+            RamPattern::Random { seed } => {
+                // splitmix64
+                let mut state = seed;
+
+                ram.chunks_mut(8).for_each(|chunk| {
+                    state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+                    let mut z = state;
+                    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+                    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+                    z ^= z >> 31;
+
+                    chunk.copy_from_slice(&z.to_le_bytes()[..chunk.len()]);
+                });
+            }
+        }
+    }
+}
+
+/// Start of the RAM area used by the COSMAC VIP interpreter to store return addresses
+pub const VIP_STACK_AREA_START: Address = Address(0xEA0);
+
+/// Length of the VIP stack area, in bytes (two bytes per stack slot)
+pub const VIP_STACK_AREA_LEN: usize = STACK_SIZE * 2;
+
+/// Maximum depth of the stack
+pub const STACK_SIZE: usize = 16;
+
+/// Address of a slot of the VIP stack area
+fn vip_stack_slot(slot: usize) -> Address {
+    // Slots are below `STACK_SIZE`, the offset fits in an u16
+    VIP_STACK_AREA_START + (slot * 2) as u16
+}
+
+/// Where the return addresses of subroutines are stored
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StackMode {
+    /// Dedicated storage, outside of the addressable RAM
+    #[default]
+    Internal,
+    /// Dedicated storage, and writes to the VIP stack area in RAM are reported
+    ///
+    /// Useful to detect ROMs accidentally overwriting memory the original interpreter relied on.
+    Watched,
+    /// Stored in the VIP stack area of the RAM, like on the original COSMAC VIP
+    ///
+    /// ROMs deliberately manipulating the stack through memory operations work in this mode.
+    MemoryMapped,
+}
+
+/// What happens to memory accesses running past the end of the RAM
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MemoryBounds {
+    /// Accesses wrap around to the start of the RAM
+    #[default]
+    Wrap,
+    /// Accesses raise `Fault::MemoryOutOfBounds`, to catch ROMs relying on it by accident
+    Fault,
+}
+
+/// A write to the VIP stack area while the stack mode is `StackMode::Watched`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct StackAreaViolation {
+    /// Address of the offending instruction
+    pub pc: Address,
+    /// First modified address in the stack area
+    pub addr: Address,
+}
+
+#[derive(Clone)]
+pub struct State {
+    /// Memory: 4 kB (or 4096 bytes) of RAM
+    pub ram: [u8; RAM_SIZE],
+    /// Behavior of the `ram_*` accessors past the end of the RAM
+    pub memory_bounds: MemoryBounds,
+    /// Program counter, points at the current instruction in memory
+    pub pc: Address,
+    // Index register pointing at location of a sprite when drawing
+    pub index: Address,
+    /// Address of the font sprites, used by FX29
+    pub font_address: Address,
+    /// Stores return addresses when calling subroutines (unused when the stack is memory-mapped)
+    stack: [Address; STACK_SIZE],
+    /// Where return addresses are stored, should not be changed while a subroutine is running
+    pub stack_mode: StackMode,
+    /// Stack pointer, points to the next available slot in the stack
+    stack_pointer: u8,
+    /// Number of return addresses the stack can hold, at most `STACK_SIZE` (12 on the COSMAC VIP)
+    pub stack_depth: usize,
+    /// Delay timer register, will be decremented at a rate of 60 Hz until 0 is reached
+    pub delay_timer: u8,
+    /// Sound timer register, a "beep" will be produced until it reaches 0
+    pub sound_timer: u8,
+    /// General-purpose registers
+    registers: [u8; 16],
+    /// Chip8 Screen
+    pub screen: Screen,
+    /// Hexadecimal keypad
+    pub keypad: Keypad,
+    /// Set when the execution is suspended until a key is pressed
+    pub waiting_for_key: Option<WaitingForKey>,
+    /// Set when the execution is suspended until the next vertical blank (display wait)
+    pub waiting_for_vblank: bool,
+    /// Random number generator
+    pub rng: MachineRng,
+    /// Fault raised by the instruction being executed
+    fault: Option<Fault>,
+    /// RAM ranges whose accesses through the `ram_*` accessors stop the execution
+    #[cfg(feature = "unstable")]
+    pub watchpoints: Watchpoints,
+    /// First watched access of the instruction being executed
+    #[cfg(feature = "unstable")]
+    watch_hit: Cell<Option<(Address, Access)>>,
+    /// Devices answering the `ram_read` and `ram_write` accessors in place of the RAM they are
+    /// mapped onto
+    #[cfg(all(feature = "unstable", feature = "alloc"))]
+    pub peripherals: Peripherals,
+    /// Sees every access made through the `ram_*` accessors, shared with the host to read back what
+    /// it gathered
+    #[cfg(all(feature = "unstable", feature = "alloc"))]
+    pub memory_observer: Option<Rc<RefCell<dyn MemoryObserver>>>,
+}
+
+/// A fault raised by an instruction, reported by the machine as a `MachineError`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Fault {
+    /// The instruction isn't supported by the instruction set
+    UnknownOpCode,
+    /// Pushed to a full stack
+    StackOverflow,
+    /// Popped from an empty stack
+    StackUnderflow,
+    /// Accessed memory past the end of the RAM with `MemoryBounds::Fault`
+    MemoryOutOfBounds,
+}
+
+impl State {
+    fn new(game_code: &[u8], ram_pattern: RamPattern, layout: MemoryLayout) -> Self {
+        use crate::font;
+
+        let mut ram = [0; RAM_SIZE];
+
+        ram_pattern.fill(&mut ram);
+
+        let font_start = layout.font.as_usize();
+        ram[font_start..font_start + font::STANDARD.len()].copy_from_slice(font::STANDARD);
+        let entry = layout.entry.as_usize();
+        ram[entry..entry + game_code.len()].copy_from_slice(game_code);
+
+        Self {
+            ram,
+            memory_bounds: MemoryBounds::default(),
+            pc: layout.entry,
+            index: Address(0),
+            font_address: layout.font,
+            stack: [Address(0); STACK_SIZE],
+            stack_mode: StackMode::default(),
+            stack_pointer: 0,
+            stack_depth: STACK_SIZE,
+            delay_timer: 0,
+            sound_timer: 0,
+            registers: [0; 16],
+            screen: Screen::default(),
+            keypad: Keypad::default(),
+            waiting_for_key: None,
+            waiting_for_vblank: false,
+            rng: MachineRng::default(),
+            fault: None,
+            #[cfg(feature = "unstable")]
+            watchpoints: Watchpoints::default(),
+            #[cfg(feature = "unstable")]
+            watch_hit: Cell::new(None),
+            #[cfg(all(feature = "unstable", feature = "alloc"))]
+            peripherals: Peripherals::default(),
+            #[cfg(all(feature = "unstable", feature = "alloc"))]
+            memory_observer: None,
+        }
+    }
+}
+
+impl State {
+    /// Pushes a return address, the stack is left untouched when full
+    pub fn stack_push(&mut self, value: Address) -> Result<(), Fault> {
+        let slot = usize::from(self.stack_pointer);
+
+        if slot >= self.stack_capacity() {
+            return Err(Fault::StackOverflow);
+        }
+
+        // Written like any other memory access, so that peripherals, watchpoints and the memory
+        // observer see it
+        if self.stack_mode == StackMode::MemoryMapped {
+            let [high, low] = value.0.to_be_bytes();
+            self.ram_write(vip_stack_slot(slot), 0, high)?;
+            self.ram_write(vip_stack_slot(slot), 1, low)?;
+        }
+
+        // Also kept internally with a memory-mapped stack, for `stack_frames`
+        self.stack[slot] = value;
+
+        self.stack_pointer += 1;
+
+        Ok(())
+    }
+
+    /// Number of return addresses on the stack
+    pub fn stack_len(&self) -> usize {
+        usize::from(self.stack_pointer)
+    }
+
+    /// Number of return addresses the stack can hold
+    pub fn stack_capacity(&self) -> usize {
+        core::cmp::min(self.stack_depth, STACK_SIZE)
+    }
+
+    /// Return addresses on the stack, the innermost call last
+    ///
+    /// With `StackMode::MemoryMapped`, these are the addresses as pushed: a ROM patching the stack
+    /// area in RAM only changes what `stack_pop` returns.
+    pub fn stack_frames(&self) -> &[Address] {
+        &self.stack[..self.stack_len()]
+    }
+
+    pub fn stack_pop(&mut self) -> Result<Address, Fault> {
+        if self.stack_pointer == 0 {
+            return Err(Fault::StackUnderflow);
+        }
+
+        self.stack_pointer -= 1;
+        let slot = usize::from(self.stack_pointer);
+
+        match self.stack_mode {
+            StackMode::Internal | StackMode::Watched => Ok(self.stack[slot]),
+            StackMode::MemoryMapped => {
+                let high = self.ram_read(vip_stack_slot(slot), 0)?;
+                let low = self.ram_read(vip_stack_slot(slot), 1)?;
+                Ok(Address::new_masked(u16::from_be_bytes([high, low])))
+            }
+        }
+    }
+
+    /// Reads the byte at `addr + offset`, from the peripheral mapped there if any
+    pub fn ram_read(&self, addr: Address, offset: u16) -> Result<u8, Fault> {
+        let addr = self.ram_address(addr, offset)?;
+        #[cfg(feature = "unstable")]
+        self.watch(addr, 1, Access::Read);
+
+        #[cfg(all(feature = "unstable", feature = "alloc"))]
+        let value = match self.peripherals.get(addr) {
+            Some(peripheral) => peripheral.read(addr),
+            None => self.ram[addr],
+        };
+        #[cfg(not(all(feature = "unstable", feature = "alloc")))]
+        let value = self.ram[addr];
+
+        #[cfg(feature = "unstable")]
+        self.observe(addr, value, Access::Read);
+        Ok(value)
+    }
+
+    /// Writes the byte at `addr + offset`, to the peripheral mapped there if any
+    pub fn ram_write(&mut self, addr: Address, offset: u16, value: u8) -> Result<(), Fault> {
+        let addr = self.ram_address(addr, offset)?;
+        #[cfg(feature = "unstable")]
+        {
+            self.watch(addr, 1, Access::Write);
+            self.observe(addr, value, Access::Write);
+        }
+
+        #[cfg(all(feature = "unstable", feature = "alloc"))]
+        if let Some(peripheral) = self.peripherals.get_mut(addr) {
+            peripheral.write(addr, value);
+            return Ok(());
+        }
+
+        self.ram[addr] = value;
+        Ok(())
+    }
+
+    /// Bytes from `addr` to `addr + len` (excluded), straight from the RAM even where peripherals
+    /// are mapped
+    ///
+    /// Slices can't wrap around, `Fault::MemoryOutOfBounds` is returned past the end of the RAM
+    /// whatever the memory bounds.
+    pub fn ram_slice(&self, addr: Address, len: u16) -> Result<&[u8], Fault> {
+        let start = addr.as_usize();
+        let slice = self
+            .ram
+            .get(start..start + usize::from(len))
+            .ok_or(Fault::MemoryOutOfBounds)?;
+
+        #[cfg(feature = "unstable")]
+        {
+            self.watch(addr, len, Access::Read);
+
+            for (addr, &value) in (0..len).map(|offset| addr + offset).zip(slice) {
+                self.observe(addr, value, Access::Read);
+            }
+        }
+
+        Ok(slice)
+    }
+
+    /// Reports the access to the memory observer
+    #[cfg(feature = "unstable")]
+    #[cfg_attr(not(all(feature = "unstable", feature = "alloc")), allow(unused_variables))]
+    fn observe(&self, addr: Address, value: u8, access: Access) {
+        #[cfg(all(feature = "unstable", feature = "alloc"))]
+        if let Some(observer) = &self.memory_observer {
+            observer.borrow_mut().observe(addr, value, access);
+        }
+    }
+
+    /// Records the first access of the instruction hitting a watchpoint
+    #[cfg(feature = "unstable")]
+    fn watch(&self, addr: Address, len: u16, access: Access) {
+        if self.watch_hit.get().is_none() && self.watchpoints.hit(addr.as_usize(), usize::from(len), access) {
+            let first = (0..len)
+                .map(|offset| addr + offset)
+                .find(|addr| self.watchpoints.hit(addr.as_usize(), 1, access))
+                .unwrap_or(addr);
+            self.watch_hit.set(Some((first, access)));
+        }
+    }
+
+    fn ram_address(&self, addr: Address, offset: u16) -> Result<Address, Fault> {
+        let unbounded = u32::from(addr.0) + u32::from(offset);
+
+        if self.memory_bounds == MemoryBounds::Fault && unbounded > u32::from(Address::MAX) {
+            Err(Fault::MemoryOutOfBounds)
+        } else {
+            Ok(addr + offset)
+        }
+    }
+
+    /// Reports a fault of the instruction being executed, the machine then applies its fault policy
+    pub fn raise(&mut self, fault: Fault) {
+        self.fault = Some(fault);
+    }
+
+    /// Fault raised by the instruction being executed, not reported by the machine yet
+    pub fn fault(&self) -> Option<Fault> {
+        self.fault
+    }
+
+    /// Return address in a stack slot, read without going through the memory accessors
+    fn stack_slot(&self, slot: usize) -> Address {
+        match self.stack_mode {
+            StackMode::Internal | StackMode::Watched => self.stack[slot],
+            StackMode::MemoryMapped => {
+                let addr = vip_stack_slot(slot);
+                Address::new_masked(u16::from_be_bytes([self.ram[addr], self.ram[addr + 1]]))
+            }
+        }
+    }
+
+    fn stack_area(&self) -> [u8; VIP_STACK_AREA_LEN] {
+        let start = VIP_STACK_AREA_START.as_usize();
+        let mut area = [0; VIP_STACK_AREA_LEN];
+        area.copy_from_slice(&self.ram[start..start + VIP_STACK_AREA_LEN]);
+        area
+    }
+
+    /// Advances the "wait for key" sub-state according to the keypad
+    fn poll_key(&mut self) {
+        match self.waiting_for_key {
+            Some(WaitingForKey::Press { target, await_release }) => {
+                if let Some(key) = self.keypad.first_pressed() {
+                    if await_release {
+                        self.waiting_for_key = Some(WaitingForKey::Release { target, key });
+                    } else {
+                        self.reg_write(target, key.get());
+                        self.waiting_for_key = None;
+                    }
+                }
+            }
+            Some(WaitingForKey::Release { target, key }) => {
+                if !self.keypad.is_pressed(key) {
+                    self.reg_write(target, key.get());
+                    self.waiting_for_key = None;
+                }
+            }
+            None => {}
+        }
+    }
+
+    pub fn reg_write(&mut self, reg: RegIdent, value: u8) {
+        self.registers[usize::from(reg.get())] = value;
+    }
+
+    /// Writes the result of an operation, then its flag in VF
+    ///
+    /// The flag prevails when the result targets VF.
+    pub fn reg_write_with_flag(&mut self, target: RegIdent, value: u8, flag: bool) {
+        self.reg_write(target, value);
+        self.reg_write(RegIdent::VF, u8::from(flag));
+    }
+
+    pub fn reg_read(&self, reg: RegIdent) -> u8 {
+        self.registers[usize::from(reg.get())]
+    }
+}
+
+impl State {
+    /// Builds a state from scratch, typically to test instructions in isolation
+    ///
+    /// The state starts zeroed, with the standard font loaded and the program counter at 0x200.
+    pub fn builder() -> StateBuilder {
+        StateBuilder {
+            state: State::new(&[], RamPattern::Zeroed, MemoryLayout::default()),
+        }
+    }
+}
+
+/// Fluent construction of a `State`, see `State::builder`
+///
+/// Addresses are given as plain integers for brevity, and panic when they don't fit in 12 bits.
+#[derive(Clone)]
+pub struct StateBuilder {
+    state: State,
+}
+
+impl StateBuilder {
+    pub fn register(mut self, reg: RegIdent, value: u8) -> Self {
+        self.state.reg_write(reg, value);
+        self
+    }
+
+    /// Sets the registers from V0 onwards
+    pub fn registers(mut self, values: &[u8]) -> Self {
+        self.state.registers[..values.len()].copy_from_slice(values);
+        self
+    }
+
+    pub fn index(mut self, addr: u16) -> Self {
+        self.state.index = Address::new(addr).expect("a 12-bit address");
+        self
+    }
+
+    pub fn pc(mut self, addr: u16) -> Self {
+        self.state.pc = Address::new(addr).expect("a 12-bit address");
+        self
+    }
+
+    /// Copies the bytes in RAM, starting at the given address
+    pub fn ram(mut self, addr: u16, bytes: &[u8]) -> Self {
+        let start = usize::from(addr);
+        self.state.ram[start..start + bytes.len()].copy_from_slice(bytes);
+        self
+    }
+
+    /// Pushes the return addresses on the stack, in order
+    pub fn stack(mut self, addrs: &[u16]) -> Self {
+        for addr in addrs {
+            let addr = Address::new(*addr).expect("a 12-bit address");
+            self.state.stack_push(addr).expect("a stack with enough room");
+        }
+        self
+    }
+
+    /// Sets the given pixels, as (x, y) positions
+    pub fn pixels(mut self, pixels: &[(u8, u8)]) -> Self {
+        for (x, y) in pixels {
+            self.state.screen.set_pixel(*x, *y);
+        }
+        self
+    }
+
+    pub fn screen(mut self, screen: Screen) -> Self {
+        self.state.screen = screen;
+        self
+    }
+
+    pub fn pressed(mut self, keys: &[Key]) -> Self {
+        for key in keys {
+            self.state.keypad.press(*key);
+        }
+        self
+    }
+
+    pub fn delay_timer(mut self, value: u8) -> Self {
+        self.state.delay_timer = value;
+        self
+    }
+
+    pub fn sound_timer(mut self, value: u8) -> Self {
+        self.state.sound_timer = value;
+        self
+    }
+
+    pub fn memory_bounds(mut self, memory_bounds: MemoryBounds) -> Self {
+        self.state.memory_bounds = memory_bounds;
+        self
+    }
+
+    pub fn rng(mut self, rng: impl Into<MachineRng>) -> Self {
+        self.state.rng = rng.into();
+        self
+    }
+
+    pub fn build(self) -> State {
+        self.state
+    }
+}
+
+impl fmt::Display for State {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pc_ram_start = self.pc.as_usize();
+        let pc_ram_end = core::cmp::min(pc_ram_start + 4, self.ram.len());
+        let memory_at_pc = &self.ram[pc_ram_start..pc_ram_end];
+
+        let i_ram_start = self.index.as_usize();
+        let i_ram_end = core::cmp::min(i_ram_start + 4, self.ram.len());
+        let memory_at_index = &self.ram[i_ram_start..i_ram_end];
+
+        writeln!(f, "pc: {}", self.pc)?;
+        writeln!(f, "ram[{pc_ram_start:03x}..{pc_ram_end:03x}]: {memory_at_pc:02x?}")?;
+        writeln!(f, "i: {}", self.index)?;
+        writeln!(f, "ram[{i_ram_start:03x}..{i_ram_end:03x}]: {memory_at_index:02x?}")?;
+        writeln!(f, "sp: {}", self.stack_pointer)?;
+        write!(f, "stack ({:?}): [", self.stack_mode)?;
+        for slot in 0..STACK_SIZE {
+            if slot != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{:?}", self.stack_slot(slot))?;
+        }
+        writeln!(f, "]")?;
+        writeln!(f, "dt: {:02x}", self.delay_timer)?;
+        writeln!(f, "st: {:02x}", self.sound_timer)?;
+        writeln!(f, "registers: {:02x?}", self.registers)?;
+        writeln!(f, "keypad: {:?}", self.keypad)?;
+        if let Some(waiting_for_key) = self.waiting_for_key {
+            writeln!(f, "waiting for key: {waiting_for_key:?}")?;
+        }
+        if self.waiting_for_vblank {
+            writeln!(f, "waiting for vblank")?;
+        }
+        write!(f, "screen:\n{}", self.screen)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::make_nop_set;
+    use crate::{opcodes, poke};
+
+    #[test]
+    fn ram_patterns() {
+        let state = State::new(&[0x12, 0x00], RamPattern::Filled(0xFF), MemoryLayout::default());
+        assert_eq!(state.ram[0x000], 0xFF);
+        assert_eq!(state.ram[0x050], 0xF0);
+        assert_eq!(state.ram[0x200..0x202], [0x12, 0x00]);
+        assert_eq!(state.ram[0x202], 0xFF);
+        assert_eq!(state.ram[0xFFF], 0xFF);
+
+        let a = State::new(&[], RamPattern::Random { seed: 42 }, MemoryLayout::default());
+        let b = State::new(&[], RamPattern::Random { seed: 42 }, MemoryLayout::default());
+        let c = State::new(&[], RamPattern::Random { seed: 43 }, MemoryLayout::default());
+        assert_eq!(a.ram, b.ram);
+        assert_ne!(a.ram, c.ram);
+        assert!(a.ram[0x300..].iter().any(|&byte| byte != 0));
+    }
+
+    #[test]
+    fn start_delay() {
+        let power_on = PowerOn {
+            start_delay: 2,
+            ..PowerOn::default()
+        };
+        let mut machine = Machine::with_power_on(&[0x00, 0xE0], make_nop_set(), 60, power_on);
+
+        machine.cycle().unwrap();
+        machine.cycle().unwrap();
+        assert_eq!(machine.state.pc, Address(0x200));
+
+        machine.cycle().unwrap();
+        assert_eq!(machine.state.pc, Address(0x202));
+    }
+
+    #[test]
+    fn wait_for_key_press_and_release() {
+        use crate::keypad::Key;
+
+        let mut machine = Machine::new(&[0x00, 0xE0], make_nop_set(), 60);
+        machine.state.waiting_for_key = Some(WaitingForKey::Press {
+            target: RegIdent::V3,
+            await_release: true,
+        });
+
+        machine.cycle().unwrap();
+        assert!(machine.state.waiting_for_key.is_some());
+
+        machine.keypad_mut().press(Key::KB);
+        machine.cycle().unwrap();
+        machine.cycle().unwrap();
+        assert_eq!(machine.state.pc, Address(0x200));
+        assert_eq!(machine.state.reg_read(RegIdent::V3), 0x0);
+
+        machine.keypad_mut().release(Key::KB);
+        machine.cycle().unwrap();
+        assert_eq!(machine.state.waiting_for_key, None);
+        assert_eq!(machine.state.reg_read(RegIdent::V3), 0xB);
+
+        machine.cycle().unwrap();
+        assert_eq!(machine.state.pc, Address(0x202));
+    }
+
+    #[test]
+    fn wait_for_key_press_only() {
+        use crate::keypad::Key;
+
+        let mut machine = Machine::new(&[], make_nop_set(), 60);
+        machine.state.waiting_for_key = Some(WaitingForKey::Press {
+            target: RegIdent::V3,
+            await_release: false,
+        });
+
+        machine.keypad_mut().press(Key::K7);
+        machine.cycle().unwrap();
+        assert_eq!(machine.state.waiting_for_key, None);
+        assert_eq!(machine.state.reg_read(RegIdent::V3), 0x7);
+    }
+
+    #[test]
+    fn hooks() {
+        let mut set = make_nop_set();
+        set.op_00e0 = &|_: OpCode, state: &mut State| state.reg_write(RegIdent::V0, 0x42);
+
+        let mut machine = Machine::new(&[0x00, 0xE0], set, 60);
+        let mut before = None;
+        let mut after = None;
+
+        machine
+            .cycle_with_hooks(
+                |state, opcode| {
+                    assert_eq!(opcode.get_inner(), 0x00E0);
+                    before = Some(state.reg_read(RegIdent::V0));
+                },
+                |state, _| after = Some(state.reg_read(RegIdent::V0)),
+            )
+            .unwrap();
+
+        assert_eq!(before, Some(0x00));
+        assert_eq!(after, Some(0x42));
+    }
+
+    #[test]
+    fn run_frame() {
+        let mut game_code = [0; 40];
+        game_code.chunks_mut(2).for_each(|op| op.copy_from_slice(&[0x00, 0xE0]));
+        poke!(game_code, 20 => opcodes![0x0000]);
+
+        let mut set = make_nop_set();
+        set.op_00e0 = &|_: OpCode, state: &mut State| {
+            if state.pc == Address(0x202) {
+                state.screen.clear();
+            }
+        };
+
+        let mut machine = Machine::new(&game_code, set, 600);
+        machine.fault_policy = FaultPolicy::Halt;
+        machine.state.delay_timer = 5;
+        machine.state.sound_timer = 2;
+
+        let summary = machine.run_frame();
+        assert_eq!(
+            summary,
+            FrameSummary {
+                cycles: 10,
+                screen_changed: true,
+                beeping: true,
+                halted: false,
+                #[cfg(feature = "unstable")]
+                breakpoint: None,
+                #[cfg(feature = "unstable")]
+                watchpoint: None,
+            }
+        );
+        assert_eq!(machine.state.delay_timer, 4);
+
+        let summary = machine.run_frame();
+        assert_eq!(
+            summary,
+            FrameSummary {
+                cycles: 1,
+                screen_changed: false,
+                beeping: false,
+                halted: true,
+                #[cfg(feature = "unstable")]
+                breakpoint: None,
+                #[cfg(feature = "unstable")]
+                watchpoint: None,
+            }
+        );
+        assert_eq!(machine.state.delay_timer, 3);
+    }
+
+    #[test]
+    fn limits() {
+        let mut machine = Machine::new(&[0x00, 0xE0, 0x00, 0x00, 0x00, 0x00, 0x00, 0xE0], make_nop_set(), 60);
+        machine.limits = Limits {
+            max_cycles_per_call: 2,
+            max_consecutive_faults: 2,
+        };
+
+        assert_eq!(machine.run_cycles(10), 2);
+        assert_eq!(machine.state.pc, Address(0x204));
+        assert_eq!(machine.consecutive_faults, 1);
+        assert!(!machine.halted);
+
+        assert_eq!(machine.run_cycles(1), 1);
+        assert!(machine.halted);
+        assert_eq!(machine.run_cycles(10), 0);
+        assert_eq!(machine.state.pc, Address(0x206));
+
+        let mut machine = Machine::new(&[0x00, 0x00, 0x00, 0xE0, 0x00, 0x00], make_nop_set(), 60);
+        machine.limits.max_consecutive_faults = 2;
+        assert_eq!(machine.run_cycles(3), 3);
+        assert_eq!(machine.consecutive_faults, 1);
+        assert!(!machine.halted);
+    }
+
+    #[cfg(feature = "history")]
+    #[test]
+    fn history_dumped_on_fault() {
+        let mut set = make_nop_set();
+        set.op_6xnn = &|op: OpCode, state: &mut State| state.reg_write(op.get_x(), op.get_nn());
+
+        let mut machine = Machine::new(&opcodes![0x00E0, 0x632A, 0x0000], set, 60);
+
+        machine.run_cycles(2);
+        assert_eq!(machine.history.len(), 2);
+        assert!(machine.fault_history.is_none());
+
+        machine.cycle().unwrap_err();
+        let fault_history = machine.fault_history.as_ref().unwrap();
+        assert_eq!(fault_history.len(), 3);
+        assert_eq!(fault_history.iter().last().unwrap().pc, Address(0x204));
+
+        let load = fault_history.iter().nth(1).unwrap();
+        assert_eq!(load.registers[0x3], Some(0x2A));
+        assert_eq!(load.registers.iter().flatten().count(), 1);
+    }
+
+    #[test]
+    fn display_wait() {
+        let mut set = make_nop_set();
+        set.op_00e0 = &|_: OpCode, state: &mut State| state.waiting_for_vblank = true;
+
+        let mut machine = Machine::new(&[0x00, 0xE0, 0x00, 0xE0], set, 60);
+        machine.run_cycles(3);
+        assert_eq!(machine.state.pc, Address(0x202));
+
+        machine.vblank();
+        machine.cycle().unwrap();
+        assert_eq!(machine.state.pc, Address(0x204));
+    }
+
+    #[test]
+    fn memory_mapped_stack() {
+        let mut state = State::new(&[], RamPattern::Zeroed, MemoryLayout::default());
+        state.stack_mode = StackMode::MemoryMapped;
+
+        state.stack_push(Address(0x2A4)).unwrap();
+        state.stack_push(Address(0x3B6)).unwrap();
+        assert_eq!(state.ram[0xEA0..0xEA4], [0x02, 0xA4, 0x03, 0xB6]);
+        assert_eq!(state.stack_frames(), [Address(0x2A4), Address(0x3B6)]);
+
+        // A ROM deliberately patching its own return address
+        poke!(state.ram, 0xEA3 => [0xB8]);
+        assert_eq!(state.stack_pop(), Ok(Address(0x3B8)));
+        assert_eq!(state.stack_pop(), Ok(Address(0x2A4)));
+
+        // Calls and returns are accesses to the stack area like any other
+        #[cfg(feature = "unstable")]
+        {
+            use crate::debug::WatchKind;
+
+            state.watchpoints.insert(Watchpoint {
+                start: Address(0xEA1),
+                len: 1,
+                kind: WatchKind::ReadWrite,
+            });
+            state.stack_push(Address(0x2A4)).unwrap();
+            assert_eq!(state.watch_hit.take(), Some((Address(0xEA1), Access::Write)));
+            state.stack_pop().unwrap();
+            assert_eq!(state.watch_hit.take(), Some((Address(0xEA1), Access::Read)));
+        }
+    }
+
+    #[test]
+    fn watched_stack_area() {
+        fn poke(_: OpCode, state: &mut State) {
+            state.ram[0xEA5] = 0xFF;
+        }
+
+        let mut set = make_nop_set();
+        set.op_00e0 = &poke;
+
+        let mut machine = Machine::new(&[0x00, 0xE0, 0x00, 0xE0], set, 60);
+        machine.state.stack_mode = StackMode::Watched;
+
+        machine.cycle().unwrap();
+        assert_eq!(
+            machine.stack_area_violation,
+            Some(StackAreaViolation {
+                pc: Address(0x200),
+                addr: Address(0xEA5),
+            })
+        );
+
+        // Only the first violation is reported
+        machine.cycle().unwrap();
+        assert_eq!(machine.stack_area_violation.unwrap().pc, Address(0x200));
+    }
+
+    #[test]
+    fn address_stays_in_address_space() {
+        assert_eq!(Address::new(0xFFF), Some(Address(0xFFF)));
+        assert_eq!(Address::new(0x1000), None);
+        assert_eq!(Address::new_masked(0x1234), Address(0x234));
+
+        assert_eq!(Address(0xFFE) + 4, Address(0x002));
+        assert_eq!(Address(0x001) - 2, Address(0xFFF));
+
+        let mut machine = Machine::new(&[], make_nop_set(), 60);
+        machine.state.pc = Address(0xFFE);
+        assert_eq!(
+            machine.cycle(),
+            Err(MachineError::UnknownOpCode {
+                pc: Address(0xFFE),
+                opcode: OpCode::new(0x0000),
+            })
+        );
+        assert_eq!(machine.state.pc, Address(0x000));
+    }
+
+    #[test]
+    fn timers_tick_at_60hz() {
+        let mut machine = Machine::new(&[0x00, 0xE0], make_nop_set(), 700);
+        machine.state.delay_timer = 0xFF;
+
+        for _ in 0..700 {
+            machine.state.pc = Address(0x200);
+            machine.cycle().unwrap();
+        }
+
+        // Exactly one second worth of ticks, regardless of 700 not being a multiple of 60
+        assert_eq!(machine.state.delay_timer, 0xFF - 60);
+
+        machine.timer_clock = TimerClock::External;
+
+        for _ in 0..700 {
+            machine.state.pc = Address(0x200);
+            machine.cycle().unwrap();
+        }
+        assert_eq!(machine.state.delay_timer, 0xFF - 60);
+
+        machine.tick_timers();
+        assert_eq!(machine.state.delay_timer, 0xFF - 61);
+
+        // Frames leave the timers to the external clock too
+        machine.state.pc = Address(0x200);
+        machine.run_frame();
+        assert_eq!(machine.state.delay_timer, 0xFF - 61);
+    }
+
+    #[test]
+    fn halted_machine_is_silent() {
+        let mut machine = Machine::new(&[0x12, 0x00], make_nop_set(), 60);
+        machine.state.sound_timer = 10;
+        assert!(machine.is_beeping());
+
+        // Jumps to itself
+        machine.instruction_set.op_1nnn = &|op: OpCode, state: &mut State| state.pc = op.get_nnn();
+        assert_eq!(machine.cycle(), Ok(CycleOutcome::Halted));
+        assert!(!machine.is_beeping());
+        assert!(!machine.run_frame().beeping);
+    }
+
+    #[test]
+    fn default_fault_policy_continues() {
+        let mut machine = Machine::new(&opcodes![0xFFFF, 0x00E0, 0xFFFF, 0x00E0], make_nop_set(), 60);
+
+        for pc in [0x200, 0x204] {
+            assert_eq!(
+                machine.cycle(),
+                Err(MachineError::UnknownOpCode {
+                    pc: Address(pc),
+                    opcode: OpCode::new(0xFFFF),
+                })
+            );
+            assert!(!machine.is_halted());
+            assert_eq!(machine.cycle(), Ok(CycleOutcome::Executed(OpCode::new(0x00E0))));
+        }
+    }
+
+    #[test]
+    fn stack_faults() {
+        fn call(_: OpCode, state: &mut State) {
+            if let Err(fault) = state.stack_push(state.pc) {
+                state.raise(fault);
+            }
+        }
+
+        fn ret(_: OpCode, state: &mut State) {
+            match state.stack_pop() {
+                Ok(addr) => state.pc = addr,
+                Err(fault) => state.raise(fault),
+            }
+        }
+
+        let mut set = make_nop_set();
+        set.op_2nnn = &call;
+        set.op_00ee = &ret;
+
+        let mut machine = Machine::new(&[0x00, 0xEE, 0x22, 0x00], set, 60);
+        machine.fault_policy = FaultPolicy::Halt;
+        assert_eq!(
+            machine.cycle(),
+            Err(MachineError::StackUnderflow { pc: Address(0x200) })
+        );
+        assert_eq!(machine.state.pc, Address(0x202));
+        assert!(machine.halted);
+
+        machine.halted = false;
+        machine.consecutive_faults = 0;
+
+        for _ in 0..STACK_SIZE {
+            machine.state.pc = Address(0x202);
+            assert_eq!(machine.cycle(), Ok(CycleOutcome::Executed(OpCode::new(0x2200))));
+        }
+
+        machine.state.pc = Address(0x202);
+        assert_eq!(machine.cycle(), Err(MachineError::StackOverflow { pc: Address(0x202) }));
+        assert_eq!(machine.cycle(), Ok(CycleOutcome::Halted));
+    }
+
+    #[test]
+    fn stack_depth() {
+        let mut state = State::new(&[], RamPattern::default(), MemoryLayout::default());
+        state.stack_depth = 12;
+
+        assert_eq!(state.stack_capacity(), 12);
+
+        for _ in 0..12 {
+            state.stack_push(Address(0x200)).unwrap();
+        }
+        assert_eq!(state.stack_push(Address(0x200)), Err(Fault::StackOverflow));
+
+        for _ in 0..12 {
+            state.stack_pop().unwrap();
+        }
+        assert_eq!(state.stack_pop(), Err(Fault::StackUnderflow));
+    }
+
+    #[test]
+    fn flag_prevails_over_result() {
+        let mut state = State::new(&[], RamPattern::default(), MemoryLayout::default());
+
+        state.reg_write_with_flag(RegIdent::V3, 0x42, true);
+        assert_eq!(state.reg_read(RegIdent::V3), 0x42);
+        assert_eq!(state.reg_read(RegIdent::VF), 0x01);
+
+        state.reg_write_with_flag(RegIdent::VF, 0x42, false);
+        assert_eq!(state.reg_read(RegIdent::VF), 0x00);
+    }
+
+    #[test]
+    fn fault_policies() {
+        let rom = [0x00, 0x00, 0x00, 0xE0];
+
+        let mut machine = Machine::new(&rom, make_nop_set(), 60);
+        machine.limits.max_consecutive_faults = 2;
+        machine.fault_policy = FaultPolicy::Halt;
+        machine.cycle().unwrap_err();
+        assert!(machine.halted);
+
+        let mut machine = Machine::new(&rom, make_nop_set(), 60);
+        machine.limits.max_consecutive_faults = 2;
+        machine.fault_policy = FaultPolicy::IgnoreAndContinue;
+        machine.cycle().unwrap_err();
+        assert!(!machine.halted);
+        assert_eq!(machine.cycle(), Ok(CycleOutcome::Executed(OpCode::new(0x00E0))));
+
+        let mut machine = Machine::new(&rom, make_nop_set(), 60);
+        machine.limits.max_consecutive_faults = 2;
+        machine.fault_policy = FaultPolicy::TrapToHandler(|state, error| {
+            assert_eq!(
+                *error,
+                MachineError::UnknownOpCode {
+                    pc: Address(0x200),
+                    opcode: OpCode::new(0x0000)
+                }
+            );
+            state.pc = Address(0x200);
+            state.ram[0x201] = 0xE0;
+            true
+        });
+        machine.cycle().unwrap_err();
+        assert!(!machine.halted);
+        assert_eq!(machine.cycle(), Ok(CycleOutcome::Executed(OpCode::new(0x00E0))));
+    }
+
+    #[test]
+    fn consecutive_execution_faults() {
+        let mut set = make_nop_set();
+        set.op_00e0 = &|_: OpCode, state: &mut State| state.raise(Fault::StackOverflow);
+        let rom = [0x00, 0xE0, 0x00, 0xE0, 0x00, 0xE0, 0x00, 0xE0];
+
+        for fault_policy in [FaultPolicy::IgnoreAndContinue, FaultPolicy::TrapToHandler(|_, _| true)] {
+            let mut machine = Machine::new(&rom, set, 60);
+            machine.limits.max_consecutive_faults = 3;
+            machine.fault_policy = fault_policy;
+
+            for _ in 0..2 {
+                machine.cycle().unwrap_err();
+                assert!(!machine.halted);
+            }
+
+            machine.cycle().unwrap_err();
+            assert_eq!(machine.consecutive_faults, 3);
+            assert!(machine.halted);
+        }
+    }
+
+    #[test]
+    fn memory_bounds() {
+        let mut state = State::new(&[], RamPattern::default(), MemoryLayout::default());
+        state.ram[0x001] = 0x42;
+
+        assert_eq!(state.ram_read(Address(0xFFF), 2), Ok(0x42));
+        assert_eq!(state.ram_slice(Address(0xFFE), 2).map(<[u8]>::len), Ok(2));
+        assert_eq!(state.ram_slice(Address(0xFFF), 2), Err(Fault::MemoryOutOfBounds));
+
+        state.memory_bounds = MemoryBounds::Fault;
+        assert_eq!(state.ram_read(Address(0xFFF), 0), Ok(0x00));
+        assert_eq!(state.ram_read(Address(0xFFF), 2), Err(Fault::MemoryOutOfBounds));
+        assert_eq!(state.ram_write(Address(0xFFF), 1, 0xFF), Err(Fault::MemoryOutOfBounds));
+
+        let mut machine = Machine::new(&[], make_nop_set(), 60);
+        machine.state.memory_bounds = MemoryBounds::Fault;
+        machine.state.pc = Address(0xFFF);
+        assert_eq!(
+            machine.cycle(),
+            Err(MachineError::MemoryOutOfBounds { pc: Address(0xFFF) })
+        );
+    }
+
+    #[cfg(all(feature = "unstable", feature = "alloc"))]
+    #[test]
+    fn peripherals() {
+        use crate::peripheral::Peripheral;
+
+        /// Counts the bytes written, one counter per address
+        #[derive(Clone, Default)]
+        struct Counters([u8; 2]);
+
+        impl Peripheral for Counters {
+            fn read(&self, addr: Address) -> u8 {
+                self.0[addr.as_usize() - 0xF00]
+            }
+
+            fn write(&mut self, addr: Address, _: u8) {
+                self.0[addr.as_usize() - 0xF00] += 1;
+            }
+        }
+
+        let mut machine = Machine::new(&[], make_nop_set(), 60);
+        let state = &mut machine.state;
+        state.peripherals.map(Address(0xF00), 2, Counters::default()).unwrap();
+
+        state.ram_write(Address(0xEFF), 2, 0x42).unwrap();
+        state.ram_write(Address(0xF01), 0, 0x42).unwrap();
+        state.ram_write(Address(0xF02), 0, 0x42).unwrap();
+        assert_eq!(state.ram_read(Address(0xF00), 1), Ok(2));
+        assert_eq!(state.ram_read(Address(0xF02), 0), Ok(0x42));
+        // The RAM under the peripheral is untouched
+        assert_eq!(state.ram_slice(Address(0xF00), 2), Ok(&[0, 0][..]));
+
+        machine.reset();
+        assert_eq!(machine.state.ram_read(Address(0xF01), 0), Ok(2));
+    }
+
+    #[cfg(all(feature = "unstable", feature = "alloc"))]
+    #[test]
+    fn memory_observer() {
+        use alloc::vec::Vec;
+
+        use crate::debug::MemoryObserver;
+
+        #[derive(Default)]
+        struct Log(Vec<(u16, u8, Access)>);
+
+        impl MemoryObserver for Log {
+            fn observe(&mut self, addr: Address, value: u8, access: Access) {
+                self.0.push((addr.get(), value, access));
+            }
+        }
+
+        fn store(_: OpCode, state: &mut State) {
+            state.ram_write(state.index, 0, 0x42).unwrap();
+        }
+
+        let mut set = make_nop_set();
+        set.op_annn = &store;
+        let mut machine = Machine::new(&opcodes![0xA300], set, 60);
+        machine.state.index = Address(0x300);
+
+        let mut stepped_over = machine.clone();
+
+        let log = Rc::new(RefCell::new(Log::default()));
+        machine.state.memory_observer = Some(log.clone());
+        machine.cycle().unwrap();
+
+        assert_eq!(
+            log.borrow().0,
+            [
+                (0x200, 0xA3, Access::Read),
+                (0x201, 0x00, Access::Read),
+                (0x300, 0x42, Access::Write),
+            ]
+        );
+
+        // Stepping over doesn't report the instruction it peeks at
+        let over_log = Rc::new(RefCell::new(Log::default()));
+        stepped_over.state.memory_observer = Some(over_log.clone());
+        assert_eq!(stepped_over.step_over().stop, StepStop::Completed);
+        assert_eq!(over_log.borrow().0, log.borrow().0);
+    }
+
+    #[test]
+    fn halts_on_jump_to_itself() {
+        fn jump(opcode: OpCode, state: &mut State) {
+            state.pc = opcode.get_nnn();
+        }
+
+        let mut set = make_nop_set();
+        set.op_1nnn = &jump;
+
+        let mut machine = Machine::new(&[0x12, 0x02, 0x12, 0x02], set, 60);
+        assert_eq!(machine.cycle(), Ok(CycleOutcome::Executed(OpCode::new(0x1202))));
+        assert!(!machine.is_halted());
+
+        assert_eq!(machine.cycle(), Ok(CycleOutcome::Halted));
+        assert!(machine.is_halted());
+        assert_eq!(machine.state.pc, Address(0x202));
+    }
+
+    #[test]
+    fn unsupported_instruction() {
+        let mut set = make_nop_set();
+        set.op_00f1 = crate::make_instruction!(crate::instruction::Unsupported::execute);
+
+        let mut machine = Machine::new(&[0x00, 0xF1], set, 60);
+        assert_eq!(
+            machine.cycle(),
+            Err(MachineError::UnknownOpCode {
+                pc: Address(0x200),
+                opcode: OpCode::new(0x00F1),
+            })
+        );
+    }
+
+    #[test]
+    fn reset() {
+        fn set_v0(_: OpCode, state: &mut State) {
+            state.reg_write(RegIdent::V0, 0x42);
+            state.screen.set_pixel(0, 0);
+            state.ram[0x200] = 0xFF;
+        }
+
+        let mut set = make_nop_set();
+        set.op_6xnn = &set_v0;
+
+        let mut machine = Machine::new(&[0x60, 0x42], set, 60);
+        machine.state.stack_depth = 12;
+        machine.cycle().unwrap();
+
+        machine.reset();
+        assert_eq!(machine.state.pc, Address(0x200));
+        assert_eq!(machine.state.reg_read(RegIdent::V0), 0x00);
+        assert_eq!(machine.screen().pixel_iter().count(), 0);
+        assert_eq!(machine.state.ram[0x200..0x202], [0x60, 0x42]);
+        assert_eq!(machine.state.stack_depth, 12);
+        assert_eq!(machine.counter, 0);
+    }
+
+    #[test]
+    fn load_rom() {
+        let mut machine = Machine::new(&[0x00, 0xE0, 0x00, 0xE0], make_nop_set(), 60);
+        machine.cycle().unwrap();
+
+        assert_eq!(machine.load_rom(&[]), Err(RomError::Empty));
+        assert_eq!(
+            machine.load_rom(&[0; MAX_ROM_SIZE + 1]),
+            Err(RomError::TooLarge {
+                len: MAX_ROM_SIZE + 1,
+                max: MAX_ROM_SIZE
+            })
+        );
+        assert_eq!(machine.state.pc, Address(0x202));
+
+        machine.load_rom(&[0x60, 0x42]).unwrap();
+        assert_eq!(machine.state.pc, Address(0x200));
+        assert_eq!(machine.state.ram[0x200..0x204], [0x60, 0x42, 0x00, 0x00]);
+
+        machine.reset();
+        assert_eq!(machine.state.ram[0x200..0x204], [0x60, 0x42, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn builder() {
+        use crate::rng::{Rng, SequenceRng};
+
+        let rom = [0x60, 0x42];
+
+        assert_eq!(
+            Machine::builder().rom(&rom).build().err(),
+            Some(BuildError::MissingInstructionSet)
+        );
+
+        let builder = Machine::builder().rom(&rom).instruction_set(|_| make_nop_set());
+
+        assert_eq!(
+            builder.clone().frequency(0).build().err(),
+            Some(BuildError::ZeroFrequency)
+        );
+        assert_eq!(
+            builder.clone().rom(&[]).build().err(),
+            Some(BuildError::Rom(RomError::Empty))
+        );
+        assert_eq!(
+            builder.clone().entry(0x1000).build().err(),
+            Some(BuildError::EntryOutOfBounds { entry: 0x1000 })
+        );
+        assert_eq!(
+            builder.clone().entry(0xFFF).build().err(),
+            Some(BuildError::Rom(RomError::TooLarge { len: 2, max: 1 }))
+        );
+        assert_eq!(
+            builder.clone().font_at(0xFC0).build().err(),
+            Some(BuildError::FontOutOfBounds { font: 0xFC0 })
+        );
+        assert_eq!(
+            builder.clone().font_at(0x1B2).build().err(),
+            Some(BuildError::FontOverlapsRom)
+        );
+
+        let mut machine = builder
+            .entry(0x600)
+            .font_at(0x100)
+            .rng(SequenceRng::new(&[7]))
+            .frequency(600)
+            .build()
+            .unwrap();
+
+        assert_eq!(machine.state.pc, Address(0x600));
+        assert_eq!(machine.state.ram[0x600..0x602], rom);
+        assert_eq!(machine.state.ram[0x100..0x150], *crate::font::STANDARD);
+        assert_eq!(machine.state.font_address, Address(0x100));
+        assert_eq!(machine.state.rng.next_u8(), 7);
+        assert_eq!(machine.frequency_hz, 600);
+
+        machine.reset();
+        assert_eq!(machine.state.pc, Address(0x600));
+        assert_eq!(machine.state.font_address, Address(0x100));
+    }
+
+    #[test]
+    fn host_calls() {
+        fn host_call(addr: Address, state: &mut State) -> HostCallOutcome {
+            match addr.get() {
+                0x001 => {
+                    state.reg_write(RegIdent::V1, state.reg_read(RegIdent::V0));
+                    HostCallOutcome::Continue
+                }
+                0x0FF => HostCallOutcome::Halt,
+                _ => HostCallOutcome::Unknown,
+            }
+        }
+
+        let rom = opcodes![0x0001, 0x0002, 0x00FF];
+        let mut machine = Machine::builder()
+            .rom(&rom)
+            .instruction_set(|_| make_nop_set())
+            .host_call(host_call)
+            .build()
+            .unwrap();
+        machine.state.reg_write(RegIdent::V0, 0x42);
+        machine.cycle().unwrap();
+        assert_eq!(machine.state.reg_read(RegIdent::V1), 0x42);
+
+        assert_eq!(
+            machine.cycle(),
+            Err(MachineError::UnknownOpCode {
+                pc: Address(0x202),
+                opcode: OpCode::new(0x0002),
+            })
+        );
+        assert_eq!(machine.cycle(), Ok(CycleOutcome::Halted));
+        assert!(machine.is_halted());
+        assert_eq!(machine.state.pc, Address(0x204));
+    }
+
+    #[cfg(all(feature = "unstable", feature = "alloc"))]
+    #[test]
+    fn patches() {
+        use crate::instruction::OP_6XNN;
+
+        let rom = opcodes![0x6342, 0x6342];
+        let mut machine = Machine::builder()
+            .rom(&rom)
+            .instruction_set(|_| make_nop_set())
+            .build()
+            .unwrap();
+
+        // Captures its configuration, and takes over the instruction set
+        let offset = 1;
+        let patch = move |op: OpCode, state: &mut State| {
+            state.reg_write(op.get_x(), op.get_nn() + offset);
+        };
+        machine.patches.patch(OP_6XNN, patch).unwrap();
+        machine.cycle().unwrap();
+        assert_eq!(machine.state.reg_read(RegIdent::V3), 0x43);
+
+        machine.state.reg_write(RegIdent::V3, 0);
+        assert!(machine.patches.unpatch(OP_6XNN).is_some());
+        machine.cycle().unwrap();
+        assert_eq!(machine.state.reg_read(RegIdent::V3), 0);
+    }
+
+    #[test]
+    fn snapshot() {
+        use crate::snapshot::SnapshotError;
+
+        let rom = [0x60, 0x42, 0x12, 0x02];
+        let mut machine = Machine::new(&rom, make_nop_set(), 600);
+        machine.state.reg_write(RegIdent::V3, 0x42);
+        machine.state.delay_timer = 10;
+        machine.state.screen.set_pixel(5, 7);
+        machine.state.waiting_for_key = Some(WaitingForKey::Release {
+            target: RegIdent::V1,
+            key: Key::K4,
+        });
+        machine.state.stack_push(Address(0x204)).unwrap();
+
+        let snapshot = machine.snapshot();
+        let decoded = Snapshot::from_bytes(&snapshot.to_bytes()).unwrap();
+
+        machine.reset();
+        machine.halted = true;
+        machine.restore(&decoded);
+
+        assert!(!machine.is_halted());
+        assert_eq!(machine.state.reg_read(RegIdent::V3), 0x42);
+        assert_eq!(machine.state.delay_timer, 10);
+        assert_eq!(machine.screen().get_vectored(5, 7), 0x80);
+        assert_eq!(machine.state.waiting_for_key, snapshot.waiting_for_key);
+        assert_eq!(machine.state.stack_pop(), Ok(Address(0x204)));
+        assert_eq!(machine.state.ram[0x200..0x204], rom);
+
+        let mut bytes = snapshot.to_bytes();
+        assert_eq!(
+            Snapshot::from_bytes(&bytes[..Snapshot::ENCODED_LEN - 1]).err(),
+            Some(SnapshotError::InvalidLength {
+                len: Snapshot::ENCODED_LEN - 1
+            })
+        );
+        bytes[0] = b'X';
+        assert_eq!(Snapshot::from_bytes(&bytes).err(), Some(SnapshotError::InvalidMagic));
+        bytes[0] = b'T';
+        bytes[4] = 2;
+        assert_eq!(
+            Snapshot::from_bytes(&bytes).err(),
+            Some(SnapshotError::UnsupportedVersion { version: 2 })
+        );
+        bytes[4] = 1;
+        bytes[5] = 3;
+        assert_eq!(Snapshot::from_bytes(&bytes).err(), Some(SnapshotError::InvalidValue));
+        bytes[5] = 0;
+        bytes[Snapshot::ENCODED_LEN - crate::screen::Screen::ENCODED_LEN] = 2;
+        assert_eq!(Snapshot::from_bytes(&bytes).err(), Some(SnapshotError::InvalidValue));
+    }
+
+    #[test]
+    fn snapshot_migration() {
+        let mut machine = Machine::builder()
+            .rom(&[0x12, 0x00])
+            .quirks(Quirks::schip())
+            .instruction_set(|_| make_nop_set())
+            .build()
+            .unwrap();
+        machine.state.reg_write(RegIdent::V3, 0x42);
+
+        let snapshot = machine.snapshot();
+        assert_eq!(snapshot.variant(), Variant::SuperChip);
+        assert_eq!(
+            Snapshot::from_bytes(&snapshot.to_bytes()).unwrap().variant(),
+            Variant::SuperChip
+        );
+
+        // Version 0: the magic number directly followed by the hardware state
+        let bytes = snapshot.to_bytes();
+        let mut v0 = [0; Snapshot::ENCODED_LEN - 2];
+        v0[..4].copy_from_slice(&bytes[..4]);
+        v0[4..].copy_from_slice(&bytes[6..]);
+
+        let decoded = Snapshot::from_bytes(&v0).unwrap();
+        assert_eq!(decoded.variant(), Variant::Chip8);
+        assert_eq!(decoded.registers, snapshot.registers);
+        assert_eq!(decoded.pc(), snapshot.pc());
+    }
+
+    #[test]
+    fn run_until_first_draw() {
+        let run = |rom: &[u8], max_cycles| {
+            let mut machine = Machine::new(rom, make_nop_set(), 600);
+            machine.fault_policy = FaultPolicy::Halt;
+            machine.run_until_first_draw(max_cycles)
+        };
+
+        assert_eq!(
+            run(&opcodes![0x6001, 0x6102, 0xD015], 100),
+            FastStart {
+                stop: FastStartStop::Draw,
+                cycles: 3
+            }
+        );
+        assert_eq!(
+            run(&opcodes![0x6001, 0xE0A1, 0xD015], 100),
+            FastStart {
+                stop: FastStartStop::InputPoll,
+                cycles: 2
+            }
+        );
+        assert_eq!(
+            run(&[0x60; 40], 10),
+            FastStart {
+                stop: FastStartStop::CycleLimit,
+                cycles: 10
+            }
+        );
+        // 5XY1 is unknown, the machine halts on the fault
+        assert_eq!(
+            run(&opcodes![0x6001, 0x5121], 100),
+            FastStart {
+                stop: FastStartStop::Halted,
+                cycles: 3
+            }
+        );
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn breakpoints() {
+        let mut set = make_nop_set();
+        set.op_7xnn = &|op: OpCode, state: &mut State| {
+            state.reg_write(op.get_x(), state.reg_read(op.get_x()).wrapping_add(op.get_nn()));
+        };
+
+        let mut machine = Machine::new(&[0x70, 0x01].repeat(20), set, 600);
+        assert!(machine.add_breakpoint(Address(0x204)));
+        assert!(machine.add_breakpoint(Address(0x208)));
+
+        assert_eq!(machine.cycle(), Ok(CycleOutcome::Executed(OpCode::new(0x7001))));
+        assert_eq!(machine.cycle(), Ok(CycleOutcome::Executed(OpCode::new(0x7001))));
+        assert_eq!(machine.cycle(), Ok(CycleOutcome::BreakpointHit { pc: Address(0x204) }));
+        assert_eq!(machine.state.reg_read(RegIdent::V0), 2);
+
+        // Running again resumes the execution
+        let summary = machine.run_frame();
+        assert_eq!(summary.breakpoint, Some(Address(0x208)));
+        assert_eq!(summary.cycles, 2);
+        assert_eq!(machine.state.pc, Address(0x208));
+        assert_eq!(machine.state.reg_read(RegIdent::V0), 4);
+
+        assert!(machine.remove_breakpoint(Address(0x204)));
+        assert_eq!(machine.run_cycles(5), 5);
+        assert_eq!(machine.state.reg_read(RegIdent::V0), 9);
+
+        machine.reset();
+        assert_eq!(machine.run_cycles(10), 4);
+        assert_eq!(machine.state.pc, Address(0x208));
+    }
+
+    #[cfg(all(feature = "unstable", feature = "alloc"))]
+    #[test]
+    fn breakpoints_at_symbols() {
+        let mut machine = Machine::new(&[0x00, 0xE0].repeat(4), make_nop_set(), 600);
+        machine.symbols = "204 draw".parse().unwrap();
+
+        assert_eq!(machine.add_breakpoint_at_symbol("draw"), Some(true));
+        assert_eq!(machine.add_breakpoint_at_symbol("missing"), None);
+        assert_eq!(machine.run_frame().breakpoint, Some(Address(0x204)));
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn step_over_and_out() {
+        let mut set = make_nop_set();
+        set.op_00ee = &|_: OpCode, state: &mut State| state.pc = state.stack_pop().unwrap();
+        set.op_1nnn = &|op: OpCode, state: &mut State| state.pc = op.get_nnn();
+        set.op_2nnn = &|op: OpCode, state: &mut State| {
+            state.stack_push(state.pc).unwrap();
+            state.pc = op.get_nnn();
+        };
+        set.op_6xnn = &|op: OpCode, state: &mut State| state.reg_write(op.get_x(), op.get_nn());
+        set.op_7xnn = &|op: OpCode, state: &mut State| {
+            state.reg_write(op.get_x(), state.reg_read(op.get_x()).wrapping_add(op.get_nn()));
+        };
+
+        #[rustfmt::skip]
+        let rom = opcodes![
+            0x2206, 0x6001, 0x1204, // main: calls 0x206, then loops
+            0x220C, 0x7101, 0x00EE, // 0x206: calls 0x20C
+            0x7201, 0x00EE,         // 0x20C
+        ];
+        let mut machine = Machine::new(&rom, set, 600);
+
+        let step = machine.step_over();
+        assert_eq!(
+            step,
+            Step {
+                stop: StepStop::Completed,
+                cycles: 6
+            }
+        );
+        assert_eq!(machine.state.pc, Address(0x202));
+        assert_eq!(machine.state.reg_read(RegIdent::V1), 1);
+        assert_eq!(machine.state.reg_read(RegIdent::V2), 1);
+
+        assert_eq!(
+            machine.step_over(),
+            Step {
+                stop: StepStop::Completed,
+                cycles: 1
+            }
+        );
+        assert_eq!(machine.state.reg_read(RegIdent::V0), 1);
+        assert_eq!(machine.step_over().stop, StepStop::Halted);
+
+        machine.reset();
+        machine.run_cycles(2);
+        assert_eq!(machine.call_depth(), 2);
+        assert_eq!(
+            machine.step_out(),
+            Step {
+                stop: StepStop::Completed,
+                cycles: 2
+            }
+        );
+        assert_eq!(machine.state.pc, Address(0x208));
+        assert_eq!(machine.call_depth(), 1);
+
+        machine.reset();
+        machine.add_breakpoint(Address(0x20C));
+        assert_eq!(
+            machine.step_over(),
+            Step {
+                stop: StepStop::Breakpoint(Address(0x20C)),
+                cycles: 3
+            }
+        );
+        assert_eq!(
+            machine.step_out(),
+            Step {
+                stop: StepStop::Completed,
+                cycles: 2
+            }
+        );
+        assert_eq!(machine.state.pc, Address(0x208));
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn conditional_breakpoints() {
+        let mut set = make_nop_set();
+        set.op_7xnn = &|op: OpCode, state: &mut State| {
+            state.reg_write(op.get_x(), state.reg_read(op.get_x()).wrapping_add(op.get_nn()));
+        };
+
+        // V3 += 4 five times, then VF += 1
+        let rom = opcodes![0x7304, 0x7304, 0x7304, 0x7304, 0x7304, 0x7F01, 0x7304];
+        let mut machine = Machine::new(&rom, set, 600);
+        assert!(machine.add_conditional_breakpoint("when V3 == 0x10".parse().unwrap()));
+        assert!(machine.add_conditional_breakpoint("when VF changes".parse().unwrap()));
+
+        assert_eq!(machine.run_cycles(100), 4);
+        assert_eq!(machine.state.pc, Address(0x208));
+        assert_eq!(machine.state.reg_read(RegIdent::V3), 0x10);
+
+        assert_eq!(machine.run_cycles(100), 2);
+        assert_eq!(machine.state.pc, Address(0x20C));
+        assert_eq!(machine.state.reg_read(RegIdent::VF), 1);
+
+        assert!(machine
+            .conditional_breakpoints
+            .remove(&"when VF changes".parse().unwrap()));
+        assert_eq!(machine.run_cycles(1), 1);
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn watchpoints() {
+        use crate::debug::{Access, WatchKind};
+
+        let mut set = make_nop_set();
+        set.op_annn = &|op: OpCode, state: &mut State| state.index = op.get_nnn();
+        set.op_fx55 = &|op: OpCode, state: &mut State| {
+            for offset in 0..=op.get_x().get() {
+                let value = state.reg_read(RegIdent::try_from(offset).unwrap());
+                state.ram_write(state.index, u16::from(offset), value).unwrap();
+            }
+        };
+        set.op_fx65 = &|_: OpCode, state: &mut State| {
+            let value = state.ram_slice(state.index, 2).unwrap()[1];
+            state.reg_write(RegIdent::V0, value);
+        };
+
+        // I = 0x300, stores V0-V3, I = 0x302, loads 2 bytes, stores V0
+        let rom = opcodes![0xA300, 0xF355, 0xA302, 0xF065, 0xF055];
+        let mut machine = Machine::new(&rom, set, 600);
+        assert!(machine.add_watchpoint(Watchpoint {
+            start: Address(0x302),
+            len: 2,
+            kind: WatchKind::ReadWrite,
+        }));
+
+        assert_eq!(machine.run_cycles(10), 2);
+        assert_eq!(machine.cycle(), Ok(CycleOutcome::Executed(OpCode::new(0xA302))));
+
+        let summary = machine.run_frame();
+        assert_eq!(summary.cycles, 1);
+        assert_eq!(
+            summary.watchpoint,
+            Some(WatchpointHit {
+                pc: Address(0x206),
+                opcode: OpCode::new(0xF065),
+                addr: Address(0x302),
+                access: Access::Read,
+            })
+        );
+
+        assert!(machine.remove_watchpoint(Address(0x302)));
+        assert_eq!(machine.cycle(), Ok(CycleOutcome::Executed(OpCode::new(0xF055))));
+    }
+
+    #[test]
+    fn cosmac_vip_timing() {
+        let mut set = make_nop_set();
+        set.op_1nnn = &|op: OpCode, state: &mut State| state.pc = op.get_nnn();
+
+        let mut machine = Machine::new(&opcodes![0x6001].repeat(100), set, 6000);
+        assert_eq!(machine.run_frame().cycles, 100);
+
+        // 6XNN takes 46 machine cycles, the 56th instruction crosses the end of the frame
+        machine.reset();
+        machine.timing = Timing::CosmacVip;
+        assert_eq!(machine.run_frame().cycles, 56);
+        assert_eq!(machine.state.pc, Address(0x200 + 56 * 2));
+
+        // Waiting for the vertical blank idles until the end of the frame, 40 machine cycles at a time
+        set.op_6xnn = &|_: OpCode, state: &mut State| state.waiting_for_vblank = true;
+        let mut machine = Machine::new(&opcodes![0x6001], set, 60);
+        machine.timing = Timing::CosmacVip;
+        assert_eq!(machine.run_frame().cycles, 1 + 64);
+    }
+
+    #[test]
+    fn run_until_screen_change() {
+        let mut set = make_nop_set();
+        set.op_00e0 = &|_: OpCode, state: &mut State| state.screen.clear();
+        set.op_dxyn = &|_: OpCode, state: &mut State| {
+            state.screen.flip_pixel(0, 0);
+        };
+
+        let rom = opcodes![0x6001, 0x6002, 0xD011, 0x6003, 0x00E0, 0x6004];
+        let mut machine = Machine::new(&rom, set, 600);
+
+        assert_eq!(machine.run_until_screen_change(100), 3);
+        assert!(machine.screen().is_changed());
+        assert_eq!(machine.run_until_screen_change(100), 2);
+        assert!(machine.screen().is_changed());
+
+        assert_eq!(machine.run_until_screen_change(1), 1);
+        assert!(!machine.screen().is_changed());
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn coverage() {
+        let mut set = make_nop_set();
+        set.op_1nnn = &|op: OpCode, state: &mut State| state.pc = op.get_nnn();
+
+        // The instruction at 0x202 is jumped over
+        let rom = opcodes![0x1204, 0x00E0, 0x00E0, 0x1206];
+        let mut machine = Machine::new(&rom, set, 60);
+
+        machine.run_cycles(1);
+        assert!(machine.coverage().is_none());
+
+        machine.enable_coverage();
+        machine.reset();
+        machine.run_cycles(10);
+
+        let coverage = machine.coverage().unwrap();
+        assert!(coverage.iter().eq([Address(0x200), Address(0x204), Address(0x206)]));
+        assert!(coverage.missed(Address(0x200), rom.len()).eq([Address(0x202)]));
+
+        assert_eq!(machine.disable_coverage().map(|coverage| coverage.len()), Some(3));
+        assert!(machine.coverage().is_none());
+    }
+
+    #[cfg(all(feature = "unstable", feature = "alloc"))]
+    #[test]
+    fn profiling() {
+        let mut set = make_nop_set();
+        set.op_1nnn = &|op: OpCode, state: &mut State| state.pc = op.get_nnn();
+
+        // Loops over 0x202 and 0x204 after the first instruction
+        let rom = opcodes![0x00E0, 0x00E0, 0x1202];
+        let mut machine = Machine::new(&rom, set, 60);
+        machine.enable_profiling(1);
+        machine.run_cycles(9);
+
+        let profile = machine.profile().unwrap();
+        assert_eq!(profile.samples(), 9);
+        assert_eq!(profile.count(Address(0x200)), 1);
+        assert_eq!(profile.count(Address(0x202)), 4);
+        assert_eq!(profile.hottest(1)[0].addr, Address(0x202));
+
+        assert!(machine.disable_profiling().is_some());
+        assert!(machine.profile().is_none());
+    }
+
+    #[cfg(all(feature = "unstable", feature = "alloc"))]
+    #[test]
+    fn decode_cache() {
+        use crate::decode_cache::DecodeCacheStats;
+        use crate::instruction::OP_6XNN;
+
+        let rom = opcodes![0x00E0, 0x6001, 0x00E0];
+        let mut machine = Machine::new(&rom, make_nop_set(), 60);
+        machine.enable_decode_cache();
+        machine.run_cycles(3);
+
+        // Overwrites the second instruction before running the ROM again
+        machine.reset();
+        poke!(machine.state.ram, 0x202 => [0x61, 0x02]);
+        machine.run_cycles(3);
+
+        assert_eq!(
+            machine.decode_cache().unwrap().stats(),
+            DecodeCacheStats {
+                hits: 2,
+                misses: 3,
+                invalidations: 1,
+            }
+        );
+
+        // Patches take over the cached instructions, on the ROM loaded again by the reset
+        let patch = |op: OpCode, state: &mut State| state.reg_write(op.get_x(), op.get_nn());
+        machine.patches.patch(OP_6XNN, patch).unwrap();
+        machine.reset();
+        machine.run_cycles(3);
+        assert_eq!(machine.state.reg_read(RegIdent::V0), 0x01);
+
+        assert!(machine.disable_decode_cache().is_some());
+        assert!(machine.decode_cache().is_none());
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn opcode_stats() {
+        use crate::instruction::{OP_00E0, OP_6XNN};
+
+        let mut machine = Machine::new(&opcodes![0x00E0, 0x6001, 0x6102, 0x0000], make_nop_set(), 60);
+        machine.enable_opcode_stats();
+        machine.run_cycles(4);
+
+        let stats = machine.opcode_stats().unwrap();
+        assert_eq!(stats.count(OP_00E0), 1);
+        assert_eq!(stats.count(OP_6XNN), 2);
+        assert_eq!(stats.total(), 3);
+
+        assert!(machine.disable_opcode_stats().is_some());
+        assert!(machine.opcode_stats().is_none());
+    }
+
+    #[cfg(feature = "unstable")]
+    #[test]
+    fn perf_counters() {
+        let mut set = make_nop_set();
+        set.op_dxyn = &|_: OpCode, state: &mut State| {
+            state.screen.flip_pixel(0, 0);
+        };
+
+        let mut machine = Machine::new(&opcodes![0x6001, 0xD011, 0x6002, 0xD011].repeat(10), set, 240);
+        machine.run_frame();
+        let first = machine.perf_counters();
+        assert_eq!(
+            first,
+            PerfCounters {
+                instructions: 4,
+                draws: 2,
+                frames: 1,
+            }
+        );
+
+        machine.run_cycles(2);
+        machine.run_frame();
+        assert_eq!(
+            machine.perf_counters().since(&first),
+            PerfCounters {
+                instructions: 6,
+                draws: 3,
+                frames: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn determinism() {
+        use crate::rng::Rng;
+
+        fn make_set(_: &Quirks) -> InstructionSet {
+            let mut set = make_nop_set();
+            set.op_cxnn = &|op: OpCode, state: &mut State| {
+                let value = state.rng.next_u8() & op.get_nn();
+                state.reg_write(op.get_x(), value);
+            };
+            set
+        }
+
+        let make_machine = |seed| {
+            Machine::builder()
+                .rom(&[0xC0; 60])
+                .instruction_set(make_set)
+                .frequency(600)
+                .deterministic(seed)
+                .build()
+                .unwrap()
+        };
+
+        let mut a = make_machine(42);
+        let mut b = make_machine(42);
+        let mut c = make_machine(0xDEAD_BEEF);
+        assert_eq!(a.check_deterministic(), Ok(()));
+
+        a.run_frame();
+        b.run_frame();
+        c.run_frame();
+        assert_eq!(a.state_hash(), b.state_hash());
+        assert_ne!(a.state_hash(), c.state_hash());
+
+        a.run_frame();
+        b.run_frame();
+        assert_eq!(a.state_hash(), b.state_hash());
+
+        b.state.delay_timer = 1;
+        assert_ne!(a.state_hash(), b.state_hash());
+
+        a.timer_clock = TimerClock::External;
+        assert_eq!(a.check_deterministic(), Err(NonDeterminism::ExternalTimerClock));
     }
 }
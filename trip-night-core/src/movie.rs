@@ -0,0 +1,298 @@
+//! Input movies, replaying a run deterministically
+//!
+//! A movie records, for every frame, the keys held down and the seed the random number generator
+//! was reset with. Replaying these frames on a freshly started machine running the same ROM
+//! reproduces the run exactly.
+//!
+//! Movies are shared as text: a header with the format version and the SHA-1 of the ROM, then one
+//! line per frame with the seed in hexadecimal, followed by the keys pressed (`+`) or released (`-`)
+//! since the previous frame:
+//!
+//! ```text
+//! trip-night-movie 1
+//! rom da39a3ee5e6b4b0d3255bfef95601890afd80709
+//! 8d2a4f51
+//! 0b5d1c2e +5
+//! 71f0a3c9
+//! 3e8b7d02 -5 +A
+//! ```
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::keypad::{Key, Keypad};
+use crate::machine::{FrameSummary, Machine};
+use crate::rng::{Rng, XorShiftRng};
+use crate::rom_db::RomHash;
+
+/// Version of the text format written by `Movie`'s `Display` implementation
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Inputs of a recorded run
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Movie {
+    /// ROM the run was recorded with
+    pub rom: RomHash,
+    pub frames: Vec<MovieFrame>,
+}
+
+/// Inputs of one frame
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MovieFrame {
+    /// Seed of the xorshift generator the machine uses during the frame
+    pub seed: u32,
+    /// Keys held down during the frame
+    pub keypad: Keypad,
+}
+
+impl MovieFrame {
+    fn run(&self, machine: &mut Machine) -> FrameSummary {
+        machine.state.keypad = self.keypad;
+        machine.state.rng = XorShiftRng::new(self.seed).into();
+        machine.run_frame()
+    }
+}
+
+/// A movie text which can't be parsed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MovieError {
+    /// Line of the error, starting at 1
+    pub line: usize,
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "malformed movie at line {}", self.line)
+    }
+}
+
+impl Movie {
+    /// Whether the movie was recorded with the ROM loaded in the machine
+    pub fn matches(&self, machine: &Machine) -> bool {
+        RomHash::of(machine.rom()) == self.rom
+    }
+
+    pub fn parse(text: &str) -> Result<Self, MovieError> {
+        let mut lines = text.lines().enumerate().map(|(idx, line)| (idx + 1, line.trim()));
+
+        let version = lines
+            .next()
+            .and_then(|(_, content)| content.strip_prefix("trip-night-movie "))
+            .and_then(|version| version.parse::<u32>().ok());
+
+        if version != Some(FORMAT_VERSION) {
+            return Err(MovieError { line: 1 });
+        }
+
+        let rom = lines
+            .next()
+            .and_then(|(_, content)| content.strip_prefix("rom "))
+            .and_then(|rom| rom.parse().ok())
+            .ok_or(MovieError { line: 2 })?;
+
+        let mut frames = Vec::new();
+        let mut keypad = Keypad::default();
+
+        for (line, content) in lines.filter(|(_, content)| !content.is_empty()) {
+            let invalid = MovieError { line };
+            let mut fields = content.split_whitespace();
+
+            let seed = fields.next().and_then(|seed| u32::from_str_radix(seed, 16).ok());
+            let seed = seed.ok_or(invalid)?;
+
+            for event in fields {
+                let (pressed, key) = match event.split_at(1) {
+                    ("+", key) => (true, key),
+                    ("-", key) => (false, key),
+                    _ => return Err(invalid),
+                };
+                let key = u8::from_str_radix(key, 16).ok().and_then(|key| Key::try_from(key).ok());
+                keypad.set(key.ok_or(invalid)?, pressed);
+            }
+
+            frames.push(MovieFrame { seed, keypad });
+        }
+
+        Ok(Self { rom, frames })
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "trip-night-movie {FORMAT_VERSION}")?;
+        writeln!(f, "rom {}", self.rom)?;
+
+        let mut previous = Keypad::default();
+
+        for frame in &self.frames {
+            write!(f, "{:08x}", frame.seed)?;
+
+            for key in (0..16).filter_map(|key| Key::try_from(key).ok()) {
+                match (previous.is_pressed(key), frame.keypad.is_pressed(key)) {
+                    (false, true) => write!(f, " +{key}")?,
+                    (true, false) => write!(f, " -{key}")?,
+                    _ => {}
+                }
+            }
+
+            writeln!(f)?;
+            previous = frame.keypad;
+        }
+
+        Ok(())
+    }
+}
+
+/// Runs a machine frame by frame while recording a movie
+pub struct MovieRecorder {
+    movie: Movie,
+    seeds: XorShiftRng,
+}
+
+impl MovieRecorder {
+    /// Starts recording a machine which was just started, the frame seeds being derived from `seed`
+    pub fn new(machine: &Machine, seed: u32) -> Self {
+        Self {
+            movie: Movie {
+                rom: RomHash::of(machine.rom()),
+                frames: Vec::new(),
+            },
+            seeds: XorShiftRng::new(seed),
+        }
+    }
+
+    /// Runs a frame with the given keys held down, and records it
+    pub fn run_frame(&mut self, machine: &mut Machine, keypad: Keypad) -> FrameSummary {
+        let frame = MovieFrame {
+            seed: u32::from_be_bytes([(); 4].map(|()| self.seeds.next_u8())),
+            keypad,
+        };
+
+        self.movie.frames.push(frame);
+        frame.run(machine)
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+/// Feeds the frames of a movie back to a machine
+pub struct MovieReplayer<'a> {
+    frames: core::slice::Iter<'a, MovieFrame>,
+}
+
+impl<'a> MovieReplayer<'a> {
+    /// Replays the movie on a machine which was just started, see `Movie::matches`
+    pub fn new(movie: &'a Movie) -> Self {
+        Self {
+            frames: movie.frames.iter(),
+        }
+    }
+
+    /// Runs the next frame of the movie, returns `None` once the movie is over
+    pub fn run_frame(&mut self, machine: &mut Machine) -> Option<FrameSummary> {
+        self.frames.next().map(|frame| frame.run(machine))
+    }
+
+    /// Number of frames left to replay
+    pub fn remaining(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::{make_nop_set, OpCode};
+    use crate::machine::State;
+    use crate::RegIdent;
+
+    fn make_machine() -> Machine {
+        let mut set = make_nop_set();
+        // Accumulates random bytes in V0, then the keys held down in V1
+        set.op_cxnn = &|_: OpCode, state: &mut State| {
+            let value = state.reg_read(RegIdent::V0) ^ state.rng.next_u8();
+            state.reg_write(RegIdent::V0, value);
+        };
+        set.op_exa1 = &|op: OpCode, state: &mut State| {
+            let key = Key::try_from(op.get_x().get()).unwrap();
+            if state.keypad.is_pressed(key) {
+                let value = state.reg_read(RegIdent::V1).wrapping_add(op.get_x().get());
+                state.reg_write(RegIdent::V1, value);
+            }
+        };
+
+        Machine::new(&[0xC0, 0xFF, 0xE5, 0xA1, 0xEA, 0xA1].repeat(50), set, 120)
+    }
+
+    #[test]
+    fn record_and_replay() {
+        let mut machine = make_machine();
+        let mut recorder = MovieRecorder::new(&machine, 42);
+        let mut keypad = Keypad::default();
+
+        for frame in 0..30 {
+            keypad.set(Key::K5, frame % 3 == 0);
+            keypad.set(Key::KA, frame > 20);
+            recorder.run_frame(&mut machine, keypad);
+        }
+
+        let movie = recorder.finish();
+        let text = alloc::string::ToString::to_string(&movie);
+        assert!(text.starts_with("trip-night-movie 1\nrom "));
+
+        let parsed = Movie::parse(&text).unwrap();
+        assert_eq!(parsed, movie);
+
+        let mut replayed = make_machine();
+        assert!(parsed.matches(&replayed));
+
+        let mut replayer = MovieReplayer::new(&parsed);
+        while replayer.run_frame(&mut replayed).is_some() {}
+
+        assert_eq!(replayer.remaining(), 0);
+        assert_eq!(
+            replayed.state.reg_read(RegIdent::V0),
+            machine.state.reg_read(RegIdent::V0)
+        );
+        assert_eq!(
+            replayed.state.reg_read(RegIdent::V1),
+            machine.state.reg_read(RegIdent::V1)
+        );
+        assert_eq!(replayed.state.pc, machine.state.pc);
+    }
+
+    #[test]
+    fn malformed() {
+        let rom = RomHash::of(&[]);
+        let header = alloc::format!("trip-night-movie 1\nrom {rom}\n");
+
+        assert_eq!(
+            Movie::parse(&alloc::format!("{header}0000002a +5\n"))
+                .unwrap()
+                .frames
+                .len(),
+            1
+        );
+        assert_eq!(Movie::parse("trip-night-movie 2\n"), Err(MovieError { line: 1 }));
+        assert_eq!(
+            Movie::parse("trip-night-movie 1\nrom 12\n"),
+            Err(MovieError { line: 2 })
+        );
+        assert_eq!(
+            Movie::parse(&alloc::format!("{header}0000002a +5\nfoo\n")),
+            Err(MovieError { line: 4 })
+        );
+        assert_eq!(
+            Movie::parse(&alloc::format!("{header}0000002a *5\n")),
+            Err(MovieError { line: 3 })
+        );
+        assert_eq!(
+            Movie::parse(&alloc::format!("{header}0000002a +G\n")),
+            Err(MovieError { line: 3 })
+        );
+    }
+}
@@ -0,0 +1,76 @@
+//! Pacing the execution with integer math only
+//!
+//! Elapsed time is counted in whole microseconds and fractional cycles are kept as an exact
+//! remainder, so that the long-term average frequency is exact even on microcontrollers without
+//! FPU. The frontends wrap this pacer, converting the durations measured by their clocks.
+
+/// Microseconds in a second
+pub const MICROS_PER_SEC: u64 = 1_000_000;
+
+/// Converts elapsed time, in microseconds, into a number of cycles to run
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pacer {
+    frequency_hz: usize,
+    /// Fraction of a cycle not run yet, in millionths of a cycle
+    remainder: u64,
+    /// Longest elapsed time caught up at once (e.g. after the window was dragged or minimized)
+    max_catch_up_us: u64,
+}
+
+impl Pacer {
+    pub fn new(frequency_hz: usize) -> Self {
+        Self {
+            frequency_hz,
+            remainder: 0,
+            max_catch_up_us: 100_000,
+        }
+    }
+
+    pub fn with_max_catch_up_us(mut self, max_catch_up_us: u64) -> Self {
+        self.max_catch_up_us = max_catch_up_us;
+        self
+    }
+
+    pub fn frequency_hz(&self) -> usize {
+        self.frequency_hz
+    }
+
+    pub fn set_frequency_hz(&mut self, frequency_hz: usize) {
+        self.frequency_hz = frequency_hz;
+        self.remainder = 0;
+    }
+
+    /// Number of cycles to run for the given elapsed time
+    pub fn cycles_for_us(&mut self, elapsed_us: u64) -> usize {
+        let elapsed_us = core::cmp::min(elapsed_us, self.max_catch_up_us);
+        let cycles = elapsed_us
+            .saturating_mul(self.frequency_hz as u64)
+            .saturating_add(self.remainder);
+        self.remainder = cycles % MICROS_PER_SEC;
+        usize::try_from(cycles / MICROS_PER_SEC).unwrap_or(usize::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn carries_fractional_cycles() {
+        // 11.669 cycles per frame of 16 667 µs
+        let mut pacer = Pacer::new(700);
+        let total: usize = (0..60).map(|_| pacer.cycles_for_us(16_667)).sum();
+        assert_eq!(total, 700);
+
+        // 233.3338 cycles per third of a second
+        let mut pacer = Pacer::new(700).with_max_catch_up_us(MICROS_PER_SEC);
+        let total: usize = (0..3).map(|_| pacer.cycles_for_us(333_334)).sum();
+        assert_eq!(total, 700);
+    }
+
+    #[test]
+    fn caps_catch_up() {
+        let mut pacer = Pacer::new(1000).with_max_catch_up_us(50_000);
+        assert_eq!(pacer.cycles_for_us(10 * MICROS_PER_SEC), 50);
+    }
+}
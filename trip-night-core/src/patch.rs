@@ -0,0 +1,120 @@
+//! Instructions replacing the ones of an instruction set at runtime
+//!
+//! The slots of an `InstructionSet` are `&'static dyn Instruction`, which rules out closures
+//! capturing configuration or host callbacks. Patches are owned instructions instead, registered
+//! slot by slot on a running machine (see `Machine::patches`) and executed in place of the
+//! instruction set:
+//!
+//! ```ignore
+//! let offset = 2;
+//! machine.patches.patch(OP_7XNN, move |op: OpCode, state: &mut State| {
+//!     state.reg_write(op.get_x(), state.reg_read(op.get_x()).wrapping_add(op.get_nn() + offset))
+//! })?;
+//! ```
+//!
+//! Patches are reference counted, so a cloned machine shares them with the original.
+
+use alloc::rc::Rc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::instruction::{Instruction, SLOT_COUNT};
+
+/// Patched instructions per slot, see the module documentation
+#[derive(Clone)]
+pub struct InstructionPatches {
+    /// One entry per slot, indexed by the `OP_*` constants
+    patches: Vec<Option<Rc<dyn Instruction>>>,
+}
+
+/// Slot of a patch which doesn't exist
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnknownSlotError {
+    /// The slot, not lower than `SLOT_COUNT`
+    pub slot: usize,
+}
+
+impl fmt::Display for UnknownSlotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown instruction slot {}", self.slot)
+    }
+}
+
+impl Default for InstructionPatches {
+    fn default() -> Self {
+        Self {
+            patches: vec![None; SLOT_COUNT],
+        }
+    }
+}
+
+impl InstructionPatches {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Executes the instruction in place of the slot, returning the patch it replaces
+    pub fn patch(
+        &mut self,
+        slot: usize,
+        instruction: impl Instruction + 'static,
+    ) -> Result<Option<Rc<dyn Instruction>>, UnknownSlotError> {
+        let patch = self.patches.get_mut(slot).ok_or(UnknownSlotError { slot })?;
+        Ok(patch.replace(Rc::new(instruction)))
+    }
+
+    /// Restores the instruction of the set in the slot, returning the removed patch
+    pub fn unpatch(&mut self, slot: usize) -> Option<Rc<dyn Instruction>> {
+        self.patches.get_mut(slot)?.take()
+    }
+
+    /// Patched instruction of a slot, if any
+    pub fn get(&self, slot: usize) -> Option<&dyn Instruction> {
+        self.patches.get(slot)?.as_deref()
+    }
+
+    /// Removes every patch
+    pub fn clear(&mut self) {
+        self.patches.fill(None);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patches.iter().all(Option::is_none)
+    }
+}
+
+impl fmt::Debug for InstructionPatches {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let slots = self.patches.iter().enumerate().filter(|(_, patch)| patch.is_some());
+        f.debug_set().entries(slots.map(|(slot, _)| slot)).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::{OpCode, OP_00E0, OP_6XNN};
+    use crate::machine::State;
+
+    #[test]
+    fn patch_slots() {
+        let mut patches = InstructionPatches::new();
+        assert!(patches.is_empty());
+
+        let first = patches.patch(OP_6XNN, |_: OpCode, _: &mut State| {});
+        assert!(first.unwrap().is_none());
+        let second = patches.patch(OP_6XNN, |_: OpCode, _: &mut State| {});
+        assert!(second.unwrap().is_some());
+
+        assert!(patches.get(OP_6XNN).is_some());
+        assert!(patches.get(OP_00E0).is_none());
+        assert_eq!(
+            patches.patch(SLOT_COUNT, |_: OpCode, _: &mut State| {}).err(),
+            Some(UnknownSlotError { slot: SLOT_COUNT })
+        );
+
+        assert!(patches.unpatch(OP_6XNN).is_some());
+        assert!(patches.is_empty());
+    }
+}
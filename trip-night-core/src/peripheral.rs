@@ -0,0 +1,212 @@
+//! Memory-mapped peripherals, answering the accesses to ranges of RAM in place of the RAM
+//!
+//! Peripherals are mapped onto the state (see `State::peripherals`) and reached by the `State::ram_read`
+//! and `State::ram_write` accessors the instructions go through, fetching included. Timers, serial
+//! ports or save RAM can thus be experimented with without touching the instructions:
+//!
+//! ```ignore
+//! machine.state.peripherals.map(Address::new(0xF00).unwrap(), 0x10, SaveRam::default())?;
+//! ```
+//!
+//! The RAM under a peripheral is left untouched, and still shows through `State::ram` and
+//! `State::ram_slice`, as well as in snapshots: peripherals are cloned along with the state, but
+//! their own contents aren't saved by snapshots nor restored by rewinding.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
+
+use crate::{Address, RAM_SIZE};
+
+/// Device answering the accesses to the range of RAM it is mapped onto
+///
+/// Reads don't take the peripheral mutably since they also happen when the machine merely inspects
+/// the memory; peripherals with side effects on reads can rely on interior mutability.
+pub trait Peripheral {
+    /// Byte at the given address, within the mapped range
+    fn read(&self, addr: Address) -> u8;
+
+    /// Stores the byte at the given address, within the mapped range
+    fn write(&mut self, addr: Address, value: u8);
+}
+
+/// Object-safe cloneable peripheral, for peripherals mapped onto the state
+pub trait DynPeripheral: Peripheral {
+    fn clone_box(&self) -> Box<dyn DynPeripheral>;
+}
+
+impl<T> DynPeripheral for T
+where
+    T: Peripheral + Clone + 'static,
+{
+    fn clone_box(&self) -> Box<dyn DynPeripheral> {
+        Box::new(self.clone())
+    }
+}
+
+/// A peripheral and the range of RAM it is mapped onto
+struct Mapping {
+    start: Address,
+    /// Number of bytes mapped from `start`, not wrapping around the address space
+    len: u16,
+    peripheral: Box<dyn DynPeripheral>,
+}
+
+impl Mapping {
+    fn contains(&self, addr: Address) -> bool {
+        (self.start.as_usize()..self.start.as_usize() + usize::from(self.len)).contains(&addr.as_usize())
+    }
+}
+
+impl Clone for Mapping {
+    fn clone(&self) -> Self {
+        Self {
+            start: self.start,
+            len: self.len,
+            peripheral: self.peripheral.clone_box(),
+        }
+    }
+}
+
+/// Peripherals mapped onto non-overlapping ranges of RAM
+#[derive(Clone, Default)]
+pub struct Peripherals {
+    mappings: Vec<Mapping>,
+}
+
+/// A range of RAM which can't be mapped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MapError {
+    /// The range is empty or goes past the end of the RAM
+    OutOfBounds { start: Address, len: u16 },
+    /// The range overlaps the one of another peripheral
+    Overlap { start: Address, len: u16 },
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::OutOfBounds { start, len } => write!(f, "{len} bytes at {start} don't fit in RAM"),
+            MapError::Overlap { start, len } => write!(f, "{len} bytes at {start} overlap another peripheral"),
+        }
+    }
+}
+
+impl Peripherals {
+    /// Maps the peripheral onto the `len` bytes from `start`
+    pub fn map(
+        &mut self,
+        start: Address,
+        len: u16,
+        peripheral: impl Peripheral + Clone + 'static,
+    ) -> Result<(), MapError> {
+        let range = start.as_usize()..start.as_usize() + usize::from(len);
+
+        if range.is_empty() || range.end > RAM_SIZE {
+            return Err(MapError::OutOfBounds { start, len });
+        }
+
+        let overlaps = |mapping: &Mapping| {
+            let mapped = mapping.start.as_usize()..mapping.start.as_usize() + usize::from(mapping.len);
+            mapped.start < range.end && range.start < mapped.end
+        };
+
+        if self.mappings.iter().any(overlaps) {
+            return Err(MapError::Overlap { start, len });
+        }
+
+        self.mappings.push(Mapping {
+            start,
+            len,
+            peripheral: Box::new(peripheral),
+        });
+
+        Ok(())
+    }
+
+    /// Removes the peripheral mapped at the given address, returns it if there was one
+    pub fn unmap(&mut self, start: Address) -> Option<Box<dyn DynPeripheral>> {
+        let position = self.mappings.iter().position(|mapping| mapping.start == start)?;
+        Some(self.mappings.remove(position).peripheral)
+    }
+
+    pub fn clear(&mut self) {
+        self.mappings.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mappings.is_empty()
+    }
+
+    /// Peripheral mapped onto the address, if any
+    pub fn get(&self, addr: Address) -> Option<&dyn DynPeripheral> {
+        self.mappings
+            .iter()
+            .find(|mapping| mapping.contains(addr))
+            .map(|mapping| &*mapping.peripheral)
+    }
+
+    pub fn get_mut(&mut self, addr: Address) -> Option<&mut (dyn DynPeripheral + 'static)> {
+        self.mappings
+            .iter_mut()
+            .find(|mapping| mapping.contains(addr))
+            .map(|mapping| &mut *mapping.peripheral)
+    }
+}
+
+impl fmt::Debug for Peripherals {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ranges = self.mappings.iter().map(|mapping| (mapping.start, mapping.len));
+        f.debug_list().entries(ranges).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Latch(u8);
+
+    impl Peripheral for Latch {
+        fn read(&self, _: Address) -> u8 {
+            self.0
+        }
+
+        fn write(&mut self, _: Address, value: u8) {
+            self.0 = value;
+        }
+    }
+
+    #[test]
+    fn mappings() {
+        let addr = |value| Address::new(value).unwrap();
+        let mut peripherals = Peripherals::default();
+
+        assert_eq!(peripherals.map(addr(0xF00), 0x10, Latch::default()), Ok(()));
+        assert_eq!(
+            peripherals.map(addr(0xF0F), 1, Latch::default()),
+            Err(MapError::Overlap {
+                start: addr(0xF0F),
+                len: 1
+            })
+        );
+        assert_eq!(
+            peripherals.map(addr(0xFFF), 2, Latch::default()),
+            Err(MapError::OutOfBounds {
+                start: addr(0xFFF),
+                len: 2
+            })
+        );
+
+        peripherals.get_mut(addr(0xF04)).unwrap().write(addr(0xF04), 0x42);
+        assert_eq!(
+            peripherals.get(addr(0xF0F)).map(|latch| latch.read(addr(0xF0F))),
+            Some(0x42)
+        );
+        assert!(peripherals.get(addr(0xF10)).is_none());
+
+        assert!(peripherals.unmap(addr(0xF00)).is_some());
+        assert!(peripherals.is_empty());
+    }
+}
@@ -0,0 +1,142 @@
+//! Profiling the execution, how often each address is executed
+//!
+//! Profiling is opt-in (see `Machine::enable_profiling`): a machine then counts the instructions
+//! executed at every address, or only one every N instructions to lower the overhead. The hottest
+//! addresses point at the code worth optimizing, and at busy-wait loops.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{Address, RAM_SIZE};
+
+/// Executions counted at an address
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HotSpot {
+    pub addr: Address,
+    pub count: u32,
+}
+
+/// Execution counts per address
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Profile {
+    /// One counter per address of the address space
+    counts: Vec<u32>,
+    interval: usize,
+    /// Instructions to skip before the next sample
+    countdown: usize,
+    samples: u64,
+}
+
+impl Profile {
+    /// Samples one instruction every `interval` executed instructions, 1 counting them all
+    pub fn new(interval: usize) -> Self {
+        Self {
+            counts: vec![0; RAM_SIZE],
+            interval: core::cmp::max(interval, 1),
+            countdown: 0,
+            samples: 0,
+        }
+    }
+
+    pub(crate) fn record(&mut self, pc: Address) {
+        if self.countdown > 0 {
+            self.countdown -= 1;
+            return;
+        }
+
+        self.countdown = self.interval - 1;
+        self.samples += 1;
+
+        let count = &mut self.counts[pc.as_usize()];
+        *count = count.saturating_add(1);
+    }
+
+    pub fn interval(&self) -> usize {
+        self.interval
+    }
+
+    /// Number of instructions sampled
+    pub fn samples(&self) -> u64 {
+        self.samples
+    }
+
+    /// Number of samples of the instruction at this address
+    pub fn count(&self, addr: Address) -> u32 {
+        self.counts[addr.as_usize()]
+    }
+
+    /// The `n` addresses sampled the most, hottest first
+    pub fn hottest(&self, n: usize) -> Vec<HotSpot> {
+        let mut hot_spots: Vec<HotSpot> = (0..=Address::MAX)
+            .map(Address::new_masked)
+            .map(|addr| HotSpot {
+                addr,
+                count: self.count(addr),
+            })
+            .filter(|hot_spot| hot_spot.count > 0)
+            .collect();
+
+        // Stable sort, ties are kept in increasing address order
+        hot_spots.sort_by(|a, b| b.count.cmp(&a.count));
+        hot_spots.truncate(n);
+        hot_spots
+    }
+
+    pub fn clear(&mut self) {
+        self.counts.fill(0);
+        self.countdown = 0;
+        self.samples = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hottest() {
+        let mut profile = Profile::new(1);
+
+        for _ in 0..3 {
+            profile.record(Address(0x204));
+        }
+        profile.record(Address(0x200));
+        profile.record(Address(0x202));
+        profile.record(Address(0x202));
+
+        assert_eq!(profile.samples(), 6);
+        assert_eq!(profile.count(Address(0x202)), 2);
+        assert_eq!(
+            profile.hottest(2),
+            [
+                HotSpot {
+                    addr: Address(0x204),
+                    count: 3
+                },
+                HotSpot {
+                    addr: Address(0x202),
+                    count: 2
+                },
+            ]
+        );
+        assert_eq!(profile.hottest(10).len(), 3);
+
+        profile.clear();
+        assert!(profile.hottest(10).is_empty());
+    }
+
+    #[test]
+    fn sampling() {
+        let mut profile = Profile::new(3);
+
+        for offset in 0..10 {
+            profile.record(Address(0x200 + offset * 2));
+        }
+
+        assert_eq!(profile.samples(), 4);
+        assert_eq!(profile.count(Address(0x200)), 1);
+        assert_eq!(profile.count(Address(0x202)), 0);
+        assert_eq!(profile.count(Address(0x206)), 1);
+        assert_eq!(profile.count(Address(0x212)), 1);
+    }
+}
@@ -0,0 +1,112 @@
+//! Behaviours differing between CHIP-8 platforms
+
+/// Set of platform-specific behaviours
+///
+/// The default value matches `make_standard_set` of the instruction crate: every quirk is disabled.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Quirks {
+    /// 8XY6 and 8XYE shift Vy and store the result in Vx, instead of shifting Vx in place
+    pub shift_uses_vy: bool,
+    /// 8XY1, 8XY2 and 8XY3 reset VF to 0
+    pub logic_resets_vf: bool,
+    /// FX55 and FX65 leave I pointing after the last accessed register
+    pub memory_increments_index: bool,
+    /// BNNN is interpreted as BXNN and jumps to XNN + Vx, instead of NNN + V0
+    pub jump_uses_vx: bool,
+    /// Sprites are clipped at the edges of the screen instead of wrapping around
+    pub clip_sprites: bool,
+    /// DXYN waits for the next vertical blank before drawing
+    pub display_wait: bool,
+    /// FX1E sets VF to 1 when I overflows past 0xFFF, and to 0 otherwise (CHIP-8 interpreter for Amiga)
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub index_overflow_sets_vf: bool,
+    /// FX0A stores the key as soon as it is pressed, instead of waiting for it to be released
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub key_press_only: bool,
+}
+
+impl Quirks {
+    /// Original CHIP-8 interpreter of the COSMAC VIP
+    pub const fn cosmac_vip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            logic_resets_vf: true,
+            memory_increments_index: true,
+            jump_uses_vx: false,
+            clip_sprites: true,
+            display_wait: true,
+            index_overflow_sets_vf: false,
+            key_press_only: false,
+        }
+    }
+
+    /// SUPER-CHIP 1.1, as found on HP48 calculators
+    pub const fn schip() -> Self {
+        Self {
+            shift_uses_vy: false,
+            logic_resets_vf: false,
+            memory_increments_index: false,
+            jump_uses_vx: true,
+            clip_sprites: true,
+            display_wait: false,
+            index_overflow_sets_vf: false,
+            key_press_only: false,
+        }
+    }
+
+    /// XO-CHIP, as implemented by Octo
+    pub const fn xo_chip() -> Self {
+        Self {
+            shift_uses_vy: true,
+            logic_resets_vf: false,
+            memory_increments_index: true,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            display_wait: false,
+            index_overflow_sets_vf: false,
+            key_press_only: false,
+        }
+    }
+
+    /// Looks up a profile by name: `standard`, `cosmac-vip`, `schip` or `xo-chip`
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name {
+            "standard" => Some(Self::default()),
+            "cosmac-vip" => Some(Self::cosmac_vip()),
+            "schip" => Some(Self::schip()),
+            "xo-chip" => Some(Self::xo_chip()),
+            _ => None,
+        }
+    }
+
+    /// Name of the profile, if these quirks match one (see `by_name`)
+    pub fn name(&self) -> Option<&'static str> {
+        ["standard", "cosmac-vip", "schip", "xo-chip"]
+            .into_iter()
+            .find(|name| Self::by_name(name).as_ref() == Some(self))
+    }
+}
+
+/// Platform emulated by a machine, recorded in snapshots
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Variant {
+    #[default]
+    Chip8,
+    SuperChip,
+    XoChip,
+}
+
+impl Variant {
+    /// Platform of a quirks profile, CHIP-8 for the profiles which aren't SUPER-CHIP or XO-CHIP
+    pub fn of(quirks: &Quirks) -> Self {
+        if *quirks == Quirks::schip() {
+            Variant::SuperChip
+        } else if *quirks == Quirks::xo_chip() {
+            Variant::XoChip
+        } else {
+            Variant::Chip8
+        }
+    }
+}
@@ -0,0 +1,122 @@
+//! Rewinding the execution, frame by frame
+//!
+//! A `Rewinder` takes a snapshot every N frames into a ring buffer of fixed capacity, the oldest
+//! snapshots being dropped first. Rewinding restores the newest snapshot at least k frames old, so
+//! the machine goes back in steps of N frames, up to N × capacity frames.
+
+use alloc::collections::VecDeque;
+
+use crate::machine::Machine;
+use crate::snapshot::Snapshot;
+
+/// Ring buffer of periodic snapshots
+pub struct Rewinder {
+    interval: u64,
+    capacity: usize,
+    /// Number of frames recorded
+    frame: u64,
+    /// Snapshots with the frame they were taken at, oldest first
+    snapshots: VecDeque<(u64, Snapshot)>,
+}
+
+impl Rewinder {
+    /// Keeps up to `capacity` snapshots, taken every `interval` frames
+    pub fn new(interval: u64, capacity: usize) -> Self {
+        Self {
+            interval: core::cmp::max(interval, 1),
+            capacity: core::cmp::max(capacity, 1),
+            frame: 0,
+            snapshots: VecDeque::new(),
+        }
+    }
+
+    /// Accounts for a frame run by the machine, to be called once after each frame
+    pub fn record_frame(&mut self, machine: &Machine) {
+        self.frame += 1;
+
+        if self.frame % self.interval == 0 {
+            if self.snapshots.len() == self.capacity {
+                self.snapshots.pop_front();
+            }
+
+            self.snapshots.push_back((self.frame, machine.snapshot()));
+        }
+    }
+
+    /// Rolls the machine back at least `frames` frames, returns how many frames were actually rolled back
+    ///
+    /// Nothing happens, and 0 is returned, when no snapshot is old enough. Snapshots newer than the
+    /// restored one are dropped, so that recording resumes from there.
+    pub fn rewind(&mut self, machine: &mut Machine, frames: u64) -> u64 {
+        let Some(target) = self.frame.checked_sub(frames) else {
+            return 0;
+        };
+
+        let Some(idx) = self.snapshots.iter().rposition(|(frame, _)| *frame <= target) else {
+            return 0;
+        };
+
+        self.snapshots.truncate(idx + 1);
+        let (frame, snapshot) = &self.snapshots[idx];
+        machine.restore(snapshot);
+
+        let rewound = self.frame - frame;
+        self.frame = *frame;
+        rewound
+    }
+
+    /// Number of frames which can be rolled back at most
+    pub fn available_frames(&self) -> u64 {
+        self.snapshots.front().map_or(0, |(frame, _)| self.frame - frame)
+    }
+
+    /// Drops every snapshot, e.g. after resetting the machine
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::{make_nop_set, OpCode};
+    use crate::machine::State;
+    use crate::RegIdent;
+
+    #[test]
+    fn rewind() {
+        let mut set = make_nop_set();
+        set.op_7xnn = &|op: OpCode, state: &mut State| {
+            state.reg_write(op.get_x(), state.reg_read(op.get_x()).wrapping_add(op.get_nn()));
+        };
+
+        // One V0 += 1 per frame
+        let rom = [0x70, 0x01].repeat(100);
+        let mut machine = Machine::new(&rom, set, 60);
+        let mut rewinder = Rewinder::new(4, 3);
+
+        for _ in 0..18 {
+            machine.run_frame();
+            rewinder.record_frame(&machine);
+        }
+
+        // Snapshots at frames 8, 12 and 16
+        assert_eq!(rewinder.available_frames(), 10);
+        assert_eq!(rewinder.rewind(&mut machine, 20), 0);
+        assert_eq!(machine.state.reg_read(RegIdent::V0), 18);
+
+        assert_eq!(rewinder.rewind(&mut machine, 5), 6);
+        assert_eq!(machine.state.reg_read(RegIdent::V0), 12);
+
+        machine.run_frame();
+        rewinder.record_frame(&machine);
+        assert_eq!(machine.state.reg_read(RegIdent::V0), 13);
+
+        assert_eq!(rewinder.rewind(&mut machine, 1), 1);
+        assert_eq!(machine.state.reg_read(RegIdent::V0), 12);
+        assert_eq!(rewinder.available_frames(), 4);
+
+        rewinder.clear();
+        assert_eq!(rewinder.rewind(&mut machine, 1), 0);
+    }
+}
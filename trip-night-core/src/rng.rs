@@ -0,0 +1,165 @@
+//! Random number generation for the CXNN instruction
+
+#[cfg(feature = "alloc")]
+use alloc::boxed::Box;
+
+/// Source of random bytes
+pub trait Rng {
+    fn next_u8(&mut self) -> u8;
+}
+
+/// xorshift32 generator, fast and good enough for games
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct XorShiftRng {
+    state: u32,
+}
+
+impl XorShiftRng {
+    const DEFAULT_SEED: u32 = 0x2545_F491;
+
+    pub fn new(seed: u32) -> Self {
+        // A zero state would only ever produce zeros
+        let state = if seed == 0 { Self::DEFAULT_SEED } else { seed };
+        Self { state }
+    }
+}
+
+impl Default for XorShiftRng {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_SEED)
+    }
+}
+
+impl Rng for XorShiftRng {
+    fn next_u8(&mut self) -> u8 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state.to_be_bytes()[0]
+    }
+}
+
+/// Deterministic generator replaying a fixed sequence of bytes in a loop, mostly useful for tests
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SequenceRng {
+    values: &'static [u8],
+    position: usize,
+}
+
+impl SequenceRng {
+    pub fn new(values: &'static [u8]) -> Self {
+        assert!(!values.is_empty(), "empty sequence");
+        Self { values, position: 0 }
+    }
+}
+
+impl Rng for SequenceRng {
+    fn next_u8(&mut self) -> u8 {
+        let value = self.values[self.position];
+        self.position = (self.position + 1) % self.values.len();
+        value
+    }
+}
+
+/// Adapter for any `rand_core` generator
+#[cfg(feature = "rand_core")]
+#[derive(Clone, Debug)]
+pub struct RandCoreRng<R>(pub R);
+
+#[cfg(feature = "rand_core")]
+impl<R: rand_core::RngCore> Rng for RandCoreRng<R> {
+    fn next_u8(&mut self) -> u8 {
+        self.0.next_u32().to_be_bytes()[0]
+    }
+}
+
+/// Object-safe cloneable generator, for custom generators stored in the machine
+#[cfg(feature = "alloc")]
+pub trait DynRng: Rng {
+    fn clone_box(&self) -> Box<dyn DynRng>;
+}
+
+#[cfg(feature = "alloc")]
+impl<T> DynRng for T
+where
+    T: Rng + Clone + 'static,
+{
+    fn clone_box(&self) -> Box<dyn DynRng> {
+        Box::new(self.clone())
+    }
+}
+
+/// Generator used by the machine
+pub enum MachineRng {
+    XorShift(XorShiftRng),
+    Sequence(SequenceRng),
+    #[cfg(feature = "alloc")]
+    Custom(Box<dyn DynRng>),
+}
+
+impl Default for MachineRng {
+    fn default() -> Self {
+        Self::XorShift(XorShiftRng::default())
+    }
+}
+
+impl Clone for MachineRng {
+    fn clone(&self) -> Self {
+        match self {
+            Self::XorShift(rng) => Self::XorShift(rng.clone()),
+            Self::Sequence(rng) => Self::Sequence(rng.clone()),
+            #[cfg(feature = "alloc")]
+            Self::Custom(rng) => Self::Custom(rng.clone_box()),
+        }
+    }
+}
+
+impl Rng for MachineRng {
+    fn next_u8(&mut self) -> u8 {
+        match self {
+            Self::XorShift(rng) => rng.next_u8(),
+            Self::Sequence(rng) => rng.next_u8(),
+            #[cfg(feature = "alloc")]
+            Self::Custom(rng) => rng.next_u8(),
+        }
+    }
+}
+
+impl From<XorShiftRng> for MachineRng {
+    fn from(rng: XorShiftRng) -> Self {
+        Self::XorShift(rng)
+    }
+}
+
+impl From<SequenceRng> for MachineRng {
+    fn from(rng: SequenceRng) -> Self {
+        Self::Sequence(rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xorshift_is_seedable() {
+        let mut a = XorShiftRng::new(42);
+        let mut b = XorShiftRng::new(42);
+        let mut c = XorShiftRng::new(43);
+
+        let a: [u8; 16] = core::array::from_fn(|_| a.next_u8());
+        let b: [u8; 16] = core::array::from_fn(|_| b.next_u8());
+        let c: [u8; 16] = core::array::from_fn(|_| c.next_u8());
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+        assert!(a.iter().any(|value| *value != a[0]));
+    }
+
+    #[test]
+    fn sequence_loops() {
+        let mut rng = MachineRng::from(SequenceRng::new(&[1, 2, 3]));
+        let values: [u8; 5] = core::array::from_fn(|_| rng.next_u8());
+        assert_eq!(values, [1, 2, 3, 1, 2]);
+    }
+}
@@ -0,0 +1,192 @@
+//! Curated database of well-known ROMs and the platform they were written for
+//!
+//! ROMs are identified by the SHA-1 hash of their content, as in the community CHIP-8 database, so
+//! that entries can be cross-checked against it. Only dumps whose behavior was verified against the
+//! listed profile are added.
+//!
+//! The data itself is only included with the `rom-db` feature, hashes and profiles are always available
+//! for frontends to store their own.
+
+use core::fmt;
+use core::str::FromStr;
+
+use crate::quirks::Quirks;
+
+/// SHA-1 hash of a ROM
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RomHash(pub [u8; 20]);
+
+impl RomHash {
+    pub fn of(rom: &[u8]) -> Self {
+        let mut state: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+
+        let mut blocks = rom.chunks_exact(64);
+
+        for block in &mut blocks {
+            sha1_compress(&mut state, block);
+        }
+
+        // Padding: a single set bit, zeroes, then the length in bits over the last 8 bytes
+        let remainder = blocks.remainder();
+        let mut tail = [0; 128];
+        tail[..remainder.len()].copy_from_slice(remainder);
+        tail[remainder.len()] = 0x80;
+
+        let tail_len = if remainder.len() < 56 { 64 } else { 128 };
+        let bit_len = (rom.len() as u64) * 8;
+        tail[tail_len - 8..tail_len].copy_from_slice(&bit_len.to_be_bytes());
+
+        for block in tail[..tail_len].chunks_exact(64) {
+            sha1_compress(&mut state, block);
+        }
+
+        let mut hash = [0; 20];
+
+        for (bytes, word) in hash.chunks_exact_mut(4).zip(state) {
+            bytes.copy_from_slice(&word.to_be_bytes());
+        }
+
+        Self(hash)
+    }
+}
+
+fn sha1_compress(state: &mut [u32; 5], block: &[u8]) {
+    let mut schedule = [0u32; 80];
+
+    for (word, bytes) in schedule.iter_mut().zip(block.chunks_exact(4)) {
+        *word = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    }
+
+    for idx in 16..80 {
+        schedule[idx] =
+            (schedule[idx - 3] ^ schedule[idx - 8] ^ schedule[idx - 14] ^ schedule[idx - 16]).rotate_left(1);
+    }
+
+    let [mut a, mut b, mut c, mut d, mut e] = *state;
+
+    for (idx, word) in schedule.into_iter().enumerate() {
+        let (f, k) = match idx {
+            0..=19 => ((b & c) | (!b & d), 0x5A82_7999),
+            20..=39 => (b ^ c ^ d, 0x6ED9_EBA1),
+            40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+            _ => (b ^ c ^ d, 0xCA62_C1D6),
+        };
+
+        let temp = a
+            .rotate_left(5)
+            .wrapping_add(f)
+            .wrapping_add(e)
+            .wrapping_add(k)
+            .wrapping_add(word);
+
+        e = d;
+        d = c;
+        c = b.rotate_left(30);
+        b = a;
+        a = temp;
+    }
+
+    for (value, added) in state.iter_mut().zip([a, b, c, d, e]) {
+        *value = value.wrapping_add(added);
+    }
+}
+
+impl fmt::Display for RomHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{byte:02x}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The string isn't made of 40 hexadecimal digits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseRomHashError;
+
+impl FromStr for RomHash {
+    type Err = ParseRomHashError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 40 || !s.is_ascii() {
+            return Err(ParseRomHashError);
+        }
+
+        let mut hash = [0; 20];
+
+        for (byte, digits) in hash.iter_mut().zip(s.as_bytes().chunks_exact(2)) {
+            let digits = core::str::from_utf8(digits).map_err(|_| ParseRomHashError)?;
+            *byte = u8::from_str_radix(digits, 16).map_err(|_| ParseRomHashError)?;
+        }
+
+        Ok(Self(hash))
+    }
+}
+
+/// Settings a ROM requires to run as intended
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RomProfile {
+    pub title: &'static str,
+    pub quirks: Quirks,
+    pub frequency_hz: usize,
+}
+
+/// Known ROMs, sorted by hash
+#[cfg(feature = "rom-db")]
+pub static PROFILES: &[(RomHash, RomProfile)] = &[];
+
+/// Looks up the profile of a well-known ROM
+#[cfg(feature = "rom-db")]
+pub fn lookup(rom: &[u8]) -> Option<&'static RomProfile> {
+    lookup_in(PROFILES, &RomHash::of(rom))
+}
+
+/// Looks up a hash in a database sorted by hash
+pub fn lookup_in<'db>(profiles: &'db [(RomHash, RomProfile)], hash: &RomHash) -> Option<&'db RomProfile> {
+    profiles
+        .binary_search_by(|(entry, _)| entry.0.cmp(&hash.0))
+        .ok()
+        .map(|idx| &profiles[idx].1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(hex: &str) -> RomHash {
+        hex.parse().unwrap()
+    }
+
+    #[test]
+    fn sha1() {
+        assert_eq!(RomHash::of(b""), hash("da39a3ee5e6b4b0d3255bfef95601890afd80709"));
+        assert_eq!(RomHash::of(b"abc"), hash("a9993e364706816aba3e25717850c26c9cd0d89d"));
+        assert_eq!(
+            RomHash::of(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            hash("84983e441c3bd26ebaae4aa1f95129e5e54670f1")
+        );
+    }
+
+    #[test]
+    fn lookup_sorted() {
+        #[cfg(feature = "rom-db")]
+        assert!(PROFILES.windows(2).all(|pair| pair[0].0 .0 < pair[1].0 .0));
+
+        let profile = |title| RomProfile {
+            title,
+            quirks: Quirks::schip(),
+            frequency_hz: 1000,
+        };
+        let profiles = [
+            (RomHash::of(b"abc"), profile("abc")),
+            (RomHash::of(b""), profile("empty")),
+        ];
+
+        assert_eq!(lookup_in(&profiles, &RomHash::of(b"")).unwrap().title, "empty");
+        assert_eq!(lookup_in(&profiles, &RomHash::of(b"abd")), None);
+        assert_eq!(hash("A9993E364706816ABA3E25717850C26C9CD0D89D"), RomHash::of(b"abc"));
+        assert_eq!("a9993e".parse::<RomHash>(), Err(ParseRomHashError));
+    }
+}
@@ -1,30 +1,129 @@
 use core::fmt;
 
-#[derive(Clone, Default, Debug)]
+/// Display resolution
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Resolution {
+    /// 64x32, the original CHIP-8 resolution
+    #[default]
+    Lores,
+    /// 128x64, introduced by SUPER-CHIP
+    Hires,
+}
+
+/// Number of drawing planes (XO-CHIP)
+pub const PLANE_COUNT: usize = 2;
+
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Screen {
-    inner: [u64; 32],
+    planes: [Plane; PLANE_COUNT],
+    /// Bit n set when plane n is selected for drawing
+    plane_mask: u8,
+    resolution: Resolution,
+    #[cfg_attr(feature = "serde", serde(skip))]
     changed: bool,
+    /// Incremented on every change, to tell copies apart
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub(crate) generation: u64,
+}
+
+impl Default for Screen {
+    fn default() -> Self {
+        Self {
+            planes: [Plane::EMPTY; PLANE_COUNT],
+            plane_mask: 0b01,
+            resolution: Resolution::default(),
+            changed: false,
+            generation: 0,
+        }
+    }
 }
 
 impl fmt::Display for Screen {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        self.inner.into_iter().try_for_each(|row| writeln!(f, "{row:064b}"))?;
-        Ok(())
+        (0..self.height()).try_for_each(|y| match self.resolution {
+            Resolution::Lores => writeln!(f, "{:064b}", self.row(y) >> 64),
+            Resolution::Hires => writeln!(f, "{:0128b}", self.row(y)),
+        })
     }
 }
 
 impl Screen {
     const MSB_ONLY: u8 = 0x1 << 7;
 
+    /// Width of the screen in the current resolution, in pixels
+    pub fn width(&self) -> u8 {
+        match self.resolution {
+            Resolution::Lores => 64,
+            Resolution::Hires => 128,
+        }
+    }
+
+    /// Height of the screen in the current resolution, in pixels
+    pub fn height(&self) -> u8 {
+        match self.resolution {
+            Resolution::Lores => 32,
+            Resolution::Hires => 64,
+        }
+    }
+
+    pub fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    /// Switches to the given resolution, all the planes are cleared
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        self.resolution = resolution;
+        self.planes.iter_mut().for_each(Plane::clear);
+        self.touch();
+    }
+
+    pub fn plane_mask(&self) -> u8 {
+        self.plane_mask
+    }
+
+    /// Selects the planes affected by drawing operations, bit n selecting plane n
+    ///
+    /// Only plane 0 is selected by default, which is the classic monochrome screen.
+    pub fn set_plane_mask(&mut self, mask: u8) {
+        self.plane_mask = mask & ((1 << PLANE_COUNT) - 1);
+    }
+
+    /// Clears the selected planes
     pub fn clear(&mut self) {
-        self.inner.iter_mut().for_each(|row| *row = 0);
-        self.changed = true;
+        self.selected_planes_mut().for_each(Plane::clear);
+        self.touch();
     }
 
     pub fn is_changed(&self) -> bool {
         self.changed
     }
 
+    /// Counter incremented on every change of the screen
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Copies the screen into `target`, unless it is already up to date
+    ///
+    /// Meant for render threads: `target` is the copy owned by the renderer, updated only through this
+    /// method, so that the lock guarding the emulated screen is held for as short as possible and not
+    /// at all when nothing changed. Returns whether the screen was copied.
+    pub fn snapshot_into(&self, target: &mut Screen) -> bool {
+        if target.generation == self.generation {
+            return false;
+        }
+
+        target.planes.clone_from(&self.planes);
+        target.plane_mask = self.plane_mask;
+        target.resolution = self.resolution;
+        target.changed = true;
+        target.generation = self.generation;
+
+        true
+    }
+
     pub fn reset_changed_flag(&mut self) {
         self.changed = false;
     }
@@ -41,65 +140,197 @@ impl Screen {
         self.flip_vectored(Self::MSB_ONLY, x, y)
     }
 
+    /// State of the pixel in the first selected plane
     pub fn get_pixel(&self, x: u8, y: u8) -> PixelState {
-        let (x, y) = Self::clamp(x, y);
-        let mask = Self::generate_mask(Self::MSB_ONLY, x);
-
-        if (self.inner[usize::from(y)] & mask) == 0 {
+        if self.get_vectored(x, y) & Self::MSB_ONLY == 0 {
             PixelState::Unset
         } else {
             PixelState::Set
         }
     }
 
+    /// Color index of the pixel, bit n being set when the pixel is set in plane n
+    pub fn get_color(&self, x: u8, y: u8) -> u8 {
+        let (x, y) = self.clamp(x, y);
+
+        self.planes
+            .iter()
+            .enumerate()
+            .filter(|(_, plane)| plane.row(self.resolution, y) & ((0x1 << 127) >> x) != 0)
+            .fold(0, |color, (idx, _)| color | 0x1 << idx)
+    }
+
     pub fn set_vectored(&mut self, vector: u8, x: u8, y: u8) {
-        let (x, y) = Self::clamp(x, y);
-        let mask = Self::generate_mask(vector, x);
+        let (x, y) = self.clamp(x, y);
+        let resolution = self.resolution;
 
-        self.inner[usize::from(y)] |= mask;
-        self.changed = true;
+        self.selected_planes_mut().for_each(|plane| match resolution {
+            Resolution::Lores => plane.lores[usize::from(y)] |= Self::generate_mask(vector, x),
+            Resolution::Hires => plane.hires[usize::from(y)] |= Self::generate_hires_mask(vector, x),
+        });
+
+        self.touch();
     }
 
     pub fn unset_vectored(&mut self, vector: u8, x: u8, y: u8) {
-        let (x, y) = Self::clamp(x, y);
-        let mask = Self::generate_mask(vector, x);
+        let (x, y) = self.clamp(x, y);
+        let resolution = self.resolution;
 
-        self.inner[usize::from(y)] &= mask;
-        self.changed = true;
+        self.selected_planes_mut().for_each(|plane| match resolution {
+            Resolution::Lores => plane.lores[usize::from(y)] &= !Self::generate_mask(vector, x),
+            Resolution::Hires => plane.hires[usize::from(y)] &= !Self::generate_hires_mask(vector, x),
+        });
+
+        self.touch();
     }
 
+    /// Flips the pixels in every selected plane, a bit unset in any of them is reported
+    ///
+    /// Pixels past the right edge of the screen are clipped.
     pub fn flip_vectored(&mut self, vector: u8, x: u8, y: u8) -> FlipResult {
-        let (x, y) = Self::clamp(x, y);
-        let mask = Self::generate_mask(vector, x);
-
-        let no_overlap = self.inner[usize::from(y)] & mask == 0;
-        self.inner[usize::from(y)] ^= mask;
-        self.changed = true;
+        let (x, y) = self.clamp(x, y);
+        self.flip_masks(y, Self::generate_mask(vector, x), Self::generate_hires_mask(vector, x))
+    }
 
-        if no_overlap {
-            FlipResult::NoUnsetBit
-        } else {
-            FlipResult::UnsetBit
-        }
+    /// Same as `flip_vectored`, but pixels past the right edge of the screen wrap around to the left edge
+    pub fn flip_vectored_wrapping(&mut self, vector: u8, x: u8, y: u8) -> FlipResult {
+        let (x, y) = self.clamp(x, y);
+        let lores_mask = u64::from_be_bytes([vector, 0, 0, 0, 0, 0, 0, 0]).rotate_right(u32::from(x));
+        let hires_mask = (u128::from(vector) << 120).rotate_right(u32::from(x));
+        self.flip_masks(y, lores_mask, hires_mask)
     }
 
+    /// Reads 8 pixels of the first selected plane
     pub fn get_vectored(&self, x: u8, y: u8) -> u8 {
-        let (x, y) = Self::clamp(x, y);
-        let mask = Self::generate_mask(0xFF, x);
+        let (x, y) = self.clamp(x, y);
 
-        ((self.inner[usize::from(y)] & mask) >> (56 - x)).try_into().unwrap()
+        let row = match self.selected_planes().next() {
+            Some(plane) => plane.row(self.resolution, y),
+            None => 0,
+        };
+
+        // Rows are left-aligned, pixels past the right edge read as unset
+        ((row << x) >> 120).try_into().unwrap()
     }
 
+    /// Iterates over the pixels set in any plane
     pub fn pixel_iter(&self) -> PixelIter<'_> {
         PixelIter {
             screen: self,
-            current_row: self.inner[0],
+            current_row: self.row(0),
             y: 0,
         }
     }
 
-    fn clamp(x: u8, y: u8) -> (u8, u8) {
-        (x & 0x3F, y & 0x1F)
+    /// Iterates over the pixels set in any plane, along with their color index (see `get_color`)
+    pub fn color_iter(&self) -> impl Iterator<Item = (u8, u8, u8)> + '_ {
+        self.pixel_iter().map(|(x, y)| (x, y, self.get_color(x, y)))
+    }
+
+    /// Size of the binary encoding of a screen
+    pub(crate) const ENCODED_LEN: usize = 2 + PLANE_COUNT * Plane::ENCODED_LEN;
+
+    /// Writes the resolution, the plane mask then the planes, in big endian
+    pub(crate) fn encode(&self, out: &mut [u8]) {
+        out[0] = match self.resolution {
+            Resolution::Lores => 0,
+            Resolution::Hires => 1,
+        };
+        out[1] = self.plane_mask;
+
+        for (plane, out) in self.planes.iter().zip(out[2..].chunks_exact_mut(Plane::ENCODED_LEN)) {
+            plane.encode(out);
+        }
+    }
+
+    /// Reads a screen written by `encode`, returns `None` if the resolution or the plane mask is invalid
+    pub(crate) fn decode(bytes: &[u8]) -> Option<Self> {
+        let resolution = match bytes[0] {
+            0 => Resolution::Lores,
+            1 => Resolution::Hires,
+            _ => return None,
+        };
+
+        if bytes[1] >= 1 << PLANE_COUNT {
+            return None;
+        }
+
+        let mut screen = Self {
+            resolution,
+            plane_mask: bytes[1],
+            changed: true,
+            ..Self::default()
+        };
+
+        for (plane, bytes) in screen
+            .planes
+            .iter_mut()
+            .zip(bytes[2..].chunks_exact(Plane::ENCODED_LEN))
+        {
+            plane.decode(bytes);
+        }
+
+        Some(screen)
+    }
+
+    /// Union of the planes at row y, left-aligned in an u128 whatever the resolution
+    fn row(&self, y: u8) -> u128 {
+        self.planes
+            .iter()
+            .fold(0, |row, plane| row | plane.row(self.resolution, y))
+    }
+
+    fn selected_planes(&self) -> impl Iterator<Item = &Plane> {
+        let mask = self.plane_mask;
+        self.planes
+            .iter()
+            .enumerate()
+            .filter(move |(idx, _)| mask & (0x1 << idx) != 0)
+            .map(|(_, plane)| plane)
+    }
+
+    fn selected_planes_mut(&mut self) -> impl Iterator<Item = &mut Plane> {
+        let mask = self.plane_mask;
+        self.planes
+            .iter_mut()
+            .enumerate()
+            .filter(move |(idx, _)| mask & (0x1 << idx) != 0)
+            .map(|(_, plane)| plane)
+    }
+
+    /// Flips row y of the selected planes with the mask matching the current resolution
+    fn flip_masks(&mut self, y: u8, lores_mask: u64, hires_mask: u128) -> FlipResult {
+        let resolution = self.resolution;
+
+        let mut no_overlap = true;
+
+        self.selected_planes_mut().for_each(|plane| match resolution {
+            Resolution::Lores => {
+                no_overlap &= plane.lores[usize::from(y)] & lores_mask == 0;
+                plane.lores[usize::from(y)] ^= lores_mask;
+            }
+            Resolution::Hires => {
+                no_overlap &= plane.hires[usize::from(y)] & hires_mask == 0;
+                plane.hires[usize::from(y)] ^= hires_mask;
+            }
+        });
+
+        self.touch();
+
+        if no_overlap {
+            FlipResult::NoUnsetBit
+        } else {
+            FlipResult::UnsetBit
+        }
+    }
+
+    fn touch(&mut self) {
+        self.changed = true;
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    fn clamp(&self, x: u8, y: u8) -> (u8, u8) {
+        (x & (self.width() - 1), y & (self.height() - 1))
     }
 
     fn generate_mask(vector: u8, x: u8) -> u64 {
@@ -107,11 +338,68 @@ impl Screen {
             .overflowing_shr(u32::from(x))
             .0
     }
+
+    fn generate_hires_mask(vector: u8, x: u8) -> u128 {
+        (u128::from(vector) << 120).overflowing_shr(u32::from(x)).0
+    }
+}
+
+/// A drawing plane, with a backing array for each resolution
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct Plane {
+    lores: [u64; 32],
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::array"))]
+    hires: [u128; 64],
+}
+
+impl Plane {
+    const EMPTY: Self = Self {
+        lores: [0; 32],
+        hires: [0; 64],
+    };
+
+    fn clear(&mut self) {
+        *self = Self::EMPTY;
+    }
+
+    const ENCODED_LEN: usize = 32 * 8 + 64 * 16;
+
+    fn encode(&self, out: &mut [u8]) {
+        let (lores, hires) = out.split_at_mut(32 * 8);
+
+        for (row, out) in self.lores.iter().zip(lores.chunks_exact_mut(8)) {
+            out.copy_from_slice(&row.to_be_bytes());
+        }
+
+        for (row, out) in self.hires.iter().zip(hires.chunks_exact_mut(16)) {
+            out.copy_from_slice(&row.to_be_bytes());
+        }
+    }
+
+    fn decode(&mut self, bytes: &[u8]) {
+        let (lores, hires) = bytes.split_at(32 * 8);
+
+        for (row, bytes) in self.lores.iter_mut().zip(lores.chunks_exact(8)) {
+            *row = u64::from_be_bytes(bytes.try_into().expect("8 bytes"));
+        }
+
+        for (row, bytes) in self.hires.iter_mut().zip(hires.chunks_exact(16)) {
+            *row = u128::from_be_bytes(bytes.try_into().expect("16 bytes"));
+        }
+    }
+
+    fn row(&self, resolution: Resolution, y: u8) -> u128 {
+        match resolution {
+            Resolution::Lores => u128::from(self.lores[usize::from(y)]) << 64,
+            Resolution::Hires => self.hires[usize::from(y)],
+        }
+    }
 }
 
 pub struct PixelIter<'a> {
     screen: &'a Screen,
-    current_row: u64,
+    current_row: u128,
     y: u8,
 }
 
@@ -120,19 +408,19 @@ impl<'a> Iterator for PixelIter<'a> {
 
     fn next(&mut self) -> Option<Self::Item> {
         while self.current_row == 0 {
-            if self.y >= 31 {
+            if self.y >= self.screen.height() - 1 {
                 return None;
             }
 
             self.y += 1;
-            self.current_row = self.screen.inner[usize::from(self.y)];
+            self.current_row = self.screen.row(self.y);
         }
 
-        // There is at most 64 leading zeros, this fits in an u8
+        // There is at most 128 leading zeros, this fits in an u8
         let x = u8::try_from(self.current_row.leading_zeros()).unwrap();
 
         // Flip the leading one
-        let mask = (0x1 << 63) >> x;
+        let mask = (0x1 << 127) >> x;
         self.current_row &= !mask;
 
         Some((x, self.y))
@@ -159,7 +447,7 @@ mod tests {
 
     fn new_screen_with_single_row(y: usize, row: u64) -> Screen {
         let mut screen = Screen::default();
-        screen.inner[y] = row;
+        screen.planes[0].lores[y] = row;
         screen
     }
 
@@ -180,7 +468,7 @@ mod tests {
         assert_eq!(screen.flip_pixel(55, 4), FlipResult::UnsetBit);
         assert_eq!(screen.is_changed(), true);
         assert_eq!(screen.get_pixel(55, 4), PixelState::Unset);
-        assert_eq!(screen.inner[4], 0xDEAD_BEEF_0000_0023);
+        assert_eq!(screen.planes[0].lores[4], 0xDEAD_BEEF_0000_0023);
         assert_eq!(screen.flip_pixel(55, 4), FlipResult::NoUnsetBit);
     }
 
@@ -192,17 +480,106 @@ mod tests {
         assert_eq!(screen.flip_vectored(0xAD, 8, 17), FlipResult::UnsetBit);
         assert_eq!(screen.is_changed(), true);
         assert_eq!(screen.get_vectored(8, 17), 0x00);
-        assert_eq!(screen.inner[17], 0xDE00_BEEF_0000_0123);
+        assert_eq!(screen.planes[0].lores[17], 0xDE00_BEEF_0000_0123);
         assert_eq!(screen.flip_vectored(0xED, 8, 17), FlipResult::NoUnsetBit);
         assert_eq!(screen.get_vectored(8, 17), 0xED);
     }
 
+    #[test]
+    fn wrapping_flip() {
+        let mut screen = Screen::default();
+        assert_eq!(screen.flip_vectored(0xFF, 60, 3), FlipResult::NoUnsetBit);
+        assert_eq!(screen.planes[0].lores[3], 0x0000_0000_0000_000F);
+
+        screen.clear();
+        assert_eq!(screen.flip_vectored_wrapping(0xFF, 60, 3), FlipResult::NoUnsetBit);
+        assert_eq!(screen.planes[0].lores[3], 0xF000_0000_0000_000F);
+        assert_eq!(screen.flip_vectored_wrapping(0x80, 64, 3), FlipResult::UnsetBit);
+
+        screen.set_resolution(Resolution::Hires);
+        screen.flip_vectored_wrapping(0xFF, 124, 3);
+        assert_eq!(screen.get_vectored(0, 3), 0xF0);
+    }
+
+    #[test]
+    fn hires() {
+        let mut screen = Screen::default();
+        screen.set_pixel(3, 2);
+        screen.set_resolution(Resolution::Hires);
+        assert_eq!((screen.width(), screen.height()), (128, 64));
+        assert_eq!(screen.pixel_iter().count(), 0);
+
+        assert_eq!(screen.flip_vectored(0xFF, 124, 63), FlipResult::NoUnsetBit);
+        assert_eq!(screen.get_vectored(124, 63), 0xF0);
+        assert_eq!(screen.get_pixel(127, 63), PixelState::Set);
+        assert_eq!(screen.get_pixel(128, 63), PixelState::Unset);
+        assert_eq!(screen.flip_pixel(127, 127), FlipResult::UnsetBit);
+
+        let pixels: [(u8, u8); 3] = {
+            let mut iter = screen.pixel_iter();
+            core::array::from_fn(|_| iter.next().unwrap())
+        };
+        assert_eq!(pixels, [(124, 63), (125, 63), (126, 63)]);
+
+        screen.unset_pixel(124, 63);
+        assert_eq!(screen.pixel_iter().count(), 2);
+    }
+
+    #[test]
+    fn planes() {
+        let mut screen = Screen::default();
+        screen.set_pixel(0, 0);
+        screen.set_pixel(1, 0);
+
+        screen.set_plane_mask(0b10);
+        screen.set_pixel(1, 0);
+        screen.set_pixel(2, 0);
+        assert_eq!(screen.get_pixel(0, 0), PixelState::Unset);
+
+        let colors: [_; 3] = {
+            let mut iter = screen.color_iter();
+            core::array::from_fn(|_| iter.next().unwrap())
+        };
+        assert_eq!(colors, [(0, 0, 0b01), (1, 0, 0b11), (2, 0, 0b10)]);
+        assert_eq!(screen.color_iter().count(), 3);
+
+        screen.set_plane_mask(0b11);
+        assert_eq!(screen.flip_pixel(0, 0), FlipResult::UnsetBit);
+        assert_eq!(screen.get_color(0, 0), 0b10);
+
+        screen.set_plane_mask(0b01);
+        screen.clear();
+        assert_eq!(screen.pixel_iter().count(), 3);
+    }
+
     #[test]
     fn clear_screen() {
         let mut screen = new_screen_with_single_row(17, 0xDEAD_BEEF_0000_0123);
         assert_eq!(screen.is_changed(), false);
         screen.clear();
-        assert_eq!(screen.inner[17], 0);
+        assert_eq!(screen.planes[0].lores[17], 0);
         assert_eq!(screen.is_changed(), true);
     }
+
+    #[test]
+    fn snapshot_into() {
+        let mut screen = Screen::default();
+        let mut copy = Screen::default();
+
+        assert!(!screen.snapshot_into(&mut copy));
+
+        screen.set_pixel(3, 4);
+        screen.set_plane_mask(0b10);
+        assert!(screen.snapshot_into(&mut copy));
+        assert_eq!(copy.generation(), screen.generation());
+        assert_eq!(copy.plane_mask(), 0b10);
+        assert_eq!(copy.get_color(3, 4), 0b01);
+
+        assert!(!screen.snapshot_into(&mut copy));
+
+        screen.set_resolution(Resolution::Hires);
+        assert!(screen.snapshot_into(&mut copy));
+        assert_eq!(copy.resolution(), Resolution::Hires);
+        assert_eq!(copy.pixel_iter().count(), 0);
+    }
 }
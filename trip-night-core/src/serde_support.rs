@@ -0,0 +1,117 @@
+//! Serialization of the types whose values must be validated, and helpers for derived implementations
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use serde::de::{self, Unexpected};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::keypad::Key;
+use crate::machine::STACK_SIZE;
+use crate::{Address, RegIdent};
+
+impl Serialize for Address {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u16(self.get())
+    }
+}
+
+impl<'de> Deserialize<'de> for Address {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = u16::deserialize(deserializer)?;
+        Address::new(value)
+            .ok_or_else(|| de::Error::invalid_value(Unexpected::Unsigned(value.into()), &"an address up to 0xFFF"))
+    }
+}
+
+impl Serialize for RegIdent {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(self.get())
+    }
+}
+
+impl<'de> Deserialize<'de> for RegIdent {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = u8::deserialize(deserializer)?;
+        RegIdent::try_from(value)
+            .map_err(|()| de::Error::invalid_value(Unexpected::Unsigned(value.into()), &"a register up to 0xF"))
+    }
+}
+
+impl Serialize for Key {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u8(self.get())
+    }
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = u8::deserialize(deserializer)?;
+        Key::try_from(value)
+            .map_err(|()| de::Error::invalid_value(Unexpected::Unsigned(value.into()), &"a key up to 0xF"))
+    }
+}
+
+/// Stack pointers past the end of the stack would make the next return panic
+pub(crate) fn deserialize_stack_pointer<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+    let value = u8::deserialize(deserializer)?;
+
+    if usize::from(value) <= STACK_SIZE {
+        Ok(value)
+    } else {
+        Err(de::Error::invalid_value(
+            Unexpected::Unsigned(value.into()),
+            &"a stack pointer up to the stack size",
+        ))
+    }
+}
+
+/// Arrays longer than the 32 elements supported by serde, for `#[serde(with = "…")]`
+pub(crate) mod array {
+    use super::*;
+
+    pub(crate) fn serialize<S, T, const N: usize>(array: &[T; N], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Serialize,
+    {
+        serializer.collect_seq(array)
+    }
+
+    pub(crate) fn deserialize<'de, D, T, const N: usize>(deserializer: D) -> Result<[T; N], D::Error>
+    where
+        D: Deserializer<'de>,
+        T: Deserialize<'de> + Copy + Default,
+    {
+        deserializer.deserialize_tuple(N, ArrayVisitor(PhantomData))
+    }
+
+    struct ArrayVisitor<T, const N: usize>(PhantomData<T>);
+
+    impl<'de, T, const N: usize> de::Visitor<'de> for ArrayVisitor<T, N>
+    where
+        T: Deserialize<'de> + Copy + Default,
+    {
+        type Value = [T; N];
+
+        fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "an array of {N} elements")
+        }
+
+        fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+            let mut array = [T::default(); N];
+
+            for (idx, element) in array.iter_mut().enumerate() {
+                *element = seq
+                    .next_element()?
+                    .ok_or_else(|| de::Error::invalid_length(idx, &self))?;
+            }
+
+            if seq.next_element::<T>()?.is_some() {
+                return Err(de::Error::invalid_length(N + 1, &self));
+            }
+
+            Ok(array)
+        }
+    }
+}
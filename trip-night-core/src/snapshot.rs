@@ -0,0 +1,264 @@
+//! Save states
+//!
+//! A snapshot captures the emulated hardware (RAM, registers, stack, timers, screen…), not the
+//! configuration of the machine (instruction set, frequency, quirks, random number generator…).
+//! Snapshots are restored on a machine configured the same way as the one they were taken from.
+//!
+//! The encoding starts with a header: a magic number, the format version and the platform of the
+//! machine (see `Variant`). Snapshots of older versions are migrated when decoded:
+//!
+//! - version 0, without format version nor platform, is read as a CHIP-8 snapshot
+
+use core::fmt;
+
+use crate::keypad::{Key, WaitingForKey};
+use crate::machine::STACK_SIZE;
+use crate::quirks::Variant;
+use crate::screen::Screen;
+use crate::{Address, RegIdent, RAM_SIZE};
+
+/// Identifies the binary encoding of snapshots
+const MAGIC: [u8; 4] = *b"T8SN";
+
+/// Version of the binary encoding written by `Snapshot::to_bytes`
+pub const FORMAT_VERSION: u8 = 1;
+
+/// Size of the version 0 encoding, which has no format version nor platform
+const V0_ENCODED_LEN: usize = Snapshot::ENCODED_LEN - 2;
+
+/// Captured state of a machine, see `Machine::snapshot` and `Machine::restore`
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Snapshot {
+    /// Missing from snapshots serialized before the platform was recorded, which are CHIP-8 ones
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) variant: Variant,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_support::array"))]
+    pub(crate) ram: [u8; RAM_SIZE],
+    pub(crate) registers: [u8; 16],
+    pub(crate) stack: [Address; STACK_SIZE],
+    #[cfg_attr(
+        feature = "serde",
+        serde(deserialize_with = "crate::serde_support::deserialize_stack_pointer")
+    )]
+    pub(crate) stack_pointer: u8,
+    pub(crate) delay_timer: u8,
+    pub(crate) sound_timer: u8,
+    pub(crate) index: Address,
+    pub(crate) pc: Address,
+    pub(crate) screen: Screen,
+    pub(crate) waiting_for_key: Option<WaitingForKey>,
+    pub(crate) waiting_for_vblank: bool,
+}
+
+/// Bytes which are not a valid encoded snapshot
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SnapshotError {
+    InvalidLength {
+        len: usize,
+    },
+    /// Not an encoded snapshot
+    InvalidMagic,
+    /// Encoded by a newer version of the crate
+    UnsupportedVersion {
+        version: u8,
+    },
+    /// A field holds a value it can't have
+    InvalidValue,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::InvalidLength { len } => {
+                write!(f, "snapshots are {} bytes long, got {len}", Snapshot::ENCODED_LEN)
+            }
+            SnapshotError::InvalidMagic => write!(f, "not a snapshot"),
+            SnapshotError::UnsupportedVersion { version } => {
+                write!(f, "snapshot format version {version} is newer than {FORMAT_VERSION}")
+            }
+            SnapshotError::InvalidValue => write!(f, "corrupted snapshot"),
+        }
+    }
+}
+
+impl Snapshot {
+    /// Size of the binary encoding
+    pub const ENCODED_LEN: usize = MAGIC.len() + 2 + RAM_SIZE + 16 + STACK_SIZE * 2 + 7 + 4 + Screen::ENCODED_LEN;
+
+    /// Platform of the machine the snapshot was taken from
+    pub fn variant(&self) -> Variant {
+        self.variant
+    }
+
+    pub fn pc(&self) -> Address {
+        self.pc
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    /// Encodes the snapshot, multi-byte values being big endian
+    pub fn to_bytes(&self) -> [u8; Self::ENCODED_LEN] {
+        let mut bytes = [0; Self::ENCODED_LEN];
+        let mut writer = Writer { bytes: &mut bytes };
+
+        writer.put(&MAGIC);
+        writer.put(&[FORMAT_VERSION, variant_tag(self.variant)]);
+        writer.put(&self.ram);
+        writer.put(&self.registers);
+
+        for addr in self.stack {
+            writer.put(&addr.get().to_be_bytes());
+        }
+
+        writer.put(&[self.stack_pointer, self.delay_timer, self.sound_timer]);
+        writer.put(&self.index.get().to_be_bytes());
+        writer.put(&self.pc.get().to_be_bytes());
+
+        // Tag, target register, then await_release or the pressed key
+        writer.put(&match self.waiting_for_key {
+            None => [0, 0, 0],
+            Some(WaitingForKey::Press { target, await_release }) => [1, target.get(), u8::from(await_release)],
+            Some(WaitingForKey::Release { target, key }) => [2, target.get(), key.get()],
+        });
+        writer.put(&[u8::from(self.waiting_for_vblank)]);
+
+        self.screen.encode(writer.bytes);
+
+        bytes
+    }
+
+    /// Decodes a snapshot of the current or of an older format version
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let invalid_length = SnapshotError::InvalidLength { len: bytes.len() };
+
+        if bytes.len() < MAGIC.len() + 2 {
+            return Err(invalid_length);
+        }
+
+        let mut reader = Reader { bytes };
+
+        if reader.take(MAGIC.len()) != MAGIC {
+            return Err(SnapshotError::InvalidMagic);
+        }
+
+        let variant = if bytes.len() == V0_ENCODED_LEN {
+            Variant::Chip8
+        } else {
+            let [version, variant]: [u8; 2] = reader.take(2).try_into().expect("2 bytes");
+
+            if version != FORMAT_VERSION {
+                return Err(SnapshotError::UnsupportedVersion { version });
+            }
+
+            if bytes.len() != Self::ENCODED_LEN {
+                return Err(invalid_length);
+            }
+
+            match variant {
+                0 => Variant::Chip8,
+                1 => Variant::SuperChip,
+                2 => Variant::XoChip,
+                _ => return Err(SnapshotError::InvalidValue),
+            }
+        };
+
+        let ram = reader.take(RAM_SIZE).try_into().expect("the RAM size");
+        let registers = reader.take(16).try_into().expect("16 bytes");
+
+        let mut stack = [Address::new_masked(0); STACK_SIZE];
+
+        for addr in &mut stack {
+            *addr = reader.address()?;
+        }
+
+        let [stack_pointer, delay_timer, sound_timer]: [u8; 3] = reader.take(3).try_into().expect("3 bytes");
+
+        if usize::from(stack_pointer) > STACK_SIZE {
+            return Err(SnapshotError::InvalidValue);
+        }
+
+        let index = reader.address()?;
+        let pc = reader.address()?;
+
+        let waiting_for_key = match *reader.take(3) {
+            [0, 0, 0] => None,
+            [1, target, await_release @ (0 | 1)] => Some(WaitingForKey::Press {
+                target: register(target)?,
+                await_release: await_release == 1,
+            }),
+            [2, target, key] => Some(WaitingForKey::Release {
+                target: register(target)?,
+                key: Key::try_from(key).map_err(|_| SnapshotError::InvalidValue)?,
+            }),
+            _ => return Err(SnapshotError::InvalidValue),
+        };
+
+        let waiting_for_vblank = match reader.take(1) {
+            [0] => false,
+            [1] => true,
+            _ => return Err(SnapshotError::InvalidValue),
+        };
+
+        let screen = Screen::decode(reader.bytes).ok_or(SnapshotError::InvalidValue)?;
+
+        Ok(Self {
+            variant,
+            ram,
+            registers,
+            stack,
+            stack_pointer,
+            delay_timer,
+            sound_timer,
+            index,
+            pc,
+            screen,
+            waiting_for_key,
+            waiting_for_vblank,
+        })
+    }
+}
+
+fn variant_tag(variant: Variant) -> u8 {
+    match variant {
+        Variant::Chip8 => 0,
+        Variant::SuperChip => 1,
+        Variant::XoChip => 2,
+    }
+}
+
+fn register(value: u8) -> Result<RegIdent, SnapshotError> {
+    RegIdent::try_from(value).map_err(|()| SnapshotError::InvalidValue)
+}
+
+struct Writer<'a> {
+    bytes: &'a mut [u8],
+}
+
+impl Writer<'_> {
+    fn put(&mut self, values: &[u8]) {
+        let (head, tail) = core::mem::take(&mut self.bytes).split_at_mut(values.len());
+        head.copy_from_slice(values);
+        self.bytes = tail;
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> &'a [u8] {
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        head
+    }
+
+    fn address(&mut self) -> Result<Address, SnapshotError> {
+        let value = u16::from_be_bytes(self.take(2).try_into().expect("2 bytes"));
+        Address::new(value).ok_or(SnapshotError::InvalidValue)
+    }
+}
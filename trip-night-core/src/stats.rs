@@ -0,0 +1,122 @@
+//! Execution statistics
+//!
+//! Every machine keeps a few running totals (see `Machine::perf_counters`), from which frontends
+//! derive rates such as instructions per second for their diagnostics.
+//!
+//! Opcode statistics are opt-in (see `Machine::enable_opcode_stats`): a machine then counts the
+//! executed instructions per slot of the instruction set. The histogram tells which instructions a
+//! ROM relies on, and which ones deserve the fastest dispatch.
+
+use core::fmt;
+
+use crate::instruction::{SLOT_COUNT, SLOT_PATTERNS};
+
+/// Running totals of a machine
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PerfCounters {
+    /// Instructions executed
+    pub instructions: u64,
+    /// Instructions which changed the screen
+    pub draws: u64,
+    /// Frames run by `Machine::run_frame`
+    pub frames: u64,
+}
+
+impl PerfCounters {
+    /// Counts since an earlier reading of the same machine
+    pub fn since(&self, earlier: &PerfCounters) -> PerfCounters {
+        PerfCounters {
+            instructions: self.instructions.wrapping_sub(earlier.instructions),
+            draws: self.draws.wrapping_sub(earlier.draws),
+            frames: self.frames.wrapping_sub(earlier.frames),
+        }
+    }
+}
+
+/// Number of executed instructions per slot of the instruction set
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OpcodeStats {
+    counts: [u64; SLOT_COUNT],
+}
+
+impl Default for OpcodeStats {
+    fn default() -> Self {
+        Self {
+            counts: [0; SLOT_COUNT],
+        }
+    }
+}
+
+impl OpcodeStats {
+    pub(crate) fn record(&mut self, slot: usize) {
+        self.counts[slot] += 1;
+    }
+
+    /// Number of executions of the instructions of a slot, e.g. `OP_DXYN`
+    pub fn count(&self, slot: usize) -> u64 {
+        self.counts.get(slot).copied().unwrap_or(0)
+    }
+
+    /// Number of executions per slot, indexed by the `OP_*` constants
+    pub fn histogram(&self) -> &[u64; SLOT_COUNT] {
+        &self.counts
+    }
+
+    /// Number of executed instructions
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn clear(&mut self) {
+        self.counts = [0; SLOT_COUNT];
+    }
+}
+
+/// One line per executed kind of instruction, the opcode pattern followed by the count
+impl fmt::Display for OpcodeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        SLOT_PATTERNS
+            .iter()
+            .zip(self.counts.iter())
+            .filter(|(_, &count)| count > 0)
+            .try_for_each(|(pattern, count)| writeln!(f, "{pattern} {count}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::decode_slot;
+    use crate::instruction::{OpCode, OP_6XNN, OP_DXYN};
+
+    #[test]
+    fn slot_patterns() {
+        for (slot, pattern) in SLOT_PATTERNS.iter().enumerate() {
+            let opcode = pattern.bytes().fold(0, |opcode, digit| {
+                let nibble = match digit {
+                    b'X' | b'Y' | b'N' => 1,
+                    digit => char::from(digit).to_digit(16).unwrap() as u16,
+                };
+                opcode << 4 | nibble
+            });
+
+            assert_eq!(decode_slot(OpCode::new(opcode)).ok(), Some(slot), "{pattern}");
+        }
+    }
+
+    #[test]
+    fn histogram() {
+        let mut stats = OpcodeStats::default();
+        stats.record(OP_6XNN);
+        stats.record(OP_DXYN);
+        stats.record(OP_DXYN);
+
+        assert_eq!(stats.total(), 3);
+        assert_eq!(stats.count(OP_DXYN), 2);
+        assert_eq!(stats.count(SLOT_COUNT), 0);
+        assert_eq!(stats.histogram()[OP_6XNN], 1);
+
+        stats.clear();
+        assert_eq!(stats.total(), 0);
+    }
+}
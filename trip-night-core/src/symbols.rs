@@ -0,0 +1,156 @@
+//! Symbol maps, naming the addresses of a ROM after the labels of its source
+//!
+//! Written by the assembler and read by debuggers and the listing, as text with one symbol per
+//! line, the hexadecimal address then the name:
+//!
+//! ```text
+//! 200 main
+//! 2a4 draw_score
+//! ```
+//!
+//! Empty lines and lines starting with `#` are ignored.
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+use core::str::FromStr;
+
+use crate::Address;
+
+/// Names of addresses, see the module documentation
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Symbols {
+    by_name: BTreeMap<String, Address>,
+    /// First name given to each address
+    by_addr: BTreeMap<Address, String>,
+}
+
+/// Line of a symbol map which isn't an address followed by a name
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseSymbolsError {
+    /// Line of the error, starting at 1
+    pub line: usize,
+}
+
+impl fmt::Display for ParseSymbolsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid symbol at line {}", self.line)
+    }
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names an address, returns the address the name had before if any
+    pub fn insert(&mut self, name: &str, addr: Address) -> Option<Address> {
+        let previous = self.by_name.insert(name.to_string(), addr);
+
+        if let Some(previous) = previous {
+            if self.by_addr.get(&previous).map(String::as_str) == Some(name) {
+                self.by_addr.remove(&previous);
+            }
+        }
+
+        self.by_addr.entry(addr).or_insert_with(|| name.to_string());
+        previous
+    }
+
+    /// Address of a name
+    pub fn address(&self, name: &str) -> Option<Address> {
+        self.by_name.get(name).copied()
+    }
+
+    /// Name of an address, the first one it was given if it has several
+    pub fn name(&self, addr: Address) -> Option<&str> {
+        self.by_addr.get(&addr).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    /// Symbols in increasing order of address, then of name
+    pub fn iter(&self) -> impl Iterator<Item = (Address, &str)> + '_ {
+        let mut symbols: Vec<(Address, &str)> =
+            self.by_name.iter().map(|(name, addr)| (*addr, name.as_str())).collect();
+        symbols.sort_unstable();
+        symbols.into_iter()
+    }
+}
+
+impl FromStr for Symbols {
+    type Err = ParseSymbolsError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut symbols = Self::new();
+
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let error = ParseSymbolsError { line: idx + 1 };
+            let mut words = line.split_whitespace();
+
+            let (Some(addr), Some(name), None) = (words.next(), words.next(), words.next()) else {
+                return Err(error);
+            };
+            let addr = addr.strip_prefix("0x").unwrap_or(addr);
+            let addr = u16::from_str_radix(addr, 16).ok().and_then(Address::new).ok_or(error)?;
+
+            symbols.insert(name, addr);
+        }
+
+        Ok(symbols)
+    }
+}
+
+impl fmt::Display for Symbols {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (addr, name) in self.iter() {
+            writeln!(f, "{addr} {name}")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn symbol_map() {
+        let symbols: Symbols = "# symbols\n2a4 draw\n\n0x200 main\n200 start\n".parse().unwrap();
+
+        assert_eq!(symbols.len(), 3);
+        assert_eq!(symbols.address("draw"), Some(Address(0x2A4)));
+        assert_eq!(symbols.name(Address(0x200)), Some("main"));
+        assert_eq!(symbols.name(Address(0x202)), None);
+        assert_eq!(symbols.to_string(), "200 main\n200 start\n2a4 draw\n");
+
+        assert_eq!(
+            "200 main\n1000 end".parse::<Symbols>(),
+            Err(ParseSymbolsError { line: 2 })
+        );
+        assert_eq!("200".parse::<Symbols>(), Err(ParseSymbolsError { line: 1 }));
+    }
+
+    #[test]
+    fn renamed_address() {
+        let mut symbols = Symbols::new();
+
+        assert_eq!(symbols.insert("main", Address(0x200)), None);
+        assert_eq!(symbols.insert("main", Address(0x300)), Some(Address(0x200)));
+        assert_eq!(symbols.name(Address(0x200)), None);
+        assert_eq!(symbols.name(Address(0x300)), Some("main"));
+    }
+}
@@ -0,0 +1,205 @@
+//! Event-sourced execution
+//!
+//! Instead of storing machine states, only the keypad inputs and the random draws are recorded,
+//! along with periodic checkpoints. Any intermediate state is regenerated on demand by re-executing
+//! from the closest checkpoint: save states and replays are nearly free, seeking costs CPU.
+
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use crate::keypad::Keypad;
+use crate::machine::Machine;
+use crate::rng::{MachineRng, Rng};
+
+/// A machine whose whole execution can be replayed
+pub struct Timeline {
+    machine: Machine,
+    cycle: u64,
+    checkpoint_interval: u64,
+    checkpoints: Vec<Checkpoint>,
+    /// Keypad changes, with the cycle before which they happened
+    inputs: Vec<(u64, Keypad)>,
+    draws: Rc<RefCell<Vec<u8>>>,
+}
+
+struct Checkpoint {
+    cycle: u64,
+    machine: Machine,
+    /// Number of inputs recorded when the checkpoint was taken
+    input_count: usize,
+    /// Number of random draws recorded when the checkpoint was taken
+    draw_count: usize,
+}
+
+impl Timeline {
+    /// Starts recording the execution of the machine, taking a checkpoint every `checkpoint_interval` cycles
+    pub fn new(mut machine: Machine, checkpoint_interval: u64) -> Self {
+        let draws = Rc::new(RefCell::new(Vec::new()));
+
+        let source = core::mem::take(&mut machine.state.rng);
+        machine.state.rng = MachineRng::Custom(alloc::boxed::Box::new(RecordingRng {
+            source,
+            draws: Rc::clone(&draws),
+        }));
+
+        let last_keypad = machine.state.keypad;
+
+        Self {
+            checkpoints: alloc::vec![Checkpoint {
+                cycle: 0,
+                machine: machine.clone(),
+                input_count: 0,
+                draw_count: 0,
+            }],
+            machine,
+            cycle: 0,
+            checkpoint_interval: core::cmp::max(checkpoint_interval, 1),
+            inputs: alloc::vec![(0, last_keypad)],
+            draws,
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    /// Keypad of the machine, inputs must go through the timeline to be recorded
+    pub fn keypad_mut(&mut self) -> &mut Keypad {
+        &mut self.machine.state.keypad
+    }
+
+    /// Number of cycles run since the recording started
+    pub fn cycle_count(&self) -> u64 {
+        self.cycle
+    }
+
+    pub fn run_cycles(&mut self, count: usize) {
+        for _ in 0..count {
+            if self.cycle % self.checkpoint_interval == 0 && self.cycle != 0 {
+                self.checkpoints.push(Checkpoint {
+                    cycle: self.cycle,
+                    machine: self.machine.clone(),
+                    input_count: self.inputs.len(),
+                    draw_count: self.draws.borrow().len(),
+                });
+            }
+
+            let keypad = self.machine.state.keypad;
+
+            if self.inputs.last().map(|(_, last)| *last) != Some(keypad) {
+                self.inputs.push((self.cycle, keypad));
+            }
+
+            // Faults are part of the recorded execution
+            let _ = self.machine.cycle();
+            self.cycle += 1;
+        }
+    }
+
+    /// Regenerates the machine as it was after the given number of cycles
+    ///
+    /// The random generator of the returned machine replays the recorded draws.
+    pub fn machine_at(&self, cycle: u64) -> Option<Machine> {
+        if cycle > self.cycle {
+            return None;
+        }
+
+        let checkpoint = self
+            .checkpoints
+            .iter()
+            .rev()
+            .find(|checkpoint| checkpoint.cycle <= cycle)
+            .expect("there is always a checkpoint at cycle 0");
+
+        let mut machine = checkpoint.machine.clone();
+        machine.state.rng = MachineRng::Custom(alloc::boxed::Box::new(ReplayingRng {
+            draws: self.draws.borrow()[checkpoint.draw_count..].into(),
+            position: 0,
+        }));
+
+        let mut inputs = self.inputs[checkpoint.input_count..].iter().peekable();
+
+        for current in checkpoint.cycle..cycle {
+            while let Some((_, keypad)) = inputs.next_if(|(at, _)| *at == current) {
+                machine.state.keypad = *keypad;
+            }
+
+            let _ = machine.cycle();
+        }
+
+        Some(machine)
+    }
+}
+
+/// Records the draws of the original generator
+#[derive(Clone)]
+struct RecordingRng {
+    source: MachineRng,
+    draws: Rc<RefCell<Vec<u8>>>,
+}
+
+impl Rng for RecordingRng {
+    fn next_u8(&mut self) -> u8 {
+        let value = self.source.next_u8();
+        self.draws.borrow_mut().push(value);
+        value
+    }
+}
+
+/// Replays recorded draws, then zeros
+#[derive(Clone)]
+struct ReplayingRng {
+    draws: Rc<[u8]>,
+    position: usize,
+}
+
+impl Rng for ReplayingRng {
+    fn next_u8(&mut self) -> u8 {
+        let value = self.draws.get(self.position).copied().unwrap_or(0);
+        self.position += 1;
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::{make_nop_set, OpCode};
+    use crate::keypad::Key;
+    use crate::machine::State;
+    use crate::RegIdent;
+
+    #[test]
+    fn replay() {
+        let mut set = make_nop_set();
+        set.op_00e0 = &|_: OpCode, state: &mut State| {
+            let value = state.reg_read(RegIdent::V0).wrapping_add(state.rng.next_u8());
+            let value = value ^ state.keypad.first_pressed().map_or(0, |key| key.get());
+            state.reg_write(RegIdent::V0, value);
+            state.pc = crate::Address(0x200);
+        };
+
+        let machine = Machine::new(&[0x00, 0xE0], set, 60);
+        let mut timeline = Timeline::new(machine, 16);
+        let mut expected = Vec::new();
+
+        for step in 0..10 {
+            if step % 3 == 0 {
+                timeline.keypad_mut().press(Key::K5);
+            } else {
+                timeline.keypad_mut().release_all();
+            }
+
+            timeline.run_cycles(7);
+            expected.push(timeline.machine().state.reg_read(RegIdent::V0));
+        }
+
+        for (step, value) in expected.iter().enumerate() {
+            let machine = timeline.machine_at(7 * (step as u64 + 1)).unwrap();
+            assert_eq!(machine.state.reg_read(RegIdent::V0), *value);
+        }
+
+        assert!(timeline.machine_at(71).is_none());
+    }
+}
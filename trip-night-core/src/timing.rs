@@ -0,0 +1,109 @@
+//! Timing models, how long instructions take
+//!
+//! By default every instruction takes one cycle, and a frame runs `frequency_hz / 60` of them. The
+//! COSMAC VIP interpreter was much less regular: its CDP1802 ran about 3668 machine cycles per 60 Hz
+//! frame, a third of them stolen by the display DMA and interrupt, and an instruction took from a few
+//! dozen machine cycles (6XNN) to several thousands (00E0, large sprites). `Timing::CosmacVip`
+//! charges every instruction its approximate cost on the VIP, so that ROMs tuned on the original
+//! hardware run at their authentic speed.
+
+use crate::decode::decode_slot;
+use crate::instruction::*;
+use crate::machine::State;
+use crate::Address;
+
+/// Machine cycles of the COSMAC VIP per 60 Hz frame (1.7609 MHz clock, 8 clock cycles per machine cycle)
+pub const VIP_CYCLES_PER_FRAME: usize = 3668;
+
+/// Machine cycles per frame left to the interpreter, once the display DMA (128 lines of 8 bytes) and
+/// the interrupt routine ran
+pub const VIP_INTERPRETER_CYCLES_PER_FRAME: usize = 2572;
+
+/// Machine cycles spent by the interpreter fetching and dispatching any instruction
+pub(crate) const VIP_FETCH_CYCLES: usize = 40;
+
+/// How long instructions take
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Timing {
+    /// Every instruction takes one cycle, `frequency_hz / 60` cycles per frame
+    #[default]
+    Uniform,
+    /// Instructions take their cost on the COSMAC VIP, `VIP_INTERPRETER_CYCLES_PER_FRAME` machine
+    /// cycles per frame whatever `frequency_hz`
+    CosmacVip,
+}
+
+/// Approximate machine cycles taken on the COSMAC VIP by the instruction at `pc`, given the state
+/// right after its execution
+///
+/// Costs include fetching the instruction. Skips taken, sprites not aligned on a byte, the digits of
+/// BCD conversions and the registers of FX55 and FX65 all make instructions longer, as on the
+/// original interpreter. Unknown opcodes only cost the fetch.
+pub fn vip_cycles(opcode: OpCode, pc: Address, state: &State) -> usize {
+    let skip = if state.pc == pc + 4 { 4 } else { 0 };
+    let x = usize::from(opcode.get_x().get());
+
+    let execution = match decode_slot(opcode) {
+        Ok(OP_00E0) => 3078,
+        Ok(OP_00EE) => 10,
+        Ok(OP_1NNN) => 12,
+        Ok(OP_2NNN) => 26,
+        Ok(OP_3XNN | OP_4XNN) => 10 + skip,
+        Ok(OP_5XY0 | OP_9XY0 | OP_EX9E | OP_EXA1) => 14 + skip,
+        Ok(OP_6XNN) => 6,
+        Ok(OP_7XNN | OP_FX07 | OP_FX15 | OP_FX18) => 10,
+        Ok(OP_8XY0 | OP_8XY1 | OP_8XY2 | OP_8XY3 | OP_8XY4 | OP_8XY5 | OP_8XY6 | OP_8XY7 | OP_8XYE) => 44,
+        Ok(OP_ANNN) => 12,
+        Ok(OP_BNNN) => 22,
+        Ok(OP_CXNN) => 36,
+        Ok(OP_DXYN) => {
+            let rows = usize::from(opcode.get_n());
+            // Unaligned sprites are shifted bit by bit, and span two bytes per row
+            let per_row = if state.reg_read(opcode.get_x()) % 8 == 0 {
+                24
+            } else {
+                68
+            };
+            26 + rows * per_row
+        }
+        Ok(OP_FX0A) => 18,
+        Ok(OP_FX1E | OP_FX29) => 16,
+        Ok(OP_FX33) => {
+            // Digits are found by repeated subtractions
+            let value = state.reg_read(opcode.get_x());
+            let digits = value / 100 + value / 10 % 10 + value % 10;
+            80 + 16 * usize::from(digits)
+        }
+        Ok(OP_FX55 | OP_FX65) => 14 + 14 * (x + 1),
+        _ => 0,
+    };
+
+    VIP_FETCH_CYCLES + execution
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RegIdent;
+
+    #[test]
+    fn vip_costs() {
+        let state = State::builder().pc(0x202).register(RegIdent::V1, 9).build();
+        let at = Address(0x200);
+
+        assert_eq!(vip_cycles(OpCode::new(0x6005), at, &state), 46);
+        assert_eq!(vip_cycles(OpCode::new(0x0000), at, &state), VIP_FETCH_CYCLES);
+
+        // Sprites at V0 = 0 are aligned, at V1 = 9 they aren't
+        assert_eq!(vip_cycles(OpCode::new(0xD005), at, &state), 40 + 26 + 5 * 24);
+        assert_eq!(vip_cycles(OpCode::new(0xD105), at, &state), 40 + 26 + 5 * 68);
+
+        assert_eq!(vip_cycles(OpCode::new(0xF133), at, &state), 40 + 80 + 16 * 9);
+        assert_eq!(vip_cycles(OpCode::new(0xF365), at, &state), 40 + 14 + 14 * 4);
+
+        let skipped = State::builder().pc(0x204).build();
+        assert_eq!(vip_cycles(OpCode::new(0x3000), at, &state), 50);
+        assert_eq!(vip_cycles(OpCode::new(0x3000), at, &skipped), 54);
+    }
+}
@@ -0,0 +1,895 @@
+//! Per-cycle execution traces
+//!
+//! Traces are written as JSON Lines, one object per executed instruction. To keep files small,
+//! fields are delta-encoded and omitted when they can be inferred from the previous record:
+//!
+//! | Key  | Value                          | Present when                                      |
+//! |------|--------------------------------|---------------------------------------------------|
+//! | `c`  | cycle counter, as a number     | first record, or cycles elapsed without execution |
+//! | `pc` | address of the instruction     | first record, or not right after the previous one |
+//! | `op` | opcode, as a hexadecimal string| always                                            |
+//! | `r`  | `[[register, value], …]`       | a register value changed                          |
+//! | `i`  | index register, hexadecimal    | I changed                                         |
+//! | `dt` | delay timer, as a number       | the delay timer changed                           |
+//! | `st` | sound timer, as a number       | the sound timer changed                           |
+//! | `s`  | `1`                            | the screen changed                                |
+//!
+//! Example:
+//!
+//! ```text
+//! {"c":1,"pc":"200","op":"00e0","s":1}
+//! {"op":"a22a","i":"22a"}
+//! {"op":"600c","r":[[0,12]]}
+//! ```
+//!
+//! # Loops
+//!
+//! Programs spend most of their time in tight loops, polling a timer or waiting for a key, which
+//! write the same records over and over. Records repeating the last ones, up to `MAX_LOOP_LEN` of
+//! them, are folded into a single `loop` line. Here, the last 3 records are repeated 250 more times:
+//!
+//! ```text
+//! {"pc":"20a","op":"f007"}
+//! {"op":"3000"}
+//! {"op":"120a"}
+//! {"loop":3,"n":250}
+//! ```
+//!
+//! The records of an incomplete repetition are written as they are after the `loop` line.
+//!
+//! # Flag traces
+//!
+//! `FlagTraceWriter` only records the instructions which may set VF (8XY1 to 8XYE and DXYN), one
+//! line each with the address, the opcode, Vx and Vy before the execution, then Vx and VF after:
+//!
+//! ```text
+//! 206 8014 vx=ff vy=01 -> vx=00 vf=1
+//! 208 8016 vx=00 vy=01 -> vx=00 vf=0
+//! ```
+//!
+//! These traces are small enough to be reviewed and kept as golden files, locking in the carry,
+//! borrow and shift semantics. `compare_flag_traces` finds the first difference with such a file.
+
+use core::fmt::{self, Write as _};
+
+use crate::decode::decode_slot;
+use crate::instruction::{OpCode, OP_8XY1, OP_8XY2, OP_8XY3, OP_8XY4, OP_8XY5, OP_8XY6, OP_8XY7, OP_8XYE, OP_DXYN};
+use crate::machine::{CycleOutcome, Machine, State};
+use crate::{Address, RegIdent};
+
+/// Maximum number of records in a folded loop, see the module documentation
+pub const MAX_LOOP_LEN: usize = 8;
+
+/// Writes the trace of a machine execution into a `fmt::Write` sink
+pub struct TraceWriter<W> {
+    out: W,
+    /// Cycle and address expected for the next record if nothing unusual happens
+    expected: Option<(usize, Address)>,
+    /// Last records, folded or not
+    recent: Recent<Line>,
+    /// Length of the loop being folded, and the number of records folded so far
+    folding: Option<(usize, usize)>,
+}
+
+impl<W: fmt::Write> TraceWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            expected: None,
+            recent: Recent::new(Line::EMPTY),
+            folding: None,
+        }
+    }
+
+    /// Writes the loop being folded, if any, and returns the sink
+    pub fn finish(mut self) -> Result<W, fmt::Error> {
+        self.flush_loop()?;
+        Ok(self.out)
+    }
+
+    /// Runs one cycle of the machine, and records it if an instruction was executed
+    pub fn step(&mut self, machine: &mut Machine) -> fmt::Result {
+        let pc = machine.state.pc;
+        let before = Registers::capture(&machine.state);
+
+        let opcode = match machine.cycle() {
+            Ok(CycleOutcome::Executed(opcode)) => opcode,
+            _ => return Ok(()),
+        };
+
+        let after = Registers::capture(&machine.state);
+        let cycle = machine.counter;
+
+        let mut line = Line::EMPTY;
+        line.write_char('{')?;
+
+        let mut fields = Fields::new(&mut line);
+
+        match self.expected {
+            Some((expected_cycle, expected_pc)) => {
+                if expected_cycle != cycle {
+                    fields.write("c", format_args!("{cycle}"))?;
+                }
+                if expected_pc != pc {
+                    fields.write("pc", format_args!("\"{pc}\""))?;
+                }
+            }
+            None => {
+                fields.write("c", format_args!("{cycle}"))?;
+                fields.write("pc", format_args!("\"{pc}\""))?;
+            }
+        }
+
+        fields.write("op", format_args!("\"{:04x}\"", opcode.get_inner()))?;
+
+        if before.registers != after.registers {
+            fields.write(
+                "r",
+                format_args!("{}", RegisterDeltas(&before.registers, &after.registers)),
+            )?;
+        }
+        if before.index != after.index {
+            fields.write("i", format_args!("\"{}\"", after.index))?;
+        }
+        if before.delay_timer != after.delay_timer {
+            fields.write("dt", format_args!("{}", after.delay_timer))?;
+        }
+        if before.sound_timer != after.sound_timer {
+            fields.write("st", format_args!("{}", after.sound_timer))?;
+        }
+        if machine.state.screen.is_changed() {
+            fields.write("s", format_args!("1"))?;
+        }
+
+        line.write_str("}\n")?;
+
+        self.expected = Some((cycle + 1, pc + 2));
+
+        self.record(line)
+    }
+
+    fn record(&mut self, line: Line) -> fmt::Result {
+        if let Some((len, folded)) = self.folding {
+            if self.recent.back(len) == Some(&line) {
+                self.folding = Some((len, folded + 1));
+                self.recent.push(line);
+                return Ok(());
+            }
+
+            self.flush_loop()?;
+        }
+
+        match (1..=self.recent.len).find(|&len| self.recent.back(len) == Some(&line)) {
+            Some(len) => self.folding = Some((len, 1)),
+            None => self.out.write_str(line.as_str())?,
+        }
+
+        self.recent.push(line);
+
+        Ok(())
+    }
+
+    fn flush_loop(&mut self) -> fmt::Result {
+        let (len, folded) = match self.folding.take() {
+            Some(folding) => folding,
+            None => return Ok(()),
+        };
+
+        if folded >= len {
+            writeln!(self.out, "{{\"loop\":{len},\"n\":{}}}", folded / len)?;
+        }
+
+        for n in (1..=folded % len).rev() {
+            let line = self.recent.back(n).expect("folded records are recent");
+            self.out.write_str(line.as_str())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Writes the trace of the instructions which may set VF into a `fmt::Write` sink
+pub struct FlagTraceWriter<W> {
+    out: W,
+}
+
+impl<W: fmt::Write> FlagTraceWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// Runs one cycle of the machine, and records it if the executed instruction may set VF
+    pub fn step(&mut self, machine: &mut Machine) -> fmt::Result {
+        let pc = machine.state.pc;
+        let before = Registers::capture(&machine.state);
+
+        let opcode = match machine.cycle() {
+            Ok(CycleOutcome::Executed(opcode)) if FlagRecord::is_flag_setter(opcode) => opcode,
+            _ => return Ok(()),
+        };
+
+        let record = FlagRecord {
+            pc,
+            opcode,
+            vx: before.registers[usize::from(opcode.get_x().get())],
+            vy: before.registers[usize::from(opcode.get_y().get())],
+            result: machine.state.reg_read(opcode.get_x()),
+            vf: machine.state.reg_read(RegIdent::VF),
+        };
+
+        writeln!(self.out, "{record}")
+    }
+}
+
+/// Execution of an instruction which may set VF
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct FlagRecord {
+    pub pc: Address,
+    pub opcode: OpCode,
+    /// Value of Vx before the execution
+    pub vx: u8,
+    /// Value of Vy before the execution
+    pub vy: u8,
+    /// Value of Vx after the execution
+    pub result: u8,
+    /// Value of VF after the execution
+    pub vf: u8,
+}
+
+impl FlagRecord {
+    /// Whether the instruction is recorded in flag traces
+    pub fn is_flag_setter(opcode: OpCode) -> bool {
+        matches!(
+            decode_slot(opcode),
+            Ok(OP_8XY1 | OP_8XY2 | OP_8XY3 | OP_8XY4 | OP_8XY5 | OP_8XY6 | OP_8XY7 | OP_8XYE | OP_DXYN)
+        )
+    }
+}
+
+impl fmt::Display for FlagRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {:04x} vx={:02x} vy={:02x} -> vx={:02x} vf={:x}",
+            self.pc,
+            self.opcode.get_inner(),
+            self.vx,
+            self.vy,
+            self.result,
+            self.vf
+        )
+    }
+}
+
+/// First difference between two flag traces
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FlagTraceMismatch<'a> {
+    /// Line of the expected trace, starting at 1 (one past the end when the expected trace is shorter)
+    pub line: usize,
+    /// Expected record, `None` when the actual trace is longer
+    pub expected: Option<&'a str>,
+    /// Actual record, `None` when the actual trace is shorter
+    pub actual: Option<&'a str>,
+}
+
+impl fmt::Display for FlagTraceMismatch<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "flag trace differs at line {}: expected `{}`, got `{}`",
+            self.line,
+            self.expected.unwrap_or("end of trace"),
+            self.actual.unwrap_or("end of trace")
+        )
+    }
+}
+
+/// Compares a flag trace with the expected one, typically a golden file
+///
+/// Blank lines and lines starting with `#` are ignored in both traces, so that golden files can be
+/// commented, and surrounding whitespaces don't matter.
+pub fn compare_flag_traces<'a>(expected: &'a str, actual: &'a str) -> Result<(), FlagTraceMismatch<'a>> {
+    let records = |trace: &'a str| {
+        trace
+            .lines()
+            .enumerate()
+            .map(|(idx, line)| (idx + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+    };
+
+    let mut expected_records = records(expected);
+    let mut actual_records = records(actual);
+    let mut last_line = 0;
+
+    loop {
+        match (expected_records.next(), actual_records.next()) {
+            (None, None) => return Ok(()),
+            (Some((line, expected)), Some((_, actual))) if expected == actual => last_line = line,
+            (expected, actual) => {
+                return Err(FlagTraceMismatch {
+                    line: expected.map_or(last_line + 1, |(line, _)| line),
+                    expected: expected.map(|(_, record)| record),
+                    actual: actual.map(|(_, record)| record),
+                })
+            }
+        }
+    }
+}
+
+struct Fields<'a, W> {
+    out: &'a mut W,
+    first: bool,
+}
+
+impl<'a, W: fmt::Write> Fields<'a, W> {
+    fn new(out: &'a mut W) -> Self {
+        Self { out, first: true }
+    }
+
+    fn write(&mut self, key: &str, value: fmt::Arguments<'_>) -> fmt::Result {
+        if !self.first {
+            self.out.write_char(',')?;
+        }
+        self.first = false;
+        write!(self.out, "\"{key}\":{value}")
+    }
+}
+
+/// Capacity of a `Line`, enough for the longest record
+const LINE_CAPACITY: usize = 256;
+
+/// Record written by `TraceWriter`, newline included
+#[derive(Clone, Copy)]
+struct Line {
+    bytes: [u8; LINE_CAPACITY],
+    len: usize,
+}
+
+impl Line {
+    const EMPTY: Self = Self {
+        bytes: [0; LINE_CAPACITY],
+        len: 0,
+    };
+
+    fn as_str(&self) -> &str {
+        core::str::from_utf8(&self.bytes[..self.len]).expect("only strings are written")
+    }
+}
+
+impl PartialEq for Line {
+    fn eq(&self, other: &Self) -> bool {
+        self.bytes[..self.len] == other.bytes[..other.len]
+    }
+}
+
+impl fmt::Write for Line {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        self.bytes
+            .get_mut(self.len..end)
+            .ok_or(fmt::Error)?
+            .copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Last records of a trace, up to `MAX_LOOP_LEN`
+struct Recent<T> {
+    items: [T; MAX_LOOP_LEN],
+    len: usize,
+    /// Position of the next record in `items`
+    next: usize,
+}
+
+impl<T: Copy> Recent<T> {
+    fn new(empty: T) -> Self {
+        Self {
+            items: [empty; MAX_LOOP_LEN],
+            len: 0,
+            next: 0,
+        }
+    }
+
+    fn push(&mut self, item: T) {
+        self.items[self.next] = item;
+        self.next = (self.next + 1) % MAX_LOOP_LEN;
+        self.len = core::cmp::min(self.len + 1, MAX_LOOP_LEN);
+    }
+
+    /// Record `n` positions back, the last one being 1
+    fn back(&self, n: usize) -> Option<&T> {
+        (1..=self.len)
+            .contains(&n)
+            .then(|| &self.items[(self.next + MAX_LOOP_LEN - n) % MAX_LOOP_LEN])
+    }
+}
+
+struct Registers {
+    registers: [u8; 16],
+    index: Address,
+    delay_timer: u8,
+    sound_timer: u8,
+}
+
+impl Registers {
+    fn capture(state: &State) -> Self {
+        let mut registers = [0; 16];
+
+        for (idx, value) in (0..).zip(registers.iter_mut()) {
+            let reg = RegIdent::try_from(idx).expect("less than 16 registers");
+            *value = state.reg_read(reg);
+        }
+
+        Self {
+            registers,
+            index: state.index,
+            delay_timer: state.delay_timer,
+            sound_timer: state.sound_timer,
+        }
+    }
+}
+
+struct RegisterDeltas<'a>(&'a [u8; 16], &'a [u8; 16]);
+
+impl fmt::Display for RegisterDeltas<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("[")?;
+
+        let changes = self
+            .0
+            .iter()
+            .zip(self.1.iter())
+            .enumerate()
+            .filter(|(_, (a, b))| a != b);
+
+        for (n, (reg, (_, value))) in changes.enumerate() {
+            if n != 0 {
+                f.write_str(",")?;
+            }
+            write!(f, "[{reg},{value}]")?;
+        }
+
+        f.write_str("]")
+    }
+}
+
+/// One executed instruction, as read back from a trace
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// Value of the cycle counter after the execution
+    pub cycle: usize,
+    pub pc: Address,
+    pub opcode: OpCode,
+    /// New value of the registers modified by the instruction
+    pub registers: [Option<u8>; 16],
+    pub index: Option<Address>,
+    pub delay_timer: Option<u8>,
+    pub sound_timer: Option<u8>,
+    pub screen_changed: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TraceError {
+    /// Line of the malformed record, starting at 1
+    pub line: usize,
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Malformed trace record at line {}", self.line)
+    }
+}
+
+/// Reads back the records of a trace written by `TraceWriter`
+pub struct TraceReader<'a> {
+    lines: core::str::Lines<'a>,
+    line: usize,
+    expected: Option<(usize, Address)>,
+    /// Last records, expanded from loops or not
+    recent: Recent<&'a str>,
+    /// Length of the loop being expanded, and the number of records left
+    expanding: Option<(usize, usize)>,
+}
+
+impl<'a> TraceReader<'a> {
+    pub fn new(trace: &'a str) -> Self {
+        Self {
+            lines: trace.lines(),
+            line: 0,
+            expected: None,
+            recent: Recent::new(""),
+            expanding: None,
+        }
+    }
+
+    /// Length of a folded loop and its number of records, if the line is a valid `loop` line
+    fn parse_loop(&self, line: &str) -> Option<(usize, usize)> {
+        let mut cursor = Cursor(line.as_bytes());
+
+        cursor.expect(b'{')?;
+        (cursor.string()? == "loop").then_some(())?;
+        cursor.expect(b':')?;
+        let len = usize::try_from(cursor.number()?).ok()?;
+        cursor.expect(b',')?;
+        (cursor.string()? == "n").then_some(())?;
+        cursor.expect(b':')?;
+        let repeats = usize::try_from(cursor.number()?).ok()?;
+        cursor.expect(b'}')?;
+
+        self.recent.back(len)?;
+
+        Some((len, len.checked_mul(repeats)?))
+    }
+
+    fn parse(&self, line: &str) -> Option<TraceRecord> {
+        let mut cursor = Cursor(line.as_bytes());
+
+        let mut cycle = self.expected.map(|(cycle, _)| cycle);
+        let mut pc = self.expected.map(|(_, pc)| pc);
+        let mut opcode = None;
+        let mut record = TraceRecord {
+            cycle: 0,
+            pc: Address(0),
+            opcode: OpCode::new(0),
+            registers: [None; 16],
+            index: None,
+            delay_timer: None,
+            sound_timer: None,
+            screen_changed: false,
+        };
+
+        cursor.expect(b'{')?;
+
+        while !cursor.eat(b'}') {
+            cursor.eat(b',');
+
+            let key = cursor.string()?;
+            cursor.expect(b':')?;
+
+            match key {
+                "c" => cycle = Some(usize::try_from(cursor.number()?).ok()?),
+                "pc" => pc = Some(Address::new(cursor.hex_string()?)?),
+                "op" => opcode = Some(OpCode::new(cursor.hex_string()?)),
+                "i" => record.index = Some(Address::new(cursor.hex_string()?)?),
+                "dt" => record.delay_timer = Some(u8::try_from(cursor.number()?).ok()?),
+                "st" => record.sound_timer = Some(u8::try_from(cursor.number()?).ok()?),
+                "s" => record.screen_changed = cursor.number()? != 0,
+                "r" => {
+                    cursor.expect(b'[')?;
+                    while !cursor.eat(b']') {
+                        cursor.eat(b',');
+                        cursor.expect(b'[')?;
+                        let reg = usize::try_from(cursor.number()?).ok()?;
+                        cursor.expect(b',')?;
+                        let value = u8::try_from(cursor.number()?).ok()?;
+                        cursor.expect(b']')?;
+                        *record.registers.get_mut(reg)? = Some(value);
+                    }
+                }
+                _ => return None,
+            }
+        }
+
+        record.cycle = cycle?;
+        record.pc = pc?;
+        record.opcode = opcode?;
+
+        Some(record)
+    }
+}
+
+impl Iterator for TraceReader<'_> {
+    type Item = Result<TraceRecord, TraceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = match self.expanding {
+            Some((len, left)) if left > 0 => {
+                self.expanding = Some((len, left - 1));
+                *self
+                    .recent
+                    .back(len)
+                    .expect("loops are checked against the recent records")
+            }
+            _ => {
+                let line = self.lines.next()?;
+                self.line += 1;
+
+                // Malformed `loop` lines are reported by `parse`
+                if let Some(expanding) = self.parse_loop(line) {
+                    self.expanding = Some(expanding);
+                    return self.next();
+                }
+
+                line
+            }
+        };
+
+        self.recent.push(line);
+
+        match self.parse(line) {
+            Some(record) => {
+                self.expected = Some((record.cycle + 1, record.pc + 2));
+                Some(Ok(record))
+            }
+            None => Some(Err(TraceError { line: self.line })),
+        }
+    }
+}
+
+/// Minimal parser for the subset of JSON produced by `TraceWriter`
+struct Cursor<'a>(&'a [u8]);
+
+impl<'a> Cursor<'a> {
+    fn skip_whitespaces(&mut self) {
+        while let Some((first, rest)) = self.0.split_first() {
+            if !first.is_ascii_whitespace() {
+                break;
+            }
+            self.0 = rest;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_whitespaces();
+
+        match self.0.split_first() {
+            Some((first, rest)) if *first == byte => {
+                self.0 = rest;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Option<()> {
+        self.eat(byte).then_some(())
+    }
+
+    fn string(&mut self) -> Option<&'a str> {
+        self.expect(b'"')?;
+        let len = self.0.iter().position(|byte| *byte == b'"')?;
+        let (content, rest) = self.0.split_at(len);
+        self.0 = &rest[1..];
+        core::str::from_utf8(content).ok()
+    }
+
+    fn hex_string(&mut self) -> Option<u16> {
+        u16::from_str_radix(self.string()?, 16).ok()
+    }
+
+    fn number(&mut self) -> Option<u64> {
+        self.skip_whitespaces();
+        let len = self
+            .0
+            .iter()
+            .position(|byte| !byte.is_ascii_digit())
+            .unwrap_or(self.0.len());
+        let (digits, rest) = self.0.split_at(len);
+        self.0 = rest;
+        core::str::from_utf8(digits).ok()?.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction::make_nop_set;
+
+    struct Buffer {
+        bytes: [u8; 256],
+        len: usize,
+    }
+
+    impl fmt::Write for Buffer {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            let end = self.len + s.len();
+            self.bytes
+                .get_mut(self.len..end)
+                .ok_or(fmt::Error)?
+                .copy_from_slice(s.as_bytes());
+            self.len = end;
+            Ok(())
+        }
+    }
+
+    fn make_machine() -> Machine {
+        let mut set = make_nop_set();
+        set.op_00e0 = &|_: OpCode, state: &mut State| state.screen.clear();
+        set.op_6xnn = &|op: OpCode, state: &mut State| state.reg_write(op.get_x(), op.get_nn());
+        set.op_annn = &|op: OpCode, state: &mut State| state.index = op.get_nnn();
+        set.op_1nnn = &|op: OpCode, state: &mut State| state.pc = op.get_nnn();
+
+        let game_code = [0x00, 0xE0, 0xA2, 0x2A, 0x60, 0x0C, 0x12, 0x04];
+        Machine::new(&game_code, set, 60)
+    }
+
+    #[test]
+    fn write_and_read_back() {
+        let mut machine = make_machine();
+        let mut writer = TraceWriter::new(Buffer {
+            bytes: [0; 256],
+            len: 0,
+        });
+
+        for _ in 0..3 {
+            writer.step(&mut machine).unwrap();
+        }
+
+        let buffer = writer.finish().unwrap();
+        let trace = core::str::from_utf8(&buffer.bytes[..buffer.len]).unwrap();
+        assert_eq!(
+            trace,
+            "{\"c\":1,\"pc\":\"200\",\"op\":\"00e0\",\"s\":1}\n\
+             {\"op\":\"a22a\",\"i\":\"22a\"}\n\
+             {\"op\":\"600c\",\"r\":[[0,12]]}\n"
+        );
+
+        let records: [TraceRecord; 3] = {
+            let mut reader = TraceReader::new(trace);
+            let mut next = || reader.next().unwrap().unwrap();
+            [next(), next(), next()]
+        };
+
+        assert_eq!(records[0].cycle, 1);
+        assert_eq!(records[0].pc, Address(0x200));
+        assert_eq!(records[0].opcode.get_inner(), 0x00E0);
+        assert!(records[0].screen_changed);
+
+        assert_eq!(records[1].cycle, 2);
+        assert_eq!(records[1].pc, Address(0x202));
+        assert_eq!(records[1].index, Some(Address(0x22A)));
+        assert!(!records[1].screen_changed);
+
+        assert_eq!(records[2].cycle, 3);
+        assert_eq!(records[2].pc, Address(0x204));
+        assert_eq!(records[2].registers[0], Some(12));
+        assert_eq!(records[2].registers[1], None);
+    }
+
+    #[test]
+    fn fold_loops() {
+        let mut machine = make_machine();
+        let mut writer = TraceWriter::new(Buffer {
+            bytes: [0; 256],
+            len: 0,
+        });
+
+        for _ in 0..10 {
+            writer.step(&mut machine).unwrap();
+        }
+
+        let buffer = writer.finish().unwrap();
+        let trace = core::str::from_utf8(&buffer.bytes[..buffer.len]).unwrap();
+        assert_eq!(
+            trace,
+            "{\"c\":1,\"pc\":\"200\",\"op\":\"00e0\",\"s\":1}\n\
+             {\"op\":\"a22a\",\"i\":\"22a\"}\n\
+             {\"op\":\"600c\",\"r\":[[0,12]]}\n\
+             {\"op\":\"1204\"}\n\
+             {\"pc\":\"204\",\"op\":\"600c\"}\n\
+             {\"loop\":2,\"n\":2}\n\
+             {\"op\":\"1204\"}\n"
+        );
+
+        let mut cycle = 0;
+
+        for record in TraceReader::new(trace) {
+            let record = record.unwrap();
+            cycle += 1;
+            assert_eq!(record.cycle, cycle);
+
+            if cycle >= 4 {
+                let (pc, opcode) = if cycle % 2 == 0 {
+                    (0x206, 0x1204)
+                } else {
+                    (0x204, 0x600C)
+                };
+                assert_eq!(record.pc, Address(pc));
+                assert_eq!(record.opcode.get_inner(), opcode);
+                assert_eq!(record.registers, [None; 16]);
+            }
+        }
+
+        assert_eq!(cycle, 10);
+    }
+
+    #[test]
+    fn skipped_cycles() {
+        let mut machine = make_machine();
+        machine.start_delay = 1;
+        machine.add_breakpoint(Address(0x202));
+        let mut writer = TraceWriter::new(Buffer {
+            bytes: [0; 256],
+            len: 0,
+        });
+
+        // Start delay, 00E0, breakpoint, A22A
+        for _ in 0..4 {
+            writer.step(&mut machine).unwrap();
+        }
+
+        machine.halted = true;
+        writer.step(&mut machine).unwrap();
+
+        let buffer = writer.finish().unwrap();
+        assert_eq!(
+            core::str::from_utf8(&buffer.bytes[..buffer.len]).unwrap(),
+            "{\"c\":2,\"pc\":\"200\",\"op\":\"00e0\",\"s\":1}\n\
+             {\"op\":\"a22a\",\"i\":\"22a\"}\n"
+        );
+    }
+
+    #[test]
+    fn flag_trace() {
+        let mut set = make_nop_set();
+        set.op_6xnn = &|op: OpCode, state: &mut State| state.reg_write(op.get_x(), op.get_nn());
+        set.op_8xy4 = &|op: OpCode, state: &mut State| {
+            let (sum, carry) = state.reg_read(op.get_x()).overflowing_add(state.reg_read(op.get_y()));
+            state.reg_write_with_flag(op.get_x(), sum, carry);
+        };
+
+        let game_code = [0x60, 0xFF, 0x61, 0x01, 0x80, 0x14, 0x80, 0x14];
+        let mut machine = Machine::new(&game_code, set, 60);
+        let mut writer = FlagTraceWriter::new(Buffer {
+            bytes: [0; 256],
+            len: 0,
+        });
+
+        for _ in 0..4 {
+            writer.step(&mut machine).unwrap();
+        }
+
+        let buffer = writer.into_inner();
+        let trace = core::str::from_utf8(&buffer.bytes[..buffer.len]).unwrap();
+        assert_eq!(
+            trace,
+            "204 8014 vx=ff vy=01 -> vx=00 vf=1\n\
+             206 8014 vx=00 vy=01 -> vx=01 vf=0\n"
+        );
+
+        let golden = "# carry\n204 8014 vx=ff vy=01 -> vx=00 vf=1\n\n206 8014 vx=00 vy=01 -> vx=01 vf=0\n";
+        assert_eq!(compare_flag_traces(golden, trace), Ok(()));
+        assert_eq!(
+            compare_flag_traces(golden, "204 8014 vx=ff vy=01 -> vx=00 vf=0\n"),
+            Err(FlagTraceMismatch {
+                line: 2,
+                expected: Some("204 8014 vx=ff vy=01 -> vx=00 vf=1"),
+                actual: Some("204 8014 vx=ff vy=01 -> vx=00 vf=0"),
+            })
+        );
+        assert_eq!(
+            compare_flag_traces(golden, "204 8014 vx=ff vy=01 -> vx=00 vf=1\n"),
+            Err(FlagTraceMismatch {
+                line: 4,
+                expected: Some("206 8014 vx=00 vy=01 -> vx=01 vf=0"),
+                actual: None,
+            })
+        );
+        assert_eq!(
+            compare_flag_traces("", trace),
+            Err(FlagTraceMismatch {
+                line: 1,
+                expected: None,
+                actual: Some("204 8014 vx=ff vy=01 -> vx=00 vf=1"),
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_record() {
+        let mut reader = TraceReader::new("{\"c\":1,\"pc\":\"200\",\"op\":\"00e0\"}\n{\"op\":}");
+        assert!(reader.next().unwrap().is_ok());
+        assert_eq!(reader.next().unwrap().err(), Some(TraceError { line: 2 }));
+        assert!(reader.next().is_none());
+
+        // Loops longer than the records read so far
+        let mut reader = TraceReader::new("{\"c\":1,\"pc\":\"200\",\"op\":\"00e0\"}\n{\"loop\":2,\"n\":1}");
+        assert!(reader.next().unwrap().is_ok());
+        assert_eq!(reader.next().unwrap().err(), Some(TraceError { line: 2 }));
+    }
+}
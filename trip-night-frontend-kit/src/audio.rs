@@ -0,0 +1,162 @@
+//! Beep generation
+//!
+//! The beep is a square wave whose duty cycle, volume and envelope can be tuned, since the raw 50%
+//! square wave at full volume is harsh on the ears.
+
+use crate::emulator::{BEEP_HZ, SAMPLE_RATE};
+
+/// Shape of the beep
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BeepSettings {
+    /// Percentage of the period the wave is high, from 1 to 99
+    pub duty_percent: u8,
+    /// Time to reach the full volume once the beep starts
+    pub attack_ms: u16,
+    /// Time to fade out once the beep stops
+    pub release_ms: u16,
+    /// From 0 to 100
+    pub volume_percent: u8,
+}
+
+impl Default for BeepSettings {
+    fn default() -> Self {
+        Self {
+            duty_percent: 50,
+            attack_ms: 5,
+            release_ms: 20,
+            volume_percent: 25,
+        }
+    }
+}
+
+impl BeepSettings {
+    /// Returns `None` if the duty cycle or the volume are out of range
+    pub fn validated(self) -> Option<Self> {
+        ((1..=99).contains(&self.duty_percent) && self.volume_percent <= 100).then_some(self)
+    }
+}
+
+/// Square wave generator, keeping its phase and envelope from one frame to the next
+#[derive(Clone, Debug)]
+pub struct Beeper {
+    settings: BeepSettings,
+    /// Position in the current period, in samples times `BEEP_HZ`
+    phase: u32,
+    /// Current gain of the envelope, from 0 to 1
+    level: f32,
+}
+
+impl Beeper {
+    pub fn new(settings: BeepSettings) -> Self {
+        Self {
+            settings,
+            phase: 0,
+            level: 0.0,
+        }
+    }
+
+    /// Appends the mono samples of one 60 Hz frame
+    pub fn render_frame(&mut self, beeping: bool, samples: &mut Vec<i16>) {
+        let count = (SAMPLE_RATE / 60) as usize;
+        let high_until = SAMPLE_RATE * u32::from(self.settings.duty_percent) / 100;
+        let amplitude = f32::from(i16::MAX) * f32::from(self.settings.volume_percent) / 100.0;
+
+        let attack_step = envelope_step(self.settings.attack_ms);
+        let release_step = envelope_step(self.settings.release_ms);
+
+        for _ in 0..count {
+            self.level = if beeping {
+                (self.level + attack_step).min(1.0)
+            } else {
+                (self.level - release_step).max(0.0)
+            };
+
+            if self.level == 0.0 {
+                self.phase = 0;
+                samples.push(0);
+                continue;
+            }
+
+            let sign = if self.phase < high_until { 1.0 } else { -1.0 };
+            samples.push((sign * amplitude * self.level) as i16);
+
+            self.phase = (self.phase + BEEP_HZ) % SAMPLE_RATE;
+        }
+    }
+}
+
+/// Change of the envelope level per sample, to go from 0 to 1 in the given time
+fn envelope_step(duration_ms: u16) -> f32 {
+    let samples = SAMPLE_RATE as f32 * f32::from(duration_ms) / 1000.0;
+
+    if samples < 1.0 {
+        1.0
+    } else {
+        1.0 / samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn square_wave() {
+        let settings = BeepSettings {
+            attack_ms: 0,
+            release_ms: 0,
+            volume_percent: 100,
+            ..BeepSettings::default()
+        };
+        let mut beeper = Beeper::new(settings);
+        let mut samples = Vec::new();
+
+        beeper.render_frame(false, &mut samples);
+        assert_eq!(samples.len(), 735);
+        assert!(samples.iter().all(|sample| *sample == 0));
+
+        samples.clear();
+        beeper.render_frame(true, &mut samples);
+
+        // 44100 / 440 ≈ 100 samples per period
+        assert!(samples[..50].iter().all(|sample| *sample == i16::MAX));
+        assert!(samples[51..100].iter().all(|sample| *sample == -i16::MAX));
+    }
+
+    #[test]
+    fn shaped_wave() {
+        let settings = BeepSettings {
+            duty_percent: 25,
+            attack_ms: 1,
+            release_ms: 1,
+            volume_percent: 50,
+        };
+        let mut beeper = Beeper::new(settings);
+        let mut samples = Vec::new();
+
+        beeper.render_frame(true, &mut samples);
+
+        // Attack over 44 samples, then a quarter of the period is high
+        assert!(samples[0] > 0 && samples[0] < samples[10]);
+        assert_eq!(samples[50], -i16::MAX / 2);
+        assert_eq!(samples[101], i16::MAX / 2);
+        assert_eq!(samples[130], -i16::MAX / 2);
+
+        samples.clear();
+        beeper.render_frame(false, &mut samples);
+
+        assert_ne!(samples[0], 0);
+        assert!(samples[50..].iter().all(|sample| *sample == 0));
+    }
+
+    #[test]
+    fn validation() {
+        assert!(BeepSettings::default().validated().is_some());
+        assert!(BeepSettings {
+            duty_percent: 100,
+            ..BeepSettings::default()
+        }
+        .validated()
+        .is_none());
+    }
+}
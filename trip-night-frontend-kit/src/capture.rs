@@ -0,0 +1,297 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use trip_night_core::screen::Screen;
+
+use crate::palette::{Palette, Rgb};
+
+/// RGB image of the screen
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    /// Row-major pixels
+    pub pixels: Vec<Rgb>,
+}
+
+impl Framebuffer {
+    pub fn from_screen(screen: &Screen, palette: &Palette) -> Self {
+        let width = usize::from(screen.width());
+        let height = usize::from(screen.height());
+        let mut pixels = vec![palette.background; width * height];
+
+        for (x, y) in screen.pixel_iter() {
+            pixels[usize::from(y) * width + usize::from(x)] = palette.foreground;
+        }
+
+        Self { width, height, pixels }
+    }
+
+    /// Writes the image as a binary PPM (P6), scaling each pixel to a `scale`×`scale` square
+    pub fn write_ppm(&self, mut out: impl Write, scale: usize) -> io::Result<()> {
+        write!(out, "P6\n{} {}\n255\n", self.width * scale, self.height * scale)?;
+
+        for row in self.pixels.chunks(self.width) {
+            let line = scale_row(row, scale);
+
+            for _ in 0..scale {
+                out.write_all(&line)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn save_ppm(&self, path: impl AsRef<Path>, scale: usize) -> io::Result<()> {
+        let file = io::BufWriter::new(fs::File::create(path)?);
+        self.write_ppm(file, scale)
+    }
+
+    /// Writes the image as a PNG, scaling each pixel to a `scale`×`scale` square
+    ///
+    /// The image data is stored without compression, which keeps the encoder tiny.
+    pub fn write_png(&self, mut out: impl Write, scale: usize) -> io::Result<()> {
+        let too_large = || io::Error::new(io::ErrorKind::InvalidInput, "image too large for PNG");
+        let width = u32::try_from(self.width * scale).map_err(|_| too_large())?;
+        let height = u32::try_from(self.height * scale).map_err(|_| too_large())?;
+
+        // Each scanline starts with its filter type, none here
+        let mut scanlines = Vec::with_capacity((self.width * scale * 3 + 1) * self.height * scale);
+
+        for row in self.pixels.chunks(self.width) {
+            let line = scale_row(row, scale);
+
+            for _ in 0..scale {
+                scanlines.push(0);
+                scanlines.extend_from_slice(&line);
+            }
+        }
+
+        let mut header = Vec::with_capacity(13);
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        // 8 bits per channel, RGB, default compression, filtering and no interlacing
+        header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+        out.write_all(b"\x89PNG\r\n\x1A\n")?;
+        write_png_chunk(&mut out, b"IHDR", &header)?;
+        write_png_chunk(&mut out, b"IDAT", &zlib_stored(&scanlines))?;
+        write_png_chunk(&mut out, b"IEND", &[])
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>, scale: usize) -> io::Result<()> {
+        let file = io::BufWriter::new(fs::File::create(path)?);
+        self.write_png(file, scale)
+    }
+}
+
+/// RGB bytes of a row of pixels, each repeated `scale` times
+fn scale_row(row: &[Rgb], scale: usize) -> Vec<u8> {
+    row.iter()
+        .flat_map(|pixel| std::iter::repeat([pixel.r, pixel.g, pixel.b]).take(scale))
+        .flatten()
+        .collect()
+}
+
+fn write_png_chunk(out: &mut impl Write, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    let len = u32::try_from(data.len()).map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "chunk too large"))?;
+
+    out.write_all(&len.to_be_bytes())?;
+    out.write_all(kind)?;
+    out.write_all(data)?;
+    out.write_all(&crc32(kind.iter().chain(data)).to_be_bytes())
+}
+
+/// Zlib stream made of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const MAX_BLOCK_LEN: usize = 0xFFFF;
+
+    let mut stream = vec![0x78, 0x01];
+    let mut blocks = data.chunks(MAX_BLOCK_LEN).peekable();
+
+    if blocks.peek().is_none() {
+        // A final empty block
+        stream.extend_from_slice(&[1, 0x00, 0x00, 0xFF, 0xFF]);
+    }
+
+    while let Some(block) = blocks.next() {
+        let len = u16::try_from(block.len()).expect("at most 0xFFFF bytes");
+        stream.push(u8::from(blocks.peek().is_none()));
+        stream.extend_from_slice(&len.to_le_bytes());
+        stream.extend_from_slice(&(!len).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32 {
+    let crc = bytes.fold(0xFFFF_FFFF, |mut crc: u32, byte| {
+        crc ^= u32::from(*byte);
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & 0u32.wrapping_sub(crc & 1));
+        }
+        crc
+    });
+
+    !crc
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    const MODULUS: u32 = 65521;
+
+    let (a, b) = bytes.iter().fold((1, 0), |(a, b), byte| {
+        let a = (a + u32::from(*byte)) % MODULUS;
+        (a, (b + a) % MODULUS)
+    });
+
+    (b << 16) | a
+}
+
+/// Name of the manifest written by `Recorder::finish`
+pub const MANIFEST_NAME: &str = "manifest.json";
+
+/// Records frames as PPM images in a directory, named by frame index and cycle count
+///
+/// A JSON manifest listing the frames along with the cycle count of the machine is written when
+/// the recording is finished, so that external tools can align frames with traces.
+pub struct Recorder {
+    directory: PathBuf,
+    scale: usize,
+    /// Cycle count and file name of each recorded frame
+    frames: Vec<(usize, String)>,
+}
+
+impl Recorder {
+    pub fn new(directory: impl Into<PathBuf>, scale: usize) -> io::Result<Self> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+
+        Ok(Self {
+            directory,
+            scale,
+            frames: Vec::new(),
+        })
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Number of frames recorded so far
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Records a frame, `cycle` being the cycle count of the machine when the frame was rendered
+    pub fn capture(&mut self, framebuffer: &Framebuffer, cycle: usize) -> io::Result<()> {
+        let name = format!("frame_{:06}_cycle_{:010}.ppm", self.frames.len(), cycle);
+        framebuffer.save_ppm(self.directory.join(&name), self.scale)?;
+        self.frames.push((cycle, name));
+        Ok(())
+    }
+
+    /// Writes the manifest, returns the number of recorded frames
+    pub fn finish(self) -> io::Result<usize> {
+        let file = io::BufWriter::new(fs::File::create(self.directory.join(MANIFEST_NAME))?);
+        self.write_manifest(file)?;
+        Ok(self.frames.len())
+    }
+
+    fn write_manifest(&self, mut out: impl Write) -> io::Result<()> {
+        writeln!(out, "{{")?;
+        writeln!(out, "  \"scale\": {},", self.scale)?;
+        writeln!(out, "  \"frames\": [")?;
+
+        for (index, (cycle, name)) in self.frames.iter().enumerate() {
+            let separator = if index + 1 < self.frames.len() { "," } else { "" };
+            writeln!(
+                out,
+                "    {{ \"index\": {index}, \"cycle\": {cycle}, \"file\": \"{name}\" }}{separator}"
+            )?;
+        }
+
+        writeln!(out, "  ]")?;
+        writeln!(out, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn screen_to_ppm() {
+        let mut screen = Screen::default();
+        screen.set_pixel(1, 0);
+
+        let framebuffer = Framebuffer::from_screen(&screen, &Palette::MONOCHROME);
+        assert_eq!(framebuffer.pixels[0], Palette::MONOCHROME.background);
+        assert_eq!(framebuffer.pixels[1], Palette::MONOCHROME.foreground);
+
+        let mut ppm = Vec::new();
+        framebuffer.write_ppm(&mut ppm, 2).unwrap();
+
+        let header = b"P6\n128 64\n255\n";
+        assert_eq!(&ppm[..header.len()], header);
+        assert_eq!(ppm.len(), header.len() + 128 * 64 * 3);
+
+        let pixels = &ppm[header.len()..];
+        assert_eq!(pixels[..6], [0; 6]);
+        assert_eq!(pixels[6..12], [0xFF; 6]);
+        assert_eq!(pixels[128 * 3 + 6..128 * 3 + 12], [0xFF; 6]);
+    }
+
+    #[test]
+    fn screen_to_png() {
+        let mut screen = Screen::default();
+        screen.set_pixel(1, 0);
+
+        let framebuffer = Framebuffer::from_screen(&screen, &Palette::MONOCHROME);
+        let mut png = Vec::new();
+        framebuffer.write_png(&mut png, 2).unwrap();
+
+        assert_eq!(png[..8], *b"\x89PNG\r\n\x1A\n");
+        assert_eq!(png[8..16], *b"\0\0\0\x0DIHDR");
+        assert_eq!(png[16..24], [0, 0, 0, 128, 0, 0, 0, 64]);
+        assert_eq!(png[png.len() - 12..], *b"\0\0\0\0IEND\xAE\x42\x60\x82");
+
+        // Scanlines of 1 + 128 * 3 bytes, in blocks of at most 0xFFFF bytes
+        let scanlines_len = 64 * (1 + 128 * 3);
+        let blocks = (scanlines_len + 0xFFFE) / 0xFFFF;
+        assert_eq!(png.len(), 8 + 25 + 12 + 2 + blocks * 5 + scanlines_len + 4 + 12);
+
+        assert_eq!(crc32(b"123456789".iter()), 0xCBF4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn manifest() {
+        let mut recorder = Recorder {
+            directory: PathBuf::new(),
+            scale: 1,
+            frames: Vec::new(),
+        };
+        recorder
+            .frames
+            .push((11, "frame_000000_cycle_0000000011.ppm".to_owned()));
+        recorder
+            .frames
+            .push((22, "frame_000001_cycle_0000000022.ppm".to_owned()));
+
+        let mut manifest = Vec::new();
+        recorder.write_manifest(&mut manifest).unwrap();
+
+        let expected = r#"{
+  "scale": 1,
+  "frames": [
+    { "index": 0, "cycle": 11, "file": "frame_000000_cycle_0000000011.ppm" },
+    { "index": 1, "cycle": 22, "file": "frame_000001_cycle_0000000022.ppm" }
+  ]
+}
+"#;
+        assert_eq!(String::from_utf8(manifest).unwrap(), expected);
+    }
+}
@@ -0,0 +1,378 @@
+use std::path::Path;
+use std::{fmt, fs, io};
+
+use trip_night_core::keypad::Key;
+use trip_night_core::quirks::Quirks;
+use trip_night_core::rom_db::RomHash;
+
+use crate::audio::BeepSettings;
+use crate::keymap::KeyMapConfig;
+use crate::palette::{Palette, Rgb};
+use crate::postprocess::EffectKind;
+
+/// Settings shared by all the frontends
+///
+/// Stored as a simple text file:
+///
+/// ```text
+/// frequency = 700
+/// background = #000000
+/// foreground = #ffffff
+/// effects = phosphor, glow
+/// beep_volume = 25
+///
+/// [keys]
+/// Key1 = 1
+/// Up = 5
+///
+/// [rom a9993e364706816aba3e25717850c26c9cd0d89d]
+/// quirks = schip
+/// frequency = 1000
+/// beep_duty = 25
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub frequency_hz: usize,
+    pub palette: Palette,
+    pub key_map: KeyMapConfig,
+    /// Post-processing effects, applied in order
+    pub effects: Vec<EffectKind>,
+    pub beep: BeepSettings,
+    /// Settings of specific ROMs, taking precedence over the ROM database
+    pub rom_overrides: Vec<RomOverride>,
+}
+
+/// Settings of a ROM chosen by the user
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RomOverride {
+    pub hash: RomHash,
+    /// Only named quirk profiles (see `Quirks::by_name`) can be stored
+    pub quirks: Option<Quirks>,
+    pub frequency_hz: Option<usize>,
+    pub beep: Option<BeepSettings>,
+}
+
+/// Settings to run a ROM with
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RomSettings {
+    pub quirks: Quirks,
+    pub frequency_hz: usize,
+    pub beep: BeepSettings,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            frequency_hz: 700,
+            palette: Palette::default(),
+            key_map: KeyMapConfig::default(),
+            effects: Vec::new(),
+            beep: BeepSettings::default(),
+            rom_overrides: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    /// A line could not be understood
+    InvalidLine {
+        line: usize,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "couldn't access configuration file: {e}"),
+            ConfigError::InvalidLine { line } => write!(f, "invalid configuration at line {line}"),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io(e) => Some(e),
+            ConfigError::InvalidLine { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl Config {
+    /// Loads the configuration file, or returns the default configuration if there is none
+    pub fn load_or_default(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ConfigError> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    /// Settings to run the given ROM with
+    ///
+    /// From lowest to highest priority: the global settings, the ROM database (with the `rom-db`
+    /// feature), then the overrides of the user.
+    pub fn rom_settings(&self, rom: &[u8]) -> RomSettings {
+        let hash = RomHash::of(rom);
+
+        let mut settings = RomSettings {
+            quirks: Quirks::default(),
+            frequency_hz: self.frequency_hz,
+            beep: self.beep,
+        };
+
+        #[cfg(feature = "rom-db")]
+        if let Some(profile) = trip_night_core::rom_db::lookup_in(trip_night_core::rom_db::PROFILES, &hash) {
+            settings.quirks = profile.quirks;
+            settings.frequency_hz = profile.frequency_hz;
+        }
+
+        if let Some(user) = self.rom_overrides.iter().find(|user| user.hash == hash) {
+            settings.quirks = user.quirks.unwrap_or(settings.quirks);
+            settings.frequency_hz = user.frequency_hz.unwrap_or(settings.frequency_hz);
+            settings.beep = user.beep.unwrap_or(settings.beep);
+        }
+
+        settings
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        let mut section = Section::Global;
+        let mut keys = KeyMapConfig::empty();
+        let mut has_keys_section = false;
+
+        for (idx, line) in text.lines().enumerate() {
+            let invalid = || ConfigError::InvalidLine { line: idx + 1 };
+
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[') {
+                let header = header.strip_suffix(']').ok_or_else(invalid)?;
+
+                section = if header == "keys" {
+                    has_keys_section = true;
+                    Section::Keys
+                } else if let Some(hash) = header.strip_prefix("rom ") {
+                    config.rom_overrides.push(RomOverride {
+                        hash: hash.trim().parse().map_err(|_| invalid())?,
+                        quirks: None,
+                        frequency_hz: None,
+                        beep: None,
+                    });
+                    Section::Rom
+                } else {
+                    return Err(invalid());
+                };
+
+                continue;
+            }
+
+            let (name, value) = line.split_once('=').ok_or_else(invalid)?;
+            let (name, value) = (name.trim(), value.trim());
+
+            match section {
+                Section::Global => match name {
+                    "frequency" => config.frequency_hz = value.parse().map_err(|_| invalid())?,
+                    "background" => config.palette.background = value.parse::<Rgb>().map_err(|_| invalid())?,
+                    "foreground" => config.palette.foreground = value.parse::<Rgb>().map_err(|_| invalid())?,
+                    "palette" => config.palette = Palette::by_name(value).ok_or_else(invalid)?,
+                    "effects" => {
+                        config.effects = value
+                            .split(',')
+                            .map(str::trim)
+                            .filter(|name| !name.is_empty())
+                            .map(|name| name.parse().map_err(|_| invalid()))
+                            .collect::<Result<_, _>>()?
+                    }
+                    _ => config.beep = parse_beep(config.beep, name, value).ok_or_else(invalid)?,
+                },
+                Section::Keys => {
+                    let key = u8::from_str_radix(value, 16).ok().and_then(|v| Key::try_from(v).ok());
+                    keys.bind(name, key.ok_or_else(invalid)?);
+                }
+                Section::Rom => {
+                    let rom = config.rom_overrides.last_mut().expect("a ROM section");
+
+                    match name {
+                        "quirks" => rom.quirks = Some(Quirks::by_name(value).ok_or_else(invalid)?),
+                        "frequency" => rom.frequency_hz = Some(value.parse().map_err(|_| invalid())?),
+                        _ => {
+                            let beep = rom.beep.unwrap_or(config.beep);
+                            rom.beep = Some(parse_beep(beep, name, value).ok_or_else(invalid)?);
+                        }
+                    }
+                }
+            }
+        }
+
+        if has_keys_section {
+            config.key_map = keys;
+        }
+
+        Ok(config)
+    }
+}
+
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "frequency = {}", self.frequency_hz)?;
+        writeln!(f, "background = {}", self.palette.background)?;
+        writeln!(f, "foreground = {}", self.palette.foreground)?;
+
+        if !self.effects.is_empty() {
+            let names: Vec<String> = self.effects.iter().map(ToString::to_string).collect();
+            writeln!(f, "effects = {}", names.join(", "))?;
+        }
+
+        write_beep(f, &self.beep)?;
+
+        writeln!(f)?;
+        writeln!(f, "[keys]")?;
+
+        for (host, key) in self.key_map.bindings() {
+            writeln!(f, "{host} = {key}")?;
+        }
+
+        for rom in &self.rom_overrides {
+            writeln!(f)?;
+            writeln!(f, "[rom {}]", rom.hash)?;
+
+            if let Some(name) = rom.quirks.and_then(|quirks| quirks.name()) {
+                writeln!(f, "quirks = {name}")?;
+            }
+
+            if let Some(frequency_hz) = rom.frequency_hz {
+                writeln!(f, "frequency = {frequency_hz}")?;
+            }
+
+            if let Some(beep) = &rom.beep {
+                write_beep(f, beep)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Sets the beep setting of the given name, returns `None` if the name or the value is invalid
+fn parse_beep(mut beep: BeepSettings, name: &str, value: &str) -> Option<BeepSettings> {
+    match name {
+        "beep_duty" => beep.duty_percent = value.parse().ok()?,
+        "beep_attack" => beep.attack_ms = value.parse().ok()?,
+        "beep_release" => beep.release_ms = value.parse().ok()?,
+        "beep_volume" => beep.volume_percent = value.parse().ok()?,
+        _ => return None,
+    }
+
+    beep.validated()
+}
+
+fn write_beep(f: &mut fmt::Formatter<'_>, beep: &BeepSettings) -> fmt::Result {
+    writeln!(f, "beep_duty = {}", beep.duty_percent)?;
+    writeln!(f, "beep_attack = {}", beep.attack_ms)?;
+    writeln!(f, "beep_release = {}", beep.release_ms)?;
+    writeln!(f, "beep_volume = {}", beep.volume_percent)
+}
+
+/// Section of the configuration file being parsed
+enum Section {
+    Global,
+    Keys,
+    /// Settings of the last ROM of `Config::rom_overrides`
+    Rom,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let mut config = Config {
+            frequency_hz: 1000,
+            palette: Palette::AMBER,
+            effects: vec![EffectKind::Curvature, EffectKind::Phosphor],
+            beep: BeepSettings {
+                volume_percent: 60,
+                ..BeepSettings::default()
+            },
+            ..Config::default()
+        };
+        config.key_map.bind("Up", Key::K5);
+        config.rom_overrides.push(RomOverride {
+            hash: RomHash::of(&[0x12, 0x00]),
+            quirks: Some(Quirks::cosmac_vip()),
+            frequency_hz: None,
+            beep: Some(BeepSettings::default()),
+        });
+
+        let parsed = Config::parse(&config.to_string()).unwrap();
+        assert_eq!(parsed, config);
+    }
+
+    #[test]
+    fn parse_partial() {
+        let config = Config::parse("# comment\npalette = phosphor\n\n[keys]\nSpace = a\n").unwrap();
+        assert_eq!(config.frequency_hz, 700);
+        assert_eq!(config.palette, Palette::PHOSPHOR);
+        assert_eq!(config.key_map.bindings(), [("Space".to_owned(), Key::KA)]);
+    }
+
+    #[test]
+    fn rom_settings() {
+        let rom = [0x12, 0x00];
+        let text = format!(
+            "frequency = 600\nbeep_volume = 10\n[rom {}]\nquirks = schip\nbeep_duty = 25\n",
+            RomHash::of(&rom)
+        );
+        let mut config = Config::parse(&text).unwrap();
+
+        let settings = config.rom_settings(&rom);
+        assert_eq!(settings.quirks, Quirks::schip());
+        assert_eq!(settings.frequency_hz, 600);
+        assert_eq!(settings.beep.duty_percent, 25);
+        assert_eq!(settings.beep.volume_percent, 10);
+
+        config.rom_overrides[0].frequency_hz = Some(1000);
+        assert_eq!(config.rom_settings(&rom).frequency_hz, 1000);
+        assert_eq!(config.rom_settings(&[0x12, 0x02]).quirks, Quirks::default());
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert!(matches!(
+            Config::parse("frequency = 700\nfrequency = fast\n"),
+            Err(ConfigError::InvalidLine { line: 2 })
+        ));
+        assert!(matches!(
+            Config::parse("[keys]\nSpace = 10\n"),
+            Err(ConfigError::InvalidLine { line: 2 })
+        ));
+        assert!(matches!(
+            Config::parse("beep_volume = 101\n"),
+            Err(ConfigError::InvalidLine { line: 1 })
+        ));
+        assert!(matches!(
+            Config::parse("[rom 1234]\n"),
+            Err(ConfigError::InvalidLine { line: 1 })
+        ));
+    }
+}
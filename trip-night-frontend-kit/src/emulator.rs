@@ -0,0 +1,103 @@
+//! Ready-made game loop for custom frontends
+//!
+//! A frontend only provides three callbacks: polling the input, presenting the screen and playing
+//! audio. Pacing, timers and the cycle budget are handled by `run_emulator`.
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use trip_night_core::keypad::Keypad;
+use trip_night_core::machine::Machine;
+use trip_night_core::screen::Screen;
+
+use crate::audio::{BeepSettings, Beeper};
+use crate::pacing::Pacer;
+
+/// Sample rate of the audio handed to `Hooks::fill_audio`
+pub const SAMPLE_RATE: u32 = 44_100;
+
+/// Frequency of the square wave played while the sound timer is active
+pub const BEEP_HZ: u32 = 440;
+
+const FRAME_DURATION: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Callbacks of a frontend
+pub struct Hooks<I, P, A>
+where
+    I: FnMut(&mut Keypad) -> bool,
+    P: FnMut(&Screen),
+    A: FnMut(&mut [i16]),
+{
+    /// Updates the keypad, returns `false` to stop the emulator
+    pub poll_input: I,
+    /// Presents the screen, may block until the next vertical sync
+    pub present: P,
+    /// Receives the mono samples of the emulated frames (the beep or silence), to queue them to the
+    /// audio device
+    pub fill_audio: A,
+}
+
+/// Runs the machine at 60 frames per second until `poll_input` returns `false`
+///
+/// Each iteration polls the input, runs as many frames as the elapsed time requires (within the
+/// limits of the machine), then presents the screen and hands over the audio of these frames.
+/// Iterations are at least one frame long, so that frontends without vertical sync don't spin.
+pub fn run_emulator<I, P, A>(machine: &mut Machine, beep: BeepSettings, mut hooks: Hooks<I, P, A>)
+where
+    I: FnMut(&mut Keypad) -> bool,
+    P: FnMut(&Screen),
+    A: FnMut(&mut [i16]),
+{
+    let mut pacer = Pacer::new(60);
+    let mut beeper = Beeper::new(beep);
+    let mut samples = Vec::new();
+    let mut last = Instant::now();
+
+    while (hooks.poll_input)(machine.keypad_mut()) {
+        let now = Instant::now();
+        let elapsed = now - last;
+        last = now;
+
+        samples.clear();
+
+        for _ in 0..pacer.cycles_for(elapsed) {
+            machine.run_frame();
+            beeper.render_frame(machine.is_beeping(), &mut samples);
+        }
+
+        (hooks.present)(machine.screen());
+
+        if !samples.is_empty() {
+            (hooks.fill_audio)(&mut samples);
+        }
+
+        thread::sleep(FRAME_DURATION.saturating_sub(now.elapsed()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stops_when_asked() {
+        let mut machine = Machine::new(&[0x12, 0x00], trip_night_core::instruction::make_nop_set(), 600);
+        let mut polls = 0;
+        let mut presents = 0;
+
+        run_emulator(
+            &mut machine,
+            BeepSettings::default(),
+            Hooks {
+                poll_input: |_| {
+                    polls += 1;
+                    polls <= 3
+                },
+                present: |_| presents += 1,
+                fill_audio: |_| {},
+            },
+        );
+
+        assert_eq!(presents, 3);
+    }
+}
@@ -0,0 +1,86 @@
+//! Two runs of a ROM composited in a single picture, like ghosts in racing games
+//!
+//! Typically used with a `MachineFarm` running the same ROM with different quirks, to see frame by
+//! frame where the behaviors diverge.
+
+use trip_night_core::screen::Screen;
+
+use crate::capture::Framebuffer;
+use crate::palette::Rgb;
+
+/// Colors of a ghost comparison
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GhostPalette {
+    pub background: Rgb,
+    /// Pixel set on both screens
+    pub both: Rgb,
+    /// Pixel only set on the primary screen
+    pub primary: Rgb,
+    /// Pixel only set on the ghost screen
+    pub ghost: Rgb,
+}
+
+impl Default for GhostPalette {
+    fn default() -> Self {
+        Self {
+            background: Rgb::new(0x00, 0x00, 0x00),
+            both: Rgb::new(0xFF, 0xFF, 0xFF),
+            primary: Rgb::new(0x33, 0xCC, 0xFF),
+            ghost: Rgb::new(0xFF, 0x55, 0x33),
+        }
+    }
+}
+
+/// Composites the ghost screen onto the primary screen
+///
+/// When the resolutions differ, the picture is large enough for both screens.
+pub fn composite(primary: &Screen, ghost: &Screen, palette: &GhostPalette) -> Framebuffer {
+    const PRIMARY: u8 = 0b01;
+    const GHOST: u8 = 0b10;
+
+    let width = usize::from(primary.width().max(ghost.width()));
+    let height = usize::from(primary.height().max(ghost.height()));
+    let mut layers = vec![0u8; width * height];
+
+    for (screen, layer) in [(primary, PRIMARY), (ghost, GHOST)] {
+        for (x, y) in screen.pixel_iter() {
+            layers[usize::from(y) * width + usize::from(x)] |= layer;
+        }
+    }
+
+    let pixels = layers
+        .into_iter()
+        .map(|layer| match layer {
+            PRIMARY => palette.primary,
+            GHOST => palette.ghost,
+            0 => palette.background,
+            _ => palette.both,
+        })
+        .collect();
+
+    Framebuffer { width, height, pixels }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layers() {
+        let mut primary = Screen::default();
+        primary.set_pixel(0, 0);
+        primary.set_pixel(1, 0);
+
+        let mut ghost = Screen::default();
+        ghost.set_pixel(1, 0);
+        ghost.set_pixel(2, 0);
+
+        let palette = GhostPalette::default();
+        let framebuffer = composite(&primary, &ghost, &palette);
+        assert_eq!((framebuffer.width, framebuffer.height), (64, 32));
+        assert_eq!(
+            framebuffer.pixels[..4],
+            [palette.primary, palette.both, palette.ghost, palette.background]
+        );
+    }
+}
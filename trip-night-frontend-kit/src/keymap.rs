@@ -0,0 +1,220 @@
+use trip_night_core::analysis::ArrowLayout;
+use trip_night_core::keypad::{Key, Keypad};
+
+/// Host keys bound to the CHIP-8 keypad
+///
+/// Host keys are identified by name (e.g. `Key1`, `Q`, `Up`, `Space`) so that the mapping can be
+/// stored in configuration files and shared by all the frontends. Each frontend translates the
+/// names into its own key codes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyMapConfig {
+    bindings: Vec<(String, Key)>,
+}
+
+impl KeyMapConfig {
+    /// Empty mapping
+    pub fn empty() -> Self {
+        Self { bindings: Vec::new() }
+    }
+
+    /// Standard layout on a QWERTY keyboard
+    ///
+    /// ```text
+    /// 1 | 2 | 3 | 4        1 | 2 | 3 | C
+    /// Q | W | E | R   =>   4 | 5 | 6 | D
+    /// A | S | D | F        7 | 8 | 9 | E
+    /// Z | X | C | V        A | 0 | B | F
+    /// ```
+    pub fn qwerty() -> Self {
+        const LAYOUT: [(&str, Key); 16] = [
+            ("Key1", Key::K1),
+            ("Key2", Key::K2),
+            ("Key3", Key::K3),
+            ("Key4", Key::KC),
+            ("Q", Key::K4),
+            ("W", Key::K5),
+            ("E", Key::K6),
+            ("R", Key::KD),
+            ("A", Key::K7),
+            ("S", Key::K8),
+            ("D", Key::K9),
+            ("F", Key::KE),
+            ("Z", Key::KA),
+            ("X", Key::K0),
+            ("C", Key::KB),
+            ("V", Key::KF),
+        ];
+
+        Self {
+            bindings: LAYOUT.iter().map(|(name, key)| (name.to_string(), *key)).collect(),
+        }
+    }
+
+    /// Adds the arrows and space bindings suggested by the ROM analysis
+    pub fn with_arrow_layout(mut self, layout: &ArrowLayout) -> Self {
+        let arrows = [
+            ("Up", layout.up),
+            ("Down", layout.down),
+            ("Left", layout.left),
+            ("Right", layout.right),
+            ("Space", layout.action),
+        ];
+
+        for (host, key) in arrows {
+            if let Some(key) = key {
+                self.bind(host, key);
+            }
+        }
+
+        self
+    }
+
+    /// Binds a host key to a CHIP-8 key, replacing the previous binding of this host key
+    pub fn bind(&mut self, host: &str, key: Key) {
+        self.unbind_host(host);
+        self.bindings.push((host.to_owned(), key));
+    }
+
+    pub fn unbind_host(&mut self, host: &str) {
+        self.bindings.retain(|(bound, _)| bound != host);
+    }
+
+    pub fn bindings(&self) -> &[(String, Key)] {
+        &self.bindings
+    }
+
+    /// Host keys bound to the given CHIP-8 key
+    pub fn hosts_for(&self, key: Key) -> impl Iterator<Item = &str> {
+        self.bindings
+            .iter()
+            .filter(move |(_, bound)| *bound == key)
+            .map(|(host, _)| host.as_str())
+    }
+
+    /// Updates the keypad according to which host keys are held down
+    pub fn update(&self, keypad: &mut Keypad, is_down: impl Fn(&str) -> bool) {
+        keypad.release_all();
+
+        for (host, key) in &self.bindings {
+            if is_down(host) {
+                keypad.press(*key);
+            }
+        }
+    }
+}
+
+impl Default for KeyMapConfig {
+    fn default() -> Self {
+        Self::qwerty()
+    }
+}
+
+/// Interactive remapping: the user presses, in turn, the host key to bind to each CHIP-8 key
+pub struct Rebinding {
+    key_map: KeyMapConfig,
+    /// Index in `Rebinding::ORDER` of the next key to bind
+    next: usize,
+}
+
+impl Rebinding {
+    /// Keys are asked for in the order of the physical keypad, row by row
+    const ORDER: [Key; 16] = [
+        Key::K1,
+        Key::K2,
+        Key::K3,
+        Key::KC,
+        Key::K4,
+        Key::K5,
+        Key::K6,
+        Key::KD,
+        Key::K7,
+        Key::K8,
+        Key::K9,
+        Key::KE,
+        Key::KA,
+        Key::K0,
+        Key::KB,
+        Key::KF,
+    ];
+
+    pub fn new() -> Self {
+        Self {
+            key_map: KeyMapConfig::empty(),
+            next: 0,
+        }
+    }
+
+    /// CHIP-8 key waiting for a host key, `None` once every key has been asked for
+    pub fn pending(&self) -> Option<Key> {
+        Self::ORDER.get(self.next).copied()
+    }
+
+    /// Binds the host key to the pending CHIP-8 key, and moves on to the next one
+    ///
+    /// A host key pressed again is moved to the pending key.
+    pub fn bind(&mut self, host: &str) {
+        if let Some(key) = self.pending() {
+            self.key_map.bind(host, key);
+            self.next += 1;
+        }
+    }
+
+    /// Leaves the pending CHIP-8 key unbound
+    pub fn skip(&mut self) {
+        self.next += 1;
+    }
+
+    pub fn finish(self) -> KeyMapConfig {
+        self.key_map
+    }
+}
+
+impl Default for Rebinding {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bindings() {
+        let layout = ArrowLayout {
+            up: Some(Key::K1),
+            down: Some(Key::K4),
+            ..ArrowLayout::default()
+        };
+        let mut map = KeyMapConfig::qwerty().with_arrow_layout(&layout);
+        assert_eq!(map.hosts_for(Key::K1).collect::<Vec<_>>(), ["Key1", "Up"]);
+
+        map.bind("Up", Key::K2);
+        assert_eq!(map.hosts_for(Key::K1).collect::<Vec<_>>(), ["Key1"]);
+        assert_eq!(map.hosts_for(Key::K2).collect::<Vec<_>>(), ["Key2", "Up"]);
+
+        let mut keypad = Keypad::default();
+        map.update(&mut keypad, |host| host == "Up" || host == "V");
+        assert_eq!(keypad.first_pressed(), Some(Key::K2));
+        assert!(keypad.is_pressed(Key::KF));
+        assert!(!keypad.is_pressed(Key::K1));
+    }
+
+    #[test]
+    fn rebinding() {
+        let mut rebinding = Rebinding::new();
+        assert_eq!(rebinding.pending(), Some(Key::K1));
+
+        rebinding.bind("Up");
+        rebinding.skip();
+        rebinding.bind("Up");
+        assert_eq!(rebinding.pending(), Some(Key::KC));
+
+        (0..13).for_each(|_| rebinding.skip());
+        assert_eq!(rebinding.pending(), None);
+        rebinding.bind("Space");
+
+        let key_map = rebinding.finish();
+        assert_eq!(key_map.bindings(), [("Up".to_owned(), Key::K3)]);
+    }
+}
@@ -0,0 +1,20 @@
+//! Frontend logic shared by the Trip Night frontends
+//!
+//! Everything here is independent from the windowing, input and audio libraries, so that features
+//! such as pacing, performance diagnostics, audio, key mapping, palettes, configuration files,
+//! screenshots, recording, overlays and translations are implemented once and behave the same in
+//! every frontend.
+
+pub mod audio;
+pub mod capture;
+pub mod config;
+pub mod emulator;
+pub mod ghost;
+pub mod keymap;
+pub mod locale;
+pub mod overlay;
+pub mod pacing;
+pub mod palette;
+pub mod perf;
+pub mod postprocess;
+pub mod sprite;
@@ -0,0 +1,232 @@
+//! Translations of the messages shown to the user by the frontends
+//!
+//! Every message has an English default. A translation file overrides any of them, one
+//! `key = template` per line, where `{0}`, `{1}`… are replaced by the arguments of the message:
+//!
+//! ```text
+//! # French
+//! paused = En pause
+//! recorded = {0} images enregistrées
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::{self, Write as _};
+use std::path::Path;
+use std::{fs, io};
+
+use crate::config::ConfigError;
+
+/// Message shown to the user, e.g. in the overlay
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Message {
+    Paused,
+    Resumed,
+    Reset,
+    StateSaved,
+    StateLoaded,
+    /// `{0}`: the opcode, `{1}`: its address
+    UnknownOpCode,
+    PaintingStarted,
+    PaintingStopped,
+    /// `{0}`: the address of the sprite
+    SpriteExported,
+    /// `{0}`: the address of the sprite
+    SpriteExportFailed,
+    RebindingCancelled,
+    KeyBindingsSaved,
+    /// `{0}`: the error
+    KeyBindingsFailed,
+    ScreenshotSaved,
+    /// `{0}`: the error
+    ScreenshotFailed,
+    Recording,
+    /// `{0}`: the number of frames
+    Recorded,
+    /// `{0}`: the error
+    RecordingFailed,
+}
+
+impl Message {
+    pub const ALL: [Message; 18] = [
+        Message::Paused,
+        Message::Resumed,
+        Message::Reset,
+        Message::StateSaved,
+        Message::StateLoaded,
+        Message::UnknownOpCode,
+        Message::PaintingStarted,
+        Message::PaintingStopped,
+        Message::SpriteExported,
+        Message::SpriteExportFailed,
+        Message::RebindingCancelled,
+        Message::KeyBindingsSaved,
+        Message::KeyBindingsFailed,
+        Message::ScreenshotSaved,
+        Message::ScreenshotFailed,
+        Message::Recording,
+        Message::Recorded,
+        Message::RecordingFailed,
+    ];
+
+    /// Key of the message in translation files
+    pub fn key(self) -> &'static str {
+        match self {
+            Message::Paused => "paused",
+            Message::Resumed => "resumed",
+            Message::Reset => "reset",
+            Message::StateSaved => "state_saved",
+            Message::StateLoaded => "state_loaded",
+            Message::UnknownOpCode => "unknown_opcode",
+            Message::PaintingStarted => "painting_started",
+            Message::PaintingStopped => "painting_stopped",
+            Message::SpriteExported => "sprite_exported",
+            Message::SpriteExportFailed => "sprite_export_failed",
+            Message::RebindingCancelled => "rebinding_cancelled",
+            Message::KeyBindingsSaved => "key_bindings_saved",
+            Message::KeyBindingsFailed => "key_bindings_failed",
+            Message::ScreenshotSaved => "screenshot_saved",
+            Message::ScreenshotFailed => "screenshot_failed",
+            Message::Recording => "recording",
+            Message::Recorded => "recorded",
+            Message::RecordingFailed => "recording_failed",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|message| message.key() == key)
+    }
+
+    /// Template of the message in English
+    pub fn english(self) -> &'static str {
+        match self {
+            Message::Paused => "Paused",
+            Message::Resumed => "Resumed",
+            Message::Reset => "Reset",
+            Message::StateSaved => "State saved",
+            Message::StateLoaded => "State loaded",
+            Message::UnknownOpCode => "Unknown opcode {0} at {1}",
+            Message::PaintingStarted => "Painting: left click paints, right click erases, F4 exports the sprite",
+            Message::PaintingStopped => "Painting stopped",
+            Message::SpriteExported => "Sprite exported at {0}",
+            Message::SpriteExportFailed => "Sprite doesn't fit in RAM at {0}",
+            Message::RebindingCancelled => "Rebinding cancelled",
+            Message::KeyBindingsSaved => "Key bindings saved",
+            Message::KeyBindingsFailed => "Saving key bindings failed: {0}",
+            Message::ScreenshotSaved => "Screenshot saved",
+            Message::ScreenshotFailed => "Screenshot failed: {0}",
+            Message::Recording => "Recording",
+            Message::Recorded => "Recorded {0} frames",
+            Message::RecordingFailed => "Recording failed: {0}",
+        }
+    }
+}
+
+/// Templates of the messages, falling back to English for the ones without translation
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Locale {
+    templates: HashMap<Message, String>,
+}
+
+impl Locale {
+    /// Loads the translation file if it exists, English is used otherwise
+    pub fn load_or_default(path: impl AsRef<Path>) -> Result<Self, ConfigError> {
+        match fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let mut locale = Self::default();
+
+        for (idx, line) in text.lines().enumerate() {
+            let invalid = || ConfigError::InvalidLine { line: idx + 1 };
+
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (key, template) = line.split_once('=').ok_or_else(invalid)?;
+            let message = Message::from_key(key.trim()).ok_or_else(invalid)?;
+            locale.set(message, template.trim());
+        }
+
+        Ok(locale)
+    }
+
+    /// Replaces the template of a message
+    pub fn set(&mut self, message: Message, template: impl Into<String>) {
+        self.templates.insert(message, template.into());
+    }
+
+    pub fn template(&self, message: Message) -> &str {
+        self.templates.get(&message).map_or(message.english(), String::as_str)
+    }
+
+    /// Text of the message, with `{n}` replaced by the nth argument
+    ///
+    /// Placeholders without matching argument are kept as is.
+    pub fn text(&self, message: Message, args: &[&dyn fmt::Display]) -> String {
+        let mut text = String::new();
+        let mut rest = self.template(message);
+
+        while let Some(start) = rest.find('{') {
+            text.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            let arg = rest
+                .find('}')
+                .and_then(|end| Some((end, args.get(rest[1..end].parse::<usize>().ok()?)?)));
+
+            match arg {
+                Some((end, arg)) => {
+                    let _ = write!(text, "{arg}");
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    text.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+
+        text.push_str(rest);
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn english_by_default() {
+        let locale = Locale::default();
+        assert_eq!(locale.text(Message::Paused, &[]), "Paused");
+        assert_eq!(locale.text(Message::Recorded, &[&42]), "Recorded 42 frames");
+        assert_eq!(
+            locale.text(Message::UnknownOpCode, &[&"0x5121", &"0x204"]),
+            "Unknown opcode 0x5121 at 0x204"
+        );
+
+        for message in Message::ALL {
+            assert_eq!(Message::from_key(message.key()), Some(message));
+        }
+    }
+
+    #[test]
+    fn translated() {
+        let locale = Locale::parse("# French\npaused = En pause\nrecorded = {0} images {1} {x}\n").unwrap();
+        assert_eq!(locale.text(Message::Paused, &[]), "En pause");
+        assert_eq!(locale.text(Message::Recorded, &[&42]), "42 images {1} {x}");
+        assert_eq!(locale.text(Message::Reset, &[]), "Reset");
+
+        assert!(matches!(
+            Locale::parse("paused = En pause\nunknown = ?\n"),
+            Err(ConfigError::InvalidLine { line: 2 })
+        ));
+    }
+}
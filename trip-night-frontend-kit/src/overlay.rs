@@ -0,0 +1,60 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Short messages displayed on top of the screen (e.g. "Screenshot saved")
+#[derive(Clone, Debug, Default)]
+pub struct Overlay {
+    messages: VecDeque<(String, Duration)>,
+}
+
+impl Overlay {
+    pub const DEFAULT_DURATION: Duration = Duration::from_secs(2);
+
+    pub fn show(&mut self, message: impl Into<String>) {
+        self.show_for(message, Self::DEFAULT_DURATION);
+    }
+
+    pub fn show_for(&mut self, message: impl Into<String>, duration: Duration) {
+        self.messages.push_back((message.into(), duration));
+    }
+
+    /// Advances time, expiring the current message when its time is up
+    pub fn update(&mut self, elapsed: Duration) {
+        if let Some((_, remaining)) = self.messages.front_mut() {
+            *remaining = remaining.saturating_sub(elapsed);
+
+            if remaining.is_zero() {
+                self.messages.pop_front();
+            }
+        }
+    }
+
+    /// Message to display right now, if any
+    pub fn current(&self) -> Option<&str> {
+        self.messages.front().map(|(message, _)| message.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages_are_queued() {
+        let mut overlay = Overlay::default();
+        assert_eq!(overlay.current(), None);
+
+        overlay.show_for("first", Duration::from_secs(1));
+        overlay.show("second");
+        assert_eq!(overlay.current(), Some("first"));
+
+        overlay.update(Duration::from_millis(600));
+        assert_eq!(overlay.current(), Some("first"));
+
+        overlay.update(Duration::from_millis(600));
+        assert_eq!(overlay.current(), Some("second"));
+
+        overlay.update(Overlay::DEFAULT_DURATION);
+        assert_eq!(overlay.current(), None);
+    }
+}
@@ -0,0 +1,63 @@
+use std::time::Duration;
+
+/// Converts elapsed wall-clock time into a number of cycles to run
+///
+/// Wraps the integer pacer of the core, shared with frontends running without FPU. Fractional
+/// cycles are carried over to the next call so that the long-term average frequency is exact
+/// regardless of the frame rate of the frontend.
+#[derive(Clone, Debug)]
+pub struct Pacer {
+    inner: trip_night_core::pacing::Pacer,
+}
+
+impl Pacer {
+    pub fn new(frequency_hz: usize) -> Self {
+        Self {
+            inner: trip_night_core::pacing::Pacer::new(frequency_hz),
+        }
+    }
+
+    /// Longest elapsed time caught up at once (e.g. after the window was dragged or minimized)
+    pub fn with_max_catch_up(self, max_catch_up: Duration) -> Self {
+        Self {
+            inner: self.inner.with_max_catch_up_us(micros(max_catch_up)),
+        }
+    }
+
+    pub fn frequency_hz(&self) -> usize {
+        self.inner.frequency_hz()
+    }
+
+    pub fn set_frequency_hz(&mut self, frequency_hz: usize) {
+        self.inner.set_frequency_hz(frequency_hz);
+    }
+
+    /// Number of cycles to run for the given elapsed time
+    pub fn cycles_for(&mut self, elapsed: Duration) -> usize {
+        self.inner.cycles_for_us(micros(elapsed))
+    }
+}
+
+fn micros(duration: Duration) -> u64 {
+    u64::try_from(duration.as_micros()).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn carries_fractional_cycles() {
+        let mut pacer = Pacer::new(700);
+        let frame = Duration::from_secs_f64(1.0 / 60.0);
+
+        let total: usize = (0..60).map(|_| pacer.cycles_for(frame)).sum();
+        assert!((699..=700).contains(&total));
+    }
+
+    #[test]
+    fn caps_catch_up() {
+        let mut pacer = Pacer::new(1000).with_max_catch_up(Duration::from_millis(50));
+        assert_eq!(pacer.cycles_for(Duration::from_secs(10)), 50);
+    }
+}
@@ -0,0 +1,104 @@
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidColorError;
+
+impl fmt::Display for InvalidColorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected a color formatted as #rrggbb")
+    }
+}
+
+impl std::error::Error for InvalidColorError {}
+
+impl FromStr for Rgb {
+    type Err = InvalidColorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.strip_prefix('#').ok_or(InvalidColorError)?;
+
+        if hex.len() != 6 {
+            return Err(InvalidColorError);
+        }
+
+        let value = u32::from_str_radix(hex, 16).map_err(|_| InvalidColorError)?;
+        let [_, r, g, b] = value.to_be_bytes();
+
+        Ok(Self { r, g, b })
+    }
+}
+
+/// Colors used to render the screen
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub background: Rgb,
+    pub foreground: Rgb,
+}
+
+impl Palette {
+    pub const MONOCHROME: Self = Self {
+        background: Rgb::new(0x00, 0x00, 0x00),
+        foreground: Rgb::new(0xFF, 0xFF, 0xFF),
+    };
+
+    /// Green phosphor CRT look
+    pub const PHOSPHOR: Self = Self {
+        background: Rgb::new(0x0A, 0x14, 0x0A),
+        foreground: Rgb::new(0x33, 0xFF, 0x66),
+    };
+
+    /// Amber CRT look
+    pub const AMBER: Self = Self {
+        background: Rgb::new(0x1A, 0x0F, 0x00),
+        foreground: Rgb::new(0xFF, 0xB0, 0x00),
+    };
+
+    pub fn by_name(name: &str) -> Option<Self> {
+        match name {
+            "monochrome" => Some(Self::MONOCHROME),
+            "phosphor" => Some(Self::PHOSPHOR),
+            "amber" => Some(Self::AMBER),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self::MONOCHROME
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_round_trip() {
+        let color: Rgb = "#33ff66".parse().unwrap();
+        assert_eq!(color, Rgb::new(0x33, 0xFF, 0x66));
+        assert_eq!(color.to_string(), "#33ff66");
+        assert_eq!("33ff66".parse::<Rgb>(), Err(InvalidColorError));
+        assert_eq!("#33ff6".parse::<Rgb>(), Err(InvalidColorError));
+    }
+}
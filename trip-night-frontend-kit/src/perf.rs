@@ -0,0 +1,114 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use trip_night_core::stats::PerfCounters;
+
+/// Rates of a machine against the wall clock
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PerfRates {
+    pub instructions_per_sec: f64,
+    pub draws_per_sec: f64,
+    pub frames_per_sec: f64,
+}
+
+impl fmt::Display for PerfRates {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.0} IPS, {:.1} draws/s, {:.1} frames/s",
+            self.instructions_per_sec, self.draws_per_sec, self.frames_per_sec
+        )
+    }
+}
+
+/// Derives rates from the counters of a machine (see `Machine::perf_counters`), refreshed once per window
+#[derive(Clone, Debug)]
+pub struct PerfMeter {
+    window: Duration,
+    /// Reading the current window started with
+    start: Option<(Instant, PerfCounters)>,
+    rates: PerfRates,
+}
+
+impl Default for PerfMeter {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(1))
+    }
+}
+
+impl PerfMeter {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            start: None,
+            rates: PerfRates::default(),
+        }
+    }
+
+    /// Takes a reading, typically once per presented frame, returns the new rates when a window ends
+    pub fn update(&mut self, counters: PerfCounters, now: Instant) -> Option<PerfRates> {
+        let Some((start, earlier)) = self.start else {
+            self.start = Some((now, counters));
+            return None;
+        };
+
+        let elapsed = now.saturating_duration_since(start);
+        if elapsed < self.window || elapsed.is_zero() {
+            return None;
+        }
+
+        let counts = counters.since(&earlier);
+        let per_sec = |count: u64| count as f64 / elapsed.as_secs_f64();
+
+        self.rates = PerfRates {
+            instructions_per_sec: per_sec(counts.instructions),
+            draws_per_sec: per_sec(counts.draws),
+            frames_per_sec: per_sec(counts.frames),
+        };
+        self.start = Some((now, counters));
+
+        Some(self.rates)
+    }
+
+    /// Rates of the last complete window, zero until one ended
+    pub fn rates(&self) -> PerfRates {
+        self.rates
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates_per_window() {
+        let mut meter = PerfMeter::new(Duration::from_millis(500));
+        let start = Instant::now();
+        let counters = |instructions, draws, frames| PerfCounters {
+            instructions,
+            draws,
+            frames,
+        };
+
+        assert_eq!(meter.update(counters(100, 1, 1), start), None);
+        assert_eq!(
+            meter.update(counters(200, 2, 10), start + Duration::from_millis(250)),
+            None
+        );
+
+        let rates = meter
+            .update(counters(600, 11, 31), start + Duration::from_millis(500))
+            .unwrap();
+        assert_eq!(rates.instructions_per_sec, 1000.0);
+        assert_eq!(rates.draws_per_sec, 20.0);
+        assert_eq!(rates.frames_per_sec, 60.0);
+        assert_eq!(meter.rates(), rates);
+        assert_eq!(rates.to_string(), "1000 IPS, 20.0 draws/s, 60.0 frames/s");
+
+        // The next window starts at the last reading
+        assert_eq!(
+            meter.update(counters(700, 11, 32), start + Duration::from_millis(900)),
+            None
+        );
+    }
+}
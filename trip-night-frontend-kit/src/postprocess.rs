@@ -0,0 +1,282 @@
+//! Visual effects applied in software to the exported framebuffer
+//!
+//! Effects are chained in a `Pipeline`, so every frontend (including the ones without GPU access)
+//! can offer the same look.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::capture::Framebuffer;
+use crate::palette::Rgb;
+
+/// A post-processing stage
+pub trait Effect {
+    fn apply(&mut self, frame: &mut Framebuffer);
+}
+
+/// Effects chained in order
+#[derive(Default)]
+pub struct Pipeline {
+    effects: Vec<Box<dyn Effect>>,
+}
+
+impl Pipeline {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pipeline of the given effects, with their default settings
+    pub fn from_kinds(kinds: &[EffectKind]) -> Self {
+        let mut pipeline = Self::new();
+        kinds.iter().for_each(|kind| pipeline.push(kind.build()));
+        pipeline
+    }
+
+    pub fn push(&mut self, effect: Box<dyn Effect>) {
+        self.effects.push(effect);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+
+    pub fn apply(&mut self, frame: &mut Framebuffer) {
+        self.effects.iter_mut().for_each(|effect| effect.apply(frame));
+    }
+}
+
+/// Effects selectable from the configuration file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EffectKind {
+    Phosphor,
+    Glow,
+    Curvature,
+}
+
+impl EffectKind {
+    pub fn build(self) -> Box<dyn Effect> {
+        match self {
+            EffectKind::Phosphor => Box::new(PhosphorDecay::new(0.6)),
+            EffectKind::Glow => Box::new(Glow::new(0.35)),
+            EffectKind::Curvature => Box::new(Curvature::new(0.08)),
+        }
+    }
+}
+
+impl fmt::Display for EffectKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EffectKind::Phosphor => write!(f, "phosphor"),
+            EffectKind::Glow => write!(f, "glow"),
+            EffectKind::Curvature => write!(f, "curvature"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct UnknownEffectError;
+
+impl fmt::Display for UnknownEffectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "expected one of: phosphor, glow, curvature")
+    }
+}
+
+impl std::error::Error for UnknownEffectError {}
+
+impl FromStr for EffectKind {
+    type Err = UnknownEffectError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "phosphor" => Ok(EffectKind::Phosphor),
+            "glow" => Ok(EffectKind::Glow),
+            "curvature" => Ok(EffectKind::Curvature),
+            _ => Err(UnknownEffectError),
+        }
+    }
+}
+
+/// Pixels fade out slowly instead of disappearing, reducing the flicker of CHIP-8 games
+pub struct PhosphorDecay {
+    /// Fraction of the brightness kept from one frame to the next
+    persistence: f32,
+    previous: Option<Framebuffer>,
+}
+
+impl PhosphorDecay {
+    pub fn new(persistence: f32) -> Self {
+        Self {
+            persistence,
+            previous: None,
+        }
+    }
+}
+
+impl Effect for PhosphorDecay {
+    fn apply(&mut self, frame: &mut Framebuffer) {
+        if let Some(previous) = &self.previous {
+            if previous.width == frame.width && previous.height == frame.height {
+                let faded = |current: u8, previous: u8| current.max((f32::from(previous) * self.persistence) as u8);
+
+                frame
+                    .pixels
+                    .iter_mut()
+                    .zip(&previous.pixels)
+                    .for_each(|(current, previous)| {
+                        *current = Rgb::new(
+                            faded(current.r, previous.r),
+                            faded(current.g, previous.g),
+                            faded(current.b, previous.b),
+                        )
+                    });
+            }
+        }
+
+        self.previous = Some(frame.clone());
+    }
+}
+
+/// Lit pixels bleed some light onto their neighbors
+pub struct Glow {
+    strength: f32,
+}
+
+impl Glow {
+    pub fn new(strength: f32) -> Self {
+        Self { strength }
+    }
+}
+
+impl Effect for Glow {
+    fn apply(&mut self, frame: &mut Framebuffer) {
+        let source = frame.pixels.clone();
+        let (width, height) = (frame.width as isize, frame.height as isize);
+
+        for y in 0..height {
+            for x in 0..width {
+                // 3x3 box blur
+                let mut sum = [0.0f32; 3];
+
+                for (dx, dy) in (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (dx, dy))) {
+                    let (nx, ny) = (x + dx, y + dy);
+
+                    if (0..width).contains(&nx) && (0..height).contains(&ny) {
+                        let pixel = source[(ny * width + nx) as usize];
+                        sum[0] += f32::from(pixel.r);
+                        sum[1] += f32::from(pixel.g);
+                        sum[2] += f32::from(pixel.b);
+                    }
+                }
+
+                let glow = |channel: u8, sum: f32| (f32::from(channel) + sum / 9.0 * self.strength).min(255.0) as u8;
+
+                let pixel = &mut frame.pixels[(y * width + x) as usize];
+                *pixel = Rgb::new(glow(pixel.r, sum[0]), glow(pixel.g, sum[1]), glow(pixel.b, sum[2]));
+            }
+        }
+    }
+}
+
+/// Approximation of the curved glass of CRT screens (barrel distortion)
+pub struct Curvature {
+    amount: f32,
+}
+
+impl Curvature {
+    pub fn new(amount: f32) -> Self {
+        Self { amount }
+    }
+}
+
+impl Effect for Curvature {
+    fn apply(&mut self, frame: &mut Framebuffer) {
+        let source = frame.pixels.clone();
+        let (width, height) = (frame.width as f32, frame.height as f32);
+
+        for y in 0..frame.height {
+            for x in 0..frame.width {
+                // Coordinates of the pixel center, from -1 to 1
+                let u = (x as f32 + 0.5) / width * 2.0 - 1.0;
+                let v = (y as f32 + 0.5) / height * 2.0 - 1.0;
+                let distortion = 1.0 + self.amount * (u * u + v * v);
+
+                let source_x = ((u * distortion + 1.0) / 2.0 * width).floor();
+                let source_y = ((v * distortion + 1.0) / 2.0 * height).floor();
+
+                frame.pixels[y * frame.width + x] =
+                    if (0.0..width).contains(&source_x) && (0.0..height).contains(&source_y) {
+                        source[source_y as usize * frame.width + source_x as usize]
+                    } else {
+                        Rgb::new(0, 0, 0)
+                    };
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Rgb = Rgb::new(0xFF, 0xFF, 0xFF);
+    const BLACK: Rgb = Rgb::new(0x00, 0x00, 0x00);
+
+    fn frame(lit: &[usize]) -> Framebuffer {
+        let mut pixels = vec![BLACK; 16 * 8];
+        lit.iter().for_each(|&idx| pixels[idx] = WHITE);
+        Framebuffer {
+            width: 16,
+            height: 8,
+            pixels,
+        }
+    }
+
+    #[test]
+    fn phosphor_decay() {
+        let mut effect = PhosphorDecay::new(0.5);
+
+        let mut first = frame(&[0]);
+        effect.apply(&mut first);
+        assert_eq!(first.pixels[0], WHITE);
+
+        let mut second = frame(&[]);
+        effect.apply(&mut second);
+        assert_eq!(second.pixels[0], Rgb::new(0x7F, 0x7F, 0x7F));
+
+        let mut third = frame(&[]);
+        effect.apply(&mut third);
+        assert_eq!(third.pixels[0], Rgb::new(0x3F, 0x3F, 0x3F));
+    }
+
+    #[test]
+    fn glow() {
+        let mut lit = frame(&[16 * 4 + 8]);
+        Glow::new(0.9).apply(&mut lit);
+        assert_eq!(lit.pixels[16 * 4 + 8], WHITE);
+        assert_eq!(lit.pixels[16 * 4 + 9], Rgb::new(25, 25, 25));
+        assert_eq!(lit.pixels[16 * 4 + 10], BLACK);
+    }
+
+    #[test]
+    fn curvature() {
+        let mut lit = frame(&(0..16 * 8).collect::<Vec<_>>());
+        Curvature::new(0.2).apply(&mut lit);
+        assert_eq!(lit.pixels[16 * 4 + 8], WHITE);
+        assert_eq!(lit.pixels[0], BLACK);
+    }
+
+    #[test]
+    fn pipeline() {
+        let kinds: Vec<EffectKind> = ["phosphor", "glow"].iter().map(|name| name.parse().unwrap()).collect();
+        assert_eq!(kinds, [EffectKind::Phosphor, EffectKind::Glow]);
+        assert_eq!("bloom".parse::<EffectKind>(), Err(UnknownEffectError));
+
+        let mut pipeline = Pipeline::from_kinds(&kinds);
+        assert!(!pipeline.is_empty());
+
+        let mut lit = frame(&[0]);
+        pipeline.apply(&mut lit);
+        assert_eq!(lit.pixels[1], Rgb::new(9, 9, 9));
+    }
+}
@@ -0,0 +1,85 @@
+//! Sprite editing for ROM developers
+//!
+//! Pixels are painted directly on the screen of a paused machine, then the 8 pixels wide sprite
+//! under the cursor is exported as bytes, ready to be pasted in the ROM sources or written to RAM.
+
+use trip_night_core::machine::{Fault, State};
+use trip_night_core::screen::Screen;
+
+/// Maximum number of rows of a DXYN sprite
+pub const MAX_HEIGHT: u8 = 15;
+
+/// Sets or unsets the pixel at the given position
+pub fn paint(screen: &mut Screen, x: u8, y: u8, set: bool) {
+    if set {
+        screen.set_pixel(x, y);
+    } else {
+        screen.unset_pixel(x, y);
+    }
+}
+
+/// Reads the sprite whose top-left corner is at the given position
+///
+/// The sprite stops at the bottom edge of the screen, and trailing empty rows are dropped.
+pub fn read(screen: &Screen, x: u8, y: u8) -> Vec<u8> {
+    let rows = MAX_HEIGHT.min(screen.height().saturating_sub(y));
+    let mut sprite: Vec<u8> = (0..rows).map(|row| screen.get_vectored(x, y + row)).collect();
+
+    while sprite.last() == Some(&0) {
+        sprite.pop();
+    }
+
+    sprite
+}
+
+/// Writes the sprite in RAM at the address held by the index register, the way FX55 would
+///
+/// The rows before a faulty one are written.
+pub fn store(state: &mut State, sprite: &[u8]) -> Result<(), Fault> {
+    for (offset, row) in (0..).zip(sprite) {
+        state.ram_write(state.index, offset, *row)?;
+    }
+
+    Ok(())
+}
+
+/// Formats the sprite as a list of hexadecimal bytes, e.g. `0xF0 0x90 0xF0`
+pub fn format(sprite: &[u8]) -> String {
+    sprite
+        .iter()
+        .map(|row| format!("{row:#04X}").replacen("0X", "0x", 1))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use trip_night_core::machine::MemoryBounds;
+
+    use super::*;
+
+    #[test]
+    fn paint_and_read() {
+        let mut screen = Screen::default();
+
+        paint(&mut screen, 10, 30, true);
+        paint(&mut screen, 17, 30, true);
+        paint(&mut screen, 11, 31, true);
+        paint(&mut screen, 11, 31, false);
+
+        assert_eq!(read(&screen, 10, 29), [0x00, 0x81]);
+        assert_eq!(read(&screen, 10, 31), []);
+        assert_eq!(format(&read(&screen, 10, 29)), "0x00 0x81");
+    }
+
+    #[test]
+    fn store_rows() {
+        let mut state = State::builder().index(0x300).build();
+        assert_eq!(store(&mut state, &[0xF0, 0x90]), Ok(()));
+        assert_eq!(state.ram[0x300..0x302], [0xF0, 0x90]);
+
+        let mut state = State::builder().index(0xFFF).memory_bounds(MemoryBounds::Fault).build();
+        assert_eq!(store(&mut state, &[0xF0, 0x90]), Err(Fault::MemoryOutOfBounds));
+        assert_eq!(state.ram[0xFFF], 0xF0);
+    }
+}
@@ -2,7 +2,10 @@
 
 use trip_night_core::decode::DecodeOpCode;
 use trip_night_core::instruction::{InstructionSet, OpCode};
-use trip_night_core::machine::State;
+use trip_night_core::keypad::{Key, WaitingForKey};
+use trip_night_core::machine::{Fault, State};
+use trip_night_core::quirks::Quirks;
+use trip_night_core::rng::Rng as _;
 use trip_night_core::{Address, RegIdent};
 
 pub fn make_standard_set() -> InstructionSet {
@@ -12,82 +15,142 @@ pub fn make_standard_set() -> InstructionSet {
     let mut set = make_nop_set();
 
     // 0×××
-    set[OP_00E0] = make_instruction!(ClearScreen::execute);
-    set[OP_00EE] = make_instruction!(Ret::execute);
+    set.op_00e0 = make_instruction!(ClearScreen::execute);
+    set.op_00ee = make_instruction!(Ret::execute);
+
+    // Custom instructions, unsupported by standard sets
+    set.op_00f1 = make_instruction!(Unsupported::execute);
 
     // 1×××
-    set[OP_1NNN] = make_instruction!(Jump::execute);
+    set.op_1nnn = make_instruction!(Jump::execute);
 
     // 2×××
-    set[OP_2NNN] = make_instruction!(Call::execute);
+    set.op_2nnn = make_instruction!(Call::execute);
 
     // 3×××
-    set[OP_3XNN] = make_instruction!(SkipEqConst::execute);
+    set.op_3xnn = make_instruction!(SkipEqConst::execute);
 
     // 4×××
-    set[OP_4XNN] = make_instruction!(SkipNeqConst::execute);
+    set.op_4xnn = make_instruction!(SkipNeqConst::execute);
 
     // 5×××
-    set[OP_5XY0] = make_instruction!(SkipEq::execute);
+    set.op_5xy0 = make_instruction!(SkipEq::execute);
 
     // 6×××
-    set[OP_6XNN] = make_instruction!(Set::execute);
+    set.op_6xnn = make_instruction!(Set::execute);
 
     // 7×××
-    set[OP_7XNN] = make_instruction!(AddConst::execute);
+    set.op_7xnn = make_instruction!(AddConst::execute);
 
     // 8×××
-    set[OP_8XY0] = make_instruction!(Assign::execute);
-    set[OP_8XY1] = make_instruction!(BitOr::execute);
-    set[OP_8XY2] = make_instruction!(BitAnd::execute);
-    set[OP_8XY3] = make_instruction!(BitXor::execute);
-    set[OP_8XY4] = make_instruction!(Add::execute);
-    set[OP_8XY5] = make_instruction!(Sub::execute);
-    set[OP_8XY6] = make_instruction!(ShiftRight::execute);
-    set[OP_8XY7] = make_instruction!(SubN::execute);
-    set[OP_8XYE] = make_instruction!(ShiftLeft::execute);
+    set.op_8xy0 = make_instruction!(Assign::execute);
+    set.op_8xy1 = make_instruction!(BitOr::execute);
+    set.op_8xy2 = make_instruction!(BitAnd::execute);
+    set.op_8xy3 = make_instruction!(BitXor::execute);
+    set.op_8xy4 = make_instruction!(Add::execute);
+    set.op_8xy5 = make_instruction!(Sub::execute);
+    set.op_8xy6 = make_instruction!(ShiftRight::execute);
+    set.op_8xy7 = make_instruction!(SubN::execute);
+    set.op_8xye = make_instruction!(ShiftLeft::execute);
 
     // 9×××
-    set[OP_9XY0] = make_instruction!(SkipNeq::execute);
+    set.op_9xy0 = make_instruction!(SkipNeq::execute);
 
     // A×××
-    set[OP_ANNN] = make_instruction!(SetIndex::execute);
+    set.op_annn = make_instruction!(SetIndex::execute);
 
     // B×××
-    set[OP_BNNN] = make_instruction!(JumpOffset::execute);
+    set.op_bnnn = make_instruction!(JumpOffset::execute);
 
     // C×××
-    set[OP_CXNN] = make_instruction!(Random::execute);
+    set.op_cxnn = make_instruction!(Random::execute);
 
     // D×××
-    set[OP_DXYN] = make_instruction!(Draw::execute);
+    set.op_dxyn = make_instruction!(Draw::execute);
 
     // E×××
-    // set[OP_EX9E] = TODO
-    // set[OP_EXA1] = TODO
+    set.op_ex9e = make_instruction!(SkipKeyPressed::execute);
+    set.op_exa1 = make_instruction!(SkipKeyNotPressed::execute);
 
     // F×××
-    // set[OP_FX07] = TODO
-    // set[OP_FX0A] = TODO
-    // set[OP_FX15] = TODO
-    // set[OP_FX18] = TODO
-    // set[OP_FX1E] = TODO
-    // set[OP_FX29] = TODO
-    // set[OP_FX33] = TODO
-    // set[OP_FX55] = TODO
-    // set[OP_FX65] = TODO
+    set.op_fx07 = make_instruction!(ReadDelay::execute);
+    set.op_fx0a = make_instruction!(WaitKey::execute);
+    set.op_fx15 = make_instruction!(SetDelay::execute);
+    set.op_fx18 = make_instruction!(SetSound::execute);
+    set.op_fx1e = make_instruction!(AddToIndex::execute);
+    set.op_fx29 = make_instruction!(SetIndexToFont::execute);
+    set.op_fx33 = make_instruction!(StoreBcd::execute);
+    set.op_fx55 = make_instruction!(StoreRegisters::execute);
+    set.op_fx65 = make_instruction!(LoadRegisters::execute);
 
     set
 }
 
 pub fn make_legacy_set() -> InstructionSet {
-    use trip_night_core::instruction::*;
     use trip_night_core::make_instruction;
 
     let mut set = make_standard_set();
 
-    set[OP_8XY6] = make_instruction!(ShiftRightLegacy::execute);
-    set[OP_8XYE] = make_instruction!(ShiftLeftLegacy::execute);
+    set.op_8xy6 = make_instruction!(ShiftRightLegacy::execute);
+    set.op_8xye = make_instruction!(ShiftLeftLegacy::execute);
+    set.op_fx55 = make_instruction!(StoreRegistersLegacy::execute);
+    set.op_fx65 = make_instruction!(LoadRegistersLegacy::execute);
+
+    set
+}
+
+/// Builds the instruction set matching the given quirks
+pub fn make_set(quirks: &Quirks) -> InstructionSet {
+    use trip_night_core::make_instruction;
+
+    let mut set = make_standard_set();
+
+    if quirks.shift_uses_vy {
+        set.op_8xy6 = make_instruction!(ShiftRightLegacy::execute);
+        set.op_8xye = make_instruction!(ShiftLeftLegacy::execute);
+    }
+
+    if quirks.logic_resets_vf {
+        set.op_8xy1 = make_instruction!(BitOrResetVf::execute);
+        set.op_8xy2 = make_instruction!(BitAndResetVf::execute);
+        set.op_8xy3 = make_instruction!(BitXorResetVf::execute);
+    }
+
+    if quirks.memory_increments_index {
+        set.op_fx55 = make_instruction!(StoreRegistersLegacy::execute);
+        set.op_fx65 = make_instruction!(LoadRegistersLegacy::execute);
+    }
+
+    if quirks.jump_uses_vx {
+        set.op_bnnn = make_instruction!(JumpOffsetVx::execute);
+    }
+
+    if quirks.index_overflow_sets_vf {
+        set.op_fx1e = make_instruction!(AddToIndexOverflow::execute);
+    }
+
+    if quirks.key_press_only {
+        set.op_fx0a = make_instruction!(WaitKeyPressOnly::execute);
+    }
+
+    set.op_dxyn = match (quirks.clip_sprites, quirks.display_wait) {
+        (false, false) => make_instruction!(Draw::execute),
+        (true, false) => make_instruction!(DrawClipped::execute),
+        (false, true) => make_instruction!(DrawWait::execute),
+        (true, true) => make_instruction!(DrawClippedWait::execute),
+    };
+
+    set
+}
+
+/// Builds the instruction set matching the given quirks, extended with the custom instructions of
+/// fantasy consoles (00F1 yield)
+pub fn make_fantasy_set(quirks: &Quirks) -> InstructionSet {
+    use trip_night_core::make_instruction;
+
+    let mut set = make_set(quirks);
+
+    set.op_00f1 = make_instruction!(Yield::execute);
 
     set
 }
@@ -146,12 +209,76 @@ impl Draw {
         let x = state.reg_read(self.x_reg);
         let y = state.reg_read(self.y_reg);
 
-        let start = usize::from(state.index.0);
-        let end = start + usize::from(self.height);
+        let sprite = match read_sprite(state, self.height) {
+            Ok(sprite) => sprite,
+            Err(fault) => {
+                state.raise(fault);
+                return;
+            }
+        };
+
+        let mut unset_bit = false;
+
+        for (i, sprite_row) in (0..self.height).zip(sprite) {
+            match state.screen.flip_vectored_wrapping(sprite_row, x, y.wrapping_add(i)) {
+                FlipResult::UnsetBit => unset_bit = true,
+                FlipResult::NoUnsetBit => {}
+            }
+        }
+
+        if unset_bit {
+            state.reg_write(RegIdent::VF, 0x01);
+        } else {
+            state.reg_write(RegIdent::VF, 0x00);
+        }
+    }
+}
+
+/// DXYN with clipping
+///
+/// Like DXYN, but the parts of the sprite past the edges of the screen are not drawn.
+///
+/// Only the starting coordinates (Vx, Vy) wrap around, a sprite starting on-screen is clipped at
+/// the right and bottom edges instead of wrapping to the opposite side.
+pub struct DrawClipped {
+    pub x_reg: RegIdent,
+    pub y_reg: RegIdent,
+    pub height: u8,
+}
+
+impl DecodeOpCode for DrawClipped {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0xD);
+        Self {
+            x_reg: opcode.get_x(),
+            y_reg: opcode.get_y(),
+            height: opcode.get_n(),
+        }
+    }
+}
+
+impl DrawClipped {
+    pub fn execute(self, state: &mut State) {
+        use trip_night_core::screen::FlipResult;
+
+        let x = state.reg_read(self.x_reg) % state.screen.width();
+        let y = state.reg_read(self.y_reg) % state.screen.height();
+
+        // Rows past the bottom edge are dropped
+        let height = core::cmp::min(self.height, state.screen.height() - y);
+
+        let sprite = match read_sprite(state, height) {
+            Ok(sprite) => sprite,
+            Err(fault) => {
+                state.raise(fault);
+                return;
+            }
+        };
 
         let mut unset_bit = false;
 
-        for (i, sprite_row) in (0..self.height).zip(state.ram[start..end].iter().cloned()) {
+        // Columns past the right edge are dropped by the screen itself
+        for (i, sprite_row) in (0..height).zip(sprite) {
             match state.screen.flip_vectored(sprite_row, x, y + i) {
                 FlipResult::UnsetBit => unset_bit = true,
                 FlipResult::NoUnsetBit => {}
@@ -166,8 +293,280 @@ impl Draw {
     }
 }
 
+/// DXYN with display wait
+///
+/// Like DXYN, then execution is suspended until the next vertical blank (original COSMAC VIP
+/// behavior, limiting games to one sprite per frame).
+pub struct DrawWait {
+    pub x_reg: RegIdent,
+    pub y_reg: RegIdent,
+    pub height: u8,
+}
+
+impl DecodeOpCode for DrawWait {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0xD);
+        Self {
+            x_reg: opcode.get_x(),
+            y_reg: opcode.get_y(),
+            height: opcode.get_n(),
+        }
+    }
+}
+
+impl DrawWait {
+    pub fn execute(self, state: &mut State) {
+        Draw {
+            x_reg: self.x_reg,
+            y_reg: self.y_reg,
+            height: self.height,
+        }
+        .execute(state);
+        state.waiting_for_vblank = true;
+    }
+}
+
+/// DXYN with clipping and display wait
+///
+/// Like DXYN with clipping, then execution is suspended until the next vertical blank.
+pub struct DrawClippedWait {
+    pub x_reg: RegIdent,
+    pub y_reg: RegIdent,
+    pub height: u8,
+}
+
+impl DecodeOpCode for DrawClippedWait {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0xD);
+        Self {
+            x_reg: opcode.get_x(),
+            y_reg: opcode.get_y(),
+            height: opcode.get_n(),
+        }
+    }
+}
+
+impl DrawClippedWait {
+    pub fn execute(self, state: &mut State) {
+        DrawClipped {
+            x_reg: self.x_reg,
+            y_reg: self.y_reg,
+            height: self.height,
+        }
+        .execute(state);
+        state.waiting_for_vblank = true;
+    }
+}
+
+//=== Input ===//
+
+/// EX9E
+///
+/// Skip next instruction if key with the value of Vx is pressed.
+///
+/// Checks the keyboard, and if the key corresponding to the value of Vx is currently in the down
+/// position, PC is increased by 2.
+pub struct SkipKeyPressed {
+    pub key: RegIdent,
+}
+
+impl DecodeOpCode for SkipKeyPressed {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0xE);
+        debug_assert_eq!(opcode.get_nn(), 0x9E);
+        Self { key: opcode.get_x() }
+    }
+}
+
+impl SkipKeyPressed {
+    pub fn execute(self, state: &mut State) {
+        let key = read_key(state, self.key);
+
+        if state.keypad.is_pressed(key) {
+            state.pc += 2;
+        }
+    }
+}
+
+/// EXA1
+///
+/// Skip next instruction if key with the value of Vx is not pressed.
+///
+/// Checks the keyboard, and if the key corresponding to the value of Vx is currently in the up
+/// position, PC is increased by 2.
+pub struct SkipKeyNotPressed {
+    pub key: RegIdent,
+}
+
+impl DecodeOpCode for SkipKeyNotPressed {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0xE);
+        debug_assert_eq!(opcode.get_nn(), 0xA1);
+        Self { key: opcode.get_x() }
+    }
+}
+
+impl SkipKeyNotPressed {
+    pub fn execute(self, state: &mut State) {
+        let key = read_key(state, self.key);
+
+        if !state.keypad.is_pressed(key) {
+            state.pc += 2;
+        }
+    }
+}
+
+/// FX0A
+///
+/// Wait for a key press, store the value of the key in Vx.
+///
+/// All execution stops until a key is pressed and then released, like on the original COSMAC VIP,
+/// then the value of that key is stored in Vx.
+pub struct WaitKey {
+    pub target: RegIdent,
+}
+
+impl DecodeOpCode for WaitKey {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0xF);
+        debug_assert_eq!(opcode.get_nn(), 0x0A);
+        Self { target: opcode.get_x() }
+    }
+}
+
+impl WaitKey {
+    pub fn execute(self, state: &mut State) {
+        state.waiting_for_key = Some(WaitingForKey::Press {
+            target: self.target,
+            await_release: true,
+        });
+    }
+}
+
+/// Press-only FX0A
+///
+/// Wait for a key press, store the value of the key in Vx (the key does not need to be released).
+pub struct WaitKeyPressOnly {
+    pub target: RegIdent,
+}
+
+impl DecodeOpCode for WaitKeyPressOnly {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0xF);
+        debug_assert_eq!(opcode.get_nn(), 0x0A);
+        Self { target: opcode.get_x() }
+    }
+}
+
+impl WaitKeyPressOnly {
+    pub fn execute(self, state: &mut State) {
+        state.waiting_for_key = Some(WaitingForKey::Press {
+            target: self.target,
+            await_release: false,
+        });
+    }
+}
+
+/// Reads the key stored in the given register (only the lowest nibble is considered)
+fn read_key(state: &State, reg: RegIdent) -> Key {
+    let value = state.reg_read(reg) & 0xF;
+    Key::try_from(value).expect("an u8 with only the first nibble set")
+}
+
+//=== Timers ===//
+
+/// FX07
+///
+/// Set Vx = delay timer value.
+///
+/// The value of DT is placed into Vx.
+pub struct ReadDelay {
+    pub target: RegIdent,
+}
+
+impl DecodeOpCode for ReadDelay {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0xF);
+        debug_assert_eq!(opcode.get_nn(), 0x07);
+        Self { target: opcode.get_x() }
+    }
+}
+
+impl ReadDelay {
+    pub fn execute(self, state: &mut State) {
+        state.reg_write(self.target, state.delay_timer);
+    }
+}
+
+/// FX15
+///
+/// Set delay timer = Vx.
+///
+/// DT is set equal to the value of Vx.
+pub struct SetDelay {
+    pub source: RegIdent,
+}
+
+impl DecodeOpCode for SetDelay {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0xF);
+        debug_assert_eq!(opcode.get_nn(), 0x15);
+        Self { source: opcode.get_x() }
+    }
+}
+
+impl SetDelay {
+    pub fn execute(self, state: &mut State) {
+        state.delay_timer = state.reg_read(self.source);
+    }
+}
+
+/// FX18
+///
+/// Set sound timer = Vx.
+///
+/// ST is set equal to the value of Vx.
+pub struct SetSound {
+    pub source: RegIdent,
+}
+
+impl DecodeOpCode for SetSound {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0xF);
+        debug_assert_eq!(opcode.get_nn(), 0x18);
+        Self { source: opcode.get_x() }
+    }
+}
+
+impl SetSound {
+    pub fn execute(self, state: &mut State) {
+        state.sound_timer = state.reg_read(self.source);
+    }
+}
+
 //=== Flow Control ===///
 
+/// 00F1 (fantasy)
+///
+/// Yield to the host.
+///
+/// The execution is suspended until the next frame, so that programs can synchronize with the
+/// display without busy-waiting on the delay timer.
+pub struct Yield;
+
+impl DecodeOpCode for Yield {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_inner(), 0x00F1);
+        Self
+    }
+}
+
+impl Yield {
+    pub fn execute(self, state: &mut State) {
+        state.waiting_for_vblank = true;
+    }
+}
+
 /// 00EE
 ///
 /// Return from a subroutine.
@@ -185,7 +584,10 @@ impl DecodeOpCode for Ret {
 
 impl Ret {
     pub fn execute(self, state: &mut State) {
-        state.pc = state.stack_pop();
+        match state.stack_pop() {
+            Ok(addr) => state.pc = addr,
+            Err(fault) => state.raise(fault),
+        }
     }
 }
 
@@ -230,8 +632,10 @@ impl DecodeOpCode for Call {
 
 impl Call {
     pub fn execute(self, state: &mut State) {
-        state.stack_push(state.pc);
-        state.pc = self.addr;
+        match state.stack_push(state.pc) {
+            Ok(()) => state.pc = self.addr,
+            Err(fault) => state.raise(fault),
+        }
     }
 }
 
@@ -385,6 +789,31 @@ impl JumpOffset {
     }
 }
 
+/// BXNN (SUPER-CHIP)
+///
+/// Jump to location xnn + Vx.
+pub struct JumpOffsetVx {
+    pub addr: Address,
+    pub offset: RegIdent,
+}
+
+impl DecodeOpCode for JumpOffsetVx {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0xB);
+        Self {
+            addr: opcode.get_nnn(),
+            offset: opcode.get_x(),
+        }
+    }
+}
+
+impl JumpOffsetVx {
+    pub fn execute(self, state: &mut State) {
+        let offset = state.reg_read(self.offset);
+        state.pc = self.addr + u16::from(offset);
+    }
+}
+
 //== Math operations ==//
 
 /// 6XNN
@@ -502,22 +931,18 @@ impl BitOr {
     }
 }
 
-/// 8XY2
-///
-/// Set Vx = Vx AND Vy.
+/// Legacy 8XY1
 ///
-/// Performs a bitwise AND on the values of Vx and Vy, then stores the result in Vx. A bitwise AND
-/// compares the corrseponding bits from two values, and if both bits are 1, then the same bit in
-/// the result is also 1. Otherwise, it is 0.
-pub struct BitAnd {
+/// Set Vx = Vx OR Vy, set VF = 0 (original COSMAC VIP behavior)
+pub struct BitOrResetVf {
     pub left: RegIdent,
     pub right: RegIdent,
 }
 
-impl DecodeOpCode for BitAnd {
+impl DecodeOpCode for BitOrResetVf {
     fn decode(opcode: OpCode) -> Self {
         debug_assert_eq!(opcode.get_first_nibble(), 0x8);
-        debug_assert_eq!(opcode.get_n(), 0x2);
+        debug_assert_eq!(opcode.get_n(), 0x1);
         Self {
             left: opcode.get_x(),
             right: opcode.get_y(),
@@ -525,16 +950,80 @@ impl DecodeOpCode for BitAnd {
     }
 }
 
-impl BitAnd {
+impl BitOrResetVf {
     pub fn execute(self, state: &mut State) {
-        let lhs = state.reg_read(self.left);
-        let rhs = state.reg_read(self.right);
-        let xored = lhs & rhs;
-        state.reg_write(self.left, xored);
+        BitOr {
+            left: self.left,
+            right: self.right,
+        }
+        .execute(state);
+        state.reg_write(RegIdent::VF, 0x0);
     }
 }
 
-/// 8XY3
+/// 8XY2
+///
+/// Set Vx = Vx AND Vy.
+///
+/// Performs a bitwise AND on the values of Vx and Vy, then stores the result in Vx. A bitwise AND
+/// compares the corrseponding bits from two values, and if both bits are 1, then the same bit in
+/// the result is also 1. Otherwise, it is 0.
+pub struct BitAnd {
+    pub left: RegIdent,
+    pub right: RegIdent,
+}
+
+impl DecodeOpCode for BitAnd {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0x8);
+        debug_assert_eq!(opcode.get_n(), 0x2);
+        Self {
+            left: opcode.get_x(),
+            right: opcode.get_y(),
+        }
+    }
+}
+
+impl BitAnd {
+    pub fn execute(self, state: &mut State) {
+        let lhs = state.reg_read(self.left);
+        let rhs = state.reg_read(self.right);
+        let xored = lhs & rhs;
+        state.reg_write(self.left, xored);
+    }
+}
+
+/// Legacy 8XY2
+///
+/// Set Vx = Vx AND Vy, set VF = 0 (original COSMAC VIP behavior)
+pub struct BitAndResetVf {
+    pub left: RegIdent,
+    pub right: RegIdent,
+}
+
+impl DecodeOpCode for BitAndResetVf {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0x8);
+        debug_assert_eq!(opcode.get_n(), 0x2);
+        Self {
+            left: opcode.get_x(),
+            right: opcode.get_y(),
+        }
+    }
+}
+
+impl BitAndResetVf {
+    pub fn execute(self, state: &mut State) {
+        BitAnd {
+            left: self.left,
+            right: self.right,
+        }
+        .execute(state);
+        state.reg_write(RegIdent::VF, 0x0);
+    }
+}
+
+/// 8XY3
 ///
 /// Set Vx = Vx XOR Vy.
 ///
@@ -566,6 +1055,36 @@ impl BitXor {
     }
 }
 
+/// Legacy 8XY3
+///
+/// Set Vx = Vx XOR Vy, set VF = 0 (original COSMAC VIP behavior)
+pub struct BitXorResetVf {
+    pub left: RegIdent,
+    pub right: RegIdent,
+}
+
+impl DecodeOpCode for BitXorResetVf {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0x8);
+        debug_assert_eq!(opcode.get_n(), 0x3);
+        Self {
+            left: opcode.get_x(),
+            right: opcode.get_y(),
+        }
+    }
+}
+
+impl BitXorResetVf {
+    pub fn execute(self, state: &mut State) {
+        BitXor {
+            left: self.left,
+            right: self.right,
+        }
+        .execute(state);
+        state.reg_write(RegIdent::VF, 0x0);
+    }
+}
+
 /// 8XY4
 ///
 /// Set Vx = Vx + Vy, set VF = carry.
@@ -594,13 +1113,7 @@ impl Add {
         let rhs = state.reg_read(self.right);
         let (added, overflowed) = lhs.overflowing_add(rhs);
 
-        if overflowed {
-            state.reg_write(RegIdent::VF, 0x1);
-        } else {
-            state.reg_write(RegIdent::VF, 0x0);
-        }
-
-        state.reg_write(self.left, added);
+        state.reg_write_with_flag(self.left, added, overflowed);
     }
 }
 
@@ -632,13 +1145,7 @@ impl Sub {
         let rhs = state.reg_read(self.right);
         let (substracted, underflowed) = lhs.overflowing_sub(rhs);
 
-        if underflowed {
-            state.reg_write(RegIdent::VF, 0x0);
-        } else {
-            state.reg_write(RegIdent::VF, 0x1);
-        }
-
-        state.reg_write(self.left, substracted);
+        state.reg_write_with_flag(self.left, substracted, !underflowed);
     }
 }
 
@@ -670,13 +1177,7 @@ impl SubN {
         let rhs = state.reg_read(self.right);
         let (substracted, underflowed) = rhs.overflowing_sub(lhs);
 
-        if underflowed {
-            state.reg_write(RegIdent::VF, 0x1);
-        } else {
-            state.reg_write(RegIdent::VF, 0x0);
-        }
-
-        state.reg_write(self.left, substracted);
+        state.reg_write_with_flag(self.left, substracted, !underflowed);
     }
 }
 
@@ -707,15 +1208,8 @@ impl ShiftRight {
         let lhs = state.reg_read(self.left);
         // NOTE: here, Vy is left unused
 
-        let (shifted, overflowed) = lhs.overflowing_shr(1);
-
-        if overflowed {
-            state.reg_write(RegIdent::VF, 0x1);
-        } else {
-            state.reg_write(RegIdent::VF, 0x0);
-        }
-
-        state.reg_write(self.left, shifted);
+        // VF receives the bit shifted out
+        state.reg_write_with_flag(self.left, lhs >> 1, lhs & 0x1 != 0);
     }
 }
 
@@ -742,15 +1236,7 @@ impl ShiftRightLegacy {
     pub fn execute(self, state: &mut State) {
         let rhs = state.reg_read(self.right);
 
-        let (shifted, overflowed) = rhs.overflowing_shr(1);
-
-        if overflowed {
-            state.reg_write(RegIdent::VF, 0x1);
-        } else {
-            state.reg_write(RegIdent::VF, 0x0);
-        }
-
-        state.reg_write(self.left, shifted);
+        state.reg_write_with_flag(self.left, rhs >> 1, rhs & 0x1 != 0);
     }
 }
 
@@ -768,7 +1254,7 @@ pub struct ShiftLeft {
 impl DecodeOpCode for ShiftLeft {
     fn decode(opcode: OpCode) -> Self {
         debug_assert_eq!(opcode.get_first_nibble(), 0x8);
-        debug_assert_eq!(opcode.get_n(), 0x6);
+        debug_assert_eq!(opcode.get_n(), 0xE);
         Self {
             left: opcode.get_x(),
             right: opcode.get_y(),
@@ -781,15 +1267,8 @@ impl ShiftLeft {
         let lhs = state.reg_read(self.left);
         // NOTE: here, Vy is left unused
 
-        let (shifted, overflowed) = lhs.overflowing_shl(1);
-
-        if overflowed {
-            state.reg_write(RegIdent::VF, 0x1);
-        } else {
-            state.reg_write(RegIdent::VF, 0x0);
-        }
-
-        state.reg_write(self.left, shifted);
+        // VF receives the bit shifted out
+        state.reg_write_with_flag(self.left, lhs << 1, lhs & 0x80 != 0);
     }
 }
 
@@ -804,7 +1283,7 @@ pub struct ShiftLeftLegacy {
 impl DecodeOpCode for ShiftLeftLegacy {
     fn decode(opcode: OpCode) -> Self {
         debug_assert_eq!(opcode.get_first_nibble(), 0x8);
-        debug_assert_eq!(opcode.get_n(), 0x6);
+        debug_assert_eq!(opcode.get_n(), 0xE);
         Self {
             left: opcode.get_x(),
             right: opcode.get_y(),
@@ -816,15 +1295,7 @@ impl ShiftLeftLegacy {
     pub fn execute(self, state: &mut State) {
         let rhs = state.reg_read(self.right);
 
-        let (shifted, overflowed) = rhs.overflowing_shl(1);
-
-        if overflowed {
-            state.reg_write(RegIdent::VF, 0x1);
-        } else {
-            state.reg_write(RegIdent::VF, 0x0);
-        }
-
-        state.reg_write(self.left, shifted);
+        state.reg_write_with_flag(self.left, rhs << 1, rhs & 0x80 != 0);
     }
 }
 
@@ -852,7 +1323,7 @@ impl DecodeOpCode for Random {
 
 impl Random {
     pub fn execute(self, state: &mut State) {
-        let random_number = 0x7A; // TODO: randomness
+        let random_number = state.rng.next_u8();
         let result = random_number & self.mask;
         state.reg_write(self.target, result);
     }
@@ -881,3 +1352,693 @@ impl SetIndex {
         state.index = self.addr;
     }
 }
+
+/// FX1E
+///
+/// Set I = I + Vx.
+///
+/// The values of I and Vx are added, and the results are stored in I. VF is not affected.
+pub struct AddToIndex {
+    pub source: RegIdent,
+}
+
+impl DecodeOpCode for AddToIndex {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0xF);
+        debug_assert_eq!(opcode.get_nn(), 0x1E);
+        Self { source: opcode.get_x() }
+    }
+}
+
+impl AddToIndex {
+    pub fn execute(self, state: &mut State) {
+        let offset = state.reg_read(self.source);
+        state.index += u16::from(offset);
+    }
+}
+
+/// Amiga FX1E
+///
+/// Set I = I + Vx, set VF = 1 when I overflows past 0xFFF (CHIP-8 interpreter for Amiga behavior).
+///
+/// At least one known game (Spacefight 2091!) relies on this behavior.
+pub struct AddToIndexOverflow {
+    pub source: RegIdent,
+}
+
+impl DecodeOpCode for AddToIndexOverflow {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0xF);
+        debug_assert_eq!(opcode.get_nn(), 0x1E);
+        Self { source: opcode.get_x() }
+    }
+}
+
+impl AddToIndexOverflow {
+    pub fn execute(self, state: &mut State) {
+        let offset = state.reg_read(self.source);
+        let sum = state.index.get() + u16::from(offset);
+        state.index = Address::new_masked(sum);
+
+        if sum > Address::MAX {
+            state.reg_write(RegIdent::VF, 0x1);
+        } else {
+            state.reg_write(RegIdent::VF, 0x0);
+        }
+    }
+}
+
+/// FX29
+///
+/// Set I = location of sprite for digit Vx.
+///
+/// The value of I is set to the location for the hexadecimal sprite corresponding to the value of
+/// Vx (only the lowest nibble is considered).
+pub struct SetIndexToFont {
+    pub digit: RegIdent,
+}
+
+impl DecodeOpCode for SetIndexToFont {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0xF);
+        debug_assert_eq!(opcode.get_nn(), 0x29);
+        Self { digit: opcode.get_x() }
+    }
+}
+
+impl SetIndexToFont {
+    pub fn execute(self, state: &mut State) {
+        use trip_night_core::font;
+
+        let digit = state.reg_read(self.digit);
+        state.index = font::char_address(state.font_address, digit);
+    }
+}
+
+/// FX33
+///
+/// Store BCD representation of Vx in memory locations I, I+1, and I+2.
+///
+/// The interpreter takes the decimal value of Vx, and places the hundreds digit in memory at
+/// location in I, the tens digit at location I+1, and the ones digit at location I+2.
+pub struct StoreBcd {
+    pub source: RegIdent,
+}
+
+impl DecodeOpCode for StoreBcd {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0xF);
+        debug_assert_eq!(opcode.get_nn(), 0x33);
+        Self { source: opcode.get_x() }
+    }
+}
+
+impl StoreBcd {
+    pub fn execute(self, state: &mut State) {
+        let value = state.reg_read(self.source);
+        let digits = [value / 100, value / 10 % 10, value % 10];
+
+        for (offset, digit) in (0..).zip(digits) {
+            if let Err(fault) = state.ram_write(state.index, offset, digit) {
+                state.raise(fault);
+                return;
+            }
+        }
+    }
+}
+
+/// FX55
+///
+/// Store registers V0 through Vx in memory starting at location I.
+///
+/// The interpreter copies the values of registers V0 through Vx into memory, starting at the
+/// address in I. I is left untouched.
+pub struct StoreRegisters {
+    pub last: RegIdent,
+}
+
+impl DecodeOpCode for StoreRegisters {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0xF);
+        debug_assert_eq!(opcode.get_nn(), 0x55);
+        Self { last: opcode.get_x() }
+    }
+}
+
+impl StoreRegisters {
+    pub fn execute(self, state: &mut State) {
+        if let Err(fault) = store_registers(state, self.last) {
+            state.raise(fault);
+        }
+    }
+}
+
+/// Legacy FX55
+///
+/// Store registers V0 through Vx in memory starting at location I, then set I = I + x + 1
+/// (original COSMAC VIP behavior).
+pub struct StoreRegistersLegacy {
+    pub last: RegIdent,
+}
+
+impl DecodeOpCode for StoreRegistersLegacy {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0xF);
+        debug_assert_eq!(opcode.get_nn(), 0x55);
+        Self { last: opcode.get_x() }
+    }
+}
+
+impl StoreRegistersLegacy {
+    pub fn execute(self, state: &mut State) {
+        match store_registers(state, self.last) {
+            Ok(()) => state.index += u16::from(self.last.get()) + 1,
+            Err(fault) => state.raise(fault),
+        }
+    }
+}
+
+/// FX65
+///
+/// Read registers V0 through Vx from memory starting at location I.
+///
+/// The interpreter reads values from memory starting at location I into registers V0 through Vx.
+/// I is left untouched.
+pub struct LoadRegisters {
+    pub last: RegIdent,
+}
+
+impl DecodeOpCode for LoadRegisters {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0xF);
+        debug_assert_eq!(opcode.get_nn(), 0x65);
+        Self { last: opcode.get_x() }
+    }
+}
+
+impl LoadRegisters {
+    pub fn execute(self, state: &mut State) {
+        if let Err(fault) = load_registers(state, self.last) {
+            state.raise(fault);
+        }
+    }
+}
+
+/// Legacy FX65
+///
+/// Read registers V0 through Vx from memory starting at location I, then set I = I + x + 1
+/// (original COSMAC VIP behavior).
+pub struct LoadRegistersLegacy {
+    pub last: RegIdent,
+}
+
+impl DecodeOpCode for LoadRegistersLegacy {
+    fn decode(opcode: OpCode) -> Self {
+        debug_assert_eq!(opcode.get_first_nibble(), 0xF);
+        debug_assert_eq!(opcode.get_nn(), 0x65);
+        Self { last: opcode.get_x() }
+    }
+}
+
+impl LoadRegistersLegacy {
+    pub fn execute(self, state: &mut State) {
+        match load_registers(state, self.last) {
+            Ok(()) => state.index += u16::from(self.last.get()) + 1,
+            Err(fault) => state.raise(fault),
+        }
+    }
+}
+
+fn store_registers(state: &mut State, last: RegIdent) -> Result<(), Fault> {
+    for offset in 0..=last.get() {
+        let reg = RegIdent::try_from(offset).expect("not past the last register");
+        state.ram_write(state.index, u16::from(offset), state.reg_read(reg))?;
+    }
+
+    Ok(())
+}
+
+fn load_registers(state: &mut State, last: RegIdent) -> Result<(), Fault> {
+    for offset in 0..=last.get() {
+        let reg = RegIdent::try_from(offset).expect("not past the last register");
+        let value = state.ram_read(state.index, u16::from(offset))?;
+        state.reg_write(reg, value);
+    }
+
+    Ok(())
+}
+
+/// Reads the rows of the sprite pointed by I, up to 16 rows
+fn read_sprite(state: &State, height: u8) -> Result<[u8; 16], Fault> {
+    let mut sprite = [0; 16];
+
+    for (offset, row) in (0..u16::from(height)).zip(sprite.iter_mut()) {
+        *row = state.ram_read(state.index, offset)?;
+    }
+
+    Ok(sprite)
+}
+
+#[cfg(test)]
+mod tests {
+    use trip_night_core::decode::decode_instruction;
+    use trip_night_core::machine::MemoryBounds;
+    use trip_night_core::rng::SequenceRng;
+
+    use super::*;
+
+    fn run(set: &InstructionSet, opcode: u16, state: &mut State) {
+        let opcode = OpCode::new(opcode);
+        decode_instruction(set, opcode)
+            .expect("a known opcode")
+            .execute(opcode, state);
+    }
+
+    fn run_standard(opcode: u16, state: &mut State) {
+        run(&make_standard_set(), opcode, state);
+    }
+
+    /// Executes every decodable opcode with every set, so that the debug assertions of the
+    /// instructions catch any drift between the decoder, the slots and the instruction tables
+    #[test]
+    fn decoder_consistency() {
+        use trip_night_core::decode::decode_slot;
+        use trip_night_core::instruction::SLOT_COUNT;
+
+        let sets = [
+            make_standard_set(),
+            make_legacy_set(),
+            make_set(&Quirks::cosmac_vip()),
+            make_set(&Quirks::schip()),
+            make_set(&Quirks::xo_chip()),
+            make_fantasy_set(&Quirks::default()),
+        ];
+        let state = State::builder().build();
+        let mut reached = [false; SLOT_COUNT];
+
+        for opcode in 0..=u16::MAX {
+            let Ok(slot) = decode_slot(OpCode::new(opcode)) else {
+                continue;
+            };
+            reached[slot] = true;
+
+            for set in &sets {
+                run(set, opcode, &mut state.clone());
+            }
+        }
+
+        let unreached = reached.iter().position(|reached| !reached);
+        assert_eq!(unreached, None, "slot never produced by the decoder");
+    }
+
+    #[test]
+    fn set_builder() {
+        use trip_night_core::instruction::{OP_6XNN, SLOT_COUNT};
+        use trip_night_core::make_instruction;
+
+        let set = InstructionSet::builder()
+            .op_6xnn(make_instruction!(Set::execute))
+            .build();
+        let mut state = State::builder().build();
+
+        run(&set, 0x6A42, &mut state);
+        run(&set, 0x7A01, &mut state);
+        assert_eq!(state.reg_read(RegIdent::VA), 0x42);
+
+        let legacy = make_standard_set()
+            .to_builder()
+            .op_8xy6(make_instruction!(ShiftRightLegacy::execute))
+            .build();
+        let mut state = State::builder().registers(&[0x00, 0x04]).build();
+        run(&legacy, 0x8016, &mut state);
+        assert_eq!(state.reg_read(RegIdent::V0), 0x02);
+
+        assert!(set.get(OP_6XNN).is_some());
+        assert!(set.get(SLOT_COUNT).is_none());
+    }
+
+    #[test]
+    fn clear_screen() {
+        let mut state = State::builder().pixels(&[(0, 0), (63, 31)]).build();
+        run_standard(0x00E0, &mut state);
+        assert_eq!(state.screen.get_vectored(0, 0), 0);
+        assert_eq!(state.screen.get_vectored(56, 31), 0);
+    }
+
+    #[test]
+    fn draw() {
+        // Glyph of "0" from the font
+        let mut state = State::builder().index(0x50).registers(&[8, 4]).build();
+
+        run_standard(0xD015, &mut state);
+        assert_eq!(state.screen.get_vectored(8, 4), 0xF0);
+        assert_eq!(state.screen.get_vectored(8, 5), 0x90);
+        assert_eq!(state.reg_read(RegIdent::VF), 0);
+
+        run_standard(0xD015, &mut state);
+        assert_eq!(state.screen.get_vectored(8, 4), 0x00);
+        assert_eq!(state.reg_read(RegIdent::VF), 1);
+        assert!(!state.waiting_for_vblank);
+    }
+
+    #[test]
+    fn draw_quirks() {
+        let state = State::builder()
+            .ram(0x300, &[0xFF])
+            .index(0x300)
+            .registers(&[60, 0])
+            .build();
+
+        let mut wrapping = state.clone();
+        run_standard(0xD011, &mut wrapping);
+        assert_eq!(wrapping.screen.get_vectored(0, 0), 0xF0);
+
+        let mut clipped = state.clone();
+        run(&make_set(&Quirks::schip()), 0xD011, &mut clipped);
+        assert_eq!(clipped.screen.get_vectored(0, 0), 0x00);
+        assert_eq!(clipped.screen.get_vectored(56, 0), 0x0F);
+        assert!(!clipped.waiting_for_vblank);
+
+        let mut waiting = state;
+        run(&make_set(&Quirks::cosmac_vip()), 0xD011, &mut waiting);
+        assert!(waiting.waiting_for_vblank);
+    }
+
+    #[test]
+    fn draw_out_of_bounds() {
+        let mut state = State::builder().index(0xFFF).memory_bounds(MemoryBounds::Fault).build();
+        run_standard(0xD012, &mut state);
+        assert_eq!(state.fault(), Some(Fault::MemoryOutOfBounds));
+        assert_eq!(state.screen.get_vectored(0, 0), 0);
+    }
+
+    #[test]
+    fn skip_key() {
+        let state = State::builder().registers(&[0x5, 0x6]).pressed(&[Key::K5]).build();
+
+        let mut pressed = state.clone();
+        run_standard(0xE09E, &mut pressed);
+        run_standard(0xE19E, &mut pressed);
+        assert_eq!(pressed.pc, Address::new(0x202).unwrap());
+
+        let mut not_pressed = state;
+        run_standard(0xE0A1, &mut not_pressed);
+        run_standard(0xE1A1, &mut not_pressed);
+        assert_eq!(not_pressed.pc, Address::new(0x202).unwrap());
+    }
+
+    #[test]
+    fn wait_key() {
+        for key_press_only in [false, true] {
+            let quirks = Quirks {
+                key_press_only,
+                ..Quirks::default()
+            };
+
+            let mut state = State::builder().build();
+            run(&make_set(&quirks), 0xF30A, &mut state);
+
+            assert_eq!(
+                state.waiting_for_key,
+                Some(WaitingForKey::Press {
+                    target: RegIdent::V3,
+                    await_release: !key_press_only
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn timers() {
+        let mut state = State::builder().registers(&[30]).delay_timer(12).build();
+
+        run_standard(0xF107, &mut state);
+        assert_eq!(state.reg_read(RegIdent::V1), 12);
+
+        run_standard(0xF015, &mut state);
+        run_standard(0xF018, &mut state);
+        assert_eq!(state.delay_timer, 30);
+        assert_eq!(state.sound_timer, 30);
+    }
+
+    #[test]
+    fn yield_to_host() {
+        let mut state = State::builder().build();
+        run(&make_fantasy_set(&Quirks::default()), 0x00F1, &mut state);
+        assert!(state.waiting_for_vblank);
+
+        let mut state = State::builder().build();
+        run_standard(0x00F1, &mut state);
+        assert!(!state.waiting_for_vblank);
+        assert_eq!(state.fault(), Some(Fault::UnknownOpCode));
+    }
+
+    #[test]
+    fn subroutines() {
+        let mut state = State::builder().pc(0x202).build();
+
+        run_standard(0x2400, &mut state);
+        assert_eq!(state.pc, Address::new(0x400).unwrap());
+        assert_eq!(state.stack_len(), 1);
+
+        run_standard(0x00EE, &mut state);
+        assert_eq!(state.pc, Address::new(0x202).unwrap());
+        assert_eq!(state.stack_len(), 0);
+        assert_eq!(state.fault(), None);
+
+        run_standard(0x00EE, &mut state);
+        assert_eq!(state.fault(), Some(Fault::StackUnderflow));
+
+        let mut full = State::builder().stack(&[0x200; 16]).build();
+        run_standard(0x2400, &mut full);
+        assert_eq!(full.fault(), Some(Fault::StackOverflow));
+        assert_eq!(full.pc, Address::new(0x200).unwrap());
+    }
+
+    #[test]
+    fn jumps() {
+        let mut state = State::builder().registers(&[0x05, 0x00, 0x00, 0x10]).build();
+
+        run_standard(0x1234, &mut state);
+        assert_eq!(state.pc, Address::new(0x234).unwrap());
+
+        run_standard(0xB300, &mut state);
+        assert_eq!(state.pc, Address::new(0x305).unwrap());
+
+        run(&make_set(&Quirks::schip()), 0xB300, &mut state);
+        assert_eq!(state.pc, Address::new(0x310).unwrap());
+    }
+
+    #[test]
+    fn skips() {
+        let cases = [
+            (0x3042, true),
+            (0x3043, false),
+            (0x4042, false),
+            (0x4043, true),
+            (0x5010, true),
+            (0x5020, false),
+            (0x9010, false),
+            (0x9020, true),
+        ];
+
+        for (opcode, skipped) in cases {
+            let mut state = State::builder().registers(&[0x42, 0x42, 0x00]).build();
+            run_standard(opcode, &mut state);
+
+            let expected = if skipped { 0x202 } else { 0x200 };
+            assert_eq!(state.pc, Address::new(expected).unwrap(), "{opcode:04X}");
+        }
+    }
+
+    #[test]
+    fn constants() {
+        let mut state = State::builder().build();
+
+        run_standard(0x6AFE, &mut state);
+        assert_eq!(state.reg_read(RegIdent::VA), 0xFE);
+
+        // No carry flag
+        run_standard(0x7A03, &mut state);
+        assert_eq!(state.reg_read(RegIdent::VA), 0x01);
+        assert_eq!(state.reg_read(RegIdent::VF), 0);
+
+        run_standard(0x8BA0, &mut state);
+        assert_eq!(state.reg_read(RegIdent::VB), 0x01);
+    }
+
+    #[test]
+    fn logic() {
+        let state = State::builder()
+            .registers(&[0b1100, 0b1010])
+            .register(RegIdent::VF, 0x42)
+            .build();
+
+        for (opcode, expected) in [(0x8011, 0b1110), (0x8012, 0b1000), (0x8013, 0b0110)] {
+            let mut standard = state.clone();
+            run_standard(opcode, &mut standard);
+            assert_eq!(standard.reg_read(RegIdent::V0), expected, "{opcode:04X}");
+            assert_eq!(standard.reg_read(RegIdent::VF), 0x42);
+
+            let mut resetting = state.clone();
+            run(&make_set(&Quirks::cosmac_vip()), opcode, &mut resetting);
+            assert_eq!(resetting.reg_read(RegIdent::V0), expected, "{opcode:04X}");
+            assert_eq!(resetting.reg_read(RegIdent::VF), 0);
+        }
+    }
+
+    #[test]
+    fn arithmetic() {
+        let cases = [
+            // (opcode, V0, V1, result, flag)
+            (0x8014, 0xFF, 0x02, 0x01, 1),
+            (0x8014, 0x01, 0x02, 0x03, 0),
+            (0x8015, 0x05, 0x02, 0x03, 1),
+            (0x8015, 0x02, 0x05, 0xFD, 0),
+            (0x8017, 0x02, 0x05, 0x03, 1),
+            (0x8017, 0x05, 0x02, 0xFD, 0),
+        ];
+
+        for (opcode, v0, v1, result, flag) in cases {
+            let mut state = State::builder().registers(&[v0, v1]).build();
+            run_standard(opcode, &mut state);
+            assert_eq!(state.reg_read(RegIdent::V0), result, "{opcode:04X}");
+            assert_eq!(state.reg_read(RegIdent::VF), flag, "{opcode:04X}");
+        }
+    }
+
+    #[test]
+    fn shift_right() {
+        let state = State::builder().registers(&[0b1000_0100, 0b0110_0000]).build();
+
+        let mut in_place = state.clone();
+        run_standard(0x8016, &mut in_place);
+        assert_eq!(in_place.reg_read(RegIdent::V0), 0b0100_0010);
+
+        assert_eq!(in_place.reg_read(RegIdent::VF), 0);
+
+        let mut from_vy = state;
+        run(&make_set(&Quirks::cosmac_vip()), 0x8016, &mut from_vy);
+        assert_eq!(from_vy.reg_read(RegIdent::V0), 0b0011_0000);
+        assert_eq!(from_vy.reg_read(RegIdent::VF), 0);
+
+        let mut odd = State::builder().registers(&[0b0000_0011]).build();
+        run_standard(0x8016, &mut odd);
+        assert_eq!(odd.reg_read(RegIdent::V0), 0b0000_0001);
+        assert_eq!(odd.reg_read(RegIdent::VF), 1);
+    }
+
+    #[test]
+    fn shift_left() {
+        let state = State::builder().registers(&[0b1000_0100, 0b0110_0000]).build();
+
+        let mut in_place = state.clone();
+        run_standard(0x801E, &mut in_place);
+        assert_eq!(in_place.reg_read(RegIdent::V0), 0b0000_1000);
+        assert_eq!(in_place.reg_read(RegIdent::VF), 1);
+
+        let mut from_vy = state;
+        run(&make_set(&Quirks::cosmac_vip()), 0x801E, &mut from_vy);
+        assert_eq!(from_vy.reg_read(RegIdent::V0), 0b1100_0000);
+        assert_eq!(from_vy.reg_read(RegIdent::VF), 0);
+    }
+
+    #[test]
+    fn hybrid_set() {
+        use trip_night_core::instruction::{OverlayError, OP_8XY6, OP_8XYE, SLOT_COUNT};
+
+        // Legacy shifts, standard loads and stores
+        let hybrid = make_standard_set()
+            .overlay(&make_legacy_set(), &[OP_8XY6, OP_8XYE])
+            .unwrap();
+
+        let mut state = State::builder().registers(&[0x00, 0x04]).build();
+        run(&hybrid, 0x8016, &mut state);
+        assert_eq!(state.reg_read(RegIdent::V0), 0x02);
+
+        state.index = Address::new(0x300).unwrap();
+        run(&hybrid, 0xF155, &mut state);
+        assert_eq!(state.index, Address::new(0x300).unwrap());
+
+        assert_eq!(
+            make_standard_set()
+                .overlay(&make_legacy_set(), &[OP_8XY6, OP_8XY6])
+                .err(),
+            Some(OverlayError::DuplicateSlot { slot: OP_8XY6 })
+        );
+        assert_eq!(
+            make_standard_set().overlay(&make_legacy_set(), &[SLOT_COUNT]).err(),
+            Some(OverlayError::UnknownSlot { slot: SLOT_COUNT })
+        );
+    }
+
+    #[test]
+    fn random() {
+        let mut state = State::builder().rng(SequenceRng::new(&[0xAB])).build();
+        run_standard(0xC30F, &mut state);
+        assert_eq!(state.reg_read(RegIdent::V3), 0x0B);
+    }
+
+    #[test]
+    fn index() {
+        let mut state = State::builder().registers(&[0x0A, 0x10]).build();
+
+        run_standard(0xA300, &mut state);
+        assert_eq!(state.index, Address::new(0x300).unwrap());
+
+        run_standard(0xF11E, &mut state);
+        assert_eq!(state.index, Address::new(0x310).unwrap());
+
+        // Glyph of "A"
+        run_standard(0xF029, &mut state);
+        assert_eq!(state.index, Address::new(0x50 + 0x0A * 5).unwrap());
+    }
+
+    #[test]
+    fn index_overflow() {
+        let amiga = Quirks {
+            index_overflow_sets_vf: true,
+            ..Quirks::default()
+        };
+
+        for (index, expected, flag) in [(0xFF0, 0x000, 1), (0xEF0, 0xF00, 0)] {
+            let mut state = State::builder().registers(&[0x10]).index(index).build();
+            run(&make_set(&amiga), 0xF01E, &mut state);
+
+            assert_eq!(state.index, Address::new(expected).unwrap());
+            assert_eq!(state.reg_read(RegIdent::VF), flag, "I = {index:03X}");
+        }
+
+        // VF is left alone without the quirk
+        let mut state = State::builder()
+            .registers(&[0x10])
+            .register(RegIdent::VF, 0x42)
+            .index(0xFF0)
+            .build();
+        run_standard(0xF01E, &mut state);
+        assert_eq!(state.reg_read(RegIdent::VF), 0x42);
+    }
+
+    #[test]
+    fn store_bcd() {
+        let mut state = State::builder().registers(&[123]).index(0x300).build();
+        run_standard(0xF033, &mut state);
+        assert_eq!(state.ram[0x300..0x303], [1, 2, 3]);
+        assert_eq!(state.index, Address::new(0x300).unwrap());
+    }
+
+    #[test]
+    fn store_and_load_registers() {
+        let mut state = State::builder().registers(&[1, 2, 3]).index(0x300).build();
+
+        run_standard(0xF155, &mut state);
+        assert_eq!(state.ram[0x300..0x303], [1, 2, 0]);
+        assert_eq!(state.index, Address::new(0x300).unwrap());
+
+        let mut state = State::builder().ram(0x300, &[4, 5, 6]).index(0x300).build();
+        run(&make_set(&Quirks::cosmac_vip()), 0xF265, &mut state);
+        assert_eq!(state.reg_read(RegIdent::V0), 4);
+        assert_eq!(state.reg_read(RegIdent::V2), 6);
+        assert_eq!(state.index, Address::new(0x303).unwrap());
+    }
+}
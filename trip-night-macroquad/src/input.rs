@@ -0,0 +1,173 @@
+use macroquad::prelude::*;
+use trip_night_core::analysis::KeyUsage;
+use trip_night_core::keypad::Key;
+use trip_night_core::screen::Screen;
+use trip_night_frontend_kit::keymap::KeyMapConfig;
+
+/// Macroquad key codes which can be bound to the keypad
+const BINDABLE: &[KeyCode] = &[
+    KeyCode::Key0,
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+    KeyCode::A,
+    KeyCode::B,
+    KeyCode::C,
+    KeyCode::D,
+    KeyCode::E,
+    KeyCode::F,
+    KeyCode::G,
+    KeyCode::H,
+    KeyCode::I,
+    KeyCode::J,
+    KeyCode::K,
+    KeyCode::L,
+    KeyCode::M,
+    KeyCode::N,
+    KeyCode::O,
+    KeyCode::P,
+    KeyCode::Q,
+    KeyCode::R,
+    KeyCode::S,
+    KeyCode::T,
+    KeyCode::U,
+    KeyCode::V,
+    KeyCode::W,
+    KeyCode::X,
+    KeyCode::Y,
+    KeyCode::Z,
+    KeyCode::Kp0,
+    KeyCode::Kp1,
+    KeyCode::Kp2,
+    KeyCode::Kp3,
+    KeyCode::Kp4,
+    KeyCode::Kp5,
+    KeyCode::Kp6,
+    KeyCode::Kp7,
+    KeyCode::Kp8,
+    KeyCode::Kp9,
+    KeyCode::Up,
+    KeyCode::Down,
+    KeyCode::Left,
+    KeyCode::Right,
+    KeyCode::Space,
+    KeyCode::Enter,
+    KeyCode::Tab,
+    KeyCode::LeftShift,
+    KeyCode::RightShift,
+    KeyCode::LeftControl,
+    KeyCode::RightControl,
+];
+
+/// Translates the host key names used by the key map into macroquad key codes
+pub struct HostKeys {
+    codes: Vec<(String, KeyCode)>,
+}
+
+impl HostKeys {
+    pub fn new() -> Self {
+        let codes = BINDABLE.iter().map(|code| (format!("{code:?}"), *code)).collect();
+        Self { codes }
+    }
+
+    pub fn code(&self, name: &str) -> Option<KeyCode> {
+        self.codes
+            .iter()
+            .find(|(code_name, _)| code_name == name)
+            .map(|(_, code)| *code)
+    }
+
+    pub fn is_down(&self, name: &str) -> bool {
+        self.code(name).map_or(false, is_key_down)
+    }
+
+    /// Name of a bindable key pressed during this frame
+    pub fn pressed(&self) -> Option<&str> {
+        self.codes
+            .iter()
+            .find(|(_, code)| is_key_pressed(*code))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// Screen pixel under the mouse cursor
+pub fn mouse_pixel(screen: &Screen, pixel_size: f32) -> Option<(u8, u8)> {
+    let (x, y) = mouse_position();
+
+    if x < 0.0 || y < 0.0 {
+        return None;
+    }
+
+    let x = (x / pixel_size) as u32;
+    let y = (y / pixel_size) as u32;
+
+    if x < u32::from(screen.width()) && y < u32::from(screen.height()) {
+        Some((x as u8, y as u8))
+    } else {
+        None
+    }
+}
+
+/// Draws the prompt of the rebinding flow
+pub fn draw_rebinding(key: Key) {
+    const FONT_SIZE: f32 = 28.0;
+
+    draw_rectangle(
+        0.0,
+        0.0,
+        screen_width(),
+        screen_height(),
+        Color::new(0.0, 0.0, 0.0, 0.8),
+    );
+
+    draw_text(
+        &format!("Press the key to bind to CHIP-8 key {key}"),
+        20.0,
+        FONT_SIZE * 1.5,
+        FONT_SIZE,
+        WHITE,
+    );
+    draw_text(
+        "Backspace: skip    Escape: cancel",
+        20.0,
+        FONT_SIZE * 3.0,
+        FONT_SIZE,
+        GRAY,
+    );
+}
+
+/// Draws the host keys bound to the keys used by the ROM (all keys when unknown)
+pub fn draw_help(key_map: &KeyMapConfig, usage: &KeyUsage) {
+    const FONT_SIZE: f32 = 28.0;
+
+    draw_rectangle(
+        0.0,
+        0.0,
+        screen_width(),
+        screen_height(),
+        Color::new(0.0, 0.0, 0.0, 0.8),
+    );
+
+    let show_all = usage.unresolved || usage.count() == 0;
+
+    let mut listed = Vec::new();
+
+    for (_, key) in key_map.bindings() {
+        if listed.contains(key) || !(show_all || usage.contains(*key)) {
+            continue;
+        }
+
+        let host_keys: Vec<&str> = key_map.hosts_for(*key).collect();
+
+        let y = FONT_SIZE * (listed.len() as f32 + 1.5);
+        draw_text(&format!("{key}: {}", host_keys.join(" / ")), 20.0, y, FONT_SIZE, WHITE);
+
+        listed.push(*key);
+    }
+}
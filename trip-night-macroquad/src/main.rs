@@ -1,75 +1,224 @@
+mod input;
+
 use std::fs::File;
 use std::io::{BufReader, Read};
 use std::time::Duration;
 
-use game_clock::Time;
 use macroquad::prelude::*;
+use trip_night_core::analysis::used_keys;
 use trip_night_core::machine::Machine;
+use trip_night_core::rewind::Rewinder;
+use trip_night_frontend_kit::capture::{Framebuffer, Recorder};
+use trip_night_frontend_kit::config::Config;
+use trip_night_frontend_kit::keymap::Rebinding;
+use trip_night_frontend_kit::locale::{Locale, Message};
+use trip_night_frontend_kit::overlay::Overlay;
+use trip_night_frontend_kit::pacing::Pacer;
+use trip_night_frontend_kit::palette::Rgb;
+use trip_night_frontend_kit::postprocess::Pipeline;
+use trip_night_frontend_kit::sprite;
+
+use crate::input::HostKeys;
 
+const CONFIG_PATH: &str = "trip-night.cfg";
+const LOCALE_PATH: &str = "trip-night.lang";
 const PIXEL_SIZE: f32 = 16.0;
-const CLOCK_FREQUENCY: usize = 700;
-const REFRESH_RATE: f64 = 30.0;
+const CAPTURE_SCALE: usize = 8;
+/// Frames between two rewind snapshots, and number of snapshots kept (10 seconds)
+const REWIND_INTERVAL: u64 = 5;
+const REWIND_CAPACITY: usize = 120;
 
 #[macroquad::main("Trip Night VM")]
 async fn main() {
+    let mut config = Config::load_or_default(CONFIG_PATH).unwrap();
+    let locale = Locale::load_or_default(LOCALE_PATH).unwrap();
+
     let mut game_code = Vec::with_capacity(4096);
     BufReader::new(File::open("games/ibm_logo.ch8").unwrap())
         .read_to_end(&mut game_code)
         .unwrap();
 
-    let standard_instruction_set = trip_night_instruction::make_standard_set();
-    let mut machine = Machine::new(&game_code, standard_instruction_set, CLOCK_FREQUENCY);
+    // Well-known ROMs get the quirks and speed they were written for, unless overridden by the user
+    let settings = config.rom_settings(&game_code);
+    let instruction_set = trip_night_instruction::make_set(&settings.quirks);
+    let mut machine = Machine::new(&game_code, instruction_set, settings.frequency_hz);
 
-    let mut time = Time::default();
-    time.set_fixed_time(Duration::from_secs_f64(1.0 / REFRESH_RATE));
+    let key_usage = used_keys(&game_code);
+    let mut key_map = match key_usage.arrow_layout() {
+        Some(layout) => config.key_map.clone().with_arrow_layout(&layout),
+        None => config.key_map.clone(),
+    };
+    let host_keys = HostKeys::new();
 
-    let step = 1.0 / CLOCK_FREQUENCY as f64;
+    // Paces frames rather than cycles
+    let mut pacer = Pacer::new(60);
+    let mut overlay = Overlay::default();
+    let mut rewinder = Rewinder::new(REWIND_INTERVAL, REWIND_CAPACITY);
+    let mut recorder: Option<Recorder> = None;
+    let mut pipeline = Pipeline::from_kinds(&config.effects);
+    let mut rebinding: Option<Rebinding> = None;
+    let mut painting = false;
 
-    clear_background(BLACK);
-    next_frame().await;
+    let background = to_color(config.palette.background);
 
     loop {
-        machine.cycle();
+        if is_quit_requested() {
+            break;
+        }
+
+        let elapsed = Duration::from_secs_f32(get_frame_time());
+
+        if is_key_pressed(KeyCode::F2) && rebinding.is_none() {
+            rebinding = Some(Rebinding::new());
+        }
+
+        if is_key_pressed(KeyCode::F5) && rebinding.is_none() {
+            machine.reset();
+            rewinder.clear();
+            overlay.show(locale.text(Message::Reset, &[]));
+        }
+
+        if is_key_pressed(KeyCode::F3) && rebinding.is_none() {
+            painting = !painting;
+
+            if painting {
+                overlay.show(locale.text(Message::PaintingStarted, &[]));
+            } else {
+                overlay.show(locale.text(Message::PaintingStopped, &[]));
+            }
+        }
+
+        if let Some(active) = rebinding.as_mut() {
+            // The emulation is paused while rebinding
+            if is_key_pressed(KeyCode::Escape) {
+                rebinding = None;
+                overlay.show(locale.text(Message::RebindingCancelled, &[]));
+            } else {
+                if is_key_pressed(KeyCode::Backspace) {
+                    active.skip();
+                } else if let Some(host) = host_keys.pressed() {
+                    active.bind(host);
+                }
+
+                if active.pending().is_none() {
+                    config.key_map = std::mem::take(active).finish();
+                    key_map = config.key_map.clone();
+                    rebinding = None;
+
+                    match config.save(CONFIG_PATH) {
+                        Ok(()) => overlay.show(locale.text(Message::KeyBindingsSaved, &[])),
+                        Err(e) => overlay.show(locale.text(Message::KeyBindingsFailed, &[&e])),
+                    }
+                }
+            }
+        } else if painting {
+            // The emulation is paused while painting
+            let pixel_size = pixel_size(machine.screen().width());
+
+            if let Some((x, y)) = input::mouse_pixel(machine.screen(), pixel_size) {
+                if is_mouse_button_down(MouseButton::Left) {
+                    sprite::paint(machine.screen_mut(), x, y, true);
+                } else if is_mouse_button_down(MouseButton::Right) {
+                    sprite::paint(machine.screen_mut(), x, y, false);
+                }
+
+                if is_key_pressed(KeyCode::F4) {
+                    let bytes = sprite::read(machine.screen(), x, y);
+                    println!("sprite at {}: {}", machine.state.index, sprite::format(&bytes));
+
+                    match sprite::store(&mut machine.state, &bytes) {
+                        Ok(()) => overlay.show(locale.text(Message::SpriteExported, &[&machine.state.index])),
+                        Err(_) => overlay.show(locale.text(Message::SpriteExportFailed, &[&machine.state.index])),
+                    }
+                }
+            }
+        } else if is_key_down(KeyCode::Backspace) {
+            // Steps back to the previous snapshot every frame the key is held
+            rewinder.rewind(&mut machine, 1);
+        } else {
+            key_map.update(machine.keypad_mut(), |host| host_keys.is_down(host));
+
+            for _ in 0..pacer.cycles_for(elapsed) {
+                machine.run_frame();
+                rewinder.record_frame(&machine);
+
+                // Every emulated frame is recorded, without post-processing
+                if let Some(active) = recorder.as_mut() {
+                    let frame = Framebuffer::from_screen(machine.screen(), &config.palette);
+
+                    if let Err(e) = active.capture(&frame, machine.counter) {
+                        overlay.show(locale.text(Message::RecordingFailed, &[&e]));
+                        recorder = None;
+                    }
+                }
+            }
+        }
 
         if machine.is_beeping() {
             println!("beep!");
         }
 
-        while time.step_fixed_update() {
-            if is_quit_requested() {
-                break;
+        let mut framebuffer = Framebuffer::from_screen(machine.screen(), &config.palette);
+        pipeline.apply(&mut framebuffer);
+
+        if is_key_pressed(KeyCode::F12) {
+            match framebuffer.save_ppm("screenshot.ppm", CAPTURE_SCALE) {
+                Ok(()) => overlay.show(locale.text(Message::ScreenshotSaved, &[])),
+                Err(e) => overlay.show(locale.text(Message::ScreenshotFailed, &[&e])),
             }
+        }
+
+        if is_key_pressed(KeyCode::F9) {
+            match recorder.take() {
+                Some(recorder) => match recorder.finish() {
+                    Ok(frame_count) => overlay.show(locale.text(Message::Recorded, &[&frame_count])),
+                    Err(e) => overlay.show(locale.text(Message::RecordingFailed, &[&e])),
+                },
+                None => match Recorder::new("recording", CAPTURE_SCALE) {
+                    Ok(new_recorder) => {
+                        overlay.show(locale.text(Message::Recording, &[]));
+                        recorder = Some(new_recorder);
+                    }
+                    Err(e) => overlay.show(locale.text(Message::RecordingFailed, &[&e])),
+                },
+            }
+        }
 
-            clear_background(BLACK);
+        clear_background(background);
 
-            for (x, y) in machine.screen().pixel_iter() {
-                let x = (x as f32) * PIXEL_SIZE;
-                let y = (y as f32) * PIXEL_SIZE;
-                draw_rectangle(x, y, PIXEL_SIZE, PIXEL_SIZE, WHITE);
+        let pixel_size = pixel_size(machine.screen().width());
+
+        for (idx, pixel) in framebuffer.pixels.iter().enumerate() {
+            if *pixel != config.palette.background {
+                let x = (idx % framebuffer.width) as f32 * pixel_size;
+                let y = (idx / framebuffer.width) as f32 * pixel_size;
+                draw_rectangle(x, y, pixel_size, pixel_size, to_color(*pixel));
             }
+        }
+
+        if is_key_down(KeyCode::F1) {
+            input::draw_help(&key_map, &key_usage);
+        }
+
+        if let Some(key) = rebinding.as_ref().and_then(Rebinding::pending) {
+            input::draw_rebinding(key);
+        }
+
+        overlay.update(elapsed);
 
-            next_frame().await;
+        if let Some(message) = overlay.current() {
+            draw_text(message, 10.0, screen_height() - 10.0, 32.0, YELLOW);
         }
 
-        time.advance_frame(Duration::from_secs_f64(step));
+        next_frame().await;
     }
 }
 
-// NOTE:
-// Standard CHIP-8 keypad:
-// 1 | 2 | 3 | C
-// - | - | - | -
-// 4 | 5 | 6 | D
-// - | - | - | -
-// 7 | 8 | 9 | E
-// - | - | - | -
-// A | 0 | B | F
-// Generally mapped as follow on a modern QWERTY keyboard:
-// 1 | 2 | 3 | 4
-// - | - | - | -
-// Q | W | E | R
-// - | - | - | -
-// A | S | D | F
-// - | - | - | -
-// Z | X | C | V
-// Note: use scancodes so it’s layout-agnostic
+/// Hi-res pixels are smaller, the picture keeps the same size
+fn pixel_size(screen_width: u8) -> f32 {
+    PIXEL_SIZE * 64.0 / f32::from(screen_width)
+}
+
+fn to_color(rgb: Rgb) -> Color {
+    Color::from_rgba(rgb.r, rgb.g, rgb.b, 0xFF)
+}